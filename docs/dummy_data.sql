@@ -2,3 +2,16 @@
 INSERT INTO users (username, password_hash) VALUES ('testuser1', 'password123');
 INSERT INTO users (username, password_hash) VALUES ('testuser2', 'password456');
 INSERT INTO users (username, password_hash) VALUES ('testuser3', 'password789');
+
+-- Make testuser1 an admin so they can import insurance plans
+UPDATE users SET is_admin = 1 WHERE username = 'testuser1';
+
+-- Insert a test insurance plan with one in-network provider
+INSERT INTO insurance_plans (name, payer) VALUES ('Acme Gold HMO', 'Acme Health');
+INSERT INTO plan_providers (plan_id, provider_name, provider_address) VALUES (1, 'CVS', '1275 Pennsylvania Avenue Northwest, Washington');
+
+-- Insert test telehealth providers
+INSERT INTO telehealth_providers (name, specialties, licensed_states, hours, price, accepted_insurance, booking_url)
+VALUES ('Anytime Primary Care', '["doctor"]', '["DC", "MD", "VA"]', '24/7', 79.0, '["Acme Gold HMO"]', 'https://example.com/anytime-primary-care/book');
+INSERT INTO telehealth_providers (name, specialties, licensed_states, hours, price, accepted_insurance, booking_url)
+VALUES ('TeleDental Consults', '["dentist"]', '["DC", "NY"]', 'Mon-Fri 8am-6pm ET', 39.0, '[]', 'https://example.com/teledental/book');
@@ -0,0 +1,36 @@
+// Local stand-in for a payer's FHIR Plan-Net API. Every search returns the
+// canned Bundle in `fixtures/plan_net/<ResourceType>.json`, ignoring search
+// parameters, so the directory client can be exercised offline:
+//
+//     cargo run --example plan_net_fixture
+//     PLAN_NET_BASE_URL=http://127.0.0.1:8090/fhir cargo run
+use actix_web::{web, App, HttpResponse, HttpServer, Responder};
+
+const EMPTY_BUNDLE: &str = r#"{"resourceType": "Bundle", "type": "searchset", "total": 0, "entry": []}"#;
+
+// Handler for `/fhir/{resource_type}` searches
+async fn search_handler(path: web::Path<String>) -> impl Responder {
+    let resource_type = path.into_inner();
+
+    // Resource types are plain identifiers; anything else can't name a fixture file
+    if !resource_type.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return HttpResponse::NotFound().finish();
+    }
+
+    let body = std::fs::read_to_string(format!("./fixtures/plan_net/{}.json", resource_type))
+        .unwrap_or_else(|_| EMPTY_BUNDLE.to_string());
+
+    HttpResponse::Ok()
+        .content_type("application/fhir+json")
+        .body(body)
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    println!("Serving Plan-Net fixtures at http://127.0.0.1:8090/fhir");
+
+    HttpServer::new(|| App::new().route("/fhir/{resource_type}", web::get().to(search_handler)))
+        .bind("127.0.0.1:8090")?
+        .run()
+        .await
+}
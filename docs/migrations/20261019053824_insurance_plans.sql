@@ -0,0 +1,20 @@
+-- Create Insurance Plans table
+CREATE TABLE IF NOT EXISTS insurance_plans (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    payer TEXT NOT NULL DEFAULT ''
+);
+
+-- Create Plan Providers table (the providers in each plan's network)
+CREATE TABLE IF NOT EXISTS plan_providers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    plan_id INTEGER NOT NULL,
+    provider_name TEXT NOT NULL,
+    provider_address TEXT NOT NULL,
+    FOREIGN KEY (plan_id) REFERENCES insurance_plans (id) ON DELETE CASCADE,
+    UNIQUE (plan_id, provider_name, provider_address)
+);
+
+-- Each user can pick the plan they are covered by; admins can import plan data
+ALTER TABLE users ADD COLUMN insurance_plan_id INTEGER REFERENCES insurance_plans (id) ON DELETE SET NULL;
+ALTER TABLE users ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,12 @@
+-- Create Telehealth Providers table (virtual-visit services, searched by state rather than distance)
+-- List columns hold JSON arrays of strings
+CREATE TABLE IF NOT EXISTS telehealth_providers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    specialties TEXT NOT NULL DEFAULT '[]',
+    licensed_states TEXT NOT NULL DEFAULT '[]',
+    hours TEXT NOT NULL DEFAULT '',
+    price REAL, -- Self-pay price per visit in USD
+    accepted_insurance TEXT NOT NULL DEFAULT '[]',
+    booking_url TEXT NOT NULL
+);
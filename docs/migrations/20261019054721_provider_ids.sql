@@ -0,0 +1,18 @@
+-- Create Providers table (the latest record seen for each provider in search results)
+CREATE TABLE IF NOT EXISTS providers (
+    id TEXT PRIMARY KEY, -- "place:<place_id>", "npi:<npi>", "plan-net:<location_id>" or a UUID for merged records
+    name TEXT NOT NULL,
+    address TEXT NOT NULL,
+    record TEXT NOT NULL, -- HealthProvider as JSON
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create Provider Aliases table (every source identifier that resolves to a provider)
+CREATE TABLE IF NOT EXISTS provider_aliases (
+    alias TEXT PRIMARY KEY,
+    provider_id TEXT NOT NULL,
+    FOREIGN KEY (provider_id) REFERENCES providers (id) ON DELETE CASCADE
+);
+
+-- Favorites point at the provider they were saved from
+ALTER TABLE favorites ADD COLUMN provider_id TEXT REFERENCES providers (id) ON DELETE SET NULL;
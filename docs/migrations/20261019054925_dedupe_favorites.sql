@@ -0,0 +1,10 @@
+-- Remove duplicate favorites, keeping the first one saved. Favorites saved
+-- before provider IDs existed are matched on title and address instead.
+DELETE FROM favorites
+WHERE id NOT IN (
+    SELECT MIN(id) FROM favorites
+    GROUP BY user_id, COALESCE(provider_id, title || char(10) || address)
+);
+
+-- Each user can favorite a provider only once
+CREATE UNIQUE INDEX IF NOT EXISTS favorites_user_provider ON favorites (user_id, provider_id);
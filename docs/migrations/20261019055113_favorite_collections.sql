@@ -0,0 +1,22 @@
+-- Create Favorite Collections table (named groups of favorites, e.g. "Mom's specialists")
+CREATE TABLE IF NOT EXISTS favorite_collections (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    position INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    UNIQUE (user_id, name)
+);
+
+-- Favorites can be filed in a collection (NULL means unsorted), ordered, and annotated
+ALTER TABLE favorites ADD COLUMN collection_id INTEGER REFERENCES favorite_collections (id) ON DELETE SET NULL;
+ALTER TABLE favorites ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE favorites ADD COLUMN notes TEXT NOT NULL DEFAULT '';
+
+-- Create Favorite Tags table (free-form tags on each favorite)
+CREATE TABLE IF NOT EXISTS favorite_tags (
+    favorite_id INTEGER NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (favorite_id, tag),
+    FOREIGN KEY (favorite_id) REFERENCES favorites (id) ON DELETE CASCADE
+);
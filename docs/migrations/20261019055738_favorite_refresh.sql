@@ -0,0 +1,37 @@
+-- Store favorite ratings as numbers. Ratings that aren't a valid 1-5 value
+-- (e.g. "undefined" from places without reviews) become NULL.
+ALTER TABLE favorites ADD COLUMN rating_value REAL;
+UPDATE favorites SET rating_value = CAST(trim(rating) AS REAL)
+    WHERE CAST(trim(rating) AS REAL) BETWEEN 1 AND 5;
+ALTER TABLE favorites DROP COLUMN rating;
+ALTER TABLE favorites RENAME COLUMN rating_value TO rating;
+
+-- Link favorites saved before provider IDs existed to a known provider with the same name and address
+UPDATE favorites SET provider_id = (
+    SELECT providers.id FROM providers
+    WHERE providers.name = favorites.title AND providers.address = favorites.address
+    LIMIT 1
+)
+WHERE provider_id IS NULL
+  AND NOT EXISTS (
+    SELECT 1 FROM favorites AS other
+    JOIN providers ON providers.id = other.provider_id
+    WHERE other.user_id = favorites.user_id
+      AND providers.name = favorites.title AND providers.address = favorites.address
+  );
+
+-- When the snapshot was last refreshed from the provider's source
+ALTER TABLE favorites ADD COLUMN refreshed_at TIMESTAMP;
+
+-- Create Favorite Changes table (what the refresher found different from the saved snapshot)
+CREATE TABLE IF NOT EXISTS favorite_changes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    favorite_id INTEGER NOT NULL,
+    field TEXT NOT NULL, -- "title", "address" or "rating"
+    old_value TEXT,
+    new_value TEXT,
+    changed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (favorite_id) REFERENCES favorites (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS favorite_changes_favorite ON favorite_changes (favorite_id, changed_at);
@@ -0,0 +1,26 @@
+-- More of the provider's details are kept in the favorite snapshot so the
+-- refresher can tell when they change. NULL means not captured yet.
+ALTER TABLE favorites ADD COLUMN phone TEXT; -- '' when the provider lists no phone
+ALTER TABLE favorites ADD COLUMN opening_hours TEXT; -- JSON array, one line per weekday
+ALTER TABLE favorites ADD COLUMN business_status TEXT;
+-- Changes to these are recorded in favorite_changes as "phone", "opening_hours" and "business_status"
+
+-- Create Notifications table (alerts about a user's favorites)
+CREATE TABLE IF NOT EXISTS notifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    favorite_id INTEGER,
+    kind TEXT NOT NULL, -- "rating_drop", "hours", "phone" or "closed"
+    message TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    read_at TIMESTAMP,
+    emailed_at TIMESTAMP, -- Set once the notification went out in an email digest
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    FOREIGN KEY (favorite_id) REFERENCES favorites (id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS notifications_user ON notifications (user_id, created_at);
+
+-- Email digest settings
+ALTER TABLE users ADD COLUMN email TEXT;
+ALTER TABLE users ADD COLUMN email_digest BOOLEAN NOT NULL DEFAULT FALSE;
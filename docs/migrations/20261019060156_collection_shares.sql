@@ -0,0 +1,14 @@
+-- Create Collection Shares table (public read-only links to a favorites collection)
+CREATE TABLE IF NOT EXISTS collection_shares (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    collection_id INTEGER NOT NULL,
+    token TEXT NOT NULL UNIQUE, -- Random, used in the /shared/{token} URL
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP, -- NULL for links that don't expire
+    revoked_at TIMESTAMP,
+    view_count INTEGER NOT NULL DEFAULT 0,
+    last_viewed_at TIMESTAMP,
+    FOREIGN KEY (collection_id) REFERENCES favorite_collections (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS collection_shares_collection ON collection_shares (collection_id);
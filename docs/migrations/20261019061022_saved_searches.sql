@@ -0,0 +1,32 @@
+-- Create Saved Searches table (`/services` queries re-run on a schedule)
+CREATE TABLE IF NOT EXISTS saved_searches (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    zip TEXT,
+    lat REAL,
+    lng REAL,
+    service_type TEXT NOT NULL,
+    in_network BOOLEAN NOT NULL DEFAULT FALSE,
+    mode TEXT, -- "virtual" for the telehealth catalog, NULL for nearby places
+    state TEXT, -- Two-letter state, for virtual searches
+    frequency TEXT NOT NULL DEFAULT 'daily', -- "daily" or "weekly"
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_run_at TIMESTAMP, -- NULL until the first run records the baseline results
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS saved_searches_user ON saved_searches (user_id);
+
+-- Providers returned by the latest run, to diff the next run against
+CREATE TABLE IF NOT EXISTS saved_search_results (
+    saved_search_id INTEGER NOT NULL,
+    provider_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    PRIMARY KEY (saved_search_id, provider_id),
+    FOREIGN KEY (saved_search_id) REFERENCES saved_searches (id) ON DELETE CASCADE
+);
+
+-- Notifications about providers appearing in or dropping out of a saved search
+-- ("new_results" and "removed_results")
+ALTER TABLE notifications ADD COLUMN saved_search_id INTEGER REFERENCES saved_searches (id) ON DELETE CASCADE;
@@ -0,0 +1,18 @@
+-- Create Search History table (recent `/services` searches, for one-click repeat).
+-- Only the ZIP code is kept: "use current location" searches are stored under the
+-- ZIP their coordinates fall in, never the coordinates themselves.
+CREATE TABLE IF NOT EXISTS search_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    zip TEXT NOT NULL,
+    service_type TEXT NOT NULL,
+    in_network BOOLEAN NOT NULL DEFAULT FALSE,
+    mode TEXT, -- "virtual" for the telehealth catalog, NULL for nearby places
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS search_history_user ON search_history (user_id, created_at);
+
+-- Users can turn history off; nothing is recorded while it's off
+ALTER TABLE users ADD COLUMN search_history BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,39 @@
+-- Create Provider Calendars table (providers that take bookings through the dashboard)
+CREATE TABLE IF NOT EXISTS provider_calendars (
+    provider_id TEXT PRIMARY KEY, -- Stable provider ID
+    name TEXT NOT NULL, -- Shown with appointments, so bookings don't depend on search records
+    address TEXT NOT NULL DEFAULT '',
+    timezone TEXT NOT NULL, -- IANA name, e.g. "America/New_York"; schedules and appointments are in local time
+    slot_minutes INTEGER NOT NULL DEFAULT 30,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create Provider Schedules table (weekly opening hours that slots are cut from)
+CREATE TABLE IF NOT EXISTS provider_schedules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    provider_id TEXT NOT NULL,
+    weekday INTEGER NOT NULL, -- 0 = Sunday ... 6 = Saturday
+    start_time TEXT NOT NULL, -- "HH:MM"
+    end_time TEXT NOT NULL,   -- "HH:MM", after start_time
+    FOREIGN KEY (provider_id) REFERENCES provider_calendars (provider_id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS provider_schedules_provider ON provider_schedules (provider_id, weekday);
+
+-- Create Appointments table
+CREATE TABLE IF NOT EXISTS appointments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    provider_id TEXT NOT NULL,
+    starts_at TEXT NOT NULL, -- "YYYY-MM-DDTHH:MM" in the provider's timezone
+    ends_at TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'booked', -- "booked" or "cancelled"
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    cancelled_at TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS appointments_user ON appointments (user_id, starts_at);
+
+-- A slot can only be booked once
+CREATE UNIQUE INDEX IF NOT EXISTS appointments_provider_slot ON appointments (provider_id, starts_at) WHERE status = 'booked';
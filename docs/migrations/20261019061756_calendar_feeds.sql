@@ -0,0 +1,12 @@
+-- Calendar apps replace an event when its SEQUENCE goes up, so rescheduling and
+-- cancelling bump it
+ALTER TABLE appointments ADD COLUMN sequence INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE appointments ADD COLUMN updated_at TIMESTAMP; -- NULL until rescheduled or cancelled
+
+-- Create Calendar Feeds table (private iCalendar subscription URL per user)
+CREATE TABLE IF NOT EXISTS calendar_feeds (
+    user_id INTEGER PRIMARY KEY,
+    token TEXT NOT NULL UNIQUE, -- Only thing needed to read the feed
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
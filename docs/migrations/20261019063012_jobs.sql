@@ -0,0 +1,20 @@
+-- Create Jobs table (persistent background job queue)
+CREATE TABLE IF NOT EXISTS jobs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL, -- Picks the handler, e.g. "appointment_reminder"
+    payload TEXT NOT NULL, -- JSON, read by the handler
+    run_at TIMESTAMP NOT NULL, -- UTC; pushed back after each failed attempt
+    status TEXT NOT NULL DEFAULT 'pending', -- "pending", "running", "done" or "dead"
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL DEFAULT 5, -- Moved to "dead" once used up
+    last_error TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    finished_at TIMESTAMP -- Set when the job succeeds or is given up on
+);
+
+CREATE INDEX IF NOT EXISTS jobs_due ON jobs (status, run_at);
+
+-- Appointment reminder settings
+ALTER TABLE users ADD COLUMN phone TEXT;
+ALTER TABLE users ADD COLUMN email_reminders BOOLEAN NOT NULL DEFAULT TRUE; -- Only sent when an email is set
+ALTER TABLE users ADD COLUMN sms_reminders BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,24 @@
+-- Create Provider Clinicians table (users who take a provider's video visits)
+CREATE TABLE IF NOT EXISTS provider_clinicians (
+    provider_id TEXT NOT NULL, -- Stable provider ID
+    user_id INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (provider_id, user_id),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS provider_clinicians_user ON provider_clinicians (user_id);
+
+-- Create Visit Events table (who joined and left an appointment's video visit, and when)
+CREATE TABLE IF NOT EXISTS visit_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    appointment_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    role TEXT NOT NULL, -- "patient" or "clinician"
+    event TEXT NOT NULL, -- "join" or "leave"
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (appointment_id) REFERENCES appointments (id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS visit_events_appointment ON visit_events (appointment_id, created_at);
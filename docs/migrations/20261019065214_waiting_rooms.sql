@@ -0,0 +1,25 @@
+-- Create Waiting Rooms table (drop-in virtual clinics, opened and closed by the provider's clinicians)
+CREATE TABLE IF NOT EXISTS waiting_rooms (
+    provider_id TEXT PRIMARY KEY, -- Stable provider ID
+    is_open BOOLEAN NOT NULL DEFAULT FALSE,
+    visit_minutes INTEGER NOT NULL DEFAULT 15, -- Typical visit length, for estimated waits
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (provider_id) REFERENCES provider_calendars (provider_id) ON DELETE CASCADE
+);
+
+-- Create Waiting Room Entries table (patients in the queue, in order of joining)
+CREATE TABLE IF NOT EXISTS waiting_room_entries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    provider_id TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    status TEXT NOT NULL DEFAULT 'waiting', -- "waiting", "admitted", "skipped" or "left"
+    joined_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    finished_at TIMESTAMP, -- When the patient was admitted or skipped, or left
+    FOREIGN KEY (provider_id) REFERENCES waiting_rooms (provider_id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS waiting_room_entries_queue ON waiting_room_entries (provider_id, status, id);
+
+-- A patient waits in a clinic's queue only once at a time
+CREATE UNIQUE INDEX IF NOT EXISTS waiting_room_entries_user ON waiting_room_entries (provider_id, user_id) WHERE status = 'waiting';
@@ -0,0 +1,41 @@
+-- Create Message Threads table (conversations between a user and a provider's staff)
+CREATE TABLE IF NOT EXISTS message_threads (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL, -- The patient
+    provider_id TEXT NOT NULL, -- Stable provider ID; its clinicians answer for the provider
+    subject BLOB NOT NULL, -- Encrypted, see encryption.rs
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP, -- Time of the latest message
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS message_threads_user ON message_threads (user_id, updated_at);
+CREATE INDEX IF NOT EXISTS message_threads_provider ON message_threads (provider_id, updated_at);
+
+-- Create Messages table
+CREATE TABLE IF NOT EXISTS messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    thread_id INTEGER NOT NULL,
+    sender_id INTEGER NOT NULL,
+    sender_role TEXT NOT NULL, -- "patient" or "staff"
+    body BLOB NOT NULL, -- Encrypted
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    read_at TIMESTAMP, -- Set when the other side opens the thread
+    FOREIGN KEY (thread_id) REFERENCES message_threads (id) ON DELETE CASCADE,
+    FOREIGN KEY (sender_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS messages_thread ON messages (thread_id, id);
+
+-- Create Message Attachments table (files kept encrypted under ATTACHMENT_DIR)
+CREATE TABLE IF NOT EXISTS message_attachments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id INTEGER NOT NULL,
+    filename TEXT NOT NULL, -- As uploaded, for downloads
+    content_type TEXT NOT NULL,
+    size INTEGER NOT NULL, -- Bytes before encryption
+    stored_name TEXT NOT NULL UNIQUE, -- Random name of the file on disk
+    FOREIGN KEY (message_id) REFERENCES messages (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS message_attachments_message ON message_attachments (message_id);
@@ -0,0 +1,26 @@
+-- Create Intake Forms table (one questionnaire per provider, filled in before visits)
+CREATE TABLE IF NOT EXISTS intake_forms (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    provider_id TEXT NOT NULL UNIQUE, -- Stable provider ID
+    title TEXT NOT NULL,
+    definition TEXT NOT NULL, -- JSON list of questions, see intake_forms.rs
+    version INTEGER NOT NULL DEFAULT 1, -- Bumped on every edit
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create Intake Responses table (a patient's answers for one appointment)
+CREATE TABLE IF NOT EXISTS intake_responses (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    appointment_id INTEGER NOT NULL UNIQUE,
+    form_id INTEGER NOT NULL,
+    form_version INTEGER NOT NULL,
+    questions TEXT NOT NULL, -- The form's questions as answered, so later edits don't change old responses
+    answers TEXT NOT NULL, -- JSON object of question ID to answer
+    submitted_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (appointment_id) REFERENCES appointments (id) ON DELETE CASCADE,
+    FOREIGN KEY (form_id) REFERENCES intake_forms (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS intake_responses_form ON intake_responses (form_id);
@@ -0,0 +1,30 @@
+-- Create Provider Claims table (staff asking to manage a provider's listing)
+CREATE TABLE IF NOT EXISTS provider_claims (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    provider_id TEXT NOT NULL, -- Stable provider ID
+    user_id INTEGER NOT NULL,
+    role TEXT NOT NULL, -- The requester's job at the provider, e.g. "Office manager"
+    message TEXT NOT NULL DEFAULT '', -- How an admin can verify them
+    status TEXT NOT NULL DEFAULT 'pending', -- "pending", "approved", "rejected" or "revoked"
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    decided_at TIMESTAMP,
+    decided_by INTEGER, -- Admin who approved, rejected or revoked the claim
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+-- A user has at most one open claim per provider
+CREATE UNIQUE INDEX IF NOT EXISTS provider_claims_open ON provider_claims (provider_id, user_id)
+    WHERE status IN ('pending', 'approved');
+CREATE INDEX IF NOT EXISTS provider_claims_status ON provider_claims (status, created_at);
+
+-- Create Provider Overrides table (corrections from a provider's staff, kept apart from upstream records)
+CREATE TABLE IF NOT EXISTS provider_overrides (
+    provider_id TEXT PRIMARY KEY, -- Stable provider ID
+    phone TEXT, -- NULL columns leave the upstream value in place
+    hours TEXT, -- JSON list of lines, e.g. ["Monday: 9:00 AM – 5:00 PM"]
+    telehealth BOOLEAN, -- Whether the provider offers video visits
+    accepted_insurance TEXT, -- JSON list of plan names
+    updated_by INTEGER NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (updated_by) REFERENCES users (id)
+);
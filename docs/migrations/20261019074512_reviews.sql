@@ -0,0 +1,33 @@
+-- Create Reviews table (first-party ratings, shown once an admin publishes them)
+CREATE TABLE IF NOT EXISTS reviews (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    provider_id TEXT NOT NULL, -- Stable provider ID
+    user_id INTEGER NOT NULL,
+    rating INTEGER NOT NULL CHECK (rating BETWEEN 1 AND 5),
+    body TEXT NOT NULL DEFAULT '',
+    status TEXT NOT NULL DEFAULT 'pending', -- "pending", "published" or "rejected"; edits go back to "pending"
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    moderated_at TIMESTAMP,
+    moderated_by INTEGER, -- Admin who published or rejected the review
+    UNIQUE (provider_id, user_id), -- One review per user per provider
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS reviews_provider ON reviews (provider_id, status);
+CREATE INDEX IF NOT EXISTS reviews_status ON reviews (status, updated_at);
+
+-- Create Review Reports table (users flagging a published review as abusive)
+CREATE TABLE IF NOT EXISTS review_reports (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    review_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    reason TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    resolved_at TIMESTAMP, -- Set when an admin acts on the review
+    UNIQUE (review_id, user_id),
+    FOREIGN KEY (review_id) REFERENCES reviews (id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS review_reports_open ON review_reports (review_id) WHERE resolved_at IS NULL;
@@ -0,0 +1,4 @@
+-- Track failed saved search runs, so a search that keeps failing (e.g. a ZIP that no
+-- longer geocodes) backs off instead of staying first in line every check
+ALTER TABLE saved_searches ADD COLUMN failures INTEGER NOT NULL DEFAULT 0; -- Failed runs in a row
+ALTER TABLE saved_searches ADD COLUMN last_attempt_at TIMESTAMP; -- Latest run, whether or not it worked
@@ -0,0 +1,803 @@
+// Appointment booking against schedules registered here by admins. A provider's
+// calendar has a timezone, a slot length and weekly opening hours; bookable slots
+// are cut from those hours, and times are kept in the provider's local time as
+// "YYYY-MM-DDTHH:MM". Booking inserts the appointment first and then checks for
+// overlaps in the same transaction, so the write lock is taken before the check
+// and two people can't book the same time.
+use crate::provider_store;
+use crate::reminders;
+use crate::session::{current_user_id, require_admin};
+
+use actix_web::http::StatusCode;
+use actix_web::{delete, get, patch, post, put, web, HttpRequest, HttpResponse, Responder};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use handlebars::Handlebars;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+
+pub const SLOT_FORMAT: &str = "%Y-%m-%dT%H:%M";
+const TIME_FORMAT: &str = "%H:%M";
+
+// Days of slots shown at once
+const DEFAULT_DAYS: i64 = 7;
+const MAX_DAYS: i64 = 28;
+
+const MIN_SLOT_MINUTES: i64 = 5;
+const MAX_SLOT_MINUTES: i64 = 240;
+
+// A provider's booking settings and weekly hours
+pub struct Calendar {
+    pub provider_id: String,
+    pub name: String,
+    pub address: String,
+    pub timezone: Tz,
+    slot_minutes: i64,
+    hours: Vec<(u32, NaiveTime, NaiveTime)>, // Weekday (0 = Sunday), opening and closing time
+}
+
+#[derive(Serialize)]
+struct Slot {
+    starts_at: String,
+    ends_at: String,
+    time: String, // For display, e.g. "9:30 AM"
+    available: bool,
+}
+
+#[derive(Serialize)]
+struct Day {
+    date: String, // For display, e.g. "Mon, Oct 20"
+    slots: Vec<Slot>,
+}
+
+#[derive(Serialize)]
+pub struct Appointment {
+    pub id: i64,
+    pub provider_id: String,
+    pub provider_name: String,
+    pub provider_address: String,
+    pub starts_at: String,
+    pub ends_at: String,
+    pub timezone: String,
+    pub when: String, // For display, e.g. "Mon, Oct 20 at 9:30 AM"
+    pub has_intake_form: bool,
+    pub intake_submitted: bool,
+}
+
+#[derive(Deserialize)]
+struct OpeningHours {
+    weekday: u32,
+    start: String,
+    end: String,
+}
+
+#[derive(Deserialize)]
+struct NewSchedule {
+    name: Option<String>, // Defaults to the provider's name from search results
+    address: Option<String>,
+    timezone: String,
+    slot_minutes: Option<i64>,
+    hours: Vec<OpeningHours>,
+}
+
+#[derive(Deserialize)]
+struct SlotQuery {
+    from: Option<String>, // "YYYY-MM-DD", defaults to today in the provider's timezone
+    days: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct BookingPage {
+    reschedule: Option<i64>, // Appointment being moved to a new time
+}
+
+#[derive(Deserialize)]
+struct NewAppointment {
+    provider_id: String,
+    starts_at: String,
+}
+
+#[derive(Deserialize)]
+struct NewTime {
+    starts_at: String,
+}
+
+fn not_logged_in() -> HttpResponse {
+    HttpResponse::Unauthorized().json(json!({
+        "status": "error",
+        "message": "User not logged in. Please log in and try again."
+    }))
+}
+
+fn error(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+pub fn display_time(time: &NaiveDateTime) -> String {
+    time.format("%-I:%M %p").to_string()
+}
+
+pub fn display_date(time: &NaiveDateTime) -> String {
+    time.format("%a, %b %-d").to_string()
+}
+
+// The current time on the provider's wall clock
+pub fn now_in(timezone: &Tz) -> NaiveDateTime {
+    Utc::now().with_timezone(timezone).naive_local()
+}
+
+// Load a provider's calendar by stable ID or any ID the provider was found under
+pub async fn load_calendar(pool: &SqlitePool, provider_id: &str) -> Result<Option<Calendar>, sqlx::Error> {
+    let calendar = sqlx::query!(
+        "SELECT provider_id AS \"provider_id!\", name, address, timezone, slot_minutes FROM provider_calendars
+         WHERE provider_id = ? OR provider_id = (SELECT provider_id FROM provider_aliases WHERE alias = ?)",
+        provider_id,
+        provider_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(calendar) = calendar else {
+        return Ok(None);
+    };
+
+    let hours = sqlx::query!(
+        "SELECT weekday, start_time, end_time FROM provider_schedules WHERE provider_id = ? ORDER BY weekday, start_time",
+        calendar.provider_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Some(Calendar {
+        timezone: calendar.timezone.parse().unwrap_or(Tz::UTC),
+        provider_id: calendar.provider_id,
+        name: calendar.name,
+        address: calendar.address,
+        slot_minutes: calendar.slot_minutes,
+        hours: hours
+            .into_iter()
+            .filter_map(|row| {
+                let start = NaiveTime::parse_from_str(&row.start_time, TIME_FORMAT).ok()?;
+                let end = NaiveTime::parse_from_str(&row.end_time, TIME_FORMAT).ok()?;
+                Some((row.weekday as u32, start, end))
+            })
+            .collect(),
+    }))
+}
+
+impl Calendar {
+    // Every slot on `date`, booked or not
+    fn slots_on(&self, date: NaiveDate) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+        let length = Duration::minutes(self.slot_minutes);
+        let weekday = date.weekday().num_days_from_sunday();
+        let mut slots = Vec::new();
+        for (_, open, close) in self.hours.iter().filter(|(day, _, _)| *day == weekday) {
+            let mut start = date.and_time(*open);
+            let close = date.and_time(*close);
+            while start + length <= close {
+                slots.push((start, start + length));
+                start += length;
+            }
+        }
+        slots
+    }
+
+    // The slot starting at `starts_at`, if there is one
+    fn slot_at(&self, starts_at: NaiveDateTime) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        self.slots_on(starts_at.date()).into_iter().find(|(start, _)| *start == starts_at)
+    }
+}
+
+// Check that `starts_at` is a future slot on the calendar, returning its start and end,
+// or why it can't be booked
+fn bookable_slot(calendar: &Calendar, starts_at: &str) -> Result<(String, String), &'static str> {
+    let Ok(starts_at) = NaiveDateTime::parse_from_str(starts_at, SLOT_FORMAT) else {
+        return Err("Appointment times look like 2026-10-20T09:30.");
+    };
+    let Some((start, end)) = calendar.slot_at(starts_at) else {
+        return Err("The provider doesn't take appointments at that time.");
+    };
+    if start <= now_in(&calendar.timezone) {
+        return Err("That time has already passed.");
+    }
+    Ok((start.format(SLOT_FORMAT).to_string(), end.format(SLOT_FORMAT).to_string()))
+}
+
+// The slots from `from` for `days` days, grouped by day; past slots are left out
+async fn list_slots(
+    pool: &SqlitePool,
+    calendar: &Calendar,
+    from: Option<NaiveDate>,
+    days: i64,
+) -> Result<Vec<Day>, sqlx::Error> {
+    let now = now_in(&calendar.timezone);
+    let from = from.unwrap_or(now.date()).max(now.date());
+    let until = (from + Duration::days(days)).to_string();
+    let from_date = from.to_string();
+
+    let booked = sqlx::query_scalar!(
+        "SELECT starts_at FROM appointments
+         WHERE provider_id = ? AND status = 'booked' AND starts_at >= ? AND starts_at < ?",
+        calendar.provider_id,
+        from_date,
+        until
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(from
+        .iter_days()
+        .take(days as usize)
+        .map(|date| Day {
+            date: display_date(&date.and_time(NaiveTime::MIN)),
+            slots: calendar
+                .slots_on(date)
+                .into_iter()
+                .filter(|(start, _)| *start > now)
+                .map(|(start, end)| {
+                    let starts_at = start.format(SLOT_FORMAT).to_string();
+                    Slot {
+                        available: !booked.contains(&starts_at),
+                        ends_at: end.format(SLOT_FORMAT).to_string(),
+                        time: display_time(&start),
+                        starts_at,
+                    }
+                })
+                .collect(),
+        })
+        .filter(|day| !day.slots.is_empty())
+        .collect())
+}
+
+// The user's booked appointments that haven't started yet, soonest first
+pub async fn list_upcoming(pool: &SqlitePool, user_id: i64) -> Result<Vec<Appointment>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT appointments.id AS \"id!\", appointments.provider_id, starts_at, ends_at,
+                provider_calendars.name, provider_calendars.address, provider_calendars.timezone,
+                EXISTS (SELECT 1 FROM intake_forms WHERE intake_forms.provider_id = appointments.provider_id)
+                    AS \"has_intake_form!: bool\",
+                EXISTS (SELECT 1 FROM intake_responses WHERE intake_responses.appointment_id = appointments.id)
+                    AS \"intake_submitted!: bool\"
+         FROM appointments JOIN provider_calendars ON provider_calendars.provider_id = appointments.provider_id
+         WHERE appointments.user_id = ? AND status = 'booked'
+         ORDER BY starts_at",
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            let timezone: Tz = row.timezone.parse().unwrap_or(Tz::UTC);
+            let start = NaiveDateTime::parse_from_str(&row.starts_at, SLOT_FORMAT).ok()?;
+            (start > now_in(&timezone)).then(|| Appointment {
+                id: row.id,
+                provider_id: row.provider_id,
+                provider_name: row.name,
+                provider_address: row.address,
+                when: format!("{} at {}", display_date(&start), display_time(&start)),
+                starts_at: row.starts_at,
+                ends_at: row.ends_at,
+                timezone: row.timezone,
+                has_intake_form: row.has_intake_form,
+                intake_submitted: row.intake_submitted,
+            })
+        })
+        .collect())
+}
+
+// Whether another booked appointment with the provider overlaps `id`'s time
+async fn has_conflict(
+    conn: &mut sqlx::SqliteConnection,
+    id: i64,
+    provider_id: &str,
+    starts_at: &str,
+    ends_at: &str,
+) -> Result<bool, sqlx::Error> {
+    let overlapping = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM appointments
+         WHERE provider_id = ? AND status = 'booked' AND id != ? AND starts_at < ? AND ends_at > ?",
+        provider_id,
+        id,
+        ends_at,
+        starts_at
+    )
+    .fetch_one(conn)
+    .await?;
+    Ok(overlapping > 0)
+}
+
+fn slot_taken() -> HttpResponse {
+    error(
+        StatusCode::CONFLICT,
+        "Sorry, that time was just booked. Please pick another.",
+    )
+}
+
+// Handler for `PUT /admin/providers/{id}/schedule` (replace a provider's calendar)
+#[put("/admin/providers/{id}/schedule")]
+async fn set_schedule(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<NewSchedule>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    if let Err(response) = require_admin(&req, pool.get_ref()).await {
+        return response;
+    }
+    let id = path.into_inner();
+    let schedule = body.into_inner();
+
+    if schedule.timezone.parse::<Tz>().is_err() {
+        return error(StatusCode::BAD_REQUEST, "Unknown timezone. Use an IANA name such as America/New_York.");
+    }
+    let slot_minutes = schedule.slot_minutes.unwrap_or(30);
+    if !(MIN_SLOT_MINUTES..=MAX_SLOT_MINUTES).contains(&slot_minutes) {
+        return error(StatusCode::BAD_REQUEST, "Slots must be between 5 and 240 minutes long.");
+    }
+    let mut hours = Vec::new();
+    for day in &schedule.hours {
+        let start = NaiveTime::parse_from_str(&day.start, TIME_FORMAT);
+        let end = NaiveTime::parse_from_str(&day.end, TIME_FORMAT);
+        match (start, end) {
+            (Ok(start), Ok(end)) if day.weekday <= 6 && start < end => {
+                hours.push((day.weekday as i64, start.format(TIME_FORMAT).to_string(), end.format(TIME_FORMAT).to_string()))
+            }
+            _ => return error(StatusCode::BAD_REQUEST, "Each day needs a weekday from 0 (Sunday) to 6 and a start before its end, as HH:MM."),
+        }
+    }
+
+    // Register the calendar under the provider's stable ID when it has appeared in a search
+    let provider = match provider_store::find(pool.get_ref(), &id).await {
+        Ok(provider) => provider,
+        Err(err) => {
+            eprintln!("Failed to fetch provider {}: {}", id, err);
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to save schedule.");
+        }
+    };
+    let provider_id = provider.as_ref().map(|provider| provider.id.clone()).unwrap_or(id);
+    let name = schedule
+        .name
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .or(provider.as_ref().map(|provider| provider.name.clone()));
+    let Some(name) = name else {
+        return error(StatusCode::BAD_REQUEST, "Provider name is required for providers that haven't appeared in a search.");
+    };
+    let address = schedule
+        .address
+        .or(provider.map(|provider| provider.address))
+        .unwrap_or_default();
+
+    let result = async {
+        let mut tx = pool.begin().await?;
+        sqlx::query!(
+            "INSERT INTO provider_calendars (provider_id, name, address, timezone, slot_minutes) VALUES (?, ?, ?, ?, ?)
+             ON CONFLICT (provider_id) DO UPDATE SET
+                 name = excluded.name, address = excluded.address, timezone = excluded.timezone,
+                 slot_minutes = excluded.slot_minutes, updated_at = CURRENT_TIMESTAMP",
+            provider_id,
+            name,
+            address,
+            schedule.timezone,
+            slot_minutes
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!("DELETE FROM provider_schedules WHERE provider_id = ?", provider_id)
+            .execute(&mut *tx)
+            .await?;
+        for (weekday, start, end) in &hours {
+            sqlx::query!(
+                "INSERT INTO provider_schedules (provider_id, weekday, start_time, end_time) VALUES (?, ?, ?, ?)",
+                provider_id,
+                weekday,
+                start,
+                end
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+    .await;
+
+    match result {
+        Ok(()) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Schedule saved.",
+            "provider_id": provider_id
+        })),
+        Err(err) => {
+            eprintln!("Failed to save schedule for {}: {}", provider_id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to save schedule.")
+        }
+    }
+}
+
+// Handler for `GET /api/providers/{id}/slots?from=YYYY-MM-DD&days=7`
+#[get("/api/providers/{id}/slots")]
+async fn get_slots(path: web::Path<String>, query: web::Query<SlotQuery>, pool: web::Data<SqlitePool>) -> impl Responder {
+    let id = path.into_inner();
+
+    let from = match query.from.as_deref().map(|from| from.parse::<NaiveDate>()) {
+        Some(Ok(from)) => Some(from),
+        Some(Err(_)) => return error(StatusCode::BAD_REQUEST, "Dates look like 2026-10-20."),
+        None => None,
+    };
+    let days = query.days.unwrap_or(DEFAULT_DAYS);
+    if !(1..=MAX_DAYS).contains(&days) {
+        return error(StatusCode::BAD_REQUEST, "Slots can be listed for 1 to 28 days at a time.");
+    }
+
+    let result = async {
+        let Some(calendar) = load_calendar(pool.get_ref(), &id).await? else {
+            return Ok(None);
+        };
+        let days = list_slots(pool.get_ref(), &calendar, from, days).await?;
+        Ok::<_, sqlx::Error>(Some((calendar, days)))
+    }
+    .await;
+
+    match result {
+        Ok(Some((calendar, days))) => HttpResponse::Ok().json(json!({
+            "provider_id": calendar.provider_id,
+            "name": calendar.name,
+            "timezone": calendar.timezone.name(),
+            "slot_minutes": calendar.slot_minutes,
+            "days": days
+        })),
+        Ok(None) => error(
+            StatusCode::NOT_FOUND,
+            "This provider doesn't take appointments through the dashboard.",
+        ),
+        Err(err) => {
+            eprintln!("Failed to list slots for {}: {}", id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch available times.")
+        }
+    }
+}
+
+// Handler for the `/providers/{id}/book` page (also used to pick a new time when rescheduling)
+#[get("/providers/{id}/book")]
+async fn booking_page(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<BookingPage>,
+    pool: web::Data<SqlitePool>,
+    hb: web::Data<Handlebars<'_>>,
+) -> impl Responder {
+    if current_user_id(&req).is_none() {
+        return HttpResponse::Found().append_header(("Location", "/login")).finish();
+    }
+    let id = path.into_inner();
+
+    let result = async {
+        let Some(calendar) = load_calendar(pool.get_ref(), &id).await? else {
+            return Ok(None);
+        };
+        let days = list_slots(pool.get_ref(), &calendar, None, MAX_DAYS).await?;
+        Ok::<_, sqlx::Error>(Some((calendar, days)))
+    }
+    .await;
+
+    match result {
+        Ok(Some((calendar, days))) => {
+            let data = json!({
+                "provider_id": calendar.provider_id,
+                "name": calendar.name,
+                "address": calendar.address,
+                "timezone": calendar.timezone.name(),
+                "days": days,
+                "reschedule": query.reschedule
+            });
+            let body = hb.render("book", &data).unwrap_or_else(|_| "Template error".to_string());
+            HttpResponse::Ok().body(body)
+        }
+        Ok(None) => HttpResponse::NotFound().body("This provider doesn't take appointments through the dashboard"),
+        Err(err) => {
+            eprintln!("Failed to load booking page for {}: {}", id, err);
+            HttpResponse::InternalServerError().body("Failed to load available times")
+        }
+    }
+}
+
+// Handler for `POST /api/appointments`
+#[post("/api/appointments")]
+async fn book_appointment(
+    req: HttpRequest,
+    body: web::Json<NewAppointment>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+
+    let calendar = match load_calendar(pool.get_ref(), &body.provider_id).await {
+        Ok(Some(calendar)) => calendar,
+        Ok(None) => {
+            return error(
+                StatusCode::NOT_FOUND,
+                "This provider doesn't take appointments through the dashboard.",
+            )
+        }
+        Err(err) => {
+            eprintln!("Failed to load calendar for {}: {}", body.provider_id, err);
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to book appointment.");
+        }
+    };
+    let (starts_at, ends_at) = match bookable_slot(&calendar, &body.starts_at) {
+        Ok(slot) => slot,
+        Err(message) => return error(StatusCode::BAD_REQUEST, message),
+    };
+
+    let result = async {
+        let mut tx = pool.begin().await?;
+        let id = sqlx::query_scalar!(
+            "INSERT INTO appointments (user_id, provider_id, starts_at, ends_at) VALUES (?, ?, ?, ?)
+             RETURNING id AS \"id!\"",
+            user_id,
+            calendar.provider_id,
+            starts_at,
+            ends_at
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if has_conflict(&mut tx, id, &calendar.provider_id, &starts_at, &ends_at).await? {
+            return Ok(None); // Dropping the transaction rolls the booking back
+        }
+        reminders::schedule(&mut tx, id, &starts_at, &calendar.timezone).await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(Some(id))
+    }
+    .await;
+
+    match result {
+        Ok(Some(id)) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": format!("Booked with {} on {}.", calendar.name, starts_at.replace('T', " at ")),
+            "id": id
+        })),
+        Ok(None) => slot_taken(),
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => slot_taken(),
+        Err(err) => {
+            eprintln!("Failed to book appointment: {}", err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to book appointment.")
+        }
+    }
+}
+
+// Handler for `PATCH /api/appointments/{id}` (reschedule)
+#[patch("/api/appointments/{id}")]
+async fn reschedule_appointment(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: web::Json<NewTime>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let appointment_id = path.into_inner();
+
+    let provider_id = sqlx::query_scalar!(
+        "SELECT provider_id FROM appointments WHERE id = ? AND user_id = ? AND status = 'booked'",
+        appointment_id,
+        user_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await;
+    let calendar = match provider_id {
+        Ok(Some(provider_id)) => load_calendar(pool.get_ref(), &provider_id).await,
+        Ok(None) => Ok(None),
+        Err(err) => Err(err),
+    };
+    let calendar = match calendar {
+        Ok(Some(calendar)) => calendar,
+        Ok(None) => return error(StatusCode::NOT_FOUND, "Appointment not found."),
+        Err(err) => {
+            eprintln!("Failed to load appointment {}: {}", appointment_id, err);
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to reschedule appointment.");
+        }
+    };
+    let (starts_at, ends_at) = match bookable_slot(&calendar, &body.starts_at) {
+        Ok(slot) => slot,
+        Err(message) => return error(StatusCode::BAD_REQUEST, message),
+    };
+
+    let result = async {
+        let mut tx = pool.begin().await?;
+        let moved = sqlx::query!(
+            "UPDATE appointments SET starts_at = ?, ends_at = ?, sequence = sequence + 1, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ? AND user_id = ? AND status = 'booked'",
+            starts_at,
+            ends_at,
+            appointment_id,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        // Cancelled (or no longer the user's) since it was loaded above
+        if moved.rows_affected() == 0 {
+            return Ok(None);
+        }
+        if has_conflict(&mut tx, appointment_id, &calendar.provider_id, &starts_at, &ends_at).await? {
+            return Ok(Some(false));
+        }
+        // Reminders queued before this move see the new sequence and skip themselves
+        reminders::schedule(&mut tx, appointment_id, &starts_at, &calendar.timezone).await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(Some(true))
+    }
+    .await;
+
+    match result {
+        Ok(Some(true)) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": format!("Moved to {}.", starts_at.replace('T', " at "))
+        })),
+        Ok(Some(false)) => slot_taken(),
+        Ok(None) => error(StatusCode::NOT_FOUND, "Appointment not found."),
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => slot_taken(),
+        Err(err) => {
+            eprintln!("Failed to reschedule appointment {}: {}", appointment_id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to reschedule appointment.")
+        }
+    }
+}
+
+// Handler for `DELETE /api/appointments/{id}` (cancel)
+#[delete("/api/appointments/{id}")]
+async fn cancel_appointment(req: HttpRequest, path: web::Path<i64>, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let appointment_id = path.into_inner();
+
+    let result = sqlx::query!(
+        "UPDATE appointments SET status = 'cancelled', cancelled_at = CURRENT_TIMESTAMP,
+                                 sequence = sequence + 1, updated_at = CURRENT_TIMESTAMP
+         WHERE id = ? AND user_id = ? AND status = 'booked'",
+        appointment_id,
+        user_id
+    )
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(res) if res.rows_affected() == 1 => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Appointment cancelled."
+        })),
+        Ok(_) => error(StatusCode::NOT_FOUND, "Appointment not found."),
+        Err(err) => {
+            eprintln!("Failed to cancel appointment {}: {}", appointment_id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to cancel appointment.")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::Connection;
+
+    fn time(value: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(value, TIME_FORMAT).unwrap()
+    }
+
+    fn at(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, SLOT_FORMAT).unwrap()
+    }
+
+    // Open Mondays 9:00-10:40 and 13:00-14:00, with 30 minute slots
+    fn calendar() -> Calendar {
+        Calendar {
+            provider_id: "p1".to_string(),
+            name: "Clinic".to_string(),
+            address: "1 Main St".to_string(),
+            timezone: Tz::UTC,
+            slot_minutes: 30,
+            hours: vec![(1, time("09:00"), time("10:40")), (1, time("13:00"), time("14:00"))],
+        }
+    }
+
+    #[test]
+    fn slots_are_cut_from_each_opening_period() {
+        let monday = NaiveDate::from_ymd_opt(2099, 10, 19).unwrap();
+        let starts: Vec<String> = calendar()
+            .slots_on(monday)
+            .into_iter()
+            .map(|(start, end)| {
+                assert_eq!(end - start, Duration::minutes(30));
+                start.format(TIME_FORMAT).to_string()
+            })
+            .collect();
+        // The 10:30 slot would run past closing, so it's left out
+        assert_eq!(starts, ["09:00", "09:30", "10:00", "13:00", "13:30"]);
+    }
+
+    #[test]
+    fn no_slots_on_closed_days() {
+        let tuesday = NaiveDate::from_ymd_opt(2099, 10, 20).unwrap();
+        assert!(calendar().slots_on(tuesday).is_empty());
+    }
+
+    #[test]
+    fn slot_at_only_matches_slot_starts() {
+        let calendar = calendar();
+        assert_eq!(
+            calendar.slot_at(at("2099-10-19T09:30")),
+            Some((at("2099-10-19T09:30"), at("2099-10-19T10:00")))
+        );
+        assert_eq!(calendar.slot_at(at("2099-10-19T09:45")), None);
+        assert_eq!(calendar.slot_at(at("2099-10-19T10:30")), None);
+        assert_eq!(calendar.slot_at(at("2099-10-20T09:30")), None);
+    }
+
+    #[test]
+    fn bookable_slot_checks_format_schedule_and_time() {
+        let calendar = calendar();
+        assert_eq!(
+            bookable_slot(&calendar, "2099-10-19T13:30"),
+            Ok(("2099-10-19T13:30".to_string(), "2099-10-19T14:00".to_string()))
+        );
+        assert!(bookable_slot(&calendar, "2099-10-19 13:30").is_err());
+        assert!(bookable_slot(&calendar, "2099-10-19T12:00").is_err());
+        assert_eq!(
+            bookable_slot(&calendar, "2000-10-16T09:00"),
+            Err("That time has already passed.")
+        );
+    }
+
+    async fn appointments_db() -> sqlx::SqliteConnection {
+        let mut conn = sqlx::SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        sqlx::query(
+            "CREATE TABLE appointments (
+                 id INTEGER PRIMARY KEY, provider_id TEXT NOT NULL, status TEXT NOT NULL,
+                 starts_at TEXT NOT NULL, ends_at TEXT NOT NULL
+             )",
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO appointments VALUES
+                 (1, 'p1', 'booked', '2099-10-19T09:00', '2099-10-19T09:30'),
+                 (2, 'p1', 'cancelled', '2099-10-19T10:00', '2099-10-19T10:30'),
+                 (3, 'p2', 'booked', '2099-10-19T13:00', '2099-10-19T13:30')",
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap();
+        conn
+    }
+
+    #[actix_web::test]
+    async fn overlapping_booked_appointments_conflict() {
+        let mut conn = appointments_db().await;
+        // Same time, and a longer appointment that covers it
+        assert!(has_conflict(&mut conn, 9, "p1", "2099-10-19T09:00", "2099-10-19T09:30").await.unwrap());
+        assert!(has_conflict(&mut conn, 9, "p1", "2099-10-19T08:45", "2099-10-19T09:15").await.unwrap());
+        assert!(has_conflict(&mut conn, 9, "p1", "2099-10-19T08:00", "2099-10-19T10:00").await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn adjacent_cancelled_and_own_appointments_dont_conflict() {
+        let mut conn = appointments_db().await;
+        // Back to back on either side
+        assert!(!has_conflict(&mut conn, 9, "p1", "2099-10-19T08:30", "2099-10-19T09:00").await.unwrap());
+        assert!(!has_conflict(&mut conn, 9, "p1", "2099-10-19T09:30", "2099-10-19T10:00").await.unwrap());
+        // Cancelled appointments free their time
+        assert!(!has_conflict(&mut conn, 9, "p1", "2099-10-19T10:00", "2099-10-19T10:30").await.unwrap());
+        // An appointment doesn't conflict with itself, e.g. when rescheduling
+        assert!(!has_conflict(&mut conn, 1, "p1", "2099-10-19T09:00", "2099-10-19T09:30").await.unwrap());
+        // Other providers' appointments don't count
+        assert!(!has_conflict(&mut conn, 9, "p1", "2099-10-19T13:00", "2099-10-19T13:30").await.unwrap());
+    }
+}
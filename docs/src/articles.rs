@@ -0,0 +1,555 @@
+// Health education articles. Admins write articles in Markdown, file them under a
+// search service type (or none, for general health topics) and publish them when
+// they're ready. Articles are rendered to HTML on the server and sanitized before
+// they're shown, searched with SQLite's full-text index, and linked from search
+// results as "learn more" reading for the service type searched for.
+use crate::find_providers::SERVICE_TYPES;
+use crate::session::{current_user_id, require_admin};
+
+use actix_web::http::StatusCode;
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use handlebars::{html_escape, Handlebars};
+use pulldown_cmark::{Options, Parser};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sqlx::SqlitePool;
+
+const MAX_TITLE_CHARS: usize = 200;
+const MAX_SUMMARY_CHARS: usize = 500;
+const MAX_BODY_CHARS: usize = 100_000;
+const MAX_SLUG_CHARS: usize = 100;
+
+// Search results shown at once, best matches first
+const SEARCH_LIMIT: i64 = 50;
+// Articles linked under a set of search results
+const LINK_LIMIT: i64 = 3;
+
+// Category for articles without a service type
+const GENERAL: &str = "general";
+
+// Marks around matched words in search snippets, swapped for <mark> after escaping
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+
+#[derive(Deserialize)]
+struct ArticleRequest {
+    #[serde(default)]
+    slug: String, // Made from the title when left empty
+    title: String,
+    #[serde(default)]
+    summary: String,
+    body: String,
+    service_type: Option<String>,
+    #[serde(default)]
+    published: bool,
+}
+
+#[derive(Deserialize)]
+struct LearnQuery {
+    q: Option<String>,
+    category: Option<String>, // A service type, or "general"
+}
+
+#[derive(Deserialize)]
+struct LinkQuery {
+    service_type: String,
+}
+
+#[derive(Deserialize)]
+struct PreviewRequest {
+    body: String,
+}
+
+fn error(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+// Markdown to HTML that's safe to show as-is: scripts, event handlers, styles and
+// unknown URL schemes are stripped, and links get rel="noopener noreferrer"
+pub fn render_markdown(markdown: &str) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, Parser::new_ext(markdown, options));
+    ammonia::clean(&html)
+}
+
+fn category_label(service_type: Option<&str>) -> &'static str {
+    SERVICE_TYPES
+        .iter()
+        .find(|(value, _)| Some(*value) == service_type)
+        .map(|(_, label)| *label)
+        .unwrap_or("General health")
+}
+
+fn categories(selected: Option<&str>) -> Vec<Value> {
+    SERVICE_TYPES
+        .iter()
+        .map(|(value, label)| (*value, *label))
+        .chain(std::iter::once((GENERAL, "General health")))
+        .map(|(value, label)| json!({ "value": value, "label": label, "selected": Some(value) == selected }))
+        .collect()
+}
+
+// "When to See a Dentist?" becomes "when-to-see-a-dentist"
+fn slugify(title: &str) -> String {
+    let slug: String = title
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { ' ' })
+        .collect();
+    slug.split_whitespace().collect::<Vec<_>>().join("-")
+}
+
+fn valid_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug.len() <= MAX_SLUG_CHARS
+        && slug.split('-').all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()))
+}
+
+// Each word of the user's query as a quoted prefix term, so FTS5 syntax in the
+// query can't cause errors; words must all match
+fn match_expression(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|word| word.chars().filter(|c| c.is_alphanumeric()).collect::<String>())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"*", word))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+fn highlight(snippet: &str) -> String {
+    html_escape(snippet)
+        .replace(MATCH_START, "<mark>")
+        .replace(MATCH_END, "</mark>")
+}
+
+// A checked article, ready to save
+struct Article {
+    slug: String,
+    title: String,
+    summary: String,
+    body: String,
+    service_type: Option<String>,
+    published: bool,
+}
+
+fn check_article(request: ArticleRequest) -> Result<Article, &'static str> {
+    let title = request.title.trim().to_string();
+    if title.is_empty() || title.chars().count() > MAX_TITLE_CHARS {
+        return Err("Titles need 1 to 200 characters.");
+    }
+    let summary = request.summary.trim().to_string();
+    if summary.chars().count() > MAX_SUMMARY_CHARS {
+        return Err("Summaries can be up to 500 characters.");
+    }
+    if request.body.trim().is_empty() || request.body.chars().count() > MAX_BODY_CHARS {
+        return Err("Articles need a body of up to 100,000 characters.");
+    }
+    let slug = match request.slug.trim() {
+        "" => slugify(&title),
+        slug => slug.to_string(),
+    };
+    if !valid_slug(&slug) {
+        return Err("Slugs can only use lowercase letters, digits and single hyphens.");
+    }
+    let service_type = request.service_type.filter(|service_type| !service_type.is_empty());
+    if service_type
+        .as_deref()
+        .is_some_and(|service_type| !SERVICE_TYPES.iter().any(|(value, _)| *value == service_type))
+    {
+        return Err("Pick one of the listed categories.");
+    }
+    Ok(Article {
+        slug,
+        title,
+        summary,
+        body: request.body,
+        service_type,
+        published: request.published,
+    })
+}
+
+// Handler for the `/learn` page: articles by category, or full-text search results
+#[get("/learn")]
+async fn learn_page(
+    req: HttpRequest,
+    query: web::Query<LearnQuery>,
+    pool: web::Data<SqlitePool>,
+    hb: web::Data<Handlebars<'_>>,
+) -> impl Responder {
+    let search = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty());
+    let category = query.category.as_deref().filter(|category| !category.is_empty());
+
+    let mut data = Map::new();
+    data.insert("q".to_string(), json!(search));
+    data.insert("categories".to_string(), json!(categories(category)));
+    data.insert("logged_in".to_string(), json!(current_user_id(&req).is_some()));
+    if let Some(category) = category {
+        data.insert(
+            "category_label".to_string(),
+            json!(category_label((category != GENERAL).then_some(category))),
+        );
+    }
+
+    let articles = match search.map(match_expression) {
+        // Nothing searchable in the query, e.g. only punctuation
+        Some(None) => Ok(Vec::new()),
+        Some(Some(expression)) => sqlx::query!(
+            "SELECT articles.slug, articles.title, articles.summary, articles.service_type,
+                    snippet(articles_fts, 2, char(2), char(3), '…', 24) AS \"snippet!: String\"
+             FROM articles_fts JOIN articles ON articles.id = articles_fts.rowid
+             WHERE articles_fts MATCH ? AND articles.published
+               AND (? IS NULL OR COALESCE(articles.service_type, 'general') = ?)
+             ORDER BY articles_fts.rank LIMIT ?",
+            expression,
+            category,
+            category,
+            SEARCH_LIMIT
+        )
+        .fetch_all(pool.get_ref())
+        .await
+        .map(|rows| {
+            rows.into_iter()
+                .map(|row| {
+                    json!({
+                        "slug": row.slug,
+                        "title": row.title,
+                        "summary": row.summary,
+                        "category": category_label(row.service_type.as_deref()),
+                        "snippet": highlight(&row.snippet)
+                    })
+                })
+                .collect()
+        }),
+        None => sqlx::query!(
+            "SELECT slug, title, summary, service_type FROM articles
+             WHERE published AND (? IS NULL OR COALESCE(service_type, 'general') = ?)
+             ORDER BY title",
+            category,
+            category
+        )
+        .fetch_all(pool.get_ref())
+        .await
+        .map(|rows| {
+            rows.into_iter()
+                .map(|row| {
+                    json!({
+                        "slug": row.slug,
+                        "title": row.title,
+                        "summary": row.summary,
+                        "category": category_label(row.service_type.as_deref())
+                    })
+                })
+                .collect::<Vec<Value>>()
+        }),
+    };
+
+    match articles {
+        Ok(articles) => {
+            data.insert("articles".to_string(), json!(articles));
+        }
+        Err(err) => {
+            eprintln!("Failed to list articles: {}", err);
+            data.insert("error".to_string(), json!("Could not fetch articles"));
+        }
+    }
+
+    let body = hb.render("learn", &data).unwrap_or_else(|_| "Template error".to_string());
+    HttpResponse::Ok().body(body)
+}
+
+// Handler for an article page; drafts are only shown to admins
+#[get("/learn/{slug}")]
+async fn article_page(
+    req: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+    hb: web::Data<Handlebars<'_>>,
+) -> impl Responder {
+    let slug = path.into_inner();
+    let article = sqlx::query!(
+        "SELECT id, title, summary, body, service_type, published, updated_at AS \"updated_at: String\"
+         FROM articles WHERE slug = ?",
+        slug
+    )
+    .fetch_optional(pool.get_ref())
+    .await;
+
+    let article = match article {
+        Ok(Some(article)) => article,
+        Ok(None) => return HttpResponse::NotFound().body("Article not found"),
+        Err(err) => {
+            eprintln!("Failed to fetch article {}: {}", slug, err);
+            return HttpResponse::InternalServerError().body("Failed to load article");
+        }
+    };
+    let is_admin = require_admin(&req, pool.get_ref()).await.is_ok();
+    if !article.published && !is_admin {
+        return HttpResponse::NotFound().body("Article not found");
+    }
+
+    let data = json!({
+        "id": article.id,
+        "title": article.title,
+        "summary": article.summary,
+        "body_html": render_markdown(&article.body),
+        "category": category_label(article.service_type.as_deref()),
+        "category_value": article.service_type.as_deref().unwrap_or(GENERAL),
+        "service_type": article.service_type,
+        "draft": !article.published,
+        "updated_at": article.updated_at,
+        "is_admin": is_admin
+    });
+    let body = hb.render("article", &data).unwrap_or_else(|_| "Template error".to_string());
+    HttpResponse::Ok().body(body)
+}
+
+// Handler for `GET /api/articles?service_type=dentist`, the "learn more" links under search results
+#[get("/api/articles")]
+async fn article_links(query: web::Query<LinkQuery>, pool: web::Data<SqlitePool>) -> impl Responder {
+    let rows = sqlx::query!(
+        "SELECT slug, title, summary FROM articles WHERE published AND service_type = ?
+         ORDER BY updated_at DESC LIMIT ?",
+        query.service_type,
+        LINK_LIMIT
+    )
+    .fetch_all(pool.get_ref())
+    .await;
+
+    match rows {
+        Ok(rows) => {
+            let articles: Vec<Value> = rows
+                .into_iter()
+                .map(|row| json!({ "url": format!("/learn/{}", row.slug), "title": row.title, "summary": row.summary }))
+                .collect();
+            HttpResponse::Ok().json(articles)
+        }
+        Err(err) => {
+            eprintln!("Failed to list articles for {}: {}", query.service_type, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to list articles.")
+        }
+    }
+}
+
+// Handler for the admin article list, drafts included
+#[get("/admin/articles")]
+async fn articles_admin_page(req: HttpRequest, pool: web::Data<SqlitePool>, hb: web::Data<Handlebars<'_>>) -> impl Responder {
+    if let Err(response) = require_admin(&req, pool.get_ref()).await {
+        return response;
+    }
+
+    let rows = sqlx::query!(
+        "SELECT articles.id, articles.slug, articles.title, articles.service_type, articles.published,
+                articles.updated_at AS \"updated_at: String\", users.username AS \"updated_by?\"
+         FROM articles LEFT JOIN users ON users.id = articles.updated_by
+         ORDER BY articles.updated_at DESC"
+    )
+    .fetch_all(pool.get_ref())
+    .await;
+
+    let mut data = Map::new();
+    match rows {
+        Ok(rows) => {
+            let articles: Vec<Value> = rows
+                .into_iter()
+                .map(|row| {
+                    json!({
+                        "id": row.id,
+                        "slug": row.slug,
+                        "title": row.title,
+                        "category": category_label(row.service_type.as_deref()),
+                        "published": row.published,
+                        "updated_at": row.updated_at,
+                        "updated_by": row.updated_by
+                    })
+                })
+                .collect();
+            data.insert("articles".to_string(), json!(articles));
+        }
+        Err(err) => {
+            eprintln!("Failed to list articles: {}", err);
+            data.insert("error".to_string(), json!("Could not fetch articles"));
+        }
+    }
+
+    let body = hb.render("articles_admin", &data).unwrap_or_else(|_| "Template error".to_string());
+    HttpResponse::Ok().body(body)
+}
+
+// Handler for the article editor; `/admin/articles/new` starts a new article
+#[get("/admin/articles/{id}")]
+async fn editor_page(
+    req: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+    hb: web::Data<Handlebars<'_>>,
+) -> impl Responder {
+    if let Err(response) = require_admin(&req, pool.get_ref()).await {
+        return response;
+    }
+    let id = path.into_inner();
+
+    let data = if id == "new" {
+        json!({ "categories": categories(None) })
+    } else {
+        let Ok(article_id) = id.parse::<i64>() else {
+            return HttpResponse::NotFound().body("Article not found");
+        };
+        let article = sqlx::query!(
+            "SELECT slug, title, summary, body, service_type, published FROM articles WHERE id = ?",
+            article_id
+        )
+        .fetch_optional(pool.get_ref())
+        .await;
+        match article {
+            Ok(Some(article)) => json!({
+                "id": article_id,
+                "slug": article.slug,
+                "title": article.title,
+                "summary": article.summary,
+                "body": article.body,
+                "published": article.published,
+                "categories": categories(Some(article.service_type.as_deref().unwrap_or(GENERAL)))
+            }),
+            Ok(None) => return HttpResponse::NotFound().body("Article not found"),
+            Err(err) => {
+                eprintln!("Failed to fetch article {}: {}", article_id, err);
+                return HttpResponse::InternalServerError().body("Failed to load article");
+            }
+        }
+    };
+
+    let body = hb.render("article_editor", &data).unwrap_or_else(|_| "Template error".to_string());
+    HttpResponse::Ok().body(body)
+}
+
+// Handler for `POST /admin/articles/preview`: the editor's rendered preview
+#[post("/admin/articles/preview")]
+async fn preview_article(req: HttpRequest, body: web::Json<PreviewRequest>, pool: web::Data<SqlitePool>) -> impl Responder {
+    if let Err(response) = require_admin(&req, pool.get_ref()).await {
+        return response;
+    }
+    HttpResponse::Ok().json(json!({ "html": render_markdown(&body.body) }))
+}
+
+// Handler for `POST /admin/articles`
+#[post("/admin/articles")]
+async fn create_article(req: HttpRequest, body: web::Json<ArticleRequest>, pool: web::Data<SqlitePool>) -> impl Responder {
+    let admin_id = match require_admin(&req, pool.get_ref()).await {
+        Ok(admin_id) => admin_id,
+        Err(response) => return response,
+    };
+    let article = match check_article(body.into_inner()) {
+        Ok(article) => article,
+        Err(message) => return error(StatusCode::BAD_REQUEST, message),
+    };
+
+    let result = sqlx::query_scalar!(
+        "INSERT INTO articles (slug, title, summary, body, service_type, published, updated_by)
+         VALUES (?, ?, ?, ?, ?, ?, ?) RETURNING id AS \"id!\"",
+        article.slug,
+        article.title,
+        article.summary,
+        article.body,
+        article.service_type,
+        article.published,
+        admin_id
+    )
+    .fetch_one(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(id) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Article saved.",
+            "id": id,
+            "url": format!("/learn/{}", article.slug)
+        })),
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            error(StatusCode::CONFLICT, "Another article already uses that slug.")
+        }
+        Err(err) => {
+            eprintln!("Failed to save article: {}", err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to save article.")
+        }
+    }
+}
+
+// Handler for `PUT /admin/articles/{id}`
+#[put("/admin/articles/{id}")]
+async fn update_article(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: web::Json<ArticleRequest>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let admin_id = match require_admin(&req, pool.get_ref()).await {
+        Ok(admin_id) => admin_id,
+        Err(response) => return response,
+    };
+    let article_id = path.into_inner();
+    let article = match check_article(body.into_inner()) {
+        Ok(article) => article,
+        Err(message) => return error(StatusCode::BAD_REQUEST, message),
+    };
+
+    let result = sqlx::query!(
+        "UPDATE articles SET slug = ?, title = ?, summary = ?, body = ?, service_type = ?, published = ?,
+                updated_by = ?, updated_at = CURRENT_TIMESTAMP
+         WHERE id = ?",
+        article.slug,
+        article.title,
+        article.summary,
+        article.body,
+        article.service_type,
+        article.published,
+        admin_id,
+        article_id
+    )
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(done) if done.rows_affected() == 0 => error(StatusCode::NOT_FOUND, "Article not found."),
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Article saved.",
+            "id": article_id,
+            "url": format!("/learn/{}", article.slug)
+        })),
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            error(StatusCode::CONFLICT, "Another article already uses that slug.")
+        }
+        Err(err) => {
+            eprintln!("Failed to update article {}: {}", article_id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to save article.")
+        }
+    }
+}
+
+// Handler for `DELETE /admin/articles/{id}`
+#[delete("/admin/articles/{id}")]
+async fn delete_article(req: HttpRequest, path: web::Path<i64>, pool: web::Data<SqlitePool>) -> impl Responder {
+    if let Err(response) = require_admin(&req, pool.get_ref()).await {
+        return response;
+    }
+    let article_id = path.into_inner();
+
+    match sqlx::query!("DELETE FROM articles WHERE id = ?", article_id).execute(pool.get_ref()).await {
+        Ok(done) if done.rows_affected() == 0 => error(StatusCode::NOT_FOUND, "Article not found."),
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Article deleted."
+        })),
+        Err(err) => {
+            eprintln!("Failed to delete article {}: {}", article_id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete article.")
+        }
+    }
+}
@@ -0,0 +1,343 @@
+// Side-by-side comparison of 2-5 providers from search results or favorites.
+// Providers are looked up by their stable IDs; Google places are filled in with
+// Place Details for hours, review counts and accessibility. Distances are only
+// shown when the caller passes the location to measure from, since the stored
+// distance is relative to whoever searched last.
+use crate::find_providers::{calculate_distance, Coordinates, HealthProvider};
+use crate::insurance;
+use crate::place_details::{cached_comparison_details, PlaceDetails, PlaceDetailsCache};
+use crate::provider_listings;
+use crate::provider_store;
+use crate::session::current_user_id;
+
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use handlebars::Handlebars;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+
+const MIN_PROVIDERS: usize = 2;
+const MAX_PROVIDERS: usize = 5;
+
+const NOT_LISTED: &str = "\u{2014}";
+
+#[derive(Deserialize)]
+struct CompareQuery {
+    ids: String,      // Comma-separated provider IDs
+    lat: Option<f64>, // Where to measure distances from
+    lng: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct Column {
+    id: String,
+    name: String,
+    address: String,
+}
+
+#[derive(Serialize)]
+struct Cell {
+    lines: Vec<String>,
+    best: bool, // Nearest, or highest rated / most reviewed
+}
+
+#[derive(Serialize)]
+struct Row {
+    label: &'static str,
+    cells: Vec<Cell>,
+    differs: bool, // Not every provider has the same value
+}
+
+#[derive(Serialize)]
+struct Comparison {
+    columns: Vec<Column>,
+    rows: Vec<Row>,
+}
+
+enum CompareError {
+    BadRequest(String),
+    NotFound(String),
+    Internal,
+}
+
+// Which of the values is best, for highlighting; ties all count as best
+fn best_by(values: &[Option<f64>], higher_is_better: bool) -> Vec<bool> {
+    let known = values.iter().flatten().copied();
+    let best = if higher_is_better {
+        known.fold(None, |best: Option<f64>, v| Some(best.map_or(v, |b| b.max(v))))
+    } else {
+        known.fold(None, |best: Option<f64>, v| Some(best.map_or(v, |b| b.min(v))))
+    };
+    // Only worth highlighting when there's something to compare against
+    let known_count = values.iter().flatten().count();
+    values
+        .iter()
+        .map(|value| known_count > 1 && value.is_some() && *value == best)
+        .collect()
+}
+
+fn row(label: &'static str, values: Vec<Vec<String>>, best: Vec<bool>) -> Row {
+    let differs = values.windows(2).any(|pair| pair[0] != pair[1]);
+    let cells = values
+        .into_iter()
+        .zip(best)
+        .map(|(lines, best)| Cell {
+            lines: if lines.is_empty() { vec![NOT_LISTED.to_string()] } else { lines },
+            best,
+        })
+        .collect();
+    Row { label, cells, differs }
+}
+
+async fn build_comparison(
+    pool: &SqlitePool,
+    cache: &PlaceDetailsCache,
+    query: &CompareQuery,
+    user_id: Option<i64>,
+) -> Result<Comparison, CompareError> {
+    let mut ids: Vec<&str> = Vec::new();
+    for id in query.ids.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    if ids.len() < MIN_PROVIDERS || ids.len() > MAX_PROVIDERS {
+        return Err(CompareError::BadRequest(format!(
+            "Choose between {} and {} providers to compare",
+            MIN_PROVIDERS, MAX_PROVIDERS
+        )));
+    }
+
+    let origin = match (query.lat, query.lng) {
+        (Some(lat), Some(lng)) => Some(Coordinates { lat, lng }),
+        _ => None,
+    };
+    let api_key = std::env::var("GOOGLE_MAPS_API_KEY").expect("GOOGLE_MAPS_API_KEY must be set in environment");
+
+    // The logged-in user's plan, to show which providers are in its network
+    let plan = match user_id {
+        Some(user_id) => insurance::user_plan(pool, user_id).await.unwrap_or_else(|err| {
+            eprintln!("Failed to fetch insurance plan: {}", err);
+            None
+        }),
+        None => None,
+    };
+
+    let mut providers: Vec<(HealthProvider, Option<PlaceDetails>)> = Vec::new();
+    for id in ids {
+        let mut provider = match provider_store::find(pool, id).await {
+            Ok(Some(provider)) => provider,
+            Ok(None) => return Err(CompareError::NotFound(format!("Provider {} not found", id))),
+            Err(err) => {
+                eprintln!("Failed to fetch provider {}: {}", id, err);
+                return Err(CompareError::Internal);
+            }
+        };
+
+        let mut details = None;
+        if let Some(place_id) = &provider.place_id {
+            match cached_comparison_details(cache, place_id, &api_key).await {
+                Ok(place_details) => details = place_details,
+                // Compare with what's stored rather than failing the whole page
+                Err(err) => eprintln!("Failed to fetch place details: {}", err),
+            }
+        }
+
+        // The provider's own corrections win over both upstream sources
+        match provider_listings::find_override(pool, &provider.id).await {
+            Ok(Some(listing)) => {
+                listing.apply(&mut provider);
+                if let Some(details) = details.as_mut() {
+                    listing.apply_to_details(details);
+                }
+            }
+            Ok(None) => {}
+            Err(err) => eprintln!("Failed to fetch listing corrections for {}: {}", id, err),
+        }
+        if let Some(plan) = &plan {
+            if let Err(err) = insurance::mark_in_network(pool, plan, std::slice::from_mut(&mut provider)).await {
+                eprintln!("Failed to check insurance network: {}", err);
+            }
+        }
+        providers.push((provider, details));
+    }
+
+    let distances: Vec<Option<f64>> = providers
+        .iter()
+        .map(|(provider, _)| match (&origin, &provider.location) {
+            (Some(origin), Some(location)) => Some(calculate_distance(origin, location.lat, location.lng)),
+            _ => None,
+        })
+        .collect();
+    let ratings: Vec<Option<f64>> = providers
+        .iter()
+        .map(|(provider, details)| {
+            details
+                .as_ref()
+                .and_then(|details| details.rating)
+                .or(provider.rating.map(|rating| (f64::from(rating) * 10.0).round() / 10.0))
+        })
+        .collect();
+    let review_counts: Vec<Option<f64>> = providers
+        .iter()
+        .map(|(provider, details)| {
+            details
+                .as_ref()
+                .and_then(|details| details.review_count)
+                .or(provider.review_count)
+                .map(f64::from)
+        })
+        .collect();
+    let no_highlight = vec![false; providers.len()];
+
+    let mut rows = Vec::new();
+    if origin.is_some() {
+        rows.push(row(
+            "Distance",
+            distances.iter().map(|d| d.map(|km| format!("{:.1} km", km)).into_iter().collect()).collect(),
+            best_by(&distances, false),
+        ));
+    }
+    rows.push(row(
+        "Rating",
+        ratings.iter().map(|r| r.map(|rating| format!("{:.1}", rating)).into_iter().collect()).collect(),
+        best_by(&ratings, true),
+    ));
+    rows.push(row(
+        "Reviews",
+        review_counts.iter().map(|n| n.map(|count| count.to_string()).into_iter().collect()).collect(),
+        best_by(&review_counts, true),
+    ));
+    rows.push(row(
+        "Phone",
+        providers
+            .iter()
+            .map(|(provider, details)| {
+                details
+                    .as_ref()
+                    .and_then(|details| details.phone.clone())
+                    .or(provider.phone.clone())
+                    .into_iter()
+                    .collect()
+            })
+            .collect(),
+        no_highlight.clone(),
+    ));
+    rows.push(row(
+        "Hours",
+        providers
+            .iter()
+            .map(|(provider, details)| {
+                details
+                    .as_ref()
+                    .map(|details| details.opening_hours.clone())
+                    .filter(|hours| !hours.is_empty())
+                    .unwrap_or_else(|| provider.hours.clone())
+            })
+            .collect(),
+        no_highlight.clone(),
+    ));
+    if let Some(plan) = &plan {
+        let in_network: Vec<bool> = providers.iter().map(|(provider, _)| provider.in_network).collect();
+        rows.push(row(
+            "In your plan's network",
+            in_network
+                .iter()
+                .map(|in_network| vec![if *in_network { format!("Yes ({})", plan.name) } else { "No".to_string() }])
+                .collect(),
+            in_network,
+        ));
+    }
+    rows.push(row(
+        "Accepted insurance",
+        providers
+            .iter()
+            .map(|(provider, _)| {
+                // Networks imported for the user's plan count even when the provider's record doesn't list it
+                let mut plans = provider.accepted_insurance.clone();
+                if let Some(plan) = plan.as_ref().filter(|_| provider.in_network) {
+                    if !plans.iter().any(|accepted| accepted.eq_ignore_ascii_case(&plan.name)) {
+                        plans.push(plan.name.clone());
+                    }
+                }
+                plans
+            })
+            .collect(),
+        no_highlight.clone(),
+    ));
+    rows.push(row(
+        "Specialties (NPI)",
+        providers.iter().map(|(provider, _)| provider.specialties()).collect(),
+        no_highlight.clone(),
+    ));
+    rows.push(row(
+        "Wheelchair accessible entrance",
+        providers
+            .iter()
+            .map(|(_, details)| {
+                match details.as_ref().and_then(|details| details.wheelchair_accessible) {
+                    Some(true) => vec!["Yes".to_string()],
+                    Some(false) => vec!["No".to_string()],
+                    None => Vec::new(),
+                }
+            })
+            .collect(),
+        no_highlight,
+    ));
+
+    let columns = providers
+        .into_iter()
+        .map(|(provider, _)| Column {
+            id: provider.id,
+            name: provider.name,
+            address: provider.address,
+        })
+        .collect();
+
+    Ok(Comparison { columns, rows })
+}
+
+// Handler for `GET /api/compare?ids=...`
+#[get("/api/compare")]
+async fn compare_json(
+    req: HttpRequest,
+    query: web::Query<CompareQuery>,
+    pool: web::Data<SqlitePool>,
+    cache: web::Data<PlaceDetailsCache>,
+) -> impl Responder {
+    match build_comparison(pool.get_ref(), cache.get_ref(), &query, current_user_id(&req)).await {
+        Ok(comparison) => HttpResponse::Ok().json(comparison),
+        Err(CompareError::BadRequest(message)) => HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": message
+        })),
+        Err(CompareError::NotFound(message)) => HttpResponse::NotFound().json(json!({
+            "status": "error",
+            "message": message
+        })),
+        Err(CompareError::Internal) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": "Failed to compare providers."
+        })),
+    }
+}
+
+// Handler for the `/compare?ids=...` page
+#[get("/compare")]
+async fn compare_page(
+    req: HttpRequest,
+    query: web::Query<CompareQuery>,
+    pool: web::Data<SqlitePool>,
+    cache: web::Data<PlaceDetailsCache>,
+    hb: web::Data<Handlebars<'_>>,
+) -> impl Responder {
+    match build_comparison(pool.get_ref(), cache.get_ref(), &query, current_user_id(&req)).await {
+        Ok(comparison) => {
+            let body = hb.render("compare", &comparison).unwrap_or_else(|_| "Template error".to_string());
+            HttpResponse::Ok().body(body)
+        }
+        Err(CompareError::BadRequest(message)) => HttpResponse::BadRequest().body(message),
+        Err(CompareError::NotFound(message)) => HttpResponse::NotFound().body(message),
+        Err(CompareError::Internal) => HttpResponse::InternalServerError().body("Failed to compare providers"),
+    }
+}
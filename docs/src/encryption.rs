@@ -0,0 +1,93 @@
+// Encryption at rest for private data such as messages and their attachments.
+// Values are sealed with AES-256-GCM under the key in MESSAGE_ENCRYPTION_KEY
+// (64 hex characters), each with a fresh random nonce that is stored in front of
+// the ciphertext. Tampered or truncated values fail to open rather than decrypting
+// to garbage.
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use std::error::Error;
+
+const NONCE_LEN: usize = 12;
+
+pub struct Cipher {
+    cipher: Aes256Gcm,
+}
+
+impl Cipher {
+    pub fn new(key: &[u8; 32]) -> Self {
+        Cipher { cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)) }
+    }
+
+    // Build the cipher from MESSAGE_ENCRYPTION_KEY
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let key = std::env::var("MESSAGE_ENCRYPTION_KEY")?;
+        let key: [u8; 32] = hex::decode(key.trim())?
+            .try_into()
+            .map_err(|_| "MESSAGE_ENCRYPTION_KEY must be 32 bytes (64 hex characters)")?;
+        Ok(Cipher::new(&key))
+    }
+
+    // Nonce followed by ciphertext
+    pub fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher.encrypt(&nonce, plaintext).expect("AES-GCM encryption doesn't fail");
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        sealed
+    }
+
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        if sealed.len() < NONCE_LEN {
+            return Err("Encrypted value is too short".into());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "Encrypted value failed to decrypt".into())
+    }
+
+    pub fn open_text(&self, sealed: &[u8]) -> Result<String, Box<dyn Error>> {
+        Ok(String::from_utf8(self.open(sealed)?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_values_open() {
+        let cipher = Cipher::new(&[7; 32]);
+        let sealed = cipher.seal(b"See you at 9:30");
+        assert_eq!(cipher.open_text(&sealed).unwrap(), "See you at 9:30");
+        // A fresh nonce each time
+        assert_ne!(cipher.seal(b"See you at 9:30"), sealed);
+        assert_eq!(cipher.open(&cipher.seal(b"")).unwrap(), b"");
+    }
+
+    #[test]
+    fn tampered_values_fail() {
+        let cipher = Cipher::new(&[7; 32]);
+        let sealed = cipher.seal(b"See you at 9:30");
+        for index in [0, NONCE_LEN, sealed.len() - 1] {
+            let mut tampered = sealed.clone();
+            tampered[index] ^= 1;
+            assert!(cipher.open(&tampered).is_err());
+        }
+    }
+
+    #[test]
+    fn truncated_values_fail() {
+        let cipher = Cipher::new(&[7; 32]);
+        let sealed = cipher.seal(b"See you at 9:30");
+        assert!(cipher.open(&sealed[..NONCE_LEN - 1]).is_err());
+        assert!(cipher.open(&sealed[..NONCE_LEN]).is_err());
+        assert!(cipher.open(&sealed[..sealed.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn wrong_key_fails() {
+        let sealed = Cipher::new(&[7; 32]).seal(b"See you at 9:30");
+        assert!(Cipher::new(&[8; 32]).open(&sealed).is_err());
+    }
+}
@@ -0,0 +1,553 @@
+// Provider lists as downloadable files, for people who work from printouts or
+// other tools instead of the website: CSV for spreadsheets, vCard (one card per
+// provider) for address books, GeoJSON for GIS tools, and a print-ready PDF.
+// Favorites and search results are exported the same way; search results are
+// looked up by the stable IDs returned from `/services`.
+use crate::favorites::list_favorites;
+use crate::find_providers::{calculate_distance, Coordinates, HealthProvider};
+use crate::provider_listings;
+use crate::provider_store;
+use crate::session::current_user_id;
+
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfLayerReference};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::error::Error;
+
+// At most this many search results can be exported at once
+const MAX_EXPORT_IDS: usize = 100;
+
+// Longest vCard content line, in octets, before it's folded
+const MAX_VCARD_LINE: usize = 75;
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    format: String,      // "csv", "vcard", "geojson" or "pdf"
+    ids: Option<String>, // Comma-separated provider IDs, for search results
+    lat: Option<f64>,    // Where to measure search result distances from
+    lng: Option<f64>,
+}
+
+#[derive(Clone, Copy)]
+enum ExportFormat {
+    Csv,
+    VCard,
+    GeoJson,
+    Pdf,
+}
+
+impl ExportFormat {
+    fn parse(format: &str) -> Option<ExportFormat> {
+        match format {
+            "csv" => Some(ExportFormat::Csv),
+            "vcard" | "vcf" => Some(ExportFormat::VCard),
+            "geojson" => Some(ExportFormat::GeoJson),
+            "pdf" => Some(ExportFormat::Pdf),
+            _ => None,
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::VCard => "text/vcard; charset=utf-8",
+            ExportFormat::GeoJson => "application/geo+json",
+            ExportFormat::Pdf => "application/pdf",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::VCard => "vcf",
+            ExportFormat::GeoJson => "geojson",
+            ExportFormat::Pdf => "pdf",
+        }
+    }
+}
+
+// One provider in an export, from a search result or a favorite
+struct ExportRow {
+    name: String,
+    address: String,
+    phone: Option<String>,
+    rating: Option<f64>,
+    distance_km: Option<f64>, // Only known for search results exported with an origin
+    provider_type: String,
+    specialties: Vec<String>,
+    accepted_insurance: Vec<String>,
+    location: Option<Coordinates>,
+}
+
+impl From<&HealthProvider> for ExportRow {
+    fn from(provider: &HealthProvider) -> Self {
+        ExportRow {
+            name: provider.name.clone(),
+            address: provider.address.clone(),
+            phone: provider.phone.clone(),
+            rating: provider.rating.map(|rating| (f64::from(rating) * 10.0).round() / 10.0),
+            distance_km: None, // The stored distance is relative to whoever searched last
+            provider_type: provider.provider_type.clone(),
+            specialties: provider.specialties(),
+            accepted_insurance: provider.accepted_insurance.clone(),
+            location: provider.location.clone(),
+        }
+    }
+}
+
+fn format_rating(rating: Option<f64>) -> String {
+    rating.map(|rating| format!("{:.1}", rating)).unwrap_or_default()
+}
+
+fn to_csv(rows: &[ExportRow]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "name",
+        "address",
+        "phone",
+        "rating",
+        "distance_km",
+        "provider_type",
+        "specialties",
+        "accepted_insurance",
+        "latitude",
+        "longitude",
+    ])?;
+
+    for row in rows {
+        writer.write_record([
+            row.name.clone(),
+            row.address.clone(),
+            row.phone.clone().unwrap_or_default(),
+            format_rating(row.rating),
+            row.distance_km.map(|km| format!("{:.2}", km)).unwrap_or_default(),
+            row.provider_type.clone(),
+            row.specialties.join("; "),
+            row.accepted_insurance.join("; "),
+            row.location.as_ref().map(|c| c.lat.to_string()).unwrap_or_default(),
+            row.location.as_ref().map(|c| c.lng.to_string()).unwrap_or_default(),
+        ])?;
+    }
+
+    Ok(writer.into_inner()?)
+}
+
+// Escape a vCard text value (RFC 6350, section 3.4)
+fn vcard_text(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+        .replace('\n', "\\n")
+}
+
+// Split lines longer than 75 octets, continuing with a space (RFC 6350, section 3.2)
+fn vcard_fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > MAX_VCARD_LINE {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+fn to_vcard(rows: &[ExportRow]) -> Vec<u8> {
+    let mut cards = String::new();
+    for row in rows {
+        let mut lines = vec![
+            "BEGIN:VCARD".to_string(),
+            "VERSION:3.0".to_string(),
+            format!("FN:{}", vcard_text(&row.name)),
+            format!("ORG:{}", vcard_text(&row.name)),
+            // Addresses aren't split into parts anywhere, so the whole address is the street
+            format!("ADR;TYPE=WORK:;;{};;;;", vcard_text(&row.address)),
+        ];
+        if let Some(phone) = &row.phone {
+            lines.push(format!("TEL;TYPE=WORK,VOICE:{}", vcard_text(phone)));
+        }
+        if let Some(location) = &row.location {
+            lines.push(format!("GEO:{};{}", location.lat, location.lng));
+        }
+        if !row.specialties.is_empty() {
+            lines.push(format!("NOTE:{}", vcard_text(&row.specialties.join(", "))));
+        }
+        lines.push("END:VCARD".to_string());
+
+        for line in lines {
+            cards.push_str(&vcard_fold(&line));
+        }
+    }
+    cards.into_bytes()
+}
+
+fn to_geojson(rows: &[ExportRow]) -> Vec<u8> {
+    let features: Vec<serde_json::Value> = rows
+        .iter()
+        .map(|row| {
+            json!({
+                "type": "Feature",
+                // GeoJSON positions are longitude first
+                "geometry": row.location.as_ref().map(|location| json!({
+                    "type": "Point",
+                    "coordinates": [location.lng, location.lat]
+                })),
+                "properties": {
+                    "name": row.name,
+                    "address": row.address,
+                    "phone": row.phone,
+                    "rating": row.rating,
+                    "distance_km": row.distance_km,
+                    "provider_type": row.provider_type,
+                    "specialties": row.specialties,
+                    "accepted_insurance": row.accepted_insurance
+                }
+            })
+        })
+        .collect();
+
+    json!({ "type": "FeatureCollection", "features": features })
+        .to_string()
+        .into_bytes()
+}
+
+// The PDF's built-in fonts only cover Latin-1, so swap out other characters
+fn pdf_text(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '\u{2013}' | '\u{2014}' => '-',
+            '\u{2018}' | '\u{2019}' => '\'',
+            '\u{201C}' | '\u{201D}' => '"',
+            '\u{00A0}' | '\u{202F}' | '\u{2009}' => ' ',
+            c if (c as u32) < 0x100 => c,
+            _ => '?',
+        })
+        .collect()
+}
+
+// Break text into lines of at most `width` characters
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + word.len() + 1 > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+// A4 pages, writing top to bottom and starting a new page when one fills up
+struct PdfWriter {
+    doc: printpdf::PdfDocumentReference,
+    layer: PdfLayerReference,
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+    y: f32,
+}
+
+impl PdfWriter {
+    const WIDTH: f32 = 210.0;
+    const HEIGHT: f32 = 297.0;
+    const MARGIN: f32 = 20.0;
+
+    fn new(title: &str) -> Result<PdfWriter, Box<dyn Error>> {
+        let (doc, page, layer) = PdfDocument::new(title, Mm(Self::WIDTH), Mm(Self::HEIGHT), "Layer 1");
+        let regular = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+        let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+        let layer = doc.get_page(page).get_layer(layer);
+        Ok(PdfWriter { doc, layer, regular, bold, y: Self::HEIGHT - Self::MARGIN })
+    }
+
+    // Start a new page unless `height` more millimeters fit on this one
+    fn reserve(&mut self, height: f32) {
+        if self.y - height < Self::MARGIN {
+            let (page, layer) = self.doc.add_page(Mm(Self::WIDTH), Mm(Self::HEIGHT), "Layer 1");
+            self.layer = self.doc.get_page(page).get_layer(layer);
+            self.y = Self::HEIGHT - Self::MARGIN;
+        }
+    }
+
+    fn line(&mut self, text: &str, size: f32, bold: bool) {
+        let height = size * 0.5;
+        self.reserve(height);
+        self.y -= height;
+        let font = if bold { &self.bold } else { &self.regular };
+        self.layer.use_text(pdf_text(text), size, Mm(Self::MARGIN), Mm(self.y), font);
+    }
+
+    fn gap(&mut self, height: f32) {
+        self.y -= height;
+    }
+}
+
+fn to_pdf(title: &str, rows: &[ExportRow]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut pdf = PdfWriter::new(title)?;
+
+    pdf.line(title, 18.0, true);
+    pdf.line(&format!("{} providers", rows.len()), 10.0, false);
+    pdf.gap(6.0);
+
+    for (index, row) in rows.iter().enumerate() {
+        // Keep each provider's name and address on the same page
+        pdf.reserve(24.0);
+        pdf.line(&format!("{}. {}", index + 1, row.name), 13.0, true);
+        for line in wrap(&row.address, 90) {
+            pdf.line(&line, 10.0, false);
+        }
+
+        let mut facts = Vec::new();
+        if let Some(phone) = &row.phone {
+            facts.push(format!("Phone: {}", phone));
+        }
+        if let Some(rating) = row.rating {
+            facts.push(format!("Rating: {:.1}", rating));
+        }
+        if let Some(km) = row.distance_km {
+            facts.push(format!("Distance: {:.1} km", km));
+        }
+        if !facts.is_empty() {
+            pdf.line(&facts.join("    "), 10.0, false);
+        }
+        if !row.specialties.is_empty() {
+            for line in wrap(&format!("Specialties: {}", row.specialties.join(", ")), 90) {
+                pdf.line(&line, 10.0, false);
+            }
+        }
+        if !row.accepted_insurance.is_empty() {
+            for line in wrap(&format!("Accepts: {}", row.accepted_insurance.join(", ")), 90) {
+                pdf.line(&line, 10.0, false);
+            }
+        }
+        pdf.gap(6.0);
+    }
+
+    Ok(pdf.doc.save_to_bytes()?)
+}
+
+// Render `rows` in the requested format as a file download
+fn export_response(format: ExportFormat, title: &str, filename: &str, rows: &[ExportRow]) -> HttpResponse {
+    let body = match format {
+        ExportFormat::Csv => to_csv(rows),
+        ExportFormat::VCard => Ok(to_vcard(rows)),
+        ExportFormat::GeoJson => Ok(to_geojson(rows)),
+        ExportFormat::Pdf => to_pdf(title, rows),
+    };
+
+    match body {
+        Ok(body) => HttpResponse::Ok()
+            .content_type(format.content_type())
+            .append_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"{}.{}\"", filename, format.extension()),
+            ))
+            .body(body),
+        Err(err) => {
+            eprintln!("Failed to generate export: {}", err);
+            HttpResponse::InternalServerError().body("Failed to generate export")
+        }
+    }
+}
+
+fn unknown_format() -> HttpResponse {
+    HttpResponse::BadRequest().body("Unknown export format. Use csv, vcard, geojson or pdf.")
+}
+
+// Handler for `GET /api/favorites/export?format=...`
+#[get("/api/favorites/export")]
+async fn export_favorites(
+    req: HttpRequest,
+    query: web::Query<ExportQuery>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().body("Please log in to export your favorites");
+    };
+    let Some(format) = ExportFormat::parse(&query.format) else {
+        return unknown_format();
+    };
+
+    let favorites = match list_favorites(pool.get_ref(), user_id).await {
+        Ok(favorites) => favorites,
+        Err(err) => {
+            eprintln!("Failed to fetch favorites for export: {}", err);
+            return HttpResponse::InternalServerError().body("Failed to fetch favorites");
+        }
+    };
+
+    let mut rows = Vec::new();
+    for favorite in favorites {
+        // The stored provider record fills in what the favorite snapshot doesn't keep
+        let mut provider = match &favorite.provider_id {
+            Some(provider_id) => provider_store::find(pool.get_ref(), provider_id).await.ok().flatten(),
+            None => None,
+        };
+        if let Some(provider) = &mut provider {
+            if let Err(err) = provider_listings::apply_overrides(pool.get_ref(), std::slice::from_mut(provider)).await {
+                eprintln!("Failed to apply listing corrections for {}: {}", provider.id, err);
+            }
+        }
+        let row = match provider {
+            Some(provider) => ExportRow {
+                name: favorite.name,
+                address: favorite.address,
+                rating: favorite.rating,
+                ..ExportRow::from(&provider)
+            },
+            None => ExportRow {
+                name: favorite.name,
+                address: favorite.address,
+                phone: None,
+                rating: favorite.rating,
+                distance_km: None,
+                provider_type: String::new(),
+                specialties: Vec::new(),
+                accepted_insurance: Vec::new(),
+                location: None,
+            },
+        };
+        rows.push(row);
+    }
+
+    export_response(format, "My Favorite Providers", "favorites", &rows)
+}
+
+// Handler for `GET /services/export?format=...&ids=...[&lat=...&lng=...]`
+#[get("/services/export")]
+async fn export_services(query: web::Query<ExportQuery>, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(format) = ExportFormat::parse(&query.format) else {
+        return unknown_format();
+    };
+
+    let ids: Vec<&str> = query
+        .ids
+        .as_deref()
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .collect();
+    if ids.is_empty() {
+        return HttpResponse::BadRequest().body("Please provide the IDs of the providers to export");
+    }
+    if ids.len() > MAX_EXPORT_IDS {
+        return HttpResponse::BadRequest().body(format!("At most {} providers can be exported at once", MAX_EXPORT_IDS));
+    }
+
+    let origin = match (query.lat, query.lng) {
+        (Some(lat), Some(lng)) => Some(Coordinates { lat, lng }),
+        _ => None,
+    };
+
+    let mut providers = Vec::new();
+    for id in ids {
+        match provider_store::find(pool.get_ref(), id).await {
+            Ok(Some(provider)) => providers.push(provider),
+            Ok(None) => {} // Unknown IDs are skipped
+            Err(err) => {
+                eprintln!("Failed to fetch provider {} for export: {}", id, err);
+                return HttpResponse::InternalServerError().body("Failed to fetch providers");
+            }
+        }
+    }
+
+    // Export what the search page shows, with the providers' own corrections
+    if let Err(err) = provider_listings::apply_overrides(pool.get_ref(), &mut providers).await {
+        eprintln!("Failed to apply listing corrections: {}", err);
+    }
+
+    let rows: Vec<ExportRow> = providers
+        .iter()
+        .map(|provider| {
+            let distance_km = match (&origin, &provider.location) {
+                (Some(origin), Some(location)) => Some(calculate_distance(origin, location.lat, location.lng)),
+                _ => None,
+            };
+            ExportRow { distance_km, ..ExportRow::from(provider) }
+        })
+        .collect();
+
+    export_response(format, "Health Service Providers", "providers", &rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row() -> ExportRow {
+        ExportRow {
+            name: "Smith, Jones; Partners".to_string(),
+            address: "1 Main St, Suite \"B\"\nSpringfield".to_string(),
+            phone: Some("555-0100".to_string()),
+            rating: Some(4.5),
+            distance_km: Some(1.234),
+            provider_type: "Clinic".to_string(),
+            specialties: vec!["Cardiology".to_string(), "Family Medicine".to_string()],
+            accepted_insurance: vec!["Aetna".to_string()],
+            location: Some(Coordinates { lat: 40.5, lng: -74.25 }),
+        }
+    }
+
+    #[test]
+    fn csv_quotes_fields() {
+        let csv = String::from_utf8(to_csv(&[row()]).unwrap()).unwrap();
+        let mut lines = csv.splitn(2, '\n');
+
+        assert!(lines.next().unwrap().starts_with("name,address,phone,rating,distance_km"));
+        assert_eq!(
+            lines.next().unwrap(),
+            "\"Smith, Jones; Partners\",\"1 Main St, Suite \"\"B\"\"\nSpringfield\",555-0100,4.5,1.23,Clinic,\
+             Cardiology; Family Medicine,Aetna,40.5,-74.25\n"
+        );
+    }
+
+    #[test]
+    fn vcard_escapes_and_folds() {
+        let mut long = row();
+        long.specialties = vec!["Pediatric Endocrinology".to_string(); 4];
+        let vcard = String::from_utf8(to_vcard(&[long])).unwrap();
+        let lines: Vec<&str> = vcard.trim_end_matches("\r\n").split("\r\n").collect();
+
+        assert!(lines.contains(&"FN:Smith\\, Jones\\; Partners"));
+        assert!(lines.contains(&"ADR;TYPE=WORK:;;1 Main St\\, Suite \"B\"\\nSpringfield;;;;"));
+        assert!(lines.contains(&"GEO:40.5;-74.25"));
+        assert!(lines.iter().all(|line| line.len() <= MAX_VCARD_LINE));
+
+        // Unfolding gives back the full note
+        let unfolded = vcard.replace("\r\n ", "");
+        assert!(unfolded.contains(&format!("NOTE:{}\r\n", ["Pediatric Endocrinology"; 4].join("\\, "))));
+    }
+
+    #[test]
+    fn vcard_folds_on_character_boundaries() {
+        let line = format!("{}é", "a".repeat(74));
+        assert_eq!(vcard_fold(&line), format!("{}\r\n é\r\n", "a".repeat(74)));
+    }
+
+    #[test]
+    fn geojson_positions_are_longitude_first() {
+        let mut unplaced = row();
+        unplaced.location = None;
+        let geojson: serde_json::Value = serde_json::from_slice(&to_geojson(&[row(), unplaced])).unwrap();
+
+        assert_eq!(geojson["type"], "FeatureCollection");
+        assert_eq!(geojson["features"][0]["geometry"]["coordinates"], json!([-74.25, 40.5]));
+        assert_eq!(geojson["features"][0]["properties"]["name"], "Smith, Jones; Partners");
+        assert!(geojson["features"][1]["geometry"].is_null());
+    }
+}
@@ -0,0 +1,253 @@
+// Background refresh of favorite snapshots. A favorite stores the photo, name,
+// address and rating seen when it was saved; this task periodically re-fetches
+// the provider behind each favorite, updates the snapshot and records in
+// `favorite_changes` which fields changed. Google places are re-read through
+// Place Details, other providers from the latest record stored by searches.
+// Changes a user would want to act on also create a notification for them.
+use crate::notifications;
+use crate::place_details::{cached_place_details, PlaceDetailsCache};
+use crate::provider_store;
+
+use actix_web::web;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::Duration;
+
+// How often to look for stale favorites, unless FAVORITE_REFRESH_INTERVAL_SECS is set
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// Favorites whose snapshot is older than this are refreshed
+const STALE_AFTER: &str = "-24 hours";
+
+// Favorites refreshed per run, to keep Place Details usage bounded
+const BATCH_SIZE: i64 = 50;
+
+// The fields of a favorite that are kept in sync with its provider
+struct Snapshot {
+    title: String,
+    address: String,
+    rating: Option<f64>,
+    photo: Option<String>,
+    phone: Option<String>,
+    opening_hours: Option<Vec<String>>, // None when the source doesn't report hours
+    business_status: Option<String>,
+}
+
+const PERMANENTLY_CLOSED: &str = "CLOSED_PERMANENTLY";
+
+// Ratings are shown to one decimal place, so smaller differences aren't changes
+fn rating_changed(old: Option<f64>, new: Option<f64>) -> bool {
+    match (old, new) {
+        (Some(old), Some(new)) => (old - new).abs() >= 0.05,
+        (old, new) => old.is_some() != new.is_some(),
+    }
+}
+
+// The provider's current data, or `None` if it can no longer be found
+async fn current_snapshot(
+    pool: &SqlitePool,
+    cache: &PlaceDetailsCache,
+    provider_id: &str,
+    api_key: &str,
+) -> Result<Option<Snapshot>, Box<dyn Error>> {
+    let provider = provider_store::find(pool, provider_id).await?;
+
+    let place_id = provider
+        .as_ref()
+        .and_then(|provider| provider.place_id.clone())
+        .or_else(|| provider_id.strip_prefix("place:").map(String::from));
+
+    if let Some(place_id) = place_id {
+        return Ok(cached_place_details(cache, &place_id, api_key).await?.map(|details| Snapshot {
+            title: details.name,
+            address: details.address,
+            rating: details.rating,
+            photo: details.photo_url,
+            phone: details.phone,
+            opening_hours: Some(details.opening_hours),
+            business_status: details.business_status,
+        }));
+    }
+
+    Ok(provider.map(|provider| Snapshot {
+        title: provider.name,
+        address: provider.address,
+        rating: provider.rating.map(|rating| (f64::from(rating) * 10.0).round() / 10.0), // Stored as f32
+        photo: provider.photo_url,
+        phone: provider.phone,
+        opening_hours: None,
+        business_status: None,
+    }))
+}
+
+// Refresh one batch of stale favorites. Returns the number of favorites refreshed.
+pub async fn refresh_stale_favorites(
+    pool: &SqlitePool,
+    cache: &PlaceDetailsCache,
+    api_key: &str,
+) -> Result<usize, Box<dyn Error>> {
+    let favorites = sqlx::query!(
+        "SELECT id AS \"id!\", user_id, provider_id AS \"provider_id!\", title, address, rating,
+                phone, opening_hours, business_status
+         FROM favorites
+         WHERE provider_id IS NOT NULL
+           AND (refreshed_at IS NULL OR refreshed_at < datetime('now', ?))
+         ORDER BY refreshed_at IS NOT NULL, refreshed_at
+         LIMIT ?",
+        STALE_AFTER,
+        BATCH_SIZE
+    )
+    .fetch_all(pool)
+    .await?;
+
+    // Several users can favorite the same provider; fetch it once per run
+    let mut snapshots: HashMap<String, Option<Snapshot>> = HashMap::new();
+    let mut refreshed = 0;
+
+    for favorite in favorites {
+        if !snapshots.contains_key(&favorite.provider_id) {
+            match current_snapshot(pool, cache, &favorite.provider_id, api_key).await {
+                Ok(snapshot) => {
+                    snapshots.insert(favorite.provider_id.clone(), snapshot);
+                }
+                Err(err) => {
+                    // Leave it stale so the next run tries again
+                    eprintln!("Failed to refresh provider {}: {}", favorite.provider_id, err);
+                    continue;
+                }
+            }
+        }
+
+        let mut tx = pool.begin().await?;
+
+        // Providers that can't be found anymore keep their last snapshot
+        if let Some(snapshot) = &snapshots[&favorite.provider_id] {
+            let mut changes: Vec<(&str, Option<String>, Option<String>)> = Vec::new();
+            let mut alerts: Vec<(&str, String)> = Vec::new();
+
+            if snapshot.title != favorite.title {
+                changes.push(("title", Some(favorite.title.clone()), Some(snapshot.title.clone())));
+            }
+            if snapshot.address != favorite.address {
+                changes.push(("address", Some(favorite.address.clone()), Some(snapshot.address.clone())));
+            }
+            if rating_changed(favorite.rating, snapshot.rating) {
+                let old_rating = favorite.rating.map(|rating| format!("{:.1}", rating));
+                let new_rating = snapshot.rating.map(|rating| format!("{:.1}", rating));
+                if let (Some(old), Some(new)) = (&old_rating, &new_rating) {
+                    if snapshot.rating < favorite.rating {
+                        alerts.push((
+                            "rating_drop",
+                            format!("{}'s rating dropped from {} to {}.", snapshot.title, old, new),
+                        ));
+                    }
+                }
+                changes.push(("rating", old_rating, new_rating));
+            }
+
+            // Phone, hours and status are only compared once a previous value was captured
+            let phone = snapshot.phone.clone().unwrap_or_default();
+            if let Some(old_phone) = favorite.phone.as_ref().filter(|old_phone| **old_phone != phone) {
+                alerts.push((
+                    "phone",
+                    if phone.is_empty() {
+                        format!("{} no longer lists a phone number.", snapshot.title)
+                    } else {
+                        format!("{} changed its phone number to {}.", snapshot.title, phone)
+                    },
+                ));
+                changes.push(("phone", Some(old_phone.clone()), Some(phone.clone())));
+            }
+
+            let opening_hours = snapshot
+                .opening_hours
+                .as_ref()
+                .map(|hours| serde_json::to_string(hours).expect("hours serialize to JSON"));
+            if let (Some(old_hours), Some(new_hours)) = (&favorite.opening_hours, &opening_hours) {
+                if old_hours != new_hours {
+                    alerts.push(("hours", format!("{} changed its opening hours.", snapshot.title)));
+                    changes.push(("opening_hours", Some(old_hours.clone()), Some(new_hours.clone())));
+                }
+            }
+
+            // Being closed is worth telling even if the previous status wasn't captured
+            let closed = snapshot.business_status.as_deref() == Some(PERMANENTLY_CLOSED);
+            if closed && favorite.business_status.as_deref() != Some(PERMANENTLY_CLOSED) {
+                alerts.push(("closed", format!("{} is marked as permanently closed.", snapshot.title)));
+            }
+            if let (Some(old_status), Some(new_status)) = (&favorite.business_status, &snapshot.business_status) {
+                if old_status != new_status {
+                    changes.push(("business_status", Some(old_status.clone()), Some(new_status.clone())));
+                }
+            }
+
+            for (kind, message) in &alerts {
+                notifications::create(&mut tx, favorite.user_id, favorite.id, kind, message).await?;
+            }
+
+            for (field, old_value, new_value) in &changes {
+                sqlx::query!(
+                    "INSERT INTO favorite_changes (favorite_id, field, old_value, new_value) VALUES (?, ?, ?, ?)",
+                    favorite.id,
+                    field,
+                    old_value,
+                    new_value
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+
+            sqlx::query!(
+                "UPDATE favorites SET
+                     title = ?, address = ?, rating = ?, photo = COALESCE(?, photo), phone = ?,
+                     opening_hours = COALESCE(?, opening_hours),
+                     business_status = COALESCE(?, business_status)
+                 WHERE id = ?",
+                snapshot.title,
+                snapshot.address,
+                snapshot.rating,
+                snapshot.photo,
+                phone,
+                opening_hours,
+                snapshot.business_status,
+                favorite.id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query!(
+            "UPDATE favorites SET refreshed_at = CURRENT_TIMESTAMP WHERE id = ?",
+            favorite.id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        refreshed += 1;
+    }
+
+    Ok(refreshed)
+}
+
+// Start the refresher on the current runtime; it runs for the life of the server
+pub fn spawn(pool: SqlitePool, cache: web::Data<PlaceDetailsCache>, api_key: String) {
+    let interval = std::env::var("FAVORITE_REFRESH_INTERVAL_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_INTERVAL);
+
+    actix_web::rt::spawn(async move {
+        let mut ticker = actix_web::rt::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match refresh_stale_favorites(&pool, cache.get_ref(), &api_key).await {
+                Ok(0) => {}
+                Ok(refreshed) => println!("Refreshed {} favorites", refreshed),
+                Err(err) => eprintln!("Favorite refresh failed: {}", err),
+            }
+        }
+    });
+}
@@ -0,0 +1,602 @@
+use crate::session::current_user_id;
+
+use actix_web::{delete, get, patch, post, put, web, HttpRequest, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+
+#[derive(Deserialize, Debug)]
+struct FavoriteService {
+    provider_id: Option<String>,
+    photo: String,
+    name: String,
+    address: String,
+    rating: Option<f64>, // None for places without reviews
+}
+
+#[derive(Serialize)]
+pub struct Favorite {
+    pub id: i64,
+    pub provider_id: Option<String>,
+    pub photo: String,
+    pub name: String,
+    pub address: String,
+    pub rating: Option<f64>,
+    pub collection_id: Option<i64>, // None when the favorite isn't in a collection
+    pub notes: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct Collection {
+    pub id: i64,
+    pub name: String,
+    pub favorites: Vec<Favorite>,
+}
+
+#[derive(Deserialize)]
+struct CollectionName {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Ordering {
+    ids: Vec<i64>, // IDs in their new display order
+}
+
+#[derive(Deserialize)]
+struct FavoriteMove {
+    collection_id: Option<i64>, // null moves the favorite out of its collection
+}
+
+#[derive(Deserialize)]
+struct FavoriteAnnotations {
+    notes: Option<String>,
+    tags: Option<Vec<String>>,
+}
+
+fn not_logged_in() -> HttpResponse {
+    HttpResponse::Unauthorized().json(json!({
+        "status": "error",
+        "message": "User not logged in. Please log in and try again."
+    }))
+}
+
+pub async fn list_favorites(pool: &SqlitePool, user_id: i64) -> Result<Vec<Favorite>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT id AS \"id!\", provider_id, photo, title AS name, address, rating, collection_id, notes
+         FROM favorites WHERE user_id = ? ORDER BY position, id",
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let tags = sqlx::query!(
+        "SELECT favorite_tags.favorite_id, favorite_tags.tag FROM favorite_tags
+         JOIN favorites ON favorites.id = favorite_tags.favorite_id
+         WHERE favorites.user_id = ? ORDER BY favorite_tags.tag",
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Favorite {
+            tags: tags
+                .iter()
+                .filter(|t| t.favorite_id == row.id)
+                .map(|t| t.tag.clone())
+                .collect(),
+            id: row.id,
+            provider_id: row.provider_id,
+            photo: row.photo,
+            name: row.name,
+            address: row.address,
+            rating: row.rating,
+            collection_id: row.collection_id,
+            notes: row.notes,
+        })
+        .collect())
+}
+
+// The user's collections in display order, each with its favorites. Favorites
+// outside any collection are returned separately.
+pub async fn list_collections(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> Result<(Vec<Collection>, Vec<Favorite>), sqlx::Error> {
+    let mut collections: Vec<Collection> = sqlx::query!(
+        "SELECT id AS \"id!\", name FROM favorite_collections WHERE user_id = ? ORDER BY position, id",
+        user_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| Collection {
+        id: row.id,
+        name: row.name,
+        favorites: Vec::new(),
+    })
+    .collect();
+
+    let mut unsorted = Vec::new();
+    for favorite in list_favorites(pool, user_id).await? {
+        match collections.iter_mut().find(|c| Some(c.id) == favorite.collection_id) {
+            Some(collection) => collection.favorites.push(favorite),
+            None => unsorted.push(favorite),
+        }
+    }
+
+    Ok((collections, unsorted))
+}
+
+// Save a favorite, or refresh the saved copy if the user already favorited
+// this provider. Returns the favorite's ID.
+async fn upsert_favorite(pool: &SqlitePool, user_id: i64, favorite: &FavoriteService) -> Result<i64, sqlx::Error> {
+    // Resolve aliases to the provider's canonical ID; unknown IDs are stored as NULL
+    let provider_id = match &favorite.provider_id {
+        Some(alias) => {
+            sqlx::query_scalar!("SELECT provider_id FROM provider_aliases WHERE alias = ?", alias)
+                .fetch_optional(pool)
+                .await?
+        }
+        None => None,
+    };
+
+    if let Some(provider_id) = provider_id {
+        return sqlx::query_scalar!(
+            "INSERT INTO favorites (user_id, photo, title, address, rating, provider_id, refreshed_at)
+             VALUES (?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP)
+             ON CONFLICT (user_id, provider_id) DO UPDATE SET
+                 photo = excluded.photo,
+                 title = excluded.title,
+                 address = excluded.address,
+                 rating = excluded.rating,
+                 refreshed_at = excluded.refreshed_at
+             RETURNING id AS \"id!\"",
+            user_id,
+            favorite.photo,
+            favorite.name,
+            favorite.address,
+            favorite.rating,
+            provider_id
+        )
+        .fetch_one(pool)
+        .await;
+    }
+
+    // Without a provider ID the unique index can't catch duplicates, so match on title and address
+    let existing = sqlx::query_scalar!(
+        "SELECT id AS \"id!\" FROM favorites WHERE user_id = ? AND provider_id IS NULL AND title = ? AND address = ?",
+        user_id,
+        favorite.name,
+        favorite.address
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    match existing {
+        Some(id) => Ok(id),
+        None => {
+            sqlx::query_scalar!(
+                "INSERT INTO favorites (user_id, photo, title, address, rating) VALUES (?, ?, ?, ?, ?) RETURNING id AS \"id!\"",
+                user_id,
+                favorite.photo,
+                favorite.name,
+                favorite.address,
+                favorite.rating
+            )
+            .fetch_one(pool)
+            .await
+        }
+    }
+}
+
+// Handler for `POST /api/favorites`
+#[post("/api/favorites")]
+async fn save_favorite(
+    req: HttpRequest,
+    favorite: web::Json<FavoriteService>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+
+    match upsert_favorite(pool.get_ref(), user_id, &favorite).await {
+        Ok(id) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Favorite saved successfully.",
+            "id": id
+        })),
+        Err(err) => {
+            eprintln!("Failed to save favorite: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Failed to save favorite. Please try again later."
+            }))
+        }
+    }
+}
+
+// Handler for `GET /api/favorites`
+#[get("/api/favorites")]
+async fn get_favorites(req: HttpRequest, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+
+    match list_favorites(pool.get_ref(), user_id).await {
+        Ok(favorites) => HttpResponse::Ok().json(favorites),
+        Err(err) => {
+            eprintln!("Failed to fetch favorites: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Could not fetch favorites"
+            }))
+        }
+    }
+}
+
+// Handler for `DELETE /api/favorites/{id}`
+#[delete("/api/favorites/{id}")]
+async fn delete_favorite(req: HttpRequest, path: web::Path<i64>, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let favorite_id = path.into_inner();
+
+    let result = sqlx::query!(
+        "DELETE FROM favorites WHERE id = ? AND user_id = ?",
+        favorite_id,
+        user_id
+    )
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(res) if res.rows_affected() == 1 => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Favorite removed."
+        })),
+        Ok(_) => HttpResponse::NotFound().json(json!({
+            "status": "error",
+            "message": "Favorite not found."
+        })),
+        Err(err) => {
+            eprintln!("Failed to delete favorite: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Failed to remove favorite. Please try again later."
+            }))
+        }
+    }
+}
+
+fn database_error(err: sqlx::Error, message: &str) -> HttpResponse {
+    eprintln!("{}: {}", message, err);
+    HttpResponse::InternalServerError().json(json!({
+        "status": "error",
+        "message": format!("{}. Please try again later.", message)
+    }))
+}
+
+fn not_found(message: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+// Handler for `GET /api/collections`
+#[get("/api/collections")]
+async fn get_collections(req: HttpRequest, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+
+    match list_collections(pool.get_ref(), user_id).await {
+        Ok((collections, unsorted)) => HttpResponse::Ok().json(json!({
+            "collections": collections,
+            "unsorted": unsorted
+        })),
+        Err(err) => database_error(err, "Could not fetch collections"),
+    }
+}
+
+// Handler for `POST /api/collections`
+#[post("/api/collections")]
+async fn create_collection(
+    req: HttpRequest,
+    body: web::Json<CollectionName>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+
+    let name = body.name.trim();
+    if name.is_empty() {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": "Collection name is required."
+        }));
+    }
+
+    // New collections go after the existing ones
+    let result = sqlx::query_scalar!(
+        "INSERT INTO favorite_collections (user_id, name, position)
+         VALUES (?, ?, (SELECT COALESCE(MAX(position), -1) + 1 FROM favorite_collections WHERE user_id = ?))
+         RETURNING id AS \"id!\"",
+        user_id,
+        name,
+        user_id
+    )
+    .fetch_one(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(id) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Collection created.",
+            "id": id
+        })),
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => HttpResponse::Conflict().json(json!({
+            "status": "error",
+            "message": "You already have a collection with that name."
+        })),
+        Err(err) => database_error(err, "Failed to create collection"),
+    }
+}
+
+// Handler for `PATCH /api/collections/{id}` (rename)
+#[patch("/api/collections/{id}")]
+async fn rename_collection(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: web::Json<CollectionName>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let collection_id = path.into_inner();
+
+    let name = body.name.trim();
+    if name.is_empty() {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": "Collection name is required."
+        }));
+    }
+
+    let result = sqlx::query!(
+        "UPDATE favorite_collections SET name = ? WHERE id = ? AND user_id = ?",
+        name,
+        collection_id,
+        user_id
+    )
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(res) if res.rows_affected() == 1 => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Collection renamed."
+        })),
+        Ok(_) => not_found("Collection not found."),
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => HttpResponse::Conflict().json(json!({
+            "status": "error",
+            "message": "You already have a collection with that name."
+        })),
+        Err(err) => database_error(err, "Failed to rename collection"),
+    }
+}
+
+// Handler for `DELETE /api/collections/{id}`. The favorites in it become unsorted.
+#[delete("/api/collections/{id}")]
+async fn delete_collection(req: HttpRequest, path: web::Path<i64>, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let collection_id = path.into_inner();
+
+    let result = sqlx::query!(
+        "DELETE FROM favorite_collections WHERE id = ? AND user_id = ?",
+        collection_id,
+        user_id
+    )
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(res) if res.rows_affected() == 1 => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Collection deleted."
+        })),
+        Ok(_) => not_found("Collection not found."),
+        Err(err) => database_error(err, "Failed to delete collection"),
+    }
+}
+
+// Handler for `PUT /api/collections/order`
+#[put("/api/collections/order")]
+async fn reorder_collections(
+    req: HttpRequest,
+    body: web::Json<Ordering>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+
+    let result: Result<(), sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+        for (position, id) in body.ids.iter().enumerate() {
+            let position = position as i64;
+            sqlx::query!(
+                "UPDATE favorite_collections SET position = ? WHERE id = ? AND user_id = ?",
+                position,
+                id,
+                user_id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+    .await;
+
+    match result {
+        Ok(()) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Collections reordered."
+        })),
+        Err(err) => database_error(err, "Failed to reorder collections"),
+    }
+}
+
+// Handler for `PUT /api/favorites/order` (order within a collection)
+#[put("/api/favorites/order")]
+async fn reorder_favorites(
+    req: HttpRequest,
+    body: web::Json<Ordering>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+
+    let result: Result<(), sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+        for (position, id) in body.ids.iter().enumerate() {
+            let position = position as i64;
+            sqlx::query!(
+                "UPDATE favorites SET position = ? WHERE id = ? AND user_id = ?",
+                position,
+                id,
+                user_id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+    .await;
+
+    match result {
+        Ok(()) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Favorites reordered."
+        })),
+        Err(err) => database_error(err, "Failed to reorder favorites"),
+    }
+}
+
+// Handler for `PUT /api/favorites/{id}/collection` (move between collections)
+#[put("/api/favorites/{id}/collection")]
+async fn move_favorite(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: web::Json<FavoriteMove>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let favorite_id = path.into_inner();
+
+    // The target collection must belong to the same user; moved favorites go to the end
+    let result = sqlx::query!(
+        "UPDATE favorites SET
+             collection_id = (SELECT id FROM favorite_collections WHERE id = ? AND user_id = ?),
+             position = (SELECT COALESCE(MAX(position), -1) + 1 FROM favorites WHERE user_id = ? AND collection_id IS ?)
+         WHERE id = ? AND user_id = ?
+           AND (? IS NULL OR EXISTS (SELECT 1 FROM favorite_collections WHERE id = ? AND user_id = ?))",
+        body.collection_id,
+        user_id,
+        user_id,
+        body.collection_id,
+        favorite_id,
+        user_id,
+        body.collection_id,
+        body.collection_id,
+        user_id
+    )
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(res) if res.rows_affected() == 1 => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Favorite moved."
+        })),
+        Ok(_) => not_found("Favorite or collection not found."),
+        Err(err) => database_error(err, "Failed to move favorite"),
+    }
+}
+
+// Handler for `PATCH /api/favorites/{id}` (private notes and tags)
+#[patch("/api/favorites/{id}")]
+async fn annotate_favorite(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: web::Json<FavoriteAnnotations>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let favorite_id = path.into_inner();
+
+    let result: Result<bool, sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+
+        let owned = sqlx::query_scalar!(
+            "SELECT id FROM favorites WHERE id = ? AND user_id = ?",
+            favorite_id,
+            user_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        if owned.is_none() {
+            return Ok(false);
+        }
+
+        if let Some(notes) = &body.notes {
+            sqlx::query!("UPDATE favorites SET notes = ? WHERE id = ?", notes, favorite_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        // Tags are replaced as a set
+        if let Some(tags) = &body.tags {
+            sqlx::query!("DELETE FROM favorite_tags WHERE favorite_id = ?", favorite_id)
+                .execute(&mut *tx)
+                .await?;
+            for tag in tags.iter().map(|tag| tag.trim()).filter(|tag| !tag.is_empty()) {
+                sqlx::query!(
+                    "INSERT OR IGNORE INTO favorite_tags (favorite_id, tag) VALUES (?, ?)",
+                    favorite_id,
+                    tag
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(true)
+    }
+    .await;
+
+    match result {
+        Ok(true) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Favorite updated."
+        })),
+        Ok(false) => not_found("Favorite not found."),
+        Err(err) => database_error(err, "Failed to update favorite"),
+    }
+}
@@ -1,7 +1,20 @@
+use crate::plan_net::find_plan_net_providers;
+use crate::provider_store;
+
 use reqwest;
 use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
 use std::error::Error;
 
+// Service types offered on the search page, with the names shown for them
+pub const SERVICE_TYPES: [(&str, &str); 5] = [
+    ("dentist", "Dentist"),
+    ("pharmacy", "Pharmacy"),
+    ("physiotherapist", "Physical Therapy"),
+    ("hospital", "Hospital"),
+    ("doctor", "Doctor"),
+];
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Service {
     pub name: String,
@@ -11,18 +24,54 @@ pub struct Service {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HealthProvider {
-    name: String,
-    address: String,
-    distance: f64,
-    provider_type: String,
-    phone: Option<String>,
-    rating: Option<f32>,
-    photo_url: Option<String>,
-    open_now: bool,
-    services: Vec<Service>, 
+    pub id: String,               // Stable provider ID, assigned by `provider_store::register`
+    #[serde(skip)]
+    pub source_ids: Vec<String>,  // IDs from each source this record was built from
+    pub place_id: Option<String>, // Google place ID, for fetching Place Details
+    pub name: String,
+    pub address: String,
+    #[serde(default)]
+    pub location: Option<Coordinates>, // Missing from records stored before locations were kept
+    pub distance: f64,
+    pub provider_type: String,
+    pub phone: Option<String>,
+    pub rating: Option<f32>,
+    #[serde(default)]
+    pub review_count: Option<u32>, // Number of ratings behind `rating`
+    pub photo_url: Option<String>,
+    pub open_now: bool,
+    pub services: Vec<Service>, 
+    pub networks: Vec<String>,           // Plan networks the provider participates in
+    pub accepted_insurance: Vec<String>, // Insurance plans accepted through those networks
+    pub in_network: bool,                // Whether the logged-in user's plan covers this provider
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hours: Vec<String>,              // Weekly hours, when the provider has listed them here
+    #[serde(default)]
+    pub telehealth: Option<bool>,        // Whether the provider offers video visits, when they've said
+    #[serde(default)]
+    pub updated_by_provider: bool,       // Whether the provider's own corrections were applied
+    #[serde(skip)]
+    pub insurance_corrected: bool,       // Whether `accepted_insurance` is the provider's own list
+    #[serde(default)]
+    pub community_rating: Option<f64>,   // Average of published reviews left on this site
+    #[serde(default)]
+    pub community_review_count: u32,     // Number of reviews behind `community_rating`
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl HealthProvider {
+    // Unique NPI taxonomy descriptions of the provider's clinicians
+    pub fn specialties(&self) -> Vec<String> {
+        let mut specialties: Vec<String> = Vec::new();
+        for service in &self.services {
+            if !service.taxonomy.is_empty() && !specialties.contains(&service.taxonomy) {
+                specialties.push(service.taxonomy.clone());
+            }
+        }
+        specialties
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Coordinates {
     pub lat: f64,
     pub lng: f64,
@@ -43,11 +92,62 @@ pub async fn geocode_address(address: &str, api_key: &str) -> Result<Coordinates
         .await?;
 
     let location = &response["results"][0]["geometry"]["location"];
-    
-    Ok(Coordinates {
-        lat: location["lat"].as_f64().unwrap(),
-        lng: location["lng"].as_f64().unwrap(),
-    })
+    let (Some(lat), Some(lng)) = (location["lat"].as_f64(), location["lng"].as_f64()) else {
+        return Err(format!("no geocoding result for {}", address).into());
+    };
+
+    Ok(Coordinates { lat, lng })
+}
+
+// Look up the two-letter state code for a ZIP code or address
+pub async fn geocode_state(address: &str, api_key: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let url = format!(
+        "https://maps.googleapis.com/maps/api/geocode/json?address={}&key={}",
+        urlencoding::encode(address), api_key
+    );
+    fetch_address_component(&url, "administrative_area_level_1").await
+}
+
+// Look up the two-letter state code for a coordinate
+pub async fn reverse_geocode_state(coordinates: &Coordinates, api_key: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let url = format!(
+        "https://maps.googleapis.com/maps/api/geocode/json?latlng={},{}&result_type=administrative_area_level_1&key={}",
+        coordinates.lat, coordinates.lng, api_key
+    );
+    fetch_address_component(&url, "administrative_area_level_1").await
+}
+
+// Look up the ZIP code for a coordinate
+pub async fn reverse_geocode_zip(coordinates: &Coordinates, api_key: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let url = format!(
+        "https://maps.googleapis.com/maps/api/geocode/json?latlng={},{}&result_type=postal_code&key={}",
+        coordinates.lat, coordinates.lng, api_key
+    );
+    fetch_address_component(&url, "postal_code").await
+}
+
+// The short name of the first result's address component of the given type
+async fn fetch_address_component(url: &str, component_type: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let response: serde_json::Value = reqwest::Client::new()
+        .get(url)
+        .send()
+        .await?
+        .json()
+        .await?;
+
+    let value = response["results"][0]["address_components"]
+        .as_array()
+        .and_then(|components| {
+            components.iter().find(|component| {
+                component["types"]
+                    .as_array()
+                    .is_some_and(|types| types.iter().any(|t| t == component_type))
+            })
+        })
+        .and_then(|component| component["short_name"].as_str())
+        .map(String::from);
+
+    Ok(value)
 }
 
 pub async fn find_health_providers(
@@ -102,22 +202,38 @@ pub async fn find_health_providers(
                     )
                 });
 
+            let lat = result["geometry"]["location"]["lat"].as_f64().unwrap();
+            let lng = result["geometry"]["location"]["lng"].as_f64().unwrap();
+
+            let place_id = result["place_id"].as_str().map(String::from);
+            let source_id = place_id.as_ref().map(|id| format!("place:{}", id));
+
             let provider = HealthProvider {
+                id: source_id.clone().unwrap_or_default(),
+                source_ids: source_id.into_iter().collect(),
+                place_id,
                 name: result["name"].as_str().unwrap_or("").to_string(),
                 address: address.to_string(),
-                distance: calculate_distance(
-                    coordinates,
-                    result["geometry"]["location"]["lat"].as_f64().unwrap(),
-                    result["geometry"]["location"]["lng"].as_f64().unwrap(),
-                ),
+                location: Some(Coordinates { lat, lng }),
+                distance: calculate_distance(coordinates, lat, lng),
                 provider_type: result["types"][0].as_str().unwrap_or("").to_string(),
                 phone: result["formatted_phone_number"]
                     .as_str()
                     .map(String::from),
                 rating: result["rating"].as_f64().map(|r| r as f32),
+                review_count: result["user_ratings_total"].as_u64().map(|n| n as u32),
                 photo_url,
                 open_now: result["opening_hours"]["open_now"].as_bool().unwrap_or(false),
                 services: services, // Include parsed services
+                networks: Vec::new(), // Nearby Search has no network information
+                accepted_insurance: Vec::new(),
+                in_network: false,
+                hours: Vec::new(),
+                telehealth: None,
+                updated_by_provider: false,
+                insurance_corrected: false,
+                community_rating: None,
+                community_review_count: 0,
             };
             providers.push(provider);
         }
@@ -126,7 +242,34 @@ pub async fn find_health_providers(
     Ok(providers)
 }
 
-fn calculate_distance(coords: &Coordinates, lat2: f64, lng2: f64) -> f64 {
+// Nearby providers from Google Places and, when configured, a Plan-Net directory,
+// merged into one record per place and registered under stable IDs
+pub async fn search_nearby(
+    pool: &SqlitePool,
+    coordinates: &Coordinates,
+    service_type: &str,
+    api_key: &str,
+) -> Result<Vec<HealthProvider>, Box<dyn Error>> {
+    let mut providers = find_health_providers(coordinates, 10000, api_key, service_type).await?;
+
+    // Add in-network providers from a FHIR Plan-Net directory when one is configured
+    if let Ok(plan_net_url) = std::env::var("PLAN_NET_BASE_URL") {
+        match find_plan_net_providers(&plan_net_url, coordinates, 10000, service_type).await {
+            Ok(plan_net_providers) => providers.extend(plan_net_providers),
+            Err(err) => eprintln!("Failed to search Plan-Net directory: {}", err),
+        }
+    }
+
+    // Combine records of the same place from different sources and give each a stable ID
+    let mut providers = provider_store::merge_duplicates(providers);
+    if let Err(err) = provider_store::register(pool, &mut providers).await {
+        eprintln!("Failed to save provider records: {}", err);
+    }
+
+    Ok(providers)
+}
+
+pub fn calculate_distance(coords: &Coordinates, lat2: f64, lng2: f64) -> f64 {
     const EARTH_RADIUS: f64 = 6371.0; // kilometers
 
     let lat1_rad = coords.lat.to_radians();
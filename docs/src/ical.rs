@@ -0,0 +1,445 @@
+// Appointments as iCalendar (RFC 5545): a `.ics` download per appointment and a
+// private subscription feed per user. Times are written in the provider's local
+// time with a VTIMEZONE built from the tz database, so calendar apps show them
+// correctly across daylight saving changes. Each event keeps its UID and carries
+// the appointment's SEQUENCE, which goes up when it's rescheduled or cancelled,
+// so subscribed calendars update the event instead of adding a new one.
+use crate::appointments::SLOT_FORMAT;
+use crate::session::current_user_id;
+use crate::sharing::new_token;
+
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::{Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::{OffsetComponents, OffsetName, Tz};
+use serde_json::json;
+use sqlx::SqlitePool;
+
+const PRODUCT_ID: &str = "-//Health Services Finder//Appointments//EN";
+
+// How far back the feed goes, in days; older appointments drop out of subscribed calendars
+const FEED_HISTORY_DAYS: i64 = 90;
+
+// Reminders before each booked appointment
+const REMINDERS: [(&str, &str); 2] = [("-P1D", "tomorrow"), ("-PT1H", "in one hour")];
+
+// Longest content line, in octets, before it's folded
+const MAX_LINE: usize = 75;
+
+struct Event {
+    id: i64,
+    provider_name: String,
+    provider_address: String,
+    starts_at: NaiveDateTime,
+    ends_at: NaiveDateTime,
+    timezone: Tz,
+    cancelled: bool,
+    sequence: i64,
+    last_modified: NaiveDateTime, // UTC
+}
+
+// Escape a text value (RFC 5545, section 3.3.11). Carriage returns are dropped so
+// CRLF line breaks come out as a single escaped newline.
+fn ical_text(value: &str) -> String {
+    value
+        .replace('\r', "")
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+        .replace('\n', "\\n")
+}
+
+fn local_time(time: &NaiveDateTime) -> String {
+    time.format("%Y%m%dT%H%M%S").to_string()
+}
+
+fn utc_time(time: &NaiveDateTime) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+// "-0500"
+fn utc_offset(offset: FixedOffset) -> String {
+    let seconds = offset.local_minus_utc();
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let minutes = seconds.abs() / 60;
+    format!("{}{:02}{:02}", sign, minutes / 60, minutes % 60)
+}
+
+// Split lines longer than 75 octets, continuing with a space (RFC 5545, section 3.1)
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > MAX_LINE {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+// UTC offset changes in `timezone` between the start of `from_year` and the end of
+// `to_year`: the instant (UTC) and the offsets before and after
+fn transitions(timezone: &Tz, from_year: i32, to_year: i32) -> Vec<(NaiveDateTime, chrono_tz::TzOffset, chrono_tz::TzOffset)> {
+    let start = NaiveDate::from_ymd_opt(from_year, 1, 1).expect("valid date").and_time(Default::default());
+    let end = NaiveDate::from_ymd_opt(to_year + 1, 1, 1).expect("valid date").and_time(Default::default());
+
+    let mut changes = Vec::new();
+    let mut time = start;
+    let mut before = timezone.offset_from_utc_datetime(&time);
+    while time < end {
+        let next = time + Duration::days(1);
+        let after = timezone.offset_from_utc_datetime(&next);
+        if after.fix() != before.fix() {
+            // Narrow the change down to the second
+            let (mut low, mut high) = (time, next);
+            while high - low > Duration::seconds(1) {
+                let middle = low + (high - low) / 2;
+                if timezone.offset_from_utc_datetime(&middle).fix() == before.fix() {
+                    low = middle;
+                } else {
+                    high = middle;
+                }
+            }
+            changes.push((high, before, after));
+        }
+        before = after;
+        time = next;
+    }
+    changes
+}
+
+// VTIMEZONE for `timezone`, covering the given years. Each change of offset is listed
+// as its own observance rather than as a recurrence rule.
+fn vtimezone(timezone: &Tz, from_year: i32, to_year: i32) -> Vec<String> {
+    let mut lines = vec!["BEGIN:VTIMEZONE".to_string(), format!("TZID:{}", timezone.name())];
+
+    // Start a year early so the offset in effect at the first event is covered
+    let changes = transitions(timezone, from_year - 1, to_year);
+    if changes.is_empty() {
+        let offset = timezone.offset_from_utc_datetime(&Utc::now().naive_utc());
+        lines.push("BEGIN:STANDARD".to_string());
+        lines.push("DTSTART:19700101T000000".to_string());
+        lines.push(format!("TZOFFSETFROM:{}", utc_offset(offset.fix())));
+        lines.push(format!("TZOFFSETTO:{}", utc_offset(offset.fix())));
+        if let Some(name) = offset.abbreviation() {
+            lines.push(format!("TZNAME:{}", name));
+        }
+        lines.push("END:STANDARD".to_string());
+    }
+    for (instant, before, after) in changes {
+        let kind = if after.dst_offset().is_zero() { "STANDARD" } else { "DAYLIGHT" };
+        lines.push(format!("BEGIN:{}", kind));
+        // The start is given in the local time in effect before the change
+        lines.push(format!("DTSTART:{}", local_time(&(instant + before.fix()))));
+        lines.push(format!("TZOFFSETFROM:{}", utc_offset(before.fix())));
+        lines.push(format!("TZOFFSETTO:{}", utc_offset(after.fix())));
+        if let Some(name) = after.abbreviation() {
+            lines.push(format!("TZNAME:{}", name));
+        }
+        lines.push(format!("END:{}", kind));
+    }
+
+    lines.push("END:VTIMEZONE".to_string());
+    lines
+}
+
+fn vevent(event: &Event, stamp: &NaiveDateTime) -> Vec<String> {
+    let tzid = event.timezone.name();
+    let mut lines = vec![
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:appointment-{}@health-services-finder", event.id),
+        format!("DTSTAMP:{}", utc_time(stamp)),
+        format!("LAST-MODIFIED:{}", utc_time(&event.last_modified)),
+        format!("SEQUENCE:{}", event.sequence),
+        format!("DTSTART;TZID={}:{}", tzid, local_time(&event.starts_at)),
+        format!("DTEND;TZID={}:{}", tzid, local_time(&event.ends_at)),
+        format!("SUMMARY:{}", ical_text(&format!("Appointment with {}", event.provider_name))),
+    ];
+    if !event.provider_address.is_empty() {
+        lines.push(format!("LOCATION:{}", ical_text(&event.provider_address)));
+    }
+    if event.cancelled {
+        lines.push("STATUS:CANCELLED".to_string());
+    } else {
+        lines.push("STATUS:CONFIRMED".to_string());
+        for (trigger, when) in REMINDERS {
+            lines.push("BEGIN:VALARM".to_string());
+            lines.push("ACTION:DISPLAY".to_string());
+            lines.push(format!("TRIGGER:{}", trigger));
+            lines.push(format!(
+                "DESCRIPTION:{}",
+                ical_text(&format!("Appointment with {} {}", event.provider_name, when))
+            ));
+            lines.push("END:VALARM".to_string());
+        }
+    }
+    lines.push("END:VEVENT".to_string());
+    lines
+}
+
+fn to_ical(events: &[Event], name: &str) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:{}", PRODUCT_ID),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        format!("X-WR-CALNAME:{}", ical_text(name)),
+    ];
+
+    // One VTIMEZONE per timezone, covering the years its events fall in
+    let mut timezones: Vec<(Tz, i32, i32)> = Vec::new();
+    for event in events {
+        let year = event.starts_at.year();
+        match timezones.iter_mut().find(|(timezone, _, _)| *timezone == event.timezone) {
+            Some((_, from, to)) => {
+                *from = (*from).min(year);
+                *to = (*to).max(year);
+            }
+            None => timezones.push((event.timezone, year, year)),
+        }
+    }
+    for (timezone, from, to) in &timezones {
+        lines.extend(vtimezone(timezone, *from, *to));
+    }
+
+    let stamp = Utc::now().naive_utc();
+    for event in events {
+        lines.extend(vevent(event, &stamp));
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold(line)).collect()
+}
+
+// The user's appointments starting on or after `since` ("YYYY-MM-DD"), or just `appointment_id`
+async fn load_events(
+    pool: &SqlitePool,
+    user_id: i64,
+    appointment_id: Option<i64>,
+    since: &str,
+) -> Result<Vec<Event>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT appointments.id AS \"id!\", starts_at, ends_at, status, sequence,
+                COALESCE(appointments.updated_at, appointments.created_at) AS \"last_modified!: String\",
+                provider_calendars.name, provider_calendars.address, provider_calendars.timezone
+         FROM appointments JOIN provider_calendars ON provider_calendars.provider_id = appointments.provider_id
+         WHERE appointments.user_id = ? AND (? IS NULL OR appointments.id = ?) AND starts_at >= ?
+         ORDER BY starts_at",
+        user_id,
+        appointment_id,
+        appointment_id,
+        since
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            Some(Event {
+                id: row.id,
+                provider_name: row.name,
+                provider_address: row.address,
+                starts_at: NaiveDateTime::parse_from_str(&row.starts_at, SLOT_FORMAT).ok()?,
+                ends_at: NaiveDateTime::parse_from_str(&row.ends_at, SLOT_FORMAT).ok()?,
+                timezone: row.timezone.parse().unwrap_or(Tz::UTC),
+                cancelled: row.status == "cancelled",
+                sequence: row.sequence,
+                last_modified: NaiveDateTime::parse_from_str(&row.last_modified, "%Y-%m-%d %H:%M:%S").ok()?,
+            })
+        })
+        .collect())
+}
+
+fn calendar_response(body: String, filename: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .append_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .body(body)
+}
+
+fn feed_url(req: &HttpRequest, token: &str) -> String {
+    let connection = req.connection_info();
+    format!("{}://{}/calendar/{}.ics", connection.scheme(), connection.host(), token)
+}
+
+// The user's feed URL, if they've created one
+pub async fn user_feed_url(req: &HttpRequest, pool: &SqlitePool, user_id: i64) -> Result<Option<String>, sqlx::Error> {
+    let token = sqlx::query_scalar!("SELECT token FROM calendar_feeds WHERE user_id = ?", user_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(token.map(|token| feed_url(req, &token)))
+}
+
+// Handler for `GET /api/appointments/{id}/ics`
+#[get("/api/appointments/{id}/ics")]
+async fn appointment_ics(req: HttpRequest, path: web::Path<i64>, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().body("User not logged in. Please log in and try again.");
+    };
+    let appointment_id = path.into_inner();
+
+    match load_events(pool.get_ref(), user_id, Some(appointment_id), "").await {
+        Ok(events) if events.is_empty() => HttpResponse::NotFound().body("Appointment not found"),
+        Ok(events) => calendar_response(
+            to_ical(&events, &format!("Appointment with {}", events[0].provider_name)),
+            &format!("appointment-{}.ics", appointment_id),
+        ),
+        Err(err) => {
+            eprintln!("Failed to load appointment {}: {}", appointment_id, err);
+            HttpResponse::InternalServerError().body("Failed to generate calendar file")
+        }
+    }
+}
+
+// Handler for `POST /api/calendar-feed` (create the feed, or replace its URL so the old one stops working)
+#[post("/api/calendar-feed")]
+async fn create_feed(req: HttpRequest, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().json(json!({
+            "status": "error",
+            "message": "User not logged in. Please log in and try again."
+        }));
+    };
+
+    let token = new_token();
+    let result = sqlx::query!(
+        "INSERT INTO calendar_feeds (user_id, token) VALUES (?, ?)
+         ON CONFLICT (user_id) DO UPDATE SET token = excluded.token, created_at = CURRENT_TIMESTAMP",
+        user_id,
+        token
+    )
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Calendar feed created.",
+            "url": feed_url(&req, &token)
+        })),
+        Err(err) => {
+            eprintln!("Failed to create calendar feed: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Failed to create calendar feed. Please try again later."
+            }))
+        }
+    }
+}
+
+// Handler for `DELETE /api/calendar-feed` (turn the feed off)
+#[delete("/api/calendar-feed")]
+async fn revoke_feed(req: HttpRequest, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().json(json!({
+            "status": "error",
+            "message": "User not logged in. Please log in and try again."
+        }));
+    };
+
+    let result = sqlx::query!("DELETE FROM calendar_feeds WHERE user_id = ?", user_id)
+        .execute(pool.get_ref())
+        .await;
+
+    match result {
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Calendar feed turned off."
+        })),
+        Err(err) => {
+            eprintln!("Failed to revoke calendar feed: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Failed to turn off calendar feed. Please try again later."
+            }))
+        }
+    }
+}
+
+// Handler for the private `/calendar/{token}.ics` subscription feed
+#[get("/calendar/{token}.ics")]
+async fn calendar_feed(path: web::Path<String>, pool: web::Data<SqlitePool>) -> impl Responder {
+    let token = path.into_inner();
+
+    let user_id = sqlx::query_scalar!("SELECT user_id AS \"user_id!\" FROM calendar_feeds WHERE token = ?", token)
+        .fetch_optional(pool.get_ref())
+        .await;
+    let user_id = match user_id {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return HttpResponse::NotFound().body("Calendar feed not found"),
+        Err(err) => {
+            eprintln!("Failed to look up calendar feed: {}", err);
+            return HttpResponse::InternalServerError().body("Failed to load calendar feed");
+        }
+    };
+
+    let since = (Utc::now() - Duration::days(FEED_HISTORY_DAYS)).format("%Y-%m-%d").to_string();
+    match load_events(pool.get_ref(), user_id, None, &since).await {
+        Ok(events) => calendar_response(to_ical(&events, "Health appointments"), "appointments.ics"),
+        Err(err) => {
+            eprintln!("Failed to load calendar feed for user {}: {}", user_id, err);
+            HttpResponse::InternalServerError().body("Failed to load calendar feed")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_escaped() {
+        assert_eq!(ical_text("a\\b, c; d\ne"), "a\\\\b\\, c\\; d\\ne");
+        assert_eq!(ical_text("Suite 2\r\nFloor 3\r"), "Suite 2\\nFloor 3");
+    }
+
+    #[test]
+    fn long_lines_fold_on_character_boundaries() {
+        // 74 ASCII octets, then a 2-octet character that would end past octet 75
+        let line = format!("{}é{}", "a".repeat(74), "b".repeat(10));
+        let folded = fold(&line);
+        let lines: Vec<&str> = folded.trim_end_matches("\r\n").split("\r\n").collect();
+
+        assert_eq!(lines, vec!["a".repeat(74), format!(" é{}", "b".repeat(10))]);
+        assert!(lines.iter().all(|line| line.len() <= MAX_LINE));
+        assert_eq!(fold("short"), "short\r\n");
+    }
+
+    #[test]
+    fn new_york_changes_offset_twice_a_year() {
+        let changes = transitions(&chrono_tz::America::New_York, 2026, 2026);
+        let changes: Vec<(String, String, String)> = changes
+            .into_iter()
+            .map(|(instant, before, after)| (utc_time(&instant), utc_offset(before.fix()), utc_offset(after.fix())))
+            .collect();
+
+        assert_eq!(
+            changes,
+            vec![
+                ("20260308T070000Z".to_string(), "-0500".to_string(), "-0400".to_string()),
+                ("20261101T060000Z".to_string(), "-0400".to_string(), "-0500".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn vtimezone_lists_each_observance() {
+        let lines = vtimezone(&chrono_tz::America::New_York, 2027, 2027);
+        let observances: Vec<&str> = lines
+            .iter()
+            .filter(|line| line.starts_with("BEGIN:") && *line != "BEGIN:VTIMEZONE")
+            .map(String::as_str)
+            .collect();
+
+        // The year before the first event is included too
+        assert_eq!(observances, vec!["BEGIN:DAYLIGHT", "BEGIN:STANDARD", "BEGIN:DAYLIGHT", "BEGIN:STANDARD"]);
+        assert!(lines.contains(&"DTSTART:20260308T020000".to_string()));
+        assert!(lines.contains(&"DTSTART:20261101T020000".to_string()));
+        assert!(lines.contains(&"TZNAME:EDT".to_string()));
+    }
+}
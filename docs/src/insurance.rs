@@ -0,0 +1,261 @@
+use crate::find_providers::HealthProvider;
+use crate::plan_net::{fetch_plan_networks, PlanNetwork};
+use crate::session::{current_user_id, require_admin};
+
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+
+#[derive(Debug, Serialize)]
+pub struct InsurancePlan {
+    pub id: i64,
+    pub name: String,
+    pub payer: String,
+}
+
+// One row of a network CSV: plan_name,payer,provider_name,provider_address
+#[derive(Deserialize)]
+struct PlanProviderRow {
+    plan_name: String,
+    payer: String,
+    provider_name: String,
+    provider_address: String,
+}
+
+#[derive(Deserialize)]
+struct PlanSelection {
+    plan_id: String, // Empty when the user clears their plan
+}
+
+pub async fn list_plans(pool: &SqlitePool) -> Result<Vec<InsurancePlan>, sqlx::Error> {
+    sqlx::query_as!(
+        InsurancePlan,
+        "SELECT id, name, payer FROM insurance_plans ORDER BY payer, name"
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// The plan the user has selected on their profile, if any
+pub async fn user_plan(pool: &SqlitePool, user_id: i64) -> Result<Option<InsurancePlan>, sqlx::Error> {
+    sqlx::query_as!(
+        InsurancePlan,
+        "SELECT insurance_plans.id, insurance_plans.name, insurance_plans.payer
+         FROM users JOIN insurance_plans ON insurance_plans.id = users.insurance_plan_id
+         WHERE users.id = ?",
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+// Lowercase and strip punctuation so "Suite 100" and "suite 100." still match
+fn normalize(value: &str) -> String {
+    value
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Match on the name and the street part of the address, since Nearby Search
+// `vicinity` omits the state and ZIP that imported addresses usually include
+pub fn provider_key(name: &str, address: &str) -> (String, String) {
+    let street = address.split(',').next().unwrap_or("");
+    (normalize(name), normalize(street))
+}
+
+// Set `in_network` on every provider that participates in the given plan
+pub async fn mark_in_network(
+    pool: &SqlitePool,
+    plan: &InsurancePlan,
+    providers: &mut [HealthProvider],
+) -> Result<(), sqlx::Error> {
+    let network = sqlx::query!(
+        "SELECT provider_name, provider_address FROM plan_providers WHERE plan_id = ?",
+        plan.id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let network_keys: Vec<(String, String)> = network
+        .iter()
+        .map(|row| provider_key(&row.provider_name, &row.provider_address))
+        .collect();
+
+    for provider in providers.iter_mut() {
+        // Plan-Net results already list the plans they accept
+        let accepts_plan = provider
+            .accepted_insurance
+            .iter()
+            .any(|accepted| accepted.eq_ignore_ascii_case(&plan.name));
+
+        // The provider's own list of accepted plans replaces the imported networks
+        provider.in_network = accepts_plan
+            || (!provider.insurance_corrected
+                && network_keys.contains(&provider_key(&provider.name, &provider.address)));
+    }
+
+    Ok(())
+}
+
+// Create the plan if needed and add the given providers to its network.
+// Returns the number of new network entries.
+async fn import_plan(pool: &SqlitePool, plan: &PlanNetwork) -> Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let plan_id = sqlx::query_scalar!(
+        "INSERT INTO insurance_plans (name, payer) VALUES (?, ?)
+         ON CONFLICT (name) DO UPDATE SET payer = excluded.payer
+         RETURNING id",
+        plan.name,
+        plan.payer
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let mut added = 0;
+    for (provider_name, provider_address) in &plan.providers {
+        added += sqlx::query!(
+            "INSERT OR IGNORE INTO plan_providers (plan_id, provider_name, provider_address) VALUES (?, ?, ?)",
+            plan_id,
+            provider_name,
+            provider_address
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    tx.commit().await?;
+    Ok(added)
+}
+
+// Handler for the `/admin/insurance/import` endpoint (CSV request body)
+#[post("/admin/insurance/import")]
+async fn import_plans_csv(req: HttpRequest, body: String, pool: web::Data<SqlitePool>) -> impl Responder {
+    if let Err(response) = require_admin(&req, pool.get_ref()).await {
+        return response;
+    }
+
+    // Group rows by plan so each plan is imported in one transaction
+    let mut plans: Vec<PlanNetwork> = Vec::new();
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(body.as_bytes());
+    for (line, row) in reader.deserialize::<PlanProviderRow>().enumerate() {
+        let row = match row {
+            Ok(row) => row,
+            Err(err) => {
+                return HttpResponse::BadRequest().json(json!({
+                    "status": "error",
+                    "message": format!("Invalid CSV on row {}: {}", line + 1, err)
+                }));
+            }
+        };
+
+        let provider = (row.provider_name, row.provider_address);
+        match plans.iter_mut().find(|plan| plan.name == row.plan_name) {
+            Some(plan) => plan.providers.push(provider),
+            None => plans.push(PlanNetwork {
+                name: row.plan_name,
+                payer: row.payer,
+                providers: vec![provider],
+            }),
+        }
+    }
+
+    let mut providers_added = 0;
+    for plan in &plans {
+        match import_plan(pool.get_ref(), plan).await {
+            Ok(added) => providers_added += added,
+            Err(err) => {
+                eprintln!("Failed to import plan {}: {}", plan.name, err);
+                return HttpResponse::InternalServerError().json(json!({
+                    "status": "error",
+                    "message": format!("Failed to import plan {}.", plan.name)
+                }));
+            }
+        }
+    }
+
+    HttpResponse::Ok().json(json!({
+        "status": "success",
+        "plans": plans.len(),
+        "providers_added": providers_added
+    }))
+}
+
+// Handler for the `/admin/insurance/import/plan-net` endpoint
+#[post("/admin/insurance/import/plan-net")]
+async fn import_plans_plan_net(req: HttpRequest, pool: web::Data<SqlitePool>) -> impl Responder {
+    if let Err(response) = require_admin(&req, pool.get_ref()).await {
+        return response;
+    }
+
+    let Ok(base_url) = std::env::var("PLAN_NET_BASE_URL") else {
+        return HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": "PLAN_NET_BASE_URL is not configured."
+        }));
+    };
+
+    let plan_networks = match fetch_plan_networks(&base_url).await {
+        Ok(plan_networks) => plan_networks,
+        Err(err) => {
+            eprintln!("Failed to fetch Plan-Net networks: {}", err);
+            return HttpResponse::BadGateway().json(json!({
+                "status": "error",
+                "message": "Failed to fetch plans from the Plan-Net directory."
+            }));
+        }
+    };
+
+    let mut providers_added = 0;
+    for plan in &plan_networks {
+        match import_plan(pool.get_ref(), plan).await {
+            Ok(added) => providers_added += added,
+            Err(err) => {
+                eprintln!("Failed to import plan {}: {}", plan.name, err);
+                return HttpResponse::InternalServerError().json(json!({
+                    "status": "error",
+                    "message": format!("Failed to import plan {}.", plan.name)
+                }));
+            }
+        }
+    }
+
+    HttpResponse::Ok().json(json!({
+        "status": "success",
+        "plans": plan_networks.len(),
+        "providers_added": providers_added
+    }))
+}
+
+// Handler for the plan selector form on the profile page
+#[post("/profile/insurance")]
+async fn select_plan(
+    req: HttpRequest,
+    form: web::Form<PlanSelection>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Found().append_header(("Location", "/login")).finish();
+    };
+
+    let plan_id = form.plan_id.parse::<i64>().ok();
+    let result = sqlx::query!(
+        "UPDATE users SET insurance_plan_id = ? WHERE id = ?",
+        plan_id,
+        user_id
+    )
+    .execute(pool.get_ref())
+    .await;
+
+    if let Err(err) = result {
+        eprintln!("Failed to update insurance plan: {}", err);
+        return HttpResponse::InternalServerError().body("Failed to save insurance plan");
+    }
+
+    HttpResponse::Found().append_header(("Location", "/profile")).finish()
+}
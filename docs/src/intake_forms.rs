@@ -0,0 +1,1025 @@
+// Pre-visit intake questionnaires. Admins build one form per provider out of typed
+// questions, each optionally shown only when an earlier question has a given answer,
+// and patients fill it in for their upcoming appointments. Forms are stored as JSON
+// and checked on save; answers are checked against the form on submission, with
+// answers to questions that aren't shown dropped. Each response keeps a copy of the
+// questions it answered so later edits to the form don't change it, and responses
+// can be exported as FHIR R4 QuestionnaireResponse resources.
+use crate::appointments::{display_date, display_time, load_calendar, now_in, SLOT_FORMAT};
+use crate::session::{current_user_id, require_admin};
+use crate::video_visits::is_clinician;
+
+use actix_web::http::StatusCode;
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use chrono::{NaiveDate, NaiveDateTime};
+use chrono_tz::Tz;
+use handlebars::Handlebars;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sqlx::SqlitePool;
+use std::collections::{BTreeMap, HashSet};
+
+const MAX_TITLE_CHARS: usize = 200;
+const MAX_QUESTIONS: usize = 100;
+const MAX_QUESTION_CHARS: usize = 500;
+const MAX_QUESTION_ID_CHARS: usize = 64;
+const MAX_OPTIONS: usize = 50;
+const MAX_ANSWER_CHARS: usize = 5000;
+
+const FHIR_CONTENT_TYPE: &str = "application/fhir+json";
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum QuestionType {
+    String, // One line
+    Text,   // Several lines
+    Boolean,
+    Integer,
+    Date, // "YYYY-MM-DD"
+    Choice,
+}
+
+// Show a question only when an earlier question's answer is (or, for multiple choice, includes) `equals`
+#[derive(Clone, Serialize, Deserialize)]
+struct Condition {
+    question: String,
+    equals: Value,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Question {
+    id: String, // Key of the answer, and the FHIR linkId
+    text: String,
+    #[serde(rename = "type")]
+    kind: QuestionType,
+    #[serde(default)]
+    required: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    options: Vec<String>, // Choice questions only
+    #[serde(default)]
+    multiple: bool, // Choice questions only: allow picking several
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min: Option<i64>, // Integer questions only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    enable_when: Option<Condition>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FormDefinition {
+    title: String,
+    questions: Vec<Question>,
+}
+
+#[derive(Deserialize)]
+struct Submission {
+    answers: Map<String, Value>,
+}
+
+// The appointment an intake response is for, with its provider's form
+struct IntakeAppointment {
+    user_id: i64,
+    provider_name: String,
+    starts_at: String,
+    timezone: String,
+    status: String,
+    form_id: Option<i64>,
+    form_title: Option<String>,
+    form_version: Option<i64>,
+    definition: Option<String>,
+}
+
+// A saved response, as needed for export
+struct StoredResponse {
+    id: i64,
+    appointment_id: i64,
+    form_id: i64,
+    form_version: i64,
+    questions: String,
+    answers: String,
+    username: String,
+    submitted_at: String, // SQLite's UTC "YYYY-MM-DD HH:MM:SS"
+}
+
+fn error(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+fn not_logged_in() -> HttpResponse {
+    error(StatusCode::UNAUTHORIZED, "User not logged in. Please log in and try again.")
+}
+
+fn valid_question_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_QUESTION_ID_CHARS
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+// Check a form as submitted by the form builder
+fn validate_definition(form: &FormDefinition) -> Result<(), String> {
+    let title = form.title.trim();
+    if title.is_empty() || title.chars().count() > MAX_TITLE_CHARS {
+        return Err("Form titles must be 1 to 200 characters long.".to_string());
+    }
+    if form.questions.is_empty() || form.questions.len() > MAX_QUESTIONS {
+        return Err(format!("Forms need 1 to {} questions.", MAX_QUESTIONS));
+    }
+
+    let mut earlier: Vec<&Question> = Vec::new();
+    for (index, question) in form.questions.iter().enumerate() {
+        let number = index + 1;
+        if !valid_question_id(&question.id) {
+            return Err(format!(
+                "Question {} needs an ID of letters, digits, '-' or '_' (up to {} characters).",
+                number, MAX_QUESTION_ID_CHARS
+            ));
+        }
+        if earlier.iter().any(|other| other.id == question.id) {
+            return Err(format!("Question {} has the same ID as another question ({}).", number, question.id));
+        }
+        let text = question.text.trim();
+        if text.is_empty() || text.chars().count() > MAX_QUESTION_CHARS {
+            return Err(format!("Question {} needs text of 1 to {} characters.", number, MAX_QUESTION_CHARS));
+        }
+
+        if question.kind == QuestionType::Choice {
+            let distinct: HashSet<&str> = question.options.iter().map(|option| option.trim()).collect();
+            if question.options.is_empty() || question.options.len() > MAX_OPTIONS {
+                return Err(format!("Question {} needs 1 to {} options.", number, MAX_OPTIONS));
+            }
+            if distinct.len() != question.options.len() || distinct.contains("") {
+                return Err(format!("Question {} has blank or repeated options.", number));
+            }
+        } else if !question.options.is_empty() || question.multiple {
+            return Err(format!("Only choice questions can have options (question {}).", number));
+        }
+        if question.kind != QuestionType::Integer && (question.min.is_some() || question.max.is_some()) {
+            return Err(format!("Only number questions can have a minimum or maximum (question {}).", number));
+        }
+        if let (Some(min), Some(max)) = (question.min, question.max) {
+            if min > max {
+                return Err(format!("Question {} has a minimum above its maximum.", number));
+            }
+        }
+
+        if let Some(condition) = &question.enable_when {
+            let Some(depends_on) = earlier.iter().find(|other| other.id == condition.question) else {
+                return Err(format!("Question {} can only depend on a question that comes before it.", number));
+            };
+            // Multiple choice conditions name one option that has to be among those picked
+            let possible = if depends_on.multiple {
+                depends_on.options.iter().any(|option| condition.equals == json!(option))
+            } else {
+                check_answer(depends_on, &condition.equals).is_ok_and(|answer| answer == condition.equals)
+            };
+            if !possible {
+                return Err(format!(
+                    "Question {} depends on an answer that question {} can't have.",
+                    number, depends_on.id
+                ));
+            }
+        }
+        earlier.push(question);
+    }
+    Ok(())
+}
+
+fn is_blank(answer: &Value) -> bool {
+    match answer {
+        Value::Null => true,
+        Value::String(text) => text.trim().is_empty(),
+        Value::Array(items) => items.is_empty(),
+        _ => false,
+    }
+}
+
+fn matches(answer: &Value, equals: &Value) -> bool {
+    answer == equals || answer.as_array().is_some_and(|items| items.contains(equals))
+}
+
+// An answer to one question in its stored form, or why it doesn't fit the question
+fn check_answer(question: &Question, answer: &Value) -> Result<Value, String> {
+    match question.kind {
+        QuestionType::String | QuestionType::Text => match answer.as_str().map(str::trim) {
+            Some(text) if text.chars().count() <= MAX_ANSWER_CHARS => Ok(json!(text)),
+            Some(_) => Err(format!("Answers can be up to {} characters.", MAX_ANSWER_CHARS)),
+            None => Err("Expected text.".to_string()),
+        },
+        QuestionType::Boolean => match answer {
+            Value::Bool(_) => Ok(answer.clone()),
+            _ => Err("Expected yes or no.".to_string()),
+        },
+        QuestionType::Integer => {
+            let number = answer
+                .as_i64()
+                .or_else(|| answer.as_str().and_then(|text| text.trim().parse().ok()))
+                .ok_or_else(|| "Expected a whole number.".to_string())?;
+            if question.min.is_some_and(|min| number < min) || question.max.is_some_and(|max| number > max) {
+                return Err(match (question.min, question.max) {
+                    (Some(min), Some(max)) => format!("Expected a number from {} to {}.", min, max),
+                    (Some(min), None) => format!("Expected a number of at least {}.", min),
+                    _ => format!("Expected a number of at most {}.", question.max.unwrap_or_default()),
+                });
+            }
+            Ok(json!(number))
+        }
+        QuestionType::Date => answer
+            .as_str()
+            .and_then(|text| NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d").ok())
+            .map(|date| json!(date.format("%Y-%m-%d").to_string()))
+            .ok_or_else(|| "Expected a date.".to_string()),
+        QuestionType::Choice => {
+            let picked: Vec<&str> = match answer {
+                Value::String(option) if !question.multiple => vec![option.as_str()],
+                Value::Array(options) if question.multiple => {
+                    options.iter().map(|option| option.as_str().unwrap_or_default()).collect()
+                }
+                _ => return Err("Expected one of the listed options.".to_string()),
+            };
+            if !picked.iter().all(|option| question.options.iter().any(|allowed| allowed == option)) {
+                return Err("Expected one of the listed options.".to_string());
+            }
+            Ok(answer.clone())
+        }
+    }
+}
+
+// Answers to the questions that are shown, or an error message per question
+fn validate_answers(
+    questions: &[Question],
+    answers: &Map<String, Value>,
+) -> Result<Map<String, Value>, BTreeMap<String, String>> {
+    let mut kept = Map::new();
+    let mut errors = BTreeMap::new();
+
+    for question in questions {
+        // Conditions only look at earlier questions, so their answers are already settled
+        if let Some(condition) = &question.enable_when {
+            if !kept.get(&condition.question).is_some_and(|answer| matches(answer, &condition.equals)) {
+                continue;
+            }
+        }
+        match answers.get(&question.id).filter(|answer| !is_blank(answer)) {
+            Some(answer) => match check_answer(question, answer) {
+                Ok(answer) => {
+                    kept.insert(question.id.clone(), answer);
+                }
+                Err(message) => {
+                    errors.insert(question.id.clone(), message);
+                }
+            },
+            None if question.required => {
+                errors.insert(question.id.clone(), "This question is required.".to_string());
+            }
+            None => {}
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(kept)
+    } else {
+        Err(errors)
+    }
+}
+
+// A question as the intake template renders it, with any earlier answer filled in
+fn question_view(question: &Question, answer: Option<&Value>) -> Value {
+    let options: Vec<Value> = question
+        .options
+        .iter()
+        .map(|option| {
+            let selected = answer.is_some_and(|answer| matches(answer, &json!(option)));
+            json!({ "value": option, "selected": selected })
+        })
+        .collect();
+    let value = match answer {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Number(number)) => number.to_string(),
+        _ => String::new(),
+    };
+
+    json!({
+        "id": question.id,
+        "text": question.text,
+        "type": question.kind,
+        "required": question.required,
+        "options": options,
+        "multiple": question.multiple,
+        // As text, so a limit of 0 still counts as set in the template
+        "min": question.min.map(|min| min.to_string()),
+        "max": question.max.map(|max| max.to_string()),
+        "value": value,
+        "yes": answer == Some(&Value::Bool(true)),
+        "no": answer == Some(&Value::Bool(false)),
+        "enable_question": question.enable_when.as_ref().map(|condition| condition.question.clone()),
+        "enable_equals": question.enable_when.as_ref().map(|condition| condition.equals.to_string()),
+    })
+}
+
+// FHIR answer values for one stored answer
+fn fhir_answers(question: &Question, answer: &Value) -> Vec<Value> {
+    match question.kind {
+        QuestionType::String | QuestionType::Text => vec![json!({ "valueString": answer })],
+        QuestionType::Boolean => vec![json!({ "valueBoolean": answer })],
+        QuestionType::Integer => vec![json!({ "valueInteger": answer })],
+        QuestionType::Date => vec![json!({ "valueDate": answer })],
+        QuestionType::Choice => {
+            let picked = match answer {
+                Value::Array(options) => options.clone(),
+                option => vec![option.clone()],
+            };
+            picked.into_iter().map(|option| json!({ "valueCoding": { "display": option } })).collect()
+        }
+    }
+}
+
+// One response as a FHIR R4 QuestionnaireResponse
+fn questionnaire_response(response: &StoredResponse) -> Result<Value, serde_json::Error> {
+    let questions: Vec<Question> = serde_json::from_str(&response.questions)?;
+    let answers: Map<String, Value> = serde_json::from_str(&response.answers)?;
+    let items: Vec<Value> = questions
+        .iter()
+        .filter_map(|question| {
+            let answer = answers.get(&question.id)?;
+            Some(json!({
+                "linkId": question.id,
+                "text": question.text,
+                "answer": fhir_answers(question, answer)
+            }))
+        })
+        .collect();
+    let authored = NaiveDateTime::parse_from_str(&response.submitted_at, "%Y-%m-%d %H:%M:%S")
+        .map(|time| time.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+        .unwrap_or_else(|_| response.submitted_at.clone());
+
+    Ok(json!({
+        "resourceType": "QuestionnaireResponse",
+        "id": response.id.to_string(),
+        "identifier": {
+            "system": "urn:health-services-finder:appointment",
+            "value": response.appointment_id.to_string()
+        },
+        "questionnaire": format!("urn:health-services-finder:intake-form:{}|{}", response.form_id, response.form_version),
+        "status": "completed",
+        "subject": { "display": response.username },
+        "authored": authored,
+        "item": items
+    }))
+}
+
+async fn load_appointment(pool: &SqlitePool, appointment_id: i64) -> Result<Option<IntakeAppointment>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT appointments.user_id, appointments.starts_at, appointments.status,
+                provider_calendars.name, provider_calendars.timezone, intake_forms.id AS form_id,
+                intake_forms.title AS form_title, intake_forms.version AS form_version, intake_forms.definition
+         FROM appointments
+         JOIN provider_calendars ON provider_calendars.provider_id = appointments.provider_id
+         LEFT JOIN intake_forms ON intake_forms.provider_id = appointments.provider_id
+         WHERE appointments.id = ?",
+        appointment_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| IntakeAppointment {
+        user_id: row.user_id,
+        provider_name: row.name,
+        starts_at: row.starts_at,
+        timezone: row.timezone,
+        status: row.status,
+        form_id: row.form_id,
+        form_title: row.form_title,
+        form_version: row.form_version,
+        definition: row.definition,
+    }))
+}
+
+// Whether the appointment hasn't started yet, in the provider's time
+fn is_upcoming(appointment: &IntakeAppointment) -> bool {
+    let timezone: Tz = appointment.timezone.parse().unwrap_or(Tz::UTC);
+    appointment.status == "booked"
+        && NaiveDateTime::parse_from_str(&appointment.starts_at, SLOT_FORMAT)
+            .is_ok_and(|start| start > now_in(&timezone))
+}
+
+// Handler for the intake form page of an appointment
+#[get("/appointments/{id}/intake")]
+async fn intake_page(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    pool: web::Data<SqlitePool>,
+    hb: web::Data<Handlebars<'_>>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Found().append_header(("Location", "/login")).finish();
+    };
+    let appointment_id = path.into_inner();
+
+    let result = async {
+        let Some(appointment) = load_appointment(pool.get_ref(), appointment_id).await? else {
+            return Ok(None);
+        };
+        let answers = sqlx::query_scalar!(
+            "SELECT answers FROM intake_responses WHERE appointment_id = ?",
+            appointment_id
+        )
+        .fetch_optional(pool.get_ref())
+        .await?;
+        Ok::<_, sqlx::Error>(Some((appointment, answers)))
+    }
+    .await;
+
+    let (appointment, answers) = match result {
+        Ok(Some((appointment, answers))) if appointment.user_id == user_id => (appointment, answers),
+        Ok(_) => return HttpResponse::NotFound().body("Appointment not found"),
+        Err(err) => {
+            eprintln!("Failed to load intake form for appointment {}: {}", appointment_id, err);
+            return HttpResponse::InternalServerError().body("Failed to load intake form");
+        }
+    };
+    let Some(definition) = appointment
+        .definition
+        .as_deref()
+        .and_then(|definition| serde_json::from_str::<Vec<Question>>(definition).ok())
+    else {
+        return HttpResponse::NotFound().body("This provider doesn't have an intake form");
+    };
+
+    let submitted = answers.is_some();
+    let answers: Map<String, Value> = answers
+        .and_then(|answers| serde_json::from_str(&answers).ok())
+        .unwrap_or_default();
+    let questions: Vec<Value> = definition
+        .iter()
+        .map(|question| question_view(question, answers.get(&question.id)))
+        .collect();
+    let when = NaiveDateTime::parse_from_str(&appointment.starts_at, SLOT_FORMAT)
+        .map(|start| format!("{} at {}", display_date(&start), display_time(&start)))
+        .unwrap_or(appointment.starts_at.clone());
+
+    let data = json!({
+        "appointment_id": appointment_id,
+        "title": appointment.form_title,
+        "provider_name": appointment.provider_name,
+        "when": when,
+        "timezone": appointment.timezone,
+        "questions": questions,
+        "submitted": submitted,
+        "editable": is_upcoming(&appointment)
+    });
+    let body = hb.render("intake", &data).unwrap_or_else(|_| "Template error".to_string());
+    HttpResponse::Ok().body(body)
+}
+
+// Handler for `POST /api/appointments/{id}/intake` (submit or update answers before the visit)
+#[post("/api/appointments/{id}/intake")]
+async fn submit_intake(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: web::Json<Submission>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let appointment_id = path.into_inner();
+
+    let appointment = match load_appointment(pool.get_ref(), appointment_id).await {
+        Ok(Some(appointment)) if appointment.user_id == user_id => appointment,
+        Ok(_) => return error(StatusCode::NOT_FOUND, "Appointment not found."),
+        Err(err) => {
+            eprintln!("Failed to load appointment {}: {}", appointment_id, err);
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to save your answers. Please try again later.");
+        }
+    };
+    let (Some(form_id), Some(form_version), Some(definition)) =
+        (appointment.form_id, appointment.form_version, appointment.definition.as_deref())
+    else {
+        return error(StatusCode::NOT_FOUND, "This provider doesn't have an intake form.");
+    };
+    if !is_upcoming(&appointment) {
+        return error(StatusCode::CONFLICT, "Intake forms can only be changed before the appointment.");
+    }
+    let questions: Vec<Question> = match serde_json::from_str(definition) {
+        Ok(questions) => questions,
+        Err(err) => {
+            eprintln!("Intake form {} is unreadable: {}", form_id, err);
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to save your answers. Please try again later.");
+        }
+    };
+
+    let answers = match validate_answers(&questions, &body.answers) {
+        Ok(answers) => Value::Object(answers).to_string(),
+        Err(errors) => {
+            return HttpResponse::BadRequest().json(json!({
+                "status": "error",
+                "message": "Some answers need attention.",
+                "errors": errors
+            }))
+        }
+    };
+
+    let result = sqlx::query!(
+        "INSERT INTO intake_responses (appointment_id, form_id, form_version, questions, answers)
+         VALUES (?, ?, ?, ?, ?)
+         ON CONFLICT (appointment_id) DO UPDATE SET form_id = excluded.form_id, form_version = excluded.form_version,
+             questions = excluded.questions, answers = excluded.answers, updated_at = CURRENT_TIMESTAMP",
+        appointment_id,
+        form_id,
+        form_version,
+        definition,
+        answers
+    )
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": format!("Thanks! {} will see your answers before your visit.", appointment.provider_name)
+        })),
+        Err(err) => {
+            eprintln!("Failed to save intake response for appointment {}: {}", appointment_id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to save your answers. Please try again later.")
+        }
+    }
+}
+
+// Handler for `GET /api/appointments/{id}/intake/fhir`, for the patient, the provider's clinicians and admins
+#[get("/api/appointments/{id}/intake/fhir")]
+async fn export_response(req: HttpRequest, path: web::Path<i64>, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let appointment_id = path.into_inner();
+
+    let row = sqlx::query!(
+        "SELECT intake_responses.id AS \"id!\", form_id, form_version, questions, answers,
+                submitted_at AS \"submitted_at: String\", appointments.user_id, appointments.provider_id,
+                users.username
+         FROM intake_responses
+         JOIN appointments ON appointments.id = intake_responses.appointment_id
+         JOIN users ON users.id = appointments.user_id
+         WHERE intake_responses.appointment_id = ?",
+        appointment_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await;
+    let row = match row {
+        Ok(Some(row)) => row,
+        Ok(None) => return error(StatusCode::NOT_FOUND, "No intake response for this appointment."),
+        Err(err) => {
+            eprintln!("Failed to load intake response for appointment {}: {}", appointment_id, err);
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to export intake response.");
+        }
+    };
+
+    if row.user_id != user_id {
+        match is_clinician(pool.get_ref(), &row.provider_id, user_id).await {
+            Ok(true) => {}
+            Ok(false) => {
+                if let Err(response) = require_admin(&req, pool.get_ref()).await {
+                    return response;
+                }
+            }
+            Err(err) => {
+                eprintln!("Failed to check clinician for {}: {}", row.provider_id, err);
+                return error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to export intake response.");
+            }
+        }
+    }
+
+    let response = StoredResponse {
+        id: row.id,
+        appointment_id,
+        form_id: row.form_id,
+        form_version: row.form_version,
+        questions: row.questions,
+        answers: row.answers,
+        username: row.username,
+        submitted_at: row.submitted_at,
+    };
+    match questionnaire_response(&response) {
+        Ok(resource) => HttpResponse::Ok().content_type(FHIR_CONTENT_TYPE).json(resource),
+        Err(err) => {
+            eprintln!("Intake response {} is unreadable: {}", response.id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to export intake response.")
+        }
+    }
+}
+
+// Handler for the admin list of intake forms
+#[get("/admin/intake-forms")]
+async fn forms_page(req: HttpRequest, pool: web::Data<SqlitePool>, hb: web::Data<Handlebars<'_>>) -> impl Responder {
+    if let Err(response) = require_admin(&req, pool.get_ref()).await {
+        return response;
+    }
+
+    let rows = sqlx::query!(
+        "SELECT intake_forms.provider_id, intake_forms.title, intake_forms.version,
+                intake_forms.updated_at AS \"updated_at: String\", provider_calendars.name AS \"provider_name?\",
+                (SELECT COUNT(*) FROM intake_responses WHERE intake_responses.form_id = intake_forms.id)
+                    AS \"responses!: i64\"
+         FROM intake_forms
+         LEFT JOIN provider_calendars ON provider_calendars.provider_id = intake_forms.provider_id
+         ORDER BY intake_forms.updated_at DESC"
+    )
+    .fetch_all(pool.get_ref())
+    .await;
+
+    let mut data = Map::new();
+    match rows {
+        Ok(rows) => {
+            let forms: Vec<Value> = rows
+                .into_iter()
+                .map(|row| {
+                    json!({
+                        "provider_id": row.provider_id,
+                        "provider_name": row.provider_name,
+                        "title": row.title,
+                        "version": row.version,
+                        "updated_at": row.updated_at,
+                        "responses": row.responses
+                    })
+                })
+                .collect();
+            data.insert("forms".to_string(), json!(forms));
+        }
+        Err(err) => {
+            eprintln!("Failed to list intake forms: {}", err);
+            data.insert("error".to_string(), json!("Could not fetch intake forms"));
+        }
+    }
+
+    let body = hb.render("intake_forms", &data).unwrap_or_else(|_| "Template error".to_string());
+    HttpResponse::Ok().body(body)
+}
+
+// Handler for the form builder page of a provider's intake form
+#[get("/admin/intake-forms/{provider_id}")]
+async fn builder_page(
+    req: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+    hb: web::Data<Handlebars<'_>>,
+) -> impl Responder {
+    if let Err(response) = require_admin(&req, pool.get_ref()).await {
+        return response;
+    }
+    let provider_id = path.into_inner();
+
+    let result = async {
+        let Some(calendar) = load_calendar(pool.get_ref(), &provider_id).await? else {
+            return Ok(None);
+        };
+        let form = sqlx::query!(
+            "SELECT title, definition, version FROM intake_forms WHERE provider_id = ?",
+            calendar.provider_id
+        )
+        .fetch_optional(pool.get_ref())
+        .await?;
+        Ok::<_, sqlx::Error>(Some((calendar, form)))
+    }
+    .await;
+
+    let (calendar, form) = match result {
+        Ok(Some(found)) => found,
+        Ok(None) => return HttpResponse::NotFound().body("Only providers with an appointment schedule can have intake forms"),
+        Err(err) => {
+            eprintln!("Failed to load intake form for {}: {}", provider_id, err);
+            return HttpResponse::InternalServerError().body("Failed to load intake form");
+        }
+    };
+
+    let definition = match &form {
+        Some(form) => json!({
+            "title": form.title,
+            "questions": serde_json::from_str::<Value>(&form.definition).unwrap_or(json!([]))
+        }),
+        None => json!({ "title": "Before your visit", "questions": [] }),
+    };
+    let data = json!({
+        "provider_id": calendar.provider_id,
+        "provider_name": calendar.name,
+        "exists": form.is_some(),
+        "version": form.as_ref().map(|form| form.version),
+        "definition": definition.to_string()
+    });
+    let body = hb.render("intake_form_builder", &data).unwrap_or_else(|_| "Template error".to_string());
+    HttpResponse::Ok().body(body)
+}
+
+// Handler for `PUT /admin/intake-forms/{provider_id}` (create or replace a provider's form)
+#[put("/admin/intake-forms/{provider_id}")]
+async fn save_form(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<FormDefinition>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    if let Err(response) = require_admin(&req, pool.get_ref()).await {
+        return response;
+    }
+    let provider_id = path.into_inner();
+    if let Err(message) = validate_definition(&body) {
+        return error(StatusCode::BAD_REQUEST, &message);
+    }
+    let title = body.title.trim();
+    let definition = serde_json::to_string(&body.questions).expect("questions serialize");
+
+    let result = async {
+        let Some(calendar) = load_calendar(pool.get_ref(), &provider_id).await? else {
+            return Ok(None);
+        };
+        let version = sqlx::query_scalar!(
+            "INSERT INTO intake_forms (provider_id, title, definition) VALUES (?, ?, ?)
+             ON CONFLICT (provider_id) DO UPDATE SET title = excluded.title, definition = excluded.definition,
+                 version = intake_forms.version + 1, updated_at = CURRENT_TIMESTAMP
+             RETURNING version",
+            calendar.provider_id,
+            title,
+            definition
+        )
+        .fetch_one(pool.get_ref())
+        .await?;
+        Ok::<_, sqlx::Error>(Some(version))
+    }
+    .await;
+
+    match result {
+        Ok(Some(version)) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": format!("Intake form saved (version {}).", version),
+            "version": version
+        })),
+        Ok(None) => error(StatusCode::NOT_FOUND, "Only providers with an appointment schedule can have intake forms."),
+        Err(err) => {
+            eprintln!("Failed to save intake form for {}: {}", provider_id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to save intake form.")
+        }
+    }
+}
+
+// Handler for `DELETE /admin/intake-forms/{provider_id}` (removes the form and its responses)
+#[delete("/admin/intake-forms/{provider_id}")]
+async fn delete_form(req: HttpRequest, path: web::Path<String>, pool: web::Data<SqlitePool>) -> impl Responder {
+    if let Err(response) = require_admin(&req, pool.get_ref()).await {
+        return response;
+    }
+    let provider_id = path.into_inner();
+
+    let result = async {
+        let Some(calendar) = load_calendar(pool.get_ref(), &provider_id).await? else {
+            return Ok(0);
+        };
+        let deleted = sqlx::query!("DELETE FROM intake_forms WHERE provider_id = ?", calendar.provider_id)
+            .execute(pool.get_ref())
+            .await?;
+        Ok::<_, sqlx::Error>(deleted.rows_affected())
+    }
+    .await;
+
+    match result {
+        Ok(1) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Intake form deleted."
+        })),
+        Ok(_) => error(StatusCode::NOT_FOUND, "No intake form for this provider."),
+        Err(err) => {
+            eprintln!("Failed to delete intake form for {}: {}", provider_id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete intake form.")
+        }
+    }
+}
+
+// Handler for `GET /admin/intake-forms/{provider_id}/responses` (all responses as a FHIR Bundle)
+#[get("/admin/intake-forms/{provider_id}/responses")]
+async fn export_responses(req: HttpRequest, path: web::Path<String>, pool: web::Data<SqlitePool>) -> impl Responder {
+    if let Err(response) = require_admin(&req, pool.get_ref()).await {
+        return response;
+    }
+    let provider_id = path.into_inner();
+
+    let rows = async {
+        let Some(calendar) = load_calendar(pool.get_ref(), &provider_id).await? else {
+            return Ok(None);
+        };
+        let rows = sqlx::query!(
+            "SELECT intake_responses.id AS \"id!\", appointment_id, form_id, form_version, questions, answers,
+                    submitted_at AS \"submitted_at: String\", users.username
+             FROM intake_responses
+             JOIN intake_forms ON intake_forms.id = intake_responses.form_id
+             JOIN appointments ON appointments.id = intake_responses.appointment_id
+             JOIN users ON users.id = appointments.user_id
+             WHERE intake_forms.provider_id = ?
+             ORDER BY intake_responses.id",
+            calendar.provider_id
+        )
+        .fetch_all(pool.get_ref())
+        .await?;
+        Ok::<_, sqlx::Error>(Some(rows))
+    }
+    .await;
+    let rows = match rows {
+        Ok(Some(rows)) => rows,
+        Ok(None) => return error(StatusCode::NOT_FOUND, "No intake form for this provider."),
+        Err(err) => {
+            eprintln!("Failed to load intake responses for {}: {}", provider_id, err);
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to export intake responses.");
+        }
+    };
+
+    let entries: Vec<Value> = rows
+        .into_iter()
+        .map(|row| StoredResponse {
+            id: row.id,
+            appointment_id: row.appointment_id,
+            form_id: row.form_id,
+            form_version: row.form_version,
+            questions: row.questions,
+            answers: row.answers,
+            username: row.username,
+            submitted_at: row.submitted_at,
+        })
+        .filter_map(|response| {
+            questionnaire_response(&response)
+                .map_err(|err| eprintln!("Intake response {} is unreadable: {}", response.id, err))
+                .ok()
+        })
+        .map(|resource| json!({ "resource": resource }))
+        .collect();
+
+    HttpResponse::Ok()
+        .content_type(FHIR_CONTENT_TYPE)
+        .append_header(("Content-Disposition", "attachment; filename=\"intake-responses.json\""))
+        .json(json!({
+            "resourceType": "Bundle",
+            "type": "collection",
+            "entry": entries
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form() -> FormDefinition {
+        serde_json::from_value(json!({
+            "title": "Before your visit",
+            "questions": [
+                { "id": "symptoms", "text": "Symptoms", "type": "choice", "required": true,
+                  "options": ["Cough", "Fever", "Rash"], "multiple": true },
+                { "id": "fever_days", "text": "Days with a fever", "type": "integer", "required": true,
+                  "min": 1, "max": 30, "enable_when": { "question": "symptoms", "equals": "Fever" } },
+                { "id": "meds", "text": "Taking any medicines?", "type": "boolean" },
+                { "id": "meds_list", "text": "Which ones?", "type": "text", "required": true,
+                  "enable_when": { "question": "meds", "equals": true } },
+                { "id": "born", "text": "Date of birth", "type": "date" }
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn answers(value: Value) -> Map<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn question(id: &str) -> Question {
+        form().questions.into_iter().find(|question| question.id == id).unwrap()
+    }
+
+    #[test]
+    fn valid_forms_pass() {
+        validate_definition(&form()).unwrap();
+    }
+
+    // A change that breaks the form, and what the error says about it
+    type Breakage = (fn(&mut FormDefinition), &'static str);
+
+    #[test]
+    fn bad_forms_are_rejected() {
+        let cases: [Breakage; 7] = [
+            (|form| form.title = " ".to_string(), "titles"),
+            (|form| form.questions[2].id = "symptoms".to_string(), "same ID"),
+            (|form| form.questions[2].id = "has space".to_string(), "needs an ID"),
+            (|form| form.questions[0].options.push("Cough".to_string()), "repeated options"),
+            (|form| form.questions[2].min = Some(1), "Only number questions"),
+            (|form| form.questions[1].min = Some(40), "minimum above its maximum"),
+            (|form| form.questions.swap(0, 1), "comes before it"),
+        ];
+        for (change, message) in cases {
+            let mut form = form();
+            change(&mut form);
+            let err = validate_definition(&form).unwrap_err();
+            assert!(err.contains(message), "{:?} doesn't mention {:?}", err, message);
+        }
+    }
+
+    #[test]
+    fn conditions_must_be_answers_the_question_can_have() {
+        let mut choice = form();
+        choice.questions[1].enable_when.as_mut().unwrap().equals = json!("Headache");
+        assert!(validate_definition(&choice).unwrap_err().contains("can't have"));
+
+        let mut boolean = form();
+        boolean.questions[3].enable_when.as_mut().unwrap().equals = json!("yes");
+        assert!(validate_definition(&boolean).unwrap_err().contains("can't have"));
+    }
+
+    #[test]
+    fn integer_answers_respect_min_and_max() {
+        let question = question("fever_days");
+        assert_eq!(check_answer(&question, &json!(3)), Ok(json!(3)));
+        assert_eq!(check_answer(&question, &json!(" 30 ")), Ok(json!(30)));
+        assert_eq!(check_answer(&question, &json!(0)), Err("Expected a number from 1 to 30.".to_string()));
+        assert_eq!(check_answer(&question, &json!(31)), Err("Expected a number from 1 to 30.".to_string()));
+        assert!(check_answer(&question, &json!(2.5)).is_err());
+        assert!(check_answer(&question, &json!("three")).is_err());
+    }
+
+    #[test]
+    fn answers_are_checked_against_their_type() {
+        assert_eq!(check_answer(&question("meds_list"), &json!("  Aspirin ")), Ok(json!("Aspirin")));
+        assert!(check_answer(&question("meds_list"), &json!(5)).is_err());
+        assert!(check_answer(&question("meds"), &json!("yes")).is_err());
+        assert_eq!(check_answer(&question("born"), &json!("1990-02-03")), Ok(json!("1990-02-03")));
+        assert!(check_answer(&question("born"), &json!("1990-02-30")).is_err());
+
+        let symptoms = question("symptoms");
+        assert_eq!(check_answer(&symptoms, &json!(["Cough", "Rash"])), Ok(json!(["Cough", "Rash"])));
+        assert!(check_answer(&symptoms, &json!(["Cough", "Headache"])).is_err());
+        // Multiple choice answers are lists
+        assert!(check_answer(&symptoms, &json!("Cough")).is_err());
+    }
+
+    #[test]
+    fn hidden_questions_are_dropped() {
+        let questions = form().questions;
+        let kept = validate_answers(
+            &questions,
+            &answers(json!({ "symptoms": ["Cough"], "fever_days": 4, "meds": false, "meds_list": "Aspirin" })),
+        )
+        .unwrap();
+        assert_eq!(Value::Object(kept), json!({ "symptoms": ["Cough"], "meds": false }));
+    }
+
+    #[test]
+    fn shown_questions_are_kept_and_checked() {
+        let questions = form().questions;
+        let kept = validate_answers(
+            &questions,
+            &answers(json!({ "symptoms": ["Fever", "Rash"], "fever_days": "2", "meds": true, "meds_list": "Aspirin" })),
+        )
+        .unwrap();
+        assert_eq!(kept["fever_days"], json!(2));
+        assert_eq!(kept["meds_list"], json!("Aspirin"));
+
+        let errors = validate_answers(
+            &questions,
+            &answers(json!({ "symptoms": ["Fever"], "fever_days": 45, "meds": true, "meds_list": " " })),
+        )
+        .unwrap_err();
+        assert_eq!(errors["fever_days"], "Expected a number from 1 to 30.");
+        assert_eq!(errors["meds_list"], "This question is required.");
+        assert_eq!(errors.len(), 2);
+
+        let errors = validate_answers(&questions, &answers(json!({ "symptoms": [] }))).unwrap_err();
+        assert_eq!(errors["symptoms"], "This question is required.");
+    }
+
+    #[test]
+    fn responses_export_as_fhir() {
+        let response = StoredResponse {
+            id: 7,
+            appointment_id: 3,
+            form_id: 2,
+            form_version: 4,
+            questions: serde_json::to_string(&form().questions).unwrap(),
+            answers: json!({ "symptoms": ["Cough", "Fever"], "fever_days": 2, "meds": false }).to_string(),
+            username: "pat".to_string(),
+            submitted_at: "2026-10-19 08:30:00".to_string(),
+        };
+        let resource = questionnaire_response(&response).unwrap();
+
+        assert_eq!(resource["resourceType"], "QuestionnaireResponse");
+        assert_eq!(resource["questionnaire"], "urn:health-services-finder:intake-form:2|4");
+        assert_eq!(resource["authored"], "2026-10-19T08:30:00Z");
+        assert_eq!(resource["subject"]["display"], "pat");
+        // Unanswered questions are left out
+        assert_eq!(
+            resource["item"],
+            json!([
+                { "linkId": "symptoms", "text": "Symptoms", "answer": [
+                    { "valueCoding": { "display": "Cough" } },
+                    { "valueCoding": { "display": "Fever" } }
+                ]},
+                { "linkId": "fever_days", "text": "Days with a fever", "answer": [{ "valueInteger": 2 }] },
+                { "linkId": "meds", "text": "Taking any medicines?", "answer": [{ "valueBoolean": false }] }
+            ])
+        );
+    }
+}
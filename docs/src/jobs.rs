@@ -0,0 +1,268 @@
+// Background jobs kept in SQLite so they survive restarts. A job has a kind, a JSON
+// payload and a time to run at; the worker started from `main` claims due jobs one
+// at a time and hands them to the handler for their kind. A failed job is retried
+// with a growing delay until it runs out of attempts, then left as "dead" for an
+// admin to look at and retry.
+use crate::reminders::{self, Channels};
+use crate::session::require_admin;
+
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::error::Error;
+use std::time::Duration;
+
+// How often to look for due jobs, unless JOB_POLL_INTERVAL_SECS is set
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+// Jobs run per poll, so a backlog doesn't hold the worker for long
+const BATCH_SIZE: usize = 50;
+
+// Delay before the first retry; doubled after each further failure
+const RETRY_DELAY_SECS: i64 = 60;
+
+// Jobs listed at once on the admin endpoint
+const LIST_LIMIT: i64 = 100;
+
+// Format of `run_at`, matching SQLite's CURRENT_TIMESTAMP so the two compare as text
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+struct Job {
+    id: i64,
+    kind: String,
+    payload: String,
+    attempts: i64,
+    max_attempts: i64,
+}
+
+#[derive(Serialize)]
+struct JobSummary {
+    id: i64,
+    kind: String,
+    payload: Value,
+    run_at: String,
+    status: String,
+    attempts: i64,
+    max_attempts: i64,
+    last_error: Option<String>,
+    created_at: String,
+    finished_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JobQuery {
+    status: Option<String>, // Defaults to "dead"
+}
+
+// Queue a job to run at `run_at` (UTC) as part of the caller's transaction
+pub async fn enqueue(
+    conn: &mut SqliteConnection,
+    kind: &str,
+    payload: &Value,
+    run_at: &NaiveDateTime,
+) -> Result<i64, sqlx::Error> {
+    let payload = payload.to_string();
+    let run_at = run_at.format(TIMESTAMP_FORMAT).to_string();
+    sqlx::query_scalar!(
+        "INSERT INTO jobs (kind, payload, run_at) VALUES (?, ?, ?) RETURNING id AS \"id!\"",
+        kind,
+        payload,
+        run_at
+    )
+    .fetch_one(conn)
+    .await
+}
+
+// Mark the next due job as running and count the attempt
+async fn claim(pool: &SqlitePool) -> Result<Option<Job>, sqlx::Error> {
+    let row = sqlx::query!(
+        "UPDATE jobs SET status = 'running', attempts = attempts + 1
+         WHERE id = (SELECT id FROM jobs WHERE status = 'pending' AND run_at <= CURRENT_TIMESTAMP
+                     ORDER BY run_at, id LIMIT 1)
+         RETURNING id AS \"id!\", kind, payload, attempts, max_attempts"
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| Job {
+        id: row.id,
+        kind: row.kind,
+        payload: row.payload,
+        attempts: row.attempts,
+        max_attempts: row.max_attempts,
+    }))
+}
+
+async fn run(pool: &SqlitePool, channels: &Channels, job: &Job) -> Result<(), Box<dyn Error>> {
+    let payload: Value = serde_json::from_str(&job.payload)?;
+    match job.kind.as_str() {
+        reminders::JOB_KIND => reminders::send(pool, channels, &payload).await,
+        other => Err(format!("No handler for job kind {:?}", other).into()),
+    }
+}
+
+// Record the outcome of a job: done, retried later, or dead once out of attempts
+async fn finish(pool: &SqlitePool, job: &Job, outcome: Result<(), Box<dyn Error>>) -> Result<(), sqlx::Error> {
+    match outcome {
+        Ok(()) => {
+            sqlx::query!(
+                "UPDATE jobs SET status = 'done', last_error = NULL, finished_at = CURRENT_TIMESTAMP WHERE id = ?",
+                job.id
+            )
+            .execute(pool)
+            .await?;
+        }
+        Err(err) if job.attempts >= job.max_attempts => {
+            eprintln!("Job {} ({}) failed for good after {} attempts: {}", job.id, job.kind, job.attempts, err);
+            let message = err.to_string();
+            sqlx::query!(
+                "UPDATE jobs SET status = 'dead', last_error = ?, finished_at = CURRENT_TIMESTAMP WHERE id = ?",
+                message,
+                job.id
+            )
+            .execute(pool)
+            .await?;
+        }
+        Err(err) => {
+            eprintln!("Job {} ({}) failed, will retry: {}", job.id, job.kind, err);
+            let message = err.to_string();
+            let delay = format!("+{} seconds", RETRY_DELAY_SECS << (job.attempts - 1).min(16));
+            sqlx::query!(
+                "UPDATE jobs SET status = 'pending', last_error = ?, run_at = DATETIME('now', ?) WHERE id = ?",
+                message,
+                delay,
+                job.id
+            )
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+// Run jobs that are due, up to a batch. Returns the number run.
+pub async fn run_due(pool: &SqlitePool, channels: &Channels) -> Result<usize, sqlx::Error> {
+    let mut ran = 0;
+    while ran < BATCH_SIZE {
+        let Some(job) = claim(pool).await? else {
+            break;
+        };
+        let outcome = run(pool, channels, &job).await;
+        finish(pool, &job, outcome).await?;
+        ran += 1;
+    }
+    Ok(ran)
+}
+
+// Start the worker on the current runtime; runs for the life of the server
+pub fn spawn_worker(pool: SqlitePool, channels: Channels) {
+    let interval = std::env::var("JOB_POLL_INTERVAL_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_POLL_INTERVAL);
+
+    actix_web::rt::spawn(async move {
+        // Jobs left running when the server stopped never finished; run them again
+        if let Err(err) = sqlx::query!("UPDATE jobs SET status = 'pending' WHERE status = 'running'")
+            .execute(&pool)
+            .await
+        {
+            eprintln!("Failed to requeue interrupted jobs: {}", err);
+        }
+
+        let mut ticker = actix_web::rt::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match run_due(&pool, &channels).await {
+                Ok(0) => {}
+                Ok(ran) => println!("Ran {} background jobs", ran),
+                Err(err) => eprintln!("Background job run failed: {}", err),
+            }
+        }
+    });
+}
+
+// Handler for `GET /admin/jobs?status=dead`
+#[get("/admin/jobs")]
+async fn list_jobs(req: HttpRequest, query: web::Query<JobQuery>, pool: web::Data<SqlitePool>) -> impl Responder {
+    if let Err(response) = require_admin(&req, pool.get_ref()).await {
+        return response;
+    }
+    let status = query.status.as_deref().unwrap_or("dead");
+
+    let rows = sqlx::query!(
+        "SELECT id AS \"id!\", kind, payload, run_at AS \"run_at: String\", status, attempts, max_attempts,
+                last_error, created_at AS \"created_at: String\", finished_at AS \"finished_at: String\"
+         FROM jobs WHERE status = ? ORDER BY run_at DESC, id DESC LIMIT ?",
+        status,
+        LIST_LIMIT
+    )
+    .fetch_all(pool.get_ref())
+    .await;
+
+    match rows {
+        Ok(rows) => {
+            let jobs: Vec<JobSummary> = rows
+                .into_iter()
+                .map(|row| JobSummary {
+                    id: row.id,
+                    kind: row.kind,
+                    payload: serde_json::from_str(&row.payload).unwrap_or(Value::Null),
+                    run_at: row.run_at,
+                    status: row.status,
+                    attempts: row.attempts,
+                    max_attempts: row.max_attempts,
+                    last_error: row.last_error,
+                    created_at: row.created_at,
+                    finished_at: row.finished_at,
+                })
+                .collect();
+            HttpResponse::Ok().json(jobs)
+        }
+        Err(err) => {
+            eprintln!("Failed to list jobs: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Failed to list jobs."
+            }))
+        }
+    }
+}
+
+// Handler for `POST /admin/jobs/{id}/retry` (give a dead job a fresh set of attempts)
+#[post("/admin/jobs/{id}/retry")]
+async fn retry_job(req: HttpRequest, path: web::Path<i64>, pool: web::Data<SqlitePool>) -> impl Responder {
+    if let Err(response) = require_admin(&req, pool.get_ref()).await {
+        return response;
+    }
+    let job_id = path.into_inner();
+
+    let result = sqlx::query!(
+        "UPDATE jobs SET status = 'pending', attempts = 0, run_at = CURRENT_TIMESTAMP, finished_at = NULL
+         WHERE id = ? AND status = 'dead'",
+        job_id
+    )
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(res) if res.rows_affected() == 1 => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Job queued to run again."
+        })),
+        Ok(_) => HttpResponse::NotFound().json(json!({
+            "status": "error",
+            "message": "No dead job with that ID."
+        })),
+        Err(err) => {
+            eprintln!("Failed to retry job {}: {}", job_id, err);
+            HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Failed to retry job."
+            }))
+        }
+    }
+}
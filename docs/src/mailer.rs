@@ -0,0 +1,58 @@
+// Outgoing email. The transport is chosen with the MAILER environment variable:
+// "log" (the default) prints messages to stdout, and "file" writes each one to
+// MAIL_DIR (default "./mail") so they can be inspected during development.
+// Other transports only need to implement `Mailer`.
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+pub trait Mailer: Send + Sync {
+    fn send(&self, email: &Email) -> Result<(), Box<dyn Error>>;
+}
+
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send(&self, email: &Email) -> Result<(), Box<dyn Error>> {
+        println!("To: {}\nSubject: {}\n\n{}\n", email.to, email.subject, email.body);
+        Ok(())
+    }
+}
+
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl Mailer for FileMailer {
+    fn send(&self, email: &Email) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(&self.dir)?;
+        let sent_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        let path = self.dir.join(format!("{}.eml", sent_at));
+        fs::write(
+            path,
+            format!("To: {}\nSubject: {}\n\n{}\n", email.to, email.subject, email.body),
+        )?;
+        Ok(())
+    }
+}
+
+// Build the mailer configured in the environment
+pub fn from_env() -> Box<dyn Mailer> {
+    match std::env::var("MAILER").as_deref() {
+        Ok("file") => Box::new(FileMailer {
+            dir: std::env::var("MAIL_DIR").unwrap_or_else(|_| "./mail".to_string()).into(),
+        }),
+        Ok("log") | Err(_) => Box::new(LogMailer),
+        Ok(other) => {
+            eprintln!("Unknown MAILER {:?}, logging emails instead", other);
+            Box::new(LogMailer)
+        }
+    }
+}
@@ -1,5 +1,35 @@
+mod appointments;
+mod articles;
+mod compare;
+mod encryption;
+mod export;
+mod favorite_refresh;
+mod favorites;
 mod find_providers;
-use find_providers::{geocode_address, find_health_providers, Coordinates};
+mod ical;
+mod insurance;
+mod intake_forms;
+mod jobs;
+mod mailer;
+mod messaging;
+mod notifications;
+mod place_details;
+mod plan_net;
+mod provider_listings;
+mod provider_store;
+mod reminders;
+mod reviews;
+mod saved_searches;
+mod search_history;
+mod session;
+mod sharing;
+mod sms;
+mod telehealth;
+mod triage;
+mod video_visits;
+mod waiting_room;
+use find_providers::{geocode_address, search_nearby, Coordinates};
+use session::current_user_id;
 
 use actix_files as fs; 
 use actix_web::{get, post, web, App, HttpServer, HttpRequest, Responder, HttpResponse, Result};
@@ -21,6 +51,9 @@ struct QueryParams {
     lat: Option<f64>,    // Optional latitude
     lng: Option<f64>,    // Optional longitude
     service_type: Option<String>,
+    in_network: Option<bool>, // Only return providers in the user's insurance network
+    mode: Option<String>,     // "virtual" searches the telehealth catalog instead of nearby places
+    state: Option<String>,    // Two-letter state for virtual search (looked up from ZIP/lat/lng if absent)
 }
 
 #[derive(Deserialize)]
@@ -29,23 +62,49 @@ struct LoginData {
     password: String,
 }
 
-#[derive(Deserialize, Debug)]
-struct FavoriteService {
-    photo: String,
-    name: String,
-    address: String,
-    rating: String,
-}
-
 struct AppState {
     logout_flag: Mutex<bool>, // Tracks logout state
 }
 
+// Add a search to the logged-in user's history
+async fn record_search(req: &HttpRequest, query: &QueryParams, pool: &SqlitePool, api_key: &str, service_type: &str) {
+    let Some(user_id) = current_user_id(req) else {
+        return;
+    };
+    let search = search_history::NewSearch {
+        zip: query.zip.as_deref(),
+        coordinates: query.lat.zip(query.lng).map(|(lat, lng)| Coordinates { lat, lng }),
+        service_type,
+        in_network: query.in_network.unwrap_or(false),
+        mode: query.mode.as_deref().filter(|mode| *mode == "virtual"),
+    };
+    if let Err(err) = search_history::record(pool, api_key, user_id, search).await {
+        eprintln!("Failed to record search history: {}", err);
+    }
+}
+
 // Handler for the `/services` endpoint
-async fn services_handler(req: HttpRequest, query: web::Query<QueryParams>) -> impl Responder {
+async fn services_handler(req: HttpRequest, query: web::Query<QueryParams>, pool: web::Data<SqlitePool>) -> impl Responder {
     let api_key = std::env::var("GOOGLE_MAPS_API_KEY")
         .expect("GOOGLE_MAPS_API_KEY must be set in environment");
 
+    // Virtual visits aren't limited by distance, only by where the provider is licensed
+    if query.mode.as_deref() == Some("virtual") {
+        let search = telehealth::VirtualSearch {
+            state: query.state.as_deref(),
+            zip: query.zip.as_deref(),
+            coordinates: query.lat.zip(query.lng).map(|(lat, lng)| Coordinates { lat, lng }),
+            specialty: query.service_type.as_deref().unwrap_or("doctor"),
+            in_network: query.in_network.unwrap_or(false),
+        };
+        let specialty = search.specialty;
+        let response = telehealth::virtual_search(&req, pool.get_ref(), &api_key, search).await;
+        if response.status().is_success() {
+            record_search(&req, &query, pool.get_ref(), &api_key, specialty).await;
+        }
+        return response;
+    }
+
     let coordinates = if let (Some(lat), Some(lng)) = (query.lat, query.lng) {
         // Use lat/lng if provided
         Coordinates { lat, lng }
@@ -66,7 +125,7 @@ async fn services_handler(req: HttpRequest, query: web::Query<QueryParams>) -> i
     // Default to a generic service type if none is specified
     let service_type = query.service_type.as_deref().unwrap_or("hospital");
 
-    let providers = match find_health_providers(&coordinates, 10000, &api_key, service_type).await {
+    let mut providers = match search_nearby(pool.get_ref(), &coordinates, service_type, &api_key).await {
         Ok(providers) => providers,
         Err(err) => {
             eprintln!("Failed to find health providers: {}", err);
@@ -74,6 +133,37 @@ async fn services_handler(req: HttpRequest, query: web::Query<QueryParams>) -> i
         }
     };
 
+    // Lay the providers' own corrections over the upstream data, before checking networks
+    if let Err(err) = provider_listings::apply_overrides(pool.get_ref(), &mut providers).await {
+        eprintln!("Failed to apply listing corrections: {}", err);
+    }
+
+    // Ratings left on this site, shown next to the Google rating
+    if let Err(err) = reviews::add_ratings(pool.get_ref(), &mut providers).await {
+        eprintln!("Failed to fetch review ratings: {}", err);
+    }
+
+    // Flag providers in the logged-in user's insurance network
+    let mut insurance_plan = None;
+    if let Some(user_id) = current_user_id(&req) {
+        match insurance::user_plan(pool.get_ref(), user_id).await {
+            Ok(Some(plan)) => {
+                if let Err(err) = insurance::mark_in_network(pool.get_ref(), &plan, &mut providers).await {
+                    eprintln!("Failed to check insurance network: {}", err);
+                }
+                insurance_plan = Some(plan.name);
+            }
+            Ok(None) => {}
+            Err(err) => eprintln!("Failed to fetch insurance plan: {}", err),
+        }
+    }
+
+    if query.in_network.unwrap_or(false) {
+        providers.retain(|provider| provider.in_network);
+    }
+
+    record_search(&req, &query, pool.get_ref(), &api_key, service_type).await;
+
     // Request username cookie 
     let mut isLoggedIn = false;
     let username = req.cookie("username").map(|cookie| cookie.value().to_string());
@@ -86,7 +176,8 @@ async fn services_handler(req: HttpRequest, query: web::Query<QueryParams>) -> i
     HttpResponse::Ok().json(json!({
         "coordinates": coordinates,
         "providers": providers,
-        "isLoggedIn": isLoggedIn
+        "isLoggedIn": isLoggedIn,
+        "insurancePlan": insurance_plan
     }))
 }
 
@@ -97,68 +188,6 @@ async fn api_key_handler() -> impl Responder {
     HttpResponse::Ok().body(api_key)
 }
 
-#[post("/favorites")]
-async fn save_favorites(
-    req: HttpRequest,
-    favorite: web::Json<FavoriteService>,
-    pool: web::Data<SqlitePool>,
-) -> impl Responder {
-    println!("Processing save_favorites request...");
-    println!("All Cookies: {:?}", req.cookies());
-
-    // Extract user ID from cookies
-    let user_id_cookie = req.cookie("user_id").map(|cookie| cookie.value().to_string());
-    if let Some(user_id_str) = user_id_cookie {
-        match user_id_str.parse::<i64>() {
-            Ok(user_id) => {
-                // Convert rating to REAL in SQL database
-                // let rating = favorite.rating.parse::<f64>().unwrap_or(0.0);
-                // Insert the favorite service into the database
-                let query_result = sqlx::query!(
-                    "INSERT INTO favorites (user_id, photo, title, address, rating) VALUES (?, ?, ?, ?, ?)",
-                    user_id,
-                    favorite.photo,
-                    favorite.name,
-                    favorite.address,
-                    favorite.rating
-                )
-                .execute(pool.get_ref())
-                .await;
-
-                match query_result {
-                    Ok(_) => {
-                        println!("Favorite saved successfully.");
-                        HttpResponse::Ok().json(json!({
-                            "status": "success",
-                            "message": "Favorite saved successfully."
-                        }))
-                    }
-                    Err(e) => {
-                        HttpResponse::InternalServerError().json(json!({
-                            "status": "error",
-                            "message": "Failed to save favorite. Please try again later."
-                        }))
-                    }
-                }
-            }
-            Err(_) => {
-                println!("Invalid user ID in cookie.");
-                HttpResponse::BadRequest().json(json!({
-                    "status": "error",
-                    "message": "Invalid user ID."
-                }))
-            }
-        }
-    } else {
-        println!("User not logged in.");
-        HttpResponse::Unauthorized().json(json!({
-            "status": "error",
-            "message": "User not logged in. Please log in and try again."
-        }))
-    }
-}
-
-
 // Handler for the `/login` endpoint
 #[post("/login")]
 async fn login_handler(
@@ -321,52 +350,134 @@ async fn profile(req: HttpRequest, pool: web::Data<SqlitePool>, hb: web::Data<Ha
 
         if let Some(user_id) = user_id_cookie {
 
-            match sqlx::query!(
-    
-                "SELECT id, photo, title AS name, address, rating FROM favorites WHERE user_id = ?",
-    
+            match favorites::list_collections(pool.get_ref(), user_id).await {
+                Ok((collections, unsorted)) => {
+                    // Every tag in use, for the tag filter buttons
+                    let mut tags: Vec<&String> = collections
+                        .iter()
+                        .flat_map(|collection| collection.favorites.iter())
+                        .chain(unsorted.iter())
+                        .flat_map(|favorite| favorite.tags.iter())
+                        .collect();
+                    tags.sort();
+                    tags.dedup();
+                    let has_favorites = !unsorted.is_empty()
+                        || collections.iter().any(|collection| !collection.favorites.is_empty());
+
+                    data.insert("tags".to_string(), json!(tags));
+                    data.insert("has_favorites".to_string(), json!(has_favorites));
+                    data.insert("collections".to_string(), json!(collections));
+                    data.insert("favorites".to_string(), json!(unsorted));
+                }
+                Err(_) => {
+                    // Handle database error
+                    data.insert("error".to_string(), json!("Could not fetch favorites"));
+                }
+            }
+        }
+
+        // Notification center and email digest settings
+        if let Some(user_id) = user_id_cookie {
+            let notifications = notifications::list_notifications(pool.get_ref(), user_id).await;
+            let unread = notifications::unread_count(pool.get_ref(), user_id).await;
+            match notifications.and_then(|notifications| Ok((notifications, unread?))) {
+                Ok((notifications, unread)) => {
+                    data.insert("notifications".to_string(), json!(notifications));
+                    data.insert("unread_notifications".to_string(), json!(unread));
+                }
+                Err(_) => {
+                    data.insert("error".to_string(), json!("Could not fetch notifications"));
+                }
+            }
+            let settings = sqlx::query!(
+                "SELECT email, email_digest, phone, email_reminders, sms_reminders FROM users WHERE id = ?",
                 user_id
-    
             )
-    
-            .fetch_all(pool.get_ref())
-    
-            .await {
-    
-                Ok(favorites) => {
-    
-                    let favorites_json: Vec<serde_json::Value> = favorites.into_iter().map(|f| json!({
-    
-                        "id": f.id,
-    
-                        "photo": f.photo,
-    
-                        "name": f.name,
-    
-                        "address": f.address,
-    
-                        "rating": f.rating
-    
+            .fetch_optional(pool.get_ref())
+            .await;
+            if let Ok(Some(settings)) = settings {
+                data.insert("email".to_string(), json!(settings.email));
+                data.insert("email_digest".to_string(), json!(settings.email_digest));
+                data.insert("phone".to_string(), json!(settings.phone));
+                data.insert("email_reminders".to_string(), json!(settings.email_reminders));
+                data.insert("sms_reminders".to_string(), json!(settings.sms_reminders));
+            }
+        }
+
+        // Saved searches, re-run in the background
+        if let Some(user_id) = user_id_cookie {
+            match saved_searches::list_saved_searches(pool.get_ref(), user_id).await {
+                Ok(searches) => {
+                    data.insert("saved_searches".to_string(), json!(searches));
+                }
+                Err(_) => {
+                    data.insert("error".to_string(), json!("Could not fetch saved searches"));
+                }
+            }
+        }
+
+        // Recent searches for one-click repeat, and whether history is kept at all
+        if let Some(user_id) = user_id_cookie {
+            let enabled = search_history::is_enabled(pool.get_ref(), user_id).await;
+            let recent = search_history::list_recent(pool.get_ref(), user_id).await;
+            match enabled.and_then(|enabled| Ok((enabled, recent?))) {
+                Ok((enabled, recent)) => {
+                    data.insert("search_history_enabled".to_string(), json!(enabled));
+                    data.insert("search_history".to_string(), json!(recent));
+                }
+                Err(_) => {
+                    data.insert("error".to_string(), json!("Could not fetch search history"));
+                }
+            }
+        }
+
+        // Upcoming appointments
+        if let Some(user_id) = user_id_cookie {
+            match appointments::list_upcoming(pool.get_ref(), user_id).await {
+                Ok(upcoming) => {
+                    data.insert("appointments".to_string(), json!(upcoming));
+                }
+                Err(_) => {
+                    data.insert("error".to_string(), json!("Could not fetch appointments"));
+                }
+            }
+            if let Ok(feed_url) = ical::user_feed_url(&req, pool.get_ref(), user_id).await {
+                data.insert("calendar_feed_url".to_string(), json!(feed_url));
+            }
+            if let Ok(visits) = video_visits::list_clinician_visits(pool.get_ref(), user_id).await {
+                data.insert("clinician_visits".to_string(), json!(visits));
+            }
+        }
+
+        // Unread secure messages
+        if let Some(user_id) = user_id_cookie {
+            if let Ok(unread) = messaging::unread_count(pool.get_ref(), user_id).await {
+                data.insert("unread_messages".to_string(), json!(unread));
+            }
+        }
+
+        // Insurance plan selector, with the user's current plan preselected
+        if let Some(user_id) = user_id_cookie {
+            let selected_plan = insurance::user_plan(pool.get_ref(), user_id).await.ok().flatten();
+            match insurance::list_plans(pool.get_ref()).await {
+                Ok(plans) => {
+                    let plans_json: Vec<serde_json::Value> = plans.into_iter().map(|plan| json!({
+                        "id": plan.id,
+                        "name": plan.name,
+                        "payer": plan.payer,
+                        "selected": selected_plan.as_ref().is_some_and(|selected| selected.id == plan.id)
                     })).collect();
-    
-                    
-    
-                    data.insert("favorites".to_string(), json!(favorites_json));
-    
+                    data.insert("insurance_plans".to_string(), json!(plans_json));
                 }
-    
                 Err(_) => {
-    
-                    // Handle database error
-    
-                    data.insert("error".to_string(), json!("Could not fetch favorites"));
-    
+                    data.insert("error".to_string(), json!("Could not fetch insurance plans"));
                 }
-    
             }
-    
+            if let Some(plan) = selected_plan {
+                data.insert("insurance_plan".to_string(), json!(plan));
+            }
         }
-    
+
         let body = hb.render("profile", &data).unwrap_or_else(|_| "Template error".to_string());
     
         HttpResponse::Ok().body(body)
@@ -441,6 +552,12 @@ async fn main() -> std::io::Result<()> {
         .await
         .expect("Failed to connect to database");
 
+    // Apply any pending migrations
+    sqlx::migrate!("./migrations")
+        .run(&pool)
+        .await
+        .expect("Failed to run database migrations");
+
     // Initialize handlebars template engine
     let mut handlebars = Handlebars::new();
     handlebars.register_template_file("index", "./templates/index.hbs")
@@ -455,6 +572,60 @@ async fn main() -> std::io::Result<()> {
     handlebars.register_template_file("login", "./templates/login.hbs")
         .expect("Failed to register login");
 
+    handlebars.register_template_file("shared", "./templates/shared.hbs")
+        .expect("Failed to register shared");
+
+    handlebars.register_template_file("compare", "./templates/compare.hbs")
+        .expect("Failed to register compare");
+
+    handlebars.register_template_file("book", "./templates/book.hbs")
+        .expect("Failed to register book");
+
+    handlebars.register_template_file("visit", "./templates/visit.hbs")
+        .expect("Failed to register visit");
+
+    handlebars.register_template_file("waiting_room", "./templates/waiting_room.hbs")
+        .expect("Failed to register waiting_room");
+
+    handlebars.register_template_file("messages", "./templates/messages.hbs")
+        .expect("Failed to register messages");
+
+    handlebars.register_template_file("intake", "./templates/intake.hbs")
+        .expect("Failed to register intake");
+
+    handlebars.register_template_file("intake_forms", "./templates/intake_forms.hbs")
+        .expect("Failed to register intake_forms");
+
+    handlebars.register_template_file("intake_form_builder", "./templates/intake_form_builder.hbs")
+        .expect("Failed to register intake_form_builder");
+
+    handlebars.register_template_file("provider_portal", "./templates/provider_portal.hbs")
+        .expect("Failed to register provider_portal");
+
+    handlebars.register_template_file("provider_listing", "./templates/provider_listing.hbs")
+        .expect("Failed to register provider_listing");
+
+    handlebars.register_template_file("reviews", "./templates/reviews.hbs")
+        .expect("Failed to register reviews");
+
+    handlebars.register_template_file("review_moderation", "./templates/review_moderation.hbs")
+        .expect("Failed to register review_moderation");
+
+    handlebars.register_template_file("triage", "./templates/triage.hbs")
+        .expect("Failed to register triage");
+
+    handlebars.register_template_file("learn", "./templates/learn.hbs")
+        .expect("Failed to register learn");
+
+    handlebars.register_template_file("article", "./templates/article.hbs")
+        .expect("Failed to register article");
+
+    handlebars.register_template_file("articles_admin", "./templates/articles_admin.hbs")
+        .expect("Failed to register articles_admin");
+
+    handlebars.register_template_file("article_editor", "./templates/article_editor.hbs")
+        .expect("Failed to register article_editor");
+
     handlebars.register_helper("times", Box::new(|h: &Helper, _: &Handlebars, _: &Context, _: &mut RenderContext, out: &mut dyn Output| -> HelperResult {
         let param = h.param(0).unwrap();
         let times = param.value().as_f64().unwrap_or(0.0) as usize;
@@ -476,22 +647,153 @@ async fn main() -> std::io::Result<()> {
         logout_flag: Mutex::new(false),
     });
 
+    // Place Details responses are cached across all workers
+    let place_details_cache = web::Data::new(place_details::PlaceDetailsCache::default());
+
+    // Video visit rooms are shared across all workers
+    let visit_rooms = web::Data::new(video_visits::VisitRooms::default());
+    let waiting_room_updates = web::Data::new(waiting_room::WaitingRoomUpdates::default());
+    let message_updates = web::Data::new(messaging::MessageUpdates::default());
+
+    // Messages and attachments are encrypted at rest
+    let cipher = web::Data::new(
+        encryption::Cipher::from_env().expect("MESSAGE_ENCRYPTION_KEY must be set to 64 hex characters"),
+    );
+
+    // Symptom guidance rules are read once at startup
+    let triage_rules = web::Data::new(triage::TriageRules::from_env().expect("Failed to load triage rules"));
+
+    // Keep favorite snapshots in sync with their providers
+    let api_key = std::env::var("GOOGLE_MAPS_API_KEY")
+        .expect("GOOGLE_MAPS_API_KEY must be set in environment");
+    favorite_refresh::spawn(pool.clone(), place_details_cache.clone(), api_key.clone());
+    saved_searches::spawn(pool.clone(), api_key);
+    search_history::spawn_expiry(pool.clone());
+    notifications::spawn_digests(pool.clone(), mailer::from_env());
+    jobs::spawn_worker(pool.clone(), reminders::Channels {
+        mailer: mailer::from_env(),
+        sms: sms::from_env(),
+    });
+
     HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(pool.clone())) // Share the database pool across handlers
             .app_data(web::Data::new(handlebars.clone())) // Share the handlebars instance
             .app_data(state.clone()) // Share the application state
+            .app_data(place_details_cache.clone()) // Share the Place Details cache
+            .app_data(visit_rooms.clone()) // Share the video visit rooms
+            .app_data(waiting_room_updates.clone()) // Share waiting room change notifications
+            .app_data(message_updates.clone()) // Share new message notifications
+            .app_data(cipher.clone()) // Share the message encryption key
+            .app_data(triage_rules.clone()) // Share the symptom guidance rules
             .app_data(web::JsonConfig::default())
             .route("/api-key", web::get().to(api_key_handler)) // Endpoint to serve the API key
             .route("/services", web::get().to(services_handler)) // Endpoint for health services
             .route("/", web::get().to(index)) // Endpoint for index page
             .route("/profile", web::get().to(profile)) // Endpoint for profile page
             .route("/register", web::get().to(register)) // Endpoint for register page
-            .service(save_favorites) // Endpoint for saving favorites
+            .service(export::export_favorites) // Endpoint for downloading favorites as CSV, vCard, GeoJSON or PDF
+            .service(export::export_services) // Endpoint for downloading search results in the same formats
+            .service(favorites::save_favorite) // Endpoint for saving (or refreshing) a favorite
+            .service(favorites::get_favorites) // Endpoint for listing favorites
+            .service(favorites::delete_favorite) // Endpoint for removing a favorite
+            .service(favorites::reorder_favorites) // Endpoint for reordering favorites
+            .service(favorites::move_favorite) // Endpoint for moving a favorite between collections
+            .service(favorites::annotate_favorite) // Endpoint for a favorite's notes and tags
+            .service(favorites::get_collections) // Endpoint for listing collections
+            .service(favorites::create_collection) // Endpoint for creating a collection
+            .service(favorites::reorder_collections) // Endpoint for reordering collections
+            .service(favorites::rename_collection) // Endpoint for renaming a collection
+            .service(favorites::delete_collection) // Endpoint for deleting a collection
+            .service(notifications::get_notifications) // Endpoint for listing notifications
+            .service(notifications::mark_all_read) // Endpoint for marking all notifications read
+            .service(notifications::mark_read) // Endpoint for marking a notification read
+            .service(notifications::update_digest_settings) // Form handler for email digest settings
+            .service(sharing::create_share) // Endpoint for creating a public link to a collection
+            .service(sharing::get_shares) // Endpoint for listing a collection's public links
+            .service(sharing::revoke_share) // Endpoint for revoking a public link
+            .service(sharing::view_shared) // Public read-only page for a shared collection
+            .service(saved_searches::save_search) // Endpoint for saving a search to re-run on a schedule
+            .service(saved_searches::get_saved_searches) // Endpoint for listing saved searches
+            .service(saved_searches::delete_saved_search) // Endpoint for deleting a saved search
+            .service(search_history::clear_history) // Endpoint for clearing the user's search history
+            .service(search_history::update_history_settings) // Form handler for turning search history on or off
+            .service(appointments::set_schedule) // Endpoint for admins to set a provider's bookable hours
+            .service(appointments::get_slots) // Endpoint for a provider's available appointment times
+            .service(appointments::booking_page) // Page for picking an appointment time
+            .service(appointments::book_appointment) // Endpoint for booking an appointment
+            .service(appointments::reschedule_appointment) // Endpoint for moving an appointment to a new time
+            .service(appointments::cancel_appointment) // Endpoint for cancelling an appointment
+            .service(ical::appointment_ics) // Endpoint for downloading an appointment as a .ics file
+            .service(ical::create_feed) // Endpoint for creating or replacing the user's calendar feed URL
+            .service(ical::revoke_feed) // Endpoint for turning off the user's calendar feed
+            .service(ical::calendar_feed) // Private iCalendar subscription feed
+            .service(reminders::update_reminder_settings) // Form handler for appointment reminder settings
+            .service(video_visits::visit_page) // Video visit page
+            .service(video_visits::signal) // WebSocket for video visit signaling
+            .service(video_visits::add_clinician) // Endpoint for admins to let a user take a provider's video visits
+            .service(video_visits::remove_clinician) // Endpoint for admins to remove a provider's clinician
+            .service(waiting_room::waiting_room_page) // Waiting room page for patients and clinicians
+            .service(waiting_room::watch) // WebSocket for live waiting room updates
+            .service(waiting_room::join_queue) // Endpoint for joining a waiting room queue
+            .service(waiting_room::leave_queue) // Endpoint for leaving a waiting room queue
+            .service(waiting_room::admit_next) // Endpoint for clinicians to admit the next patient
+            .service(waiting_room::skip_next) // Endpoint for clinicians to skip the next patient
+            .service(waiting_room::update_room) // Endpoint for clinicians to open or close the waiting room
+            .service(messaging::messages_page) // Secure messaging page
+            .service(messaging::get_threads) // Endpoint for listing the user's conversations with unread counts
+            .service(messaging::create_thread) // Endpoint for starting a conversation with a provider's staff
+            .service(messaging::get_thread) // Endpoint for reading a conversation
+            .service(messaging::send_message) // Endpoint for replying in a conversation
+            .service(messaging::upload_attachment) // Endpoint for sending a file in a conversation
+            .service(messaging::download_attachment) // Endpoint for downloading a message attachment
+            .service(messaging::get_unread) // Endpoint for the unread message count, polled as a fallback
+            .service(messaging::watch) // WebSocket for live message delivery
+            .service(intake_forms::intake_page) // Intake form page for an appointment
+            .service(intake_forms::submit_intake) // Endpoint for submitting intake answers
+            .service(intake_forms::export_response) // Endpoint for an intake response as a FHIR QuestionnaireResponse
+            .service(intake_forms::forms_page) // Admin page listing intake forms
+            .service(intake_forms::builder_page) // Admin page for building a provider's intake form
+            .service(intake_forms::save_form) // Endpoint for admins to save an intake form
+            .service(intake_forms::delete_form) // Endpoint for admins to delete an intake form
+            .service(intake_forms::export_responses) // Endpoint for admins to export intake responses as a FHIR Bundle
+            .service(provider_listings::portal_page) // Provider portal page listing the user's claims
+            .service(provider_listings::listing_page) // Page for staff to correct a listing they manage
+            .service(provider_listings::create_claim) // Endpoint for asking to manage a provider's listing
+            .service(provider_listings::update_listing) // Endpoint for staff to save corrections to a listing
+            .service(provider_listings::reset_listing) // Endpoint for staff to remove their corrections
+            .service(provider_listings::list_claims) // Endpoint for admins to list listing claims, pending ones by default
+            .service(provider_listings::decide_claim) // Endpoint for admins to approve, reject or revoke a claim
+            .service(reviews::reviews_page) // Page listing a provider's published reviews
+            .service(reviews::save_review) // Endpoint for writing or editing the user's review of a provider
+            .service(reviews::delete_review) // Endpoint for deleting the user's review of a provider
+            .service(reviews::report_review) // Endpoint for reporting an abusive review
+            .service(reviews::moderation_page) // Admin page for new and reported reviews
+            .service(reviews::moderate_review) // Endpoint for admins to publish or reject a review
+            .service(triage::triage_page) // Symptom guidance wizard page
+            .service(triage::triage_step) // Endpoint for the wizard's next question or outcome
+            .service(articles::learn_page) // Health education page with article search
+            .service(articles::article_page) // Health education article page
+            .service(articles::article_links) // Endpoint for articles about a service type, linked from search results
+            .service(articles::articles_admin_page) // Admin page listing articles, drafts included
+            .service(articles::preview_article) // Endpoint for the article editor's preview
+            .service(articles::editor_page) // Admin page for writing or editing an article
+            .service(articles::create_article) // Endpoint for admins to create an article
+            .service(articles::update_article) // Endpoint for admins to save an article
+            .service(articles::delete_article) // Endpoint for admins to delete an article
+            .service(jobs::list_jobs) // Endpoint for admins to list background jobs, dead ones by default
+            .service(jobs::retry_job) // Endpoint for admins to retry a dead background job
+            .service(provider_store::provider_details) // Endpoint for a provider's full record and Place Details
+            .service(compare::compare_page) // Side-by-side comparison page
+            .service(compare::compare_json) // Endpoint for the same comparison as JSON
             .service(login) // Endpoint for login page
             .service(login_handler) // Endpoint for login form submission
             .service(register_handler) // Endpoint for register form submission
             .service(logout) // Endpoint for logout
+            .service(insurance::select_plan) // Endpoint for the profile insurance plan selector
+            .service(insurance::import_plans_csv) // Endpoint for importing plan networks from CSV
+            .service(insurance::import_plans_plan_net) // Endpoint for importing plan networks from Plan-Net
+            .service(telehealth::import_catalog) // Endpoint for importing the telehealth catalog from CSV
             .service(fs::Files::new("/static", "./static").show_files_listing()) // Serve static files under /static
             
     })
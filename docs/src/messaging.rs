@@ -0,0 +1,759 @@
+// Secure messaging between a user and a provider's staff (the clinicians registered
+// for the provider). Conversations are threads started by the user; subjects,
+// message bodies and attachments are encrypted at rest with `encryption::Cipher`,
+// and attachments are kept as files under ATTACHMENT_DIR. New messages are pushed
+// to open `/ws/messages` connections, and the page falls back to polling the unread
+// count when the socket isn't available.
+use crate::appointments::load_calendar;
+use crate::encryption::Cipher;
+use crate::session::current_user_id;
+use crate::video_visits::{is_clinician, CLIENT_TIMEOUT, HEARTBEAT_INTERVAL};
+
+use actix_web::http::StatusCode;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use actix_ws::Message;
+use handlebars::Handlebars;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::path::PathBuf;
+use std::time::Instant;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+const MAX_SUBJECT_CHARS: usize = 200;
+const MAX_BODY_CHARS: usize = 5000;
+const MAX_FILENAME_CHARS: usize = 100;
+
+// Largest attachment accepted, before encryption
+const MAX_ATTACHMENT_BYTES: usize = 5 * 1024 * 1024;
+
+// New messages that can be waiting for slow connections before they start missing some
+const UPDATE_BUFFER: usize = 256;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Role {
+    Patient,
+    Staff,
+}
+
+impl Role {
+    fn as_str(self) -> &'static str {
+        match self {
+            Role::Patient => "patient",
+            Role::Staff => "staff",
+        }
+    }
+}
+
+// Tells open connections which thread has a new message
+pub struct MessageUpdates {
+    sender: broadcast::Sender<i64>,
+}
+
+impl Default for MessageUpdates {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(UPDATE_BUFFER);
+        MessageUpdates { sender }
+    }
+}
+
+impl MessageUpdates {
+    fn notify(&self, thread_id: i64) {
+        // Fails only when nobody is connected
+        let _ = self.sender.send(thread_id);
+    }
+}
+
+#[derive(Serialize)]
+struct ThreadSummary {
+    id: i64,
+    subject: String,
+    provider_id: String,
+    provider_name: String,
+    patient_name: String,
+    role: &'static str, // The current user's side of the conversation
+    updated_at: String,
+    unread: i64,
+}
+
+#[derive(Serialize)]
+struct AttachmentView {
+    id: i64,
+    filename: String,
+    content_type: String,
+    size: i64,
+}
+
+#[derive(Serialize)]
+struct MessageView {
+    id: i64,
+    body: String,
+    mine: bool,
+    sender_name: String,
+    sender_role: String,
+    created_at: String,
+    attachments: Vec<AttachmentView>,
+}
+
+#[derive(Deserialize)]
+struct NewThread {
+    provider_id: String,
+    subject: String,
+    body: String,
+}
+
+#[derive(Deserialize)]
+struct NewMessage {
+    body: String,
+}
+
+#[derive(Deserialize)]
+struct AttachmentUpload {
+    filename: String,
+}
+
+#[derive(Deserialize)]
+struct MessagesPage {
+    provider: Option<String>, // Start a new thread with this provider
+}
+
+fn error(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+fn not_logged_in() -> HttpResponse {
+    error(StatusCode::UNAUTHORIZED, "User not logged in. Please log in and try again.")
+}
+
+fn thread_not_found() -> HttpResponse {
+    error(StatusCode::NOT_FOUND, "Conversation not found.")
+}
+
+fn attachment_dir() -> PathBuf {
+    std::env::var("ATTACHMENT_DIR").unwrap_or_else(|_| "./attachments".to_string()).into()
+}
+
+// The content type of an allowed attachment, judged by its contents rather than
+// what the browser claimed
+fn attachment_type(bytes: &[u8], filename: &str) -> Option<&'static str> {
+    if bytes.starts_with(b"%PDF-") {
+        Some("application/pdf")
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(b"\xFF\xD8\xFF") {
+        Some("image/jpeg")
+    } else if filename.to_lowercase().ends_with(".txt") && std::str::from_utf8(bytes).is_ok() {
+        Some("text/plain; charset=utf-8")
+    } else {
+        None
+    }
+}
+
+// Keep the last path component, without control characters or quotes
+fn clean_filename(filename: &str) -> String {
+    let name = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(MAX_FILENAME_CHARS)
+        .collect();
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        "attachment".to_string()
+    } else {
+        name
+    }
+}
+
+fn open_or_placeholder(cipher: &Cipher, sealed: &[u8], what: &str, id: i64) -> String {
+    cipher.open_text(sealed).unwrap_or_else(|err| {
+        eprintln!("Failed to decrypt {} {}: {}", what, id, err);
+        "(This message couldn't be decrypted.)".to_string()
+    })
+}
+
+// The user's side of a thread, or None if they're not part of it
+async fn thread_role(pool: &SqlitePool, thread_id: i64, user_id: i64) -> Result<Option<Role>, sqlx::Error> {
+    let thread = sqlx::query!("SELECT user_id, provider_id FROM message_threads WHERE id = ?", thread_id)
+        .fetch_optional(pool)
+        .await?;
+    let Some(thread) = thread else {
+        return Ok(None);
+    };
+    if thread.user_id == user_id {
+        Ok(Some(Role::Patient))
+    } else if is_clinician(pool, &thread.provider_id, user_id).await? {
+        Ok(Some(Role::Staff))
+    } else {
+        Ok(None)
+    }
+}
+
+// Whether the provider has staff on the dashboard to answer messages
+pub async fn takes_messages(pool: &SqlitePool, provider_id: &str) -> Result<bool, sqlx::Error> {
+    let staff = sqlx::query_scalar!("SELECT COUNT(*) FROM provider_clinicians WHERE provider_id = ?", provider_id)
+        .fetch_one(pool)
+        .await?;
+    Ok(staff > 0)
+}
+
+// Unread messages across the user's threads, from either side
+pub async fn unread_count(pool: &SqlitePool, user_id: i64) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM messages JOIN message_threads ON message_threads.id = messages.thread_id
+         WHERE messages.read_at IS NULL
+           AND ((message_threads.user_id = ? AND messages.sender_role = 'staff')
+                OR (messages.sender_role = 'patient' AND message_threads.provider_id IN
+                        (SELECT provider_id FROM provider_clinicians WHERE user_id = ?)))",
+        user_id,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+}
+
+async fn list_threads(pool: &SqlitePool, cipher: &Cipher, user_id: i64) -> Result<Vec<ThreadSummary>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT message_threads.id AS \"id!\", message_threads.subject, message_threads.provider_id,
+                message_threads.user_id, message_threads.updated_at AS \"updated_at: String\",
+                provider_calendars.name AS provider_name, users.username AS patient_name,
+                (SELECT COUNT(*) FROM messages
+                 WHERE messages.thread_id = message_threads.id AND messages.read_at IS NULL
+                   AND messages.sender_role = CASE WHEN message_threads.user_id = ? THEN 'staff' ELSE 'patient' END)
+                    AS \"unread!: i64\"
+         FROM message_threads
+         JOIN provider_calendars ON provider_calendars.provider_id = message_threads.provider_id
+         JOIN users ON users.id = message_threads.user_id
+         WHERE message_threads.user_id = ?
+            OR message_threads.provider_id IN (SELECT provider_id FROM provider_clinicians WHERE user_id = ?)
+         ORDER BY message_threads.updated_at DESC, message_threads.id DESC",
+        user_id,
+        user_id,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| ThreadSummary {
+            id: row.id,
+            subject: open_or_placeholder(cipher, &row.subject, "thread", row.id),
+            provider_id: row.provider_id,
+            provider_name: row.provider_name,
+            patient_name: row.patient_name,
+            role: if row.user_id == user_id { "patient" } else { "staff" },
+            updated_at: row.updated_at,
+            unread: row.unread,
+        })
+        .collect())
+}
+
+async fn list_messages(
+    pool: &SqlitePool,
+    cipher: &Cipher,
+    thread_id: i64,
+    user_id: i64,
+) -> Result<Vec<MessageView>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT messages.id AS \"id!\", messages.sender_id, messages.sender_role, messages.body,
+                messages.created_at AS \"created_at: String\", users.username AS sender_name
+         FROM messages JOIN users ON users.id = messages.sender_id
+         WHERE messages.thread_id = ? ORDER BY messages.id",
+        thread_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let attachments = sqlx::query!(
+        "SELECT message_attachments.id AS \"id!\", message_id, filename, content_type, size
+         FROM message_attachments JOIN messages ON messages.id = message_attachments.message_id
+         WHERE messages.thread_id = ? ORDER BY message_attachments.id",
+        thread_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut messages: Vec<MessageView> = rows
+        .into_iter()
+        .map(|row| MessageView {
+            id: row.id,
+            body: open_or_placeholder(cipher, &row.body, "message", row.id),
+            mine: row.sender_id == user_id,
+            sender_name: row.sender_name,
+            sender_role: row.sender_role,
+            created_at: row.created_at,
+            attachments: Vec::new(),
+        })
+        .collect();
+    for attachment in attachments {
+        if let Some(message) = messages.iter_mut().find(|message| message.id == attachment.message_id) {
+            message.attachments.push(AttachmentView {
+                id: attachment.id,
+                filename: attachment.filename,
+                content_type: attachment.content_type,
+                size: attachment.size,
+            });
+        }
+    }
+    Ok(messages)
+}
+
+// Add a message to a thread and bump the thread to the top of the list
+async fn insert_message(
+    conn: &mut sqlx::SqliteConnection,
+    thread_id: i64,
+    sender_id: i64,
+    role: Role,
+    body: &[u8],
+) -> Result<i64, sqlx::Error> {
+    let sender_role = role.as_str();
+    let message_id = sqlx::query_scalar!(
+        "INSERT INTO messages (thread_id, sender_id, sender_role, body) VALUES (?, ?, ?, ?) RETURNING id AS \"id!\"",
+        thread_id,
+        sender_id,
+        sender_role,
+        body
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    sqlx::query!(
+        "UPDATE message_threads SET updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+        thread_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(message_id)
+}
+
+// Handler for the `/messages` page
+#[get("/messages")]
+async fn messages_page(
+    req: HttpRequest,
+    query: web::Query<MessagesPage>,
+    pool: web::Data<SqlitePool>,
+    hb: web::Data<Handlebars<'_>>,
+) -> impl Responder {
+    if current_user_id(&req).is_none() {
+        return HttpResponse::Found().append_header(("Location", "/login")).finish();
+    }
+
+    // Provider picked from search results to start a conversation with
+    let mut data = serde_json::Map::new();
+    if let Some(id) = &query.provider {
+        match load_calendar(pool.get_ref(), id).await {
+            Ok(Some(calendar)) => {
+                data.insert("new_provider_id".to_string(), json!(calendar.provider_id));
+                data.insert("new_provider_name".to_string(), json!(calendar.name));
+            }
+            Ok(None) => {}
+            Err(err) => eprintln!("Failed to load provider {}: {}", id, err),
+        }
+    }
+    data.insert("max_attachment_mb".to_string(), json!(MAX_ATTACHMENT_BYTES / (1024 * 1024)));
+
+    let body = hb.render("messages", &data).unwrap_or_else(|_| "Template error".to_string());
+    HttpResponse::Ok().body(body)
+}
+
+// Handler for `GET /api/threads`
+#[get("/api/threads")]
+async fn get_threads(req: HttpRequest, pool: web::Data<SqlitePool>, cipher: web::Data<Cipher>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+
+    match list_threads(pool.get_ref(), cipher.get_ref(), user_id).await {
+        Ok(threads) => HttpResponse::Ok().json(threads),
+        Err(err) => {
+            eprintln!("Failed to list message threads: {}", err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Could not fetch conversations. Please try again later.")
+        }
+    }
+}
+
+// Handler for `POST /api/threads` (start a conversation with a provider)
+#[post("/api/threads")]
+async fn create_thread(
+    req: HttpRequest,
+    body: web::Json<NewThread>,
+    pool: web::Data<SqlitePool>,
+    cipher: web::Data<Cipher>,
+    updates: web::Data<MessageUpdates>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let subject = body.subject.trim();
+    let text = body.body.trim();
+    if subject.is_empty() || subject.chars().count() > MAX_SUBJECT_CHARS {
+        return error(StatusCode::BAD_REQUEST, "Subjects must be 1 to 200 characters long.");
+    }
+    if text.is_empty() || text.chars().count() > MAX_BODY_CHARS {
+        return error(StatusCode::BAD_REQUEST, "Messages must be 1 to 5000 characters long.");
+    }
+
+    let result = async {
+        let Some(calendar) = load_calendar(pool.get_ref(), &body.provider_id).await? else {
+            return Ok(None);
+        };
+        if !takes_messages(pool.get_ref(), &calendar.provider_id).await? {
+            return Ok(None);
+        }
+
+        let sealed_subject = cipher.seal(subject.as_bytes());
+        let mut tx = pool.begin().await?;
+        let thread_id = sqlx::query_scalar!(
+            "INSERT INTO message_threads (user_id, provider_id, subject) VALUES (?, ?, ?) RETURNING id AS \"id!\"",
+            user_id,
+            calendar.provider_id,
+            sealed_subject
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        insert_message(&mut tx, thread_id, user_id, Role::Patient, &cipher.seal(text.as_bytes())).await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(Some(thread_id))
+    }
+    .await;
+
+    match result {
+        Ok(Some(thread_id)) => {
+            updates.notify(thread_id);
+            HttpResponse::Ok().json(json!({
+                "status": "success",
+                "message": "Message sent.",
+                "id": thread_id
+            }))
+        }
+        Ok(None) => error(StatusCode::NOT_FOUND, "This provider doesn't take messages through the dashboard."),
+        Err(err) => {
+            eprintln!("Failed to start message thread: {}", err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to send message. Please try again later.")
+        }
+    }
+}
+
+// Handler for `GET /api/threads/{id}` (read a conversation, marking the other side's messages read)
+#[get("/api/threads/{id}")]
+async fn get_thread(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    pool: web::Data<SqlitePool>,
+    cipher: web::Data<Cipher>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let thread_id = path.into_inner();
+
+    let result = async {
+        let Some(role) = thread_role(pool.get_ref(), thread_id, user_id).await? else {
+            return Ok(None);
+        };
+        let messages = list_messages(pool.get_ref(), cipher.get_ref(), thread_id, user_id).await?;
+        let role = role.as_str();
+        sqlx::query!(
+            "UPDATE messages SET read_at = CURRENT_TIMESTAMP
+             WHERE thread_id = ? AND sender_role != ? AND read_at IS NULL",
+            thread_id,
+            role
+        )
+        .execute(pool.get_ref())
+        .await?;
+        Ok::<_, sqlx::Error>(Some((role, messages)))
+    }
+    .await;
+
+    match result {
+        Ok(Some((role, messages))) => HttpResponse::Ok().json(json!({
+            "id": thread_id,
+            "role": role,
+            "messages": messages
+        })),
+        Ok(None) => thread_not_found(),
+        Err(err) => {
+            eprintln!("Failed to load message thread {}: {}", thread_id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Could not fetch conversation. Please try again later.")
+        }
+    }
+}
+
+// Handler for `POST /api/threads/{id}/messages` (reply)
+#[post("/api/threads/{id}/messages")]
+async fn send_message(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: web::Json<NewMessage>,
+    pool: web::Data<SqlitePool>,
+    cipher: web::Data<Cipher>,
+    updates: web::Data<MessageUpdates>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let thread_id = path.into_inner();
+    let text = body.body.trim();
+    if text.is_empty() || text.chars().count() > MAX_BODY_CHARS {
+        return error(StatusCode::BAD_REQUEST, "Messages must be 1 to 5000 characters long.");
+    }
+
+    let result = async {
+        let Some(role) = thread_role(pool.get_ref(), thread_id, user_id).await? else {
+            return Ok(None);
+        };
+        let mut tx = pool.begin().await?;
+        let message_id = insert_message(&mut tx, thread_id, user_id, role, &cipher.seal(text.as_bytes())).await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(Some(message_id))
+    }
+    .await;
+
+    match result {
+        Ok(Some(message_id)) => {
+            updates.notify(thread_id);
+            HttpResponse::Ok().json(json!({
+                "status": "success",
+                "message": "Message sent.",
+                "id": message_id
+            }))
+        }
+        Ok(None) => thread_not_found(),
+        Err(err) => {
+            eprintln!("Failed to send message in thread {}: {}", thread_id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to send message. Please try again later.")
+        }
+    }
+}
+
+// Handler for `POST /api/threads/{id}/attachments?filename=...` (raw file as the request body)
+#[post("/api/threads/{id}/attachments")]
+async fn upload_attachment(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    query: web::Query<AttachmentUpload>,
+    payload: web::Payload,
+    pool: web::Data<SqlitePool>,
+    cipher: web::Data<Cipher>,
+    updates: web::Data<MessageUpdates>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let thread_id = path.into_inner();
+
+    let role = match thread_role(pool.get_ref(), thread_id, user_id).await {
+        Ok(Some(role)) => role,
+        Ok(None) => return thread_not_found(),
+        Err(err) => {
+            eprintln!("Failed to load message thread {}: {}", thread_id, err);
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to upload attachment.");
+        }
+    };
+
+    let bytes = match payload.to_bytes_limited(MAX_ATTACHMENT_BYTES).await {
+        Ok(Ok(bytes)) if !bytes.is_empty() => bytes,
+        Ok(Ok(_)) => return error(StatusCode::BAD_REQUEST, "The file is empty."),
+        Ok(Err(err)) => {
+            eprintln!("Failed to read attachment: {}", err);
+            return error(StatusCode::BAD_REQUEST, "Failed to read the file.");
+        }
+        Err(_) => return error(StatusCode::PAYLOAD_TOO_LARGE, "Attachments can be up to 5 MB."),
+    };
+    let filename = clean_filename(&query.filename);
+    let Some(content_type) = attachment_type(&bytes, &filename) else {
+        return error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Attachments can be PDFs, PNG or JPEG images, or .txt files.",
+        );
+    };
+
+    let dir = attachment_dir();
+    let stored_name = Uuid::new_v4().simple().to_string();
+    let path = dir.join(&stored_name);
+    let written = std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(&path, cipher.seal(&bytes)));
+    if let Err(err) = written {
+        eprintln!("Failed to store attachment: {}", err);
+        return error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to upload attachment.");
+    }
+
+    let size = bytes.len() as i64;
+    let result = async {
+        let mut tx = pool.begin().await?;
+        let message_id = insert_message(&mut tx, thread_id, user_id, role, &cipher.seal(b"")).await?;
+        sqlx::query!(
+            "INSERT INTO message_attachments (message_id, filename, content_type, size, stored_name) VALUES (?, ?, ?, ?, ?)",
+            message_id,
+            filename,
+            content_type,
+            size,
+            stored_name
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(message_id)
+    }
+    .await;
+
+    match result {
+        Ok(message_id) => {
+            updates.notify(thread_id);
+            HttpResponse::Ok().json(json!({
+                "status": "success",
+                "message": format!("Sent {}.", filename),
+                "id": message_id
+            }))
+        }
+        Err(err) => {
+            let _ = std::fs::remove_file(&path);
+            eprintln!("Failed to save attachment in thread {}: {}", thread_id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to upload attachment.")
+        }
+    }
+}
+
+// Handler for `GET /api/attachments/{id}` (download)
+#[get("/api/attachments/{id}")]
+async fn download_attachment(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    pool: web::Data<SqlitePool>,
+    cipher: web::Data<Cipher>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().body("User not logged in. Please log in and try again.");
+    };
+    let attachment_id = path.into_inner();
+
+    let result = async {
+        let attachment = sqlx::query!(
+            "SELECT messages.thread_id, filename, content_type, stored_name
+             FROM message_attachments JOIN messages ON messages.id = message_attachments.message_id
+             WHERE message_attachments.id = ?",
+            attachment_id
+        )
+        .fetch_optional(pool.get_ref())
+        .await?;
+        let Some(attachment) = attachment else {
+            return Ok(None);
+        };
+        let role = thread_role(pool.get_ref(), attachment.thread_id, user_id).await?;
+        Ok::<_, sqlx::Error>(role.map(|_| attachment))
+    }
+    .await;
+    let attachment = match result {
+        Ok(Some(attachment)) => attachment,
+        Ok(None) => return HttpResponse::NotFound().body("Attachment not found"),
+        Err(err) => {
+            eprintln!("Failed to load attachment {}: {}", attachment_id, err);
+            return HttpResponse::InternalServerError().body("Failed to download attachment");
+        }
+    };
+
+    let contents = std::fs::read(attachment_dir().join(&attachment.stored_name))
+        .map_err(|err| err.into())
+        .and_then(|sealed| cipher.open(&sealed));
+    match contents {
+        Ok(contents) => HttpResponse::Ok()
+            .content_type(attachment.content_type)
+            .append_header(("Content-Disposition", format!("attachment; filename=\"{}\"", attachment.filename)))
+            .append_header(("X-Content-Type-Options", "nosniff"))
+            .body(contents),
+        Err(err) => {
+            eprintln!("Failed to read attachment {}: {}", attachment_id, err);
+            HttpResponse::InternalServerError().body("Failed to download attachment")
+        }
+    }
+}
+
+// Handler for `GET /api/messages/unread`, polled when the WebSocket isn't available
+#[get("/api/messages/unread")]
+async fn get_unread(req: HttpRequest, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+
+    match unread_count(pool.get_ref(), user_id).await {
+        Ok(unread) => HttpResponse::Ok().json(json!({ "unread": unread })),
+        Err(err) => {
+            eprintln!("Failed to count unread messages: {}", err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Could not fetch messages. Please try again later.")
+        }
+    }
+}
+
+// Handler for the `/ws/messages` WebSocket, which announces new messages in the user's threads
+#[get("/ws/messages")]
+async fn watch(
+    req: HttpRequest,
+    body: web::Payload,
+    pool: web::Data<SqlitePool>,
+    updates: web::Data<MessageUpdates>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+
+    let (response, mut session, mut stream) = match actix_ws::handle(&req, body) {
+        Ok(upgrade) => upgrade,
+        Err(err) => return err.error_response(),
+    };
+    let mut changes = updates.sender.subscribe();
+    let pool = pool.into_inner();
+
+    actix_web::rt::spawn(async move {
+        let mut last_heard = Instant::now();
+        let mut heartbeat = actix_web::rt::time::interval(HEARTBEAT_INTERVAL);
+        loop {
+            let thread_id = tokio::select! {
+                change = changes.recv() => match change {
+                    Ok(thread_id) => Some(thread_id),
+                    // Missed some; let the page refresh everything
+                    Err(broadcast::error::RecvError::Lagged(_)) => None,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                message = stream.recv() => match message {
+                    Some(Ok(Message::Ping(bytes))) => {
+                        last_heard = Instant::now();
+                        let _ = session.pong(&bytes).await;
+                        continue;
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {
+                        last_heard = Instant::now();
+                        continue;
+                    }
+                },
+                // Drop connections that went away without closing
+                _ = heartbeat.tick() => {
+                    if last_heard.elapsed() > CLIENT_TIMEOUT || session.ping(b"").await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            if let Some(thread_id) = thread_id {
+                match thread_role(&pool, thread_id, user_id).await {
+                    Ok(Some(_)) => {}
+                    Ok(None) => continue,
+                    Err(err) => {
+                        eprintln!("Failed to check message thread {}: {}", thread_id, err);
+                        continue;
+                    }
+                }
+            }
+            let message = json!({ "type": "message", "thread_id": thread_id });
+            if session.text(message.to_string()).await.is_err() {
+                break;
+            }
+        }
+        let _ = session.close(None).await;
+    });
+
+    response
+}
@@ -0,0 +1,357 @@
+// Alerts about changes to a user's favorites, about providers appearing in or
+// dropping out of their saved searches, and about their account, shown in the
+// notification center on the profile page. Users who add an email address and opt
+// in also get the notifications they haven't been emailed about yet as a periodic
+// digest.
+use crate::mailer::{Email, Mailer};
+use crate::session::current_user_id;
+
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::error::Error;
+use std::time::Duration;
+
+// How often digests go out, unless NOTIFICATION_DIGEST_INTERVAL_SECS is set
+const DEFAULT_DIGEST_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Notifications shown in the notification center
+const RECENT_LIMIT: i64 = 20;
+
+#[derive(Serialize)]
+pub struct Notification {
+    pub id: i64,
+    pub kind: String,
+    pub message: String,
+    pub created_at: String,
+    pub read: bool,
+}
+
+#[derive(Deserialize)]
+struct DigestSettings {
+    email: String,
+    email_digest: Option<String>, // Checkbox, only sent when checked
+}
+
+// Record a notification as part of the caller's transaction
+pub async fn create(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    favorite_id: i64,
+    kind: &str,
+    message: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO notifications (user_id, favorite_id, kind, message) VALUES (?, ?, ?, ?)",
+        user_id,
+        favorite_id,
+        kind,
+        message
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+// Record a notification about a saved search as part of the caller's transaction
+pub async fn create_for_search(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    saved_search_id: i64,
+    kind: &str,
+    message: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO notifications (user_id, saved_search_id, kind, message) VALUES (?, ?, ?, ?)",
+        user_id,
+        saved_search_id,
+        kind,
+        message
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+// Record a notification about the user's account as part of the caller's transaction
+pub async fn create_for_user(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    kind: &str,
+    message: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO notifications (user_id, kind, message) VALUES (?, ?, ?)",
+        user_id,
+        kind,
+        message
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+// The user's most recent notifications, newest first
+pub async fn list_notifications(pool: &SqlitePool, user_id: i64) -> Result<Vec<Notification>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT id AS \"id!\", kind, message, created_at AS \"created_at: String\", read_at IS NOT NULL AS \"read!: bool\"
+         FROM notifications WHERE user_id = ? ORDER BY created_at DESC, id DESC LIMIT ?",
+        user_id,
+        RECENT_LIMIT
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Notification {
+            id: row.id,
+            kind: row.kind,
+            message: row.message,
+            created_at: row.created_at,
+            read: row.read,
+        })
+        .collect())
+}
+
+pub async fn unread_count(pool: &SqlitePool, user_id: i64) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM notifications WHERE user_id = ? AND read_at IS NULL",
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+}
+
+// Email each opted-in user the notifications they haven't been emailed about.
+// Returns the number of digests sent.
+pub async fn send_digests(pool: &SqlitePool, mailer: &dyn Mailer) -> Result<usize, Box<dyn Error>> {
+    let recipients = sqlx::query!(
+        "SELECT DISTINCT users.id AS \"id!\", users.username, users.email AS \"email!\"
+         FROM users JOIN notifications ON notifications.user_id = users.id
+         WHERE users.email_digest AND users.email IS NOT NULL AND users.email != ''
+           AND notifications.emailed_at IS NULL"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut sent = 0;
+    for recipient in recipients {
+        let pending = sqlx::query!(
+            "SELECT id AS \"id!\", message FROM notifications
+             WHERE user_id = ? AND emailed_at IS NULL ORDER BY created_at, id",
+            recipient.id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let lines: Vec<String> = pending.iter().map(|n| format!("- {}", n.message)).collect();
+        let email = Email {
+            to: recipient.email,
+            subject: format!("{} updates to your favorite providers and saved searches", pending.len()),
+            body: format!(
+                "Hi {},\n\nHere's what changed since your last digest:\n\n{}\n\nSee all notifications on your profile page.",
+                recipient.username,
+                lines.join("\n")
+            ),
+        };
+
+        if let Err(err) = mailer.send(&email) {
+            // Leave the notifications pending so the next digest includes them
+            eprintln!("Failed to send digest to user {}: {}", recipient.id, err);
+            continue;
+        }
+
+        let mut tx = pool.begin().await?;
+        for notification in &pending {
+            sqlx::query!(
+                "UPDATE notifications SET emailed_at = CURRENT_TIMESTAMP WHERE id = ?",
+                notification.id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        sent += 1;
+    }
+
+    Ok(sent)
+}
+
+// Start sending digests on the current runtime; runs for the life of the server
+pub fn spawn_digests(pool: SqlitePool, mailer: Box<dyn Mailer>) {
+    let interval = std::env::var("NOTIFICATION_DIGEST_INTERVAL_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_DIGEST_INTERVAL);
+
+    actix_web::rt::spawn(async move {
+        let mut ticker = actix_web::rt::time::interval(interval);
+        // The first tick fires immediately; wait a full interval before the first digest
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            match send_digests(&pool, mailer.as_ref()).await {
+                Ok(0) => {}
+                Ok(sent) => println!("Sent {} notification digests", sent),
+                Err(err) => eprintln!("Sending notification digests failed: {}", err),
+            }
+        }
+    });
+}
+
+fn not_logged_in() -> HttpResponse {
+    HttpResponse::Unauthorized().json(json!({
+        "status": "error",
+        "message": "User not logged in. Please log in and try again."
+    }))
+}
+
+// Handler for `GET /api/notifications`
+#[get("/api/notifications")]
+async fn get_notifications(req: HttpRequest, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+
+    let result = async {
+        let notifications = list_notifications(pool.get_ref(), user_id).await?;
+        let unread = unread_count(pool.get_ref(), user_id).await?;
+        Ok::<_, sqlx::Error>((notifications, unread))
+    }
+    .await;
+
+    match result {
+        Ok((notifications, unread)) => HttpResponse::Ok().json(json!({
+            "notifications": notifications,
+            "unread": unread
+        })),
+        Err(err) => {
+            eprintln!("Failed to fetch notifications: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Could not fetch notifications. Please try again later."
+            }))
+        }
+    }
+}
+
+// Handler for `POST /api/notifications/{id}/read`
+#[post("/api/notifications/{id}/read")]
+async fn mark_read(req: HttpRequest, path: web::Path<i64>, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let notification_id = path.into_inner();
+
+    let result = sqlx::query!(
+        "UPDATE notifications SET read_at = COALESCE(read_at, CURRENT_TIMESTAMP) WHERE id = ? AND user_id = ?",
+        notification_id,
+        user_id
+    )
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(res) if res.rows_affected() == 1 => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Notification marked as read."
+        })),
+        Ok(_) => HttpResponse::NotFound().json(json!({
+            "status": "error",
+            "message": "Notification not found."
+        })),
+        Err(err) => {
+            eprintln!("Failed to mark notification {} as read: {}", notification_id, err);
+            HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Failed to update notification. Please try again later."
+            }))
+        }
+    }
+}
+
+// Handler for `POST /api/notifications/read` (mark all as read)
+#[post("/api/notifications/read")]
+async fn mark_all_read(req: HttpRequest, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+
+    let result = sqlx::query!(
+        "UPDATE notifications SET read_at = CURRENT_TIMESTAMP WHERE user_id = ? AND read_at IS NULL",
+        user_id
+    )
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "All notifications marked as read."
+        })),
+        Err(err) => {
+            eprintln!("Failed to mark notifications as read: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Failed to update notifications. Please try again later."
+            }))
+        }
+    }
+}
+
+// Handler for the email digest form on the profile page
+#[post("/profile/notifications")]
+async fn update_digest_settings(
+    req: HttpRequest,
+    form: web::Form<DigestSettings>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Found().append_header(("Location", "/login")).finish();
+    };
+
+    let email = form.email.trim();
+    let email = (!email.is_empty()).then_some(email);
+    if email.is_some_and(|email| !email.contains('@')) {
+        return HttpResponse::BadRequest().body("Please enter a valid email address");
+    }
+    // A digest needs somewhere to go
+    let email_digest = form.email_digest.is_some() && email.is_some();
+
+    let result = async {
+        let mut tx = pool.begin().await?;
+        let was_enabled = sqlx::query_scalar!("SELECT email_digest FROM users WHERE id = ?", user_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .unwrap_or(false);
+        sqlx::query!(
+            "UPDATE users SET email = ?, email_digest = ? WHERE id = ?",
+            email,
+            email_digest,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        if email_digest && !was_enabled {
+            // The first digest only covers notifications created after opting in
+            sqlx::query!(
+                "UPDATE notifications SET emailed_at = CURRENT_TIMESTAMP WHERE user_id = ? AND emailed_at IS NULL",
+                user_id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+    .await;
+
+    if let Err(err) = result {
+        eprintln!("Failed to update digest settings: {}", err);
+        return HttpResponse::InternalServerError().body("Failed to save notification settings");
+    }
+
+    HttpResponse::Found().append_header(("Location", "/profile")).finish()
+}
@@ -0,0 +1,146 @@
+// On-demand Place Details lookups. Nearby Search never returns phone numbers,
+// websites or weekly hours, so these are fetched per provider when a user asks
+// for them. Only the fields below are requested, since Place Details is billed
+// by the data SKUs the requested fields fall into.
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const DETAILS_FIELDS: &str = "place_id,name,vicinity,rating,photos,business_status,\
+    formatted_phone_number,website,opening_hours,wheelchair_accessible_entrance";
+
+// The comparison page also shows how many ratings there are, which costs more, so
+// only its lookups ask for them
+const COMPARE_FIELDS: &str = "place_id,name,vicinity,rating,user_ratings_total,photos,business_status,\
+    formatted_phone_number,website,opening_hours,wheelchair_accessible_entrance";
+
+// How long fetched details are reused before asking Google again
+const CACHE_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlaceDetails {
+    pub place_id: String,
+    pub name: String,
+    pub address: String, // Short address, in the same format Nearby Search returns
+    pub rating: Option<f64>,
+    pub review_count: Option<u32>,
+    pub photo_url: Option<String>,
+    pub business_status: Option<String>, // "OPERATIONAL", "CLOSED_TEMPORARILY" or "CLOSED_PERMANENTLY"
+    pub phone: Option<String>,
+    pub website: Option<String>,
+    pub opening_hours: Vec<String>, // One line per weekday, e.g. "Monday: 9:00 AM – 5:00 PM"
+    pub wheelchair_accessible: Option<bool>,
+}
+
+// Field mask and place ID of a cached response
+type CacheKey = (&'static str, String);
+
+// In-memory cache of Place Details responses, shared across workers and keyed by
+// the fields requested and the place ID
+#[derive(Default)]
+pub struct PlaceDetailsCache {
+    entries: Mutex<HashMap<CacheKey, (Instant, PlaceDetails)>>,
+}
+
+impl PlaceDetailsCache {
+    fn get(&self, fields: &'static str, place_id: &str) -> Option<PlaceDetails> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(&(fields, place_id.to_string()))
+            .filter(|(fetched_at, _)| fetched_at.elapsed() < CACHE_TTL)
+            .map(|(_, details)| details.clone())
+    }
+
+    fn insert(&self, fields: &'static str, place_id: &str, details: PlaceDetails) {
+        let mut entries = self.entries.lock().unwrap();
+        // Drop expired entries so the cache doesn't grow without bound
+        entries.retain(|_, (fetched_at, _)| fetched_at.elapsed() < CACHE_TTL);
+        entries.insert((fields, place_id.to_string()), (Instant::now(), details));
+    }
+}
+
+// Fetch details for a place, or `None` if Google doesn't know the place ID
+async fn fetch_place_details(
+    place_id: &str,
+    fields: &str,
+    api_key: &str,
+) -> Result<Option<PlaceDetails>, Box<dyn Error>> {
+    let url = format!(
+        "https://maps.googleapis.com/maps/api/place/details/json?place_id={}&fields={}&key={}",
+        urlencoding::encode(place_id), fields, api_key
+    );
+
+    let response: serde_json::Value = reqwest::Client::new()
+        .get(&url)
+        .send()
+        .await?
+        .json()
+        .await?;
+
+    match response["status"].as_str() {
+        Some("OK") => {}
+        Some("NOT_FOUND") | Some("INVALID_REQUEST") => return Ok(None),
+        status => return Err(format!("Place Details request failed: {:?}", status).into()),
+    }
+
+    let result = &response["result"];
+    Ok(Some(PlaceDetails {
+        place_id: result["place_id"].as_str().unwrap_or(place_id).to_string(),
+        name: result["name"].as_str().unwrap_or("").to_string(),
+        address: result["vicinity"].as_str().unwrap_or("").to_string(),
+        rating: result["rating"].as_f64(),
+        review_count: result["user_ratings_total"].as_u64().map(|n| n as u32),
+        photo_url: result["photos"][0]["photo_reference"].as_str().map(|photo_reference| {
+            format!(
+                "https://maps.googleapis.com/maps/api/place/photo?maxwidth=400&photoreference={}&key={}",
+                photo_reference, api_key
+            )
+        }),
+        business_status: result["business_status"].as_str().map(String::from),
+        phone: result["formatted_phone_number"].as_str().map(String::from),
+        website: result["website"].as_str().map(String::from),
+        opening_hours: result["opening_hours"]["weekday_text"]
+            .as_array()
+            .map(|days| days.iter().filter_map(|d| d.as_str()).map(String::from).collect())
+            .unwrap_or_default(),
+        wheelchair_accessible: result["wheelchair_accessible_entrance"].as_bool(),
+    }))
+}
+
+async fn cached_fields(
+    cache: &PlaceDetailsCache,
+    fields: &'static str,
+    place_id: &str,
+    api_key: &str,
+) -> Result<Option<PlaceDetails>, Box<dyn Error>> {
+    if let Some(details) = cache.get(fields, place_id) {
+        return Ok(Some(details));
+    }
+
+    let details = fetch_place_details(place_id, fields, api_key).await?;
+    if let Some(details) = &details {
+        cache.insert(fields, place_id, details.clone());
+    }
+    Ok(details)
+}
+
+// Return details for a place from the cache, fetching them from Google when missing or stale.
+// `review_count` is left out.
+pub async fn cached_place_details(
+    cache: &PlaceDetailsCache,
+    place_id: &str,
+    api_key: &str,
+) -> Result<Option<PlaceDetails>, Box<dyn Error>> {
+    cached_fields(cache, DETAILS_FIELDS, place_id, api_key).await
+}
+
+// Details for the comparison page, including `review_count`
+pub async fn cached_comparison_details(
+    cache: &PlaceDetailsCache,
+    place_id: &str,
+    api_key: &str,
+) -> Result<Option<PlaceDetails>, Box<dyn Error>> {
+    cached_fields(cache, COMPARE_FIELDS, place_id, api_key).await
+}
@@ -0,0 +1,582 @@
+// Client for FHIR R4 provider directories published under the Da Vinci PDex
+// Plan-Net implementation guide. Health plans expose Practitioner,
+// PractitionerRole, Organization, Location, HealthcareService and InsurancePlan
+// resources; this module searches them near a coordinate and maps each
+// Location into a `HealthProvider`, listing the practitioners and services
+// offered there along with the networks and plans they participate in.
+use crate::find_providers::{calculate_distance, Coordinates, HealthProvider, Service};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+
+const NPI_SYSTEM: &str = "http://hl7.org/fhir/sid/us-npi";
+const NUCC_SYSTEM: &str = "http://nucc.org/provider-taxonomy";
+const NETWORK_EXTENSION: &str =
+    "http://hl7.org/fhir/us/davinci-pdex-plan-net/StructureDefinition/network-reference";
+
+// Stop following `next` links after this many pages of a single search
+const MAX_PAGES: usize = 5;
+
+// Map the dashboard's service types onto the NUCC taxonomy codes Plan-Net uses for specialties
+fn specialty_for_service_type(service_type: &str) -> Option<&'static str> {
+    match service_type {
+        "dentist" => Some("122300000X"),
+        "pharmacy" => Some("3336C0003X"),
+        "physiotherapist" => Some("225100000X"),
+        _ => None,
+    }
+}
+
+// Run a FHIR search and collect every resource in the result Bundle (including
+// `_include`d ones), keyed by "Type/id" so references can be resolved locally
+async fn search(url: &str) -> Result<HashMap<String, Value>, Box<dyn Error>> {
+    let client = reqwest::Client::new();
+    let mut resources = HashMap::new();
+    let mut next_url = Some(url.to_string());
+    let mut pages = 0;
+
+    while let Some(url) = next_url.take() {
+        let bundle: Value = client
+            .get(&url)
+            .header("Accept", "application/fhir+json")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if let Some(entries) = bundle["entry"].as_array() {
+            for entry in entries {
+                let resource = &entry["resource"];
+                if let (Some(resource_type), Some(id)) =
+                    (resource["resourceType"].as_str(), resource["id"].as_str())
+                {
+                    resources.insert(format!("{}/{}", resource_type, id), resource.clone());
+                }
+            }
+        }
+
+        pages += 1;
+        if pages < MAX_PAGES {
+            next_url = bundle["link"]
+                .as_array()
+                .and_then(|links| links.iter().find(|link| link["relation"] == "next"))
+                .and_then(|link| link["url"].as_str())
+                .map(String::from);
+        }
+    }
+
+    Ok(resources)
+}
+
+// Normalize a relative or absolute reference ("http://host/fhir/Location/1") to "Location/1"
+fn reference_key(reference: &str) -> String {
+    let mut parts = reference.trim_end_matches('/').rsplit('/');
+    match (parts.next(), parts.next()) {
+        (Some(id), Some(resource_type)) => format!("{}/{}", resource_type, id),
+        _ => reference.to_string(),
+    }
+}
+
+fn references(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|refs| {
+            refs.iter()
+                .filter_map(|r| r["reference"].as_str())
+                .map(reference_key)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn resources_of_type<'a>(
+    resources: &'a HashMap<String, Value>,
+    resource_type: &'a str,
+) -> impl Iterator<Item = (&'a String, &'a Value)> {
+    resources
+        .iter()
+        .filter(move |(_, resource)| resource["resourceType"] == resource_type)
+}
+
+fn coding_display(concepts: &Value) -> Option<String> {
+    let concept = concepts.get(0)?;
+    concept["text"]
+        .as_str()
+        .or_else(|| concept["coding"][0]["display"].as_str())
+        .or_else(|| concept["coding"][0]["code"].as_str())
+        .map(String::from)
+}
+
+fn format_address(address: &Value) -> String {
+    if let Some(text) = address["text"].as_str() {
+        return text.to_string();
+    }
+
+    let mut parts: Vec<String> = address["line"]
+        .as_array()
+        .map(|lines| lines.iter().filter_map(|l| l.as_str()).map(String::from).collect())
+        .unwrap_or_default();
+    if let Some(city) = address["city"].as_str() {
+        parts.push(city.to_string());
+    }
+    let state_zip = [address["state"].as_str(), address["postalCode"].as_str()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if !state_zip.is_empty() {
+        parts.push(state_zip);
+    }
+    parts.join(", ")
+}
+
+fn format_name(practitioner: &Value) -> Option<String> {
+    let name = practitioner["name"].get(0)?;
+    if let Some(text) = name["text"].as_str() {
+        return Some(text.to_string());
+    }
+    let given = name["given"]
+        .as_array()
+        .map(|given| given.iter().filter_map(|g| g.as_str()).collect::<Vec<_>>().join(" "))
+        .unwrap_or_default();
+    let full = format!("{} {}", given, name["family"].as_str().unwrap_or("")).trim().to_string();
+    if full.is_empty() { None } else { Some(full) }
+}
+
+fn npi(resource: &Value) -> Option<String> {
+    resource["identifier"]
+        .as_array()?
+        .iter()
+        .find(|identifier| identifier["system"] == NPI_SYSTEM)
+        .and_then(|identifier| identifier["value"].as_str())
+        .map(String::from)
+}
+
+fn phone(resource: &Value) -> Option<String> {
+    resource["telecom"]
+        .as_array()?
+        .iter()
+        .find(|telecom| telecom["system"] == "phone")
+        .and_then(|telecom| telecom["value"].as_str())
+        .map(String::from)
+}
+
+// Network Organizations a PractitionerRole or HealthcareService belongs to
+fn network_references(resource: &Value) -> Vec<String> {
+    resource["extension"]
+        .as_array()
+        .map(|extensions| {
+            extensions
+                .iter()
+                .filter(|extension| extension["url"] == NETWORK_EXTENSION)
+                .filter_map(|extension| extension["valueReference"]["reference"].as_str())
+                .map(reference_key)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Networks an InsurancePlan covers, either at the product level or on one of its plans
+fn plan_network_references(plan: &Value) -> Vec<String> {
+    let mut networks = references(&plan["network"]);
+    if let Some(plans) = plan["plan"].as_array() {
+        for p in plans {
+            networks.extend(references(&p["network"]));
+        }
+    }
+    networks
+}
+
+fn push_unique(values: &mut Vec<String>, value: String) {
+    if !values.contains(&value) {
+        values.push(value);
+    }
+}
+
+pub async fn find_plan_net_providers(
+    base_url: &str,
+    coordinates: &Coordinates,
+    radius_meters: u32,
+    service_type: &str,
+) -> Result<Vec<HealthProvider>, Box<dyn Error>> {
+    let base_url = base_url.trim_end_matches('/');
+    let near = format!(
+        "{}|{}|{}|km",
+        coordinates.lat,
+        coordinates.lng,
+        radius_meters as f64 / 1000.0
+    );
+
+    let locations = search(&format!(
+        "{}/Location?near={}&_include=Location:organization&_count=50",
+        base_url,
+        urlencoding::encode(&near)
+    ))
+    .await?;
+
+    let location_ids: Vec<String> = resources_of_type(&locations, "Location")
+        .map(|(key, _)| key.clone())
+        .collect();
+    if location_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let location_param = urlencoding::encode(&location_ids.join(",")).into_owned();
+
+    let specialty = specialty_for_service_type(service_type);
+    let mut role_url = format!(
+        "{}/PractitionerRole?location={}&_include=PractitionerRole:practitioner&_include=PractitionerRole:network&_count=100",
+        base_url, location_param
+    );
+    if let Some(code) = specialty {
+        let token = format!("{}|{}", NUCC_SYSTEM, code);
+        role_url.push_str(&format!("&specialty={}", urlencoding::encode(&token)));
+    }
+    let roles = search(&role_url).await?;
+
+    let healthcare_services = search(&format!(
+        "{}/HealthcareService?location={}&_count=100",
+        base_url, location_param
+    ))
+    .await?;
+
+    // Plan-Net has no search parameter linking plans to networks, so fetch the
+    // plan catalog and match networks locally
+    let plans = search(&format!("{}/InsurancePlan?_count=100", base_url)).await?;
+
+    // Everything fetched so far, for resolving references to names
+    let mut resolved = HashMap::new();
+    resolved.extend(locations.iter().map(|(k, v)| (k.clone(), v)));
+    resolved.extend(roles.iter().map(|(k, v)| (k.clone(), v)));
+
+    let mut providers = Vec::new();
+
+    for (location_key, location) in resources_of_type(&locations, "Location") {
+        let (Some(lat), Some(lng)) = (
+            location["position"]["latitude"].as_f64(),
+            location["position"]["longitude"].as_f64(),
+        ) else {
+            continue; // Locations without a position can't be placed on the map
+        };
+
+        let location_roles: Vec<&Value> = resources_of_type(&roles, "PractitionerRole")
+            .map(|(_, role)| role)
+            .filter(|role| references(&role["location"]).contains(location_key))
+            .filter(|role| role["active"].as_bool().unwrap_or(true))
+            .collect();
+
+        let location_services: Vec<&Value> =
+            resources_of_type(&healthcare_services, "HealthcareService")
+                .map(|(_, service)| service)
+                .filter(|service| references(&service["location"]).contains(location_key))
+                .collect();
+
+        // When filtering by specialty, only keep locations where a matching practitioner works
+        if specialty.is_some() && location_roles.is_empty() {
+            continue;
+        }
+        if location_roles.is_empty() && location_services.is_empty() {
+            continue;
+        }
+
+        let mut services = Vec::new();
+        let mut network_keys = Vec::new();
+
+        for role in &location_roles {
+            let practitioner = role["practitioner"]["reference"]
+                .as_str()
+                .and_then(|reference| resolved.get(&reference_key(reference)).copied());
+
+            services.push(Service {
+                name: practitioner
+                    .and_then(format_name)
+                    .or_else(|| role["practitioner"]["display"].as_str().map(String::from))
+                    .unwrap_or_default(),
+                npi: practitioner.and_then(npi).unwrap_or_default(),
+                taxonomy: coding_display(&role["specialty"]).unwrap_or_default(),
+            });
+
+            for network in network_references(role) {
+                push_unique(&mut network_keys, network);
+            }
+        }
+
+        for service in &location_services {
+            services.push(Service {
+                name: service["name"].as_str().unwrap_or("").to_string(),
+                npi: npi(service).unwrap_or_default(),
+                taxonomy: coding_display(&service["category"])
+                    .or_else(|| coding_display(&service["type"]))
+                    .unwrap_or_default(),
+            });
+
+            for network in network_references(service) {
+                push_unique(&mut network_keys, network);
+            }
+        }
+
+        let mut networks = Vec::new();
+        for key in &network_keys {
+            let name = resolved
+                .get(key)
+                .and_then(|network| network["name"].as_str())
+                .map(String::from)
+                .unwrap_or_else(|| key.clone());
+            push_unique(&mut networks, name);
+        }
+
+        let mut accepted_insurance = Vec::new();
+        for (_, plan) in resources_of_type(&plans, "InsurancePlan") {
+            let in_network = plan_network_references(plan)
+                .iter()
+                .any(|network| network_keys.contains(network));
+            if in_network {
+                if let Some(name) = plan["name"].as_str() {
+                    push_unique(&mut accepted_insurance, name.to_string());
+                }
+            }
+        }
+
+        let organization = location["managingOrganization"]["reference"]
+            .as_str()
+            .and_then(|reference| resolved.get(&reference_key(reference)).copied());
+
+        let name = location["name"]
+            .as_str()
+            .or_else(|| organization.and_then(|org| org["name"].as_str()))
+            .unwrap_or("")
+            .to_string();
+
+        let provider_type = location_roles
+            .first()
+            .and_then(|role| coding_display(&role["specialty"]))
+            .unwrap_or_else(|| service_type.to_string());
+
+        // Prefer the location's own NPI, falling back to its directory ID
+        let source_id = match npi(location) {
+            Some(npi) => format!("npi:{}", npi),
+            None => format!("plan-net:{}", location["id"].as_str().unwrap_or("")),
+        };
+
+        providers.push(HealthProvider {
+            id: source_id.clone(),
+            source_ids: vec![source_id],
+            place_id: None,
+            name,
+            address: format_address(&location["address"]),
+            location: Some(Coordinates { lat, lng }),
+            distance: calculate_distance(coordinates, lat, lng),
+            provider_type,
+            phone: phone(location).or_else(|| location_roles.iter().find_map(|role| phone(role))),
+            rating: None, // Directories don't carry ratings
+            review_count: None,
+            photo_url: None,
+            open_now: false, // Directories publish hours, not live open/closed status
+            services,
+            networks,
+            accepted_insurance,
+            in_network: false,
+            hours: Vec::new(), // Directory hours aren't parsed
+            telehealth: None,
+            updated_by_provider: false,
+            insurance_corrected: false,
+            community_rating: None,
+            community_review_count: 0,
+        });
+    }
+
+    providers.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+
+    Ok(providers)
+}
+
+// A plan from the directory's catalog with the provider locations in its networks
+pub struct PlanNetwork {
+    pub name: String,
+    pub payer: String,
+    pub providers: Vec<(String, String)>, // (provider name, address)
+}
+
+// Walk the directory's InsurancePlan catalog and list the locations where each
+// plan's network practitioners work, for importing into `plan_providers`
+pub async fn fetch_plan_networks(base_url: &str) -> Result<Vec<PlanNetwork>, Box<dyn Error>> {
+    let base_url = base_url.trim_end_matches('/');
+    let plans = search(&format!(
+        "{}/InsurancePlan?_include=InsurancePlan:owned-by&_count=100",
+        base_url
+    ))
+    .await?;
+
+    // Several plans usually share a network, so only search each network once
+    let mut network_locations: HashMap<String, Vec<(String, String)>> = HashMap::new();
+    let mut plan_networks = Vec::new();
+
+    for (_, plan) in resources_of_type(&plans, "InsurancePlan") {
+        let Some(name) = plan["name"].as_str() else {
+            continue;
+        };
+
+        let payer = plan["ownedBy"]["reference"]
+            .as_str()
+            .and_then(|reference| plans.get(&reference_key(reference)))
+            .and_then(|owner| owner["name"].as_str())
+            .or_else(|| plan["ownedBy"]["display"].as_str())
+            .unwrap_or("")
+            .to_string();
+
+        let mut providers = Vec::new();
+        for network in plan_network_references(plan) {
+            if !network_locations.contains_key(&network) {
+                let roles = search(&format!(
+                    "{}/PractitionerRole?network={}&_include=PractitionerRole:location&_count=100",
+                    base_url,
+                    urlencoding::encode(&network)
+                ))
+                .await?;
+
+                let locations = resources_of_type(&roles, "Location")
+                    .filter_map(|(_, location)| {
+                        let name = location["name"].as_str()?;
+                        Some((name.to_string(), format_address(&location["address"])))
+                    })
+                    .collect();
+                network_locations.insert(network.clone(), locations);
+            }
+
+            for location in &network_locations[&network] {
+                if !providers.contains(location) {
+                    providers.push(location.clone());
+                }
+            }
+        }
+
+        plan_networks.push(PlanNetwork {
+            name: name.to_string(),
+            payer,
+            providers,
+        });
+    }
+
+    Ok(plan_networks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // Serve `/fhir/{resource_type}` searches from a test server, returning its base URL
+    async fn serve<F>(handler: F) -> String
+    where
+        F: Fn(String, String) -> Value + Clone + Send + 'static,
+    {
+        let server = HttpServer::new(move || {
+            let handler = handler.clone();
+            App::new().route(
+                "/fhir/{resource_type}",
+                web::get().to(move |req: actix_web::HttpRequest, path: web::Path<String>| {
+                    let base = format!("http://{}/fhir", req.connection_info().host());
+                    let bundle = handler(base, path.into_inner());
+                    async move { HttpResponse::Ok().json(bundle) }
+                }),
+            )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let address = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+        format!("http://{}/fhir", address)
+    }
+
+    // The canned Bundles the `plan_net_fixture` example serves
+    fn fixture(_base: String, resource_type: String) -> Value {
+        std::fs::read_to_string(format!("./fixtures/plan_net/{}.json", resource_type))
+            .map(|body| serde_json::from_str(&body).unwrap())
+            .unwrap_or_else(|_| json!({ "resourceType": "Bundle", "type": "searchset", "entry": [] }))
+    }
+
+    #[actix_web::test]
+    async fn locations_map_to_providers() {
+        let base_url = serve(fixture).await;
+        let near = Coordinates { lat: 38.9107, lng: -77.0437 };
+        let providers = find_plan_net_providers(&base_url, &near, 5000, "doctor").await.unwrap();
+
+        // Nearest first
+        let ids: Vec<&str> = providers.iter().map(|provider| provider.id.as_str()).collect();
+        assert_eq!(ids, ["plan-net:dupont-family-health", "plan-net:georgetown-smiles"]);
+
+        let dupont = &providers[0];
+        assert_eq!(dupont.source_ids, ["plan-net:dupont-family-health"]);
+        assert_eq!(dupont.name, "Dupont Circle Family Health");
+        assert_eq!(dupont.address, "1500 Connecticut Avenue Northwest, Washington, DC 20036");
+        assert_eq!(dupont.phone.as_deref(), Some("(202) 555-0142"));
+        assert_eq!(dupont.provider_type, "Family Medicine");
+        assert_eq!(dupont.distance, 0.0);
+        assert!(dupont.place_id.is_none() && dupont.rating.is_none() && !dupont.open_now);
+        let services: Vec<(&str, &str, &str)> = dupont
+            .services
+            .iter()
+            .map(|service| (service.name.as_str(), service.npi.as_str(), service.taxonomy.as_str()))
+            .collect();
+        assert_eq!(
+            services,
+            [
+                ("Linh Nguyen", "1234567893", "Family Medicine"),
+                ("Telehealth Primary Care Visits", "", "Medical Provider"),
+            ]
+        );
+        assert_eq!(dupont.networks, ["Acme HMO Network"]);
+        assert_eq!(dupont.accepted_insurance, ["Acme Gold HMO"]);
+
+        // Networks are matched to plans at the product level and on individual plans
+        let georgetown = &providers[1];
+        assert_eq!(georgetown.services[0].name, "Chidi Okafor, DDS");
+        let mut networks = georgetown.networks.clone();
+        networks.sort();
+        assert_eq!(networks, ["Acme HMO Network", "Bluestone PPO Network"]);
+        let mut plans = georgetown.accepted_insurance.clone();
+        plans.sort();
+        assert_eq!(plans, ["Acme Gold HMO", "Bluestone Choice PPO"]);
+    }
+
+    #[actix_web::test]
+    async fn search_follows_next_links_up_to_max_pages() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        // Every page links to another one
+        let base_url = serve(move |base: String, _| {
+            let page = counter.fetch_add(1, Ordering::SeqCst);
+            json!({
+                "resourceType": "Bundle",
+                "type": "searchset",
+                "link": [{ "relation": "next", "url": format!("{}/Location?page={}", base, page + 1) }],
+                "entry": [{ "resource": { "resourceType": "Location", "id": format!("page-{}", page) } }]
+            })
+        })
+        .await;
+
+        let resources = search(&format!("{}/Location", base_url)).await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), MAX_PAGES);
+        assert_eq!(resources.len(), MAX_PAGES);
+        assert!(resources.contains_key("Location/page-0"));
+    }
+
+    #[actix_web::test]
+    async fn search_stops_without_next_link() {
+        let base_url = serve(fixture).await;
+        let resources = search(&format!("{}/Location", base_url)).await.unwrap();
+        assert_eq!(resources.len(), 3);
+        assert!(resources.contains_key("Organization/capitol-health-group"));
+    }
+
+    #[test]
+    fn references_are_normalized() {
+        assert_eq!(reference_key("http://host/fhir/Location/1"), "Location/1");
+        assert_eq!(reference_key("Location/1/"), "Location/1");
+        assert_eq!(reference_key("1"), "1");
+    }
+}
@@ -0,0 +1,627 @@
+// Listings managed by a provider's own staff. A logged-in user asks to manage a
+// provider ID, and an admin approves or rejects the claim. Staff with an approved
+// claim can correct the listing's phone number, hours, telehealth availability and
+// accepted insurance. Corrections are kept apart from the records built from
+// upstream sources, which keep being refreshed by searches, and are laid over them
+// whenever a provider is shown, with `updated_by_provider` set so the page can say so.
+use crate::find_providers::HealthProvider;
+use crate::insurance;
+use crate::notifications;
+use crate::place_details::PlaceDetails;
+use crate::provider_store;
+use crate::reminders::valid_phone;
+use crate::session::{current_user_id, require_admin};
+
+use actix_web::http::StatusCode;
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use handlebars::Handlebars;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+
+const MAX_ROLE_CHARS: usize = 100;
+const MAX_MESSAGE_CHARS: usize = 1000;
+const MAX_HOURS_LINES: usize = 14;
+const MAX_LINE_CHARS: usize = 100;
+const MAX_PLANS: usize = 50;
+
+// Claims listed at once on the admin endpoint
+const LIST_LIMIT: i64 = 100;
+
+// Staff corrections to a listing; `None` leaves the upstream value in place
+#[derive(Default, Serialize, Deserialize)]
+pub struct ListingOverride {
+    pub phone: Option<String>,
+    pub hours: Option<Vec<String>>,
+    pub telehealth: Option<bool>,
+    pub accepted_insurance: Option<Vec<String>>,
+}
+
+impl ListingOverride {
+    fn is_empty(&self) -> bool {
+        self.phone.is_none() && self.hours.is_none() && self.telehealth.is_none() && self.accepted_insurance.is_none()
+    }
+
+    pub fn apply(&self, provider: &mut HealthProvider) {
+        if let Some(phone) = &self.phone {
+            provider.phone = Some(phone.clone());
+        }
+        if let Some(hours) = &self.hours {
+            provider.hours = hours.clone();
+        }
+        if let Some(telehealth) = self.telehealth {
+            provider.telehealth = Some(telehealth);
+        }
+        if let Some(plans) = &self.accepted_insurance {
+            provider.accepted_insurance = plans.clone();
+            provider.insurance_corrected = true;
+        }
+        provider.updated_by_provider = true;
+    }
+
+    // Place Details carry their own phone number and hours, which the staff's win over
+    pub fn apply_to_details(&self, details: &mut PlaceDetails) {
+        if let Some(phone) = &self.phone {
+            details.phone = Some(phone.clone());
+        }
+        if let Some(hours) = &self.hours {
+            details.opening_hours = hours.clone();
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ClaimRequest {
+    role: String,
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct ClaimQuery {
+    status: Option<String>, // Defaults to "pending"
+}
+
+#[derive(Deserialize)]
+struct PortalQuery {
+    claim: Option<String>, // Provider ID to show the claim form for
+}
+
+#[derive(Serialize)]
+struct ClaimSummary {
+    id: i64,
+    provider_id: String,
+    provider_name: Option<String>,
+    provider_address: Option<String>,
+    username: String,
+    role: String,
+    message: String,
+    status: String,
+    created_at: String,
+    decided_at: Option<String>,
+}
+
+fn error(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+fn not_logged_in() -> HttpResponse {
+    error(StatusCode::UNAUTHORIZED, "User not logged in. Please log in and try again.")
+}
+
+fn parse_list(value: Option<String>) -> Option<Vec<String>> {
+    value.and_then(|value| serde_json::from_str(&value).ok())
+}
+
+// Trimmed, non-blank lines, or None if there are too many or any is too long
+fn clean_lines(lines: &[String], max_lines: usize) -> Option<Vec<String>> {
+    let lines: Vec<String> = lines
+        .iter()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect();
+    let fits = lines.len() <= max_lines && lines.iter().all(|line| line.chars().count() <= MAX_LINE_CHARS);
+    fits.then_some(lines)
+}
+
+// The corrections for one provider, if its staff have made any
+pub async fn find_override(pool: &SqlitePool, provider_id: &str) -> Result<Option<ListingOverride>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT phone, hours, telehealth, accepted_insurance FROM provider_overrides WHERE provider_id = ?",
+        provider_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| ListingOverride {
+        phone: row.phone,
+        hours: parse_list(row.hours),
+        telehealth: row.telehealth,
+        accepted_insurance: parse_list(row.accepted_insurance),
+    }))
+}
+
+// Lay staff corrections over a list of search results
+pub async fn apply_overrides(pool: &SqlitePool, providers: &mut [HealthProvider]) -> Result<(), sqlx::Error> {
+    let ids = json!(providers.iter().map(|provider| &provider.id).collect::<Vec<_>>()).to_string();
+    let rows = sqlx::query!(
+        "SELECT provider_id AS \"provider_id!\", phone, hours, telehealth, accepted_insurance FROM provider_overrides
+         WHERE provider_id IN (SELECT value FROM json_each(?))",
+        ids
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let overrides: HashMap<String, ListingOverride> = rows
+        .into_iter()
+        .map(|row| {
+            let listing = ListingOverride {
+                phone: row.phone,
+                hours: parse_list(row.hours),
+                telehealth: row.telehealth,
+                accepted_insurance: parse_list(row.accepted_insurance),
+            };
+            (row.provider_id, listing)
+        })
+        .collect();
+
+    for provider in providers.iter_mut() {
+        if let Some(listing) = overrides.get(&provider.id) {
+            listing.apply(provider);
+        }
+    }
+    Ok(())
+}
+
+async fn manages_listing(pool: &SqlitePool, provider_id: &str, user_id: i64) -> Result<bool, sqlx::Error> {
+    let claims = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM provider_claims WHERE provider_id = ? AND user_id = ? AND status = 'approved'",
+        provider_id,
+        user_id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(claims > 0)
+}
+
+// The provider a listing ID refers to, if the user manages it
+async fn managed_provider(
+    pool: &SqlitePool,
+    provider_id: &str,
+    user_id: i64,
+) -> Result<Option<HealthProvider>, sqlx::Error> {
+    let Some(provider) = provider_store::find(pool, provider_id).await? else {
+        return Ok(None);
+    };
+    if manages_listing(pool, &provider.id, user_id).await? {
+        Ok(Some(provider))
+    } else {
+        Ok(None)
+    }
+}
+
+// Handler for the `/provider-portal` page: the user's claims and the listings they manage
+#[get("/provider-portal")]
+async fn portal_page(
+    req: HttpRequest,
+    query: web::Query<PortalQuery>,
+    pool: web::Data<SqlitePool>,
+    hb: web::Data<Handlebars<'_>>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Found().append_header(("Location", "/login")).finish();
+    };
+
+    let mut data = serde_json::Map::new();
+    if let Some(id) = &query.claim {
+        match provider_store::find(pool.get_ref(), id).await {
+            Ok(Some(provider)) => {
+                data.insert(
+                    "claim_provider".to_string(),
+                    json!({ "id": provider.id, "name": provider.name, "address": provider.address }),
+                );
+            }
+            Ok(None) => {
+                data.insert("error".to_string(), json!("That provider isn't in our listings."));
+            }
+            Err(err) => eprintln!("Failed to fetch provider {}: {}", id, err),
+        }
+    }
+
+    let claims = sqlx::query!(
+        "SELECT provider_claims.id AS \"id!\", provider_claims.provider_id, provider_claims.role, provider_claims.status,
+                provider_claims.created_at AS \"created_at: String\", providers.name AS \"provider_name?\",
+                providers.address AS \"provider_address?\"
+         FROM provider_claims LEFT JOIN providers ON providers.id = provider_claims.provider_id
+         WHERE provider_claims.user_id = ? ORDER BY provider_claims.created_at DESC, provider_claims.id DESC",
+        user_id
+    )
+    .fetch_all(pool.get_ref())
+    .await;
+    match claims {
+        Ok(claims) => {
+            let claims: Vec<serde_json::Value> = claims
+                .into_iter()
+                .map(|claim| {
+                    json!({
+                        "id": claim.id,
+                        "provider_id": claim.provider_id,
+                        "provider_name": claim.provider_name.unwrap_or_else(|| claim.provider_id.clone()),
+                        "provider_address": claim.provider_address,
+                        "role": claim.role,
+                        "status": claim.status,
+                        "approved": claim.status == "approved",
+                        "created_at": claim.created_at
+                    })
+                })
+                .collect();
+            data.insert("claims".to_string(), json!(claims));
+        }
+        Err(err) => {
+            eprintln!("Failed to list provider claims: {}", err);
+            data.insert("error".to_string(), json!("Could not fetch your claims"));
+        }
+    }
+
+    let body = hb.render("provider_portal", &data).unwrap_or_else(|_| "Template error".to_string());
+    HttpResponse::Ok().body(body)
+}
+
+// Handler for the listing edit page
+#[get("/provider-portal/{id}")]
+async fn listing_page(
+    req: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+    hb: web::Data<Handlebars<'_>>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Found().append_header(("Location", "/login")).finish();
+    };
+    let id = path.into_inner();
+
+    let result = async {
+        let Some(provider) = managed_provider(pool.get_ref(), &id, user_id).await? else {
+            return Ok(None);
+        };
+        let listing = find_override(pool.get_ref(), &provider.id).await?.unwrap_or_default();
+        let plans = insurance::list_plans(pool.get_ref()).await?;
+        Ok::<_, sqlx::Error>(Some((provider, listing, plans)))
+    }
+    .await;
+
+    let (provider, listing, plans) = match result {
+        Ok(Some(found)) => found,
+        Ok(None) => return HttpResponse::NotFound().body("You don't manage this listing"),
+        Err(err) => {
+            eprintln!("Failed to load listing {}: {}", id, err);
+            return HttpResponse::InternalServerError().body("Failed to load listing");
+        }
+    };
+
+    // Known plans as checkboxes, so names match what users pick on their profile
+    let accepted = listing.accepted_insurance.as_deref().unwrap_or(&provider.accepted_insurance);
+    let plan_names: Vec<&str> = plans.iter().map(|plan| plan.name.as_str()).collect();
+    let plans: Vec<serde_json::Value> = plans
+        .iter()
+        .map(|plan| {
+            json!({
+                "name": plan.name,
+                "payer": plan.payer,
+                "checked": accepted.iter().any(|name| name.eq_ignore_ascii_case(&plan.name))
+            })
+        })
+        .collect();
+    let other_plans: Vec<&String> = accepted
+        .iter()
+        .filter(|name| !plan_names.iter().any(|plan| plan.eq_ignore_ascii_case(name)))
+        .collect();
+
+    let data = json!({
+        "provider_id": provider.id,
+        "name": provider.name,
+        "address": provider.address,
+        "upstream_phone": provider.phone,
+        "upstream_insurance": provider.accepted_insurance.join(", "),
+        "phone": listing.phone,
+        "hours": listing.hours.as_deref().unwrap_or_default().join("\n"),
+        "telehealth_yes": listing.telehealth == Some(true),
+        "telehealth_no": listing.telehealth == Some(false),
+        "override_insurance": listing.accepted_insurance.is_some(),
+        "plans": plans,
+        "other_plans": other_plans.iter().map(|name| name.as_str()).collect::<Vec<_>>().join("\n"),
+        "has_override": !listing.is_empty()
+    });
+    let body = hb.render("provider_listing", &data).unwrap_or_else(|_| "Template error".to_string());
+    HttpResponse::Ok().body(body)
+}
+
+// Handler for `POST /api/providers/{id}/claims` (ask to manage a listing)
+#[post("/api/providers/{id}/claims")]
+async fn create_claim(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<ClaimRequest>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let id = path.into_inner();
+    let role = body.role.trim();
+    let message = body.message.trim();
+    if role.is_empty() || role.chars().count() > MAX_ROLE_CHARS {
+        return error(StatusCode::BAD_REQUEST, "Please say what your role is, in up to 100 characters.");
+    }
+    if message.chars().count() > MAX_MESSAGE_CHARS {
+        return error(StatusCode::BAD_REQUEST, "Notes can be up to 1000 characters.");
+    }
+
+    let provider = match provider_store::find(pool.get_ref(), &id).await {
+        Ok(Some(provider)) => provider,
+        Ok(None) => return error(StatusCode::NOT_FOUND, "Provider not found."),
+        Err(err) => {
+            eprintln!("Failed to fetch provider {}: {}", id, err);
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to submit claim. Please try again later.");
+        }
+    };
+
+    let result = sqlx::query_scalar!(
+        "INSERT INTO provider_claims (provider_id, user_id, role, message) VALUES (?, ?, ?, ?) RETURNING id AS \"id!\"",
+        provider.id,
+        user_id,
+        role,
+        message
+    )
+    .fetch_one(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(claim_id) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": format!("Thanks! We'll review your claim for {} and let you know.", provider.name),
+            "id": claim_id
+        })),
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            error(StatusCode::CONFLICT, "You already have a claim for this listing.")
+        }
+        Err(err) => {
+            eprintln!("Failed to save provider claim: {}", err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to submit claim. Please try again later.")
+        }
+    }
+}
+
+// Handler for `PUT /api/providers/{id}/listing` (replace the staff's corrections)
+#[put("/api/providers/{id}/listing")]
+async fn update_listing(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<ListingOverride>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let id = path.into_inner();
+
+    let phone = body.phone.as_deref().map(str::trim).filter(|phone| !phone.is_empty());
+    if phone.is_some_and(|phone| !valid_phone(phone)) {
+        return error(StatusCode::BAD_REQUEST, "Please enter a valid phone number.");
+    }
+    let hours = match &body.hours {
+        Some(lines) => match clean_lines(lines, MAX_HOURS_LINES) {
+            Some(lines) if lines.is_empty() => None,
+            Some(lines) => Some(lines),
+            None => return error(StatusCode::BAD_REQUEST, "Hours can be up to 14 lines of 100 characters."),
+        },
+        None => None,
+    };
+    let accepted_insurance = match &body.accepted_insurance {
+        // An empty list is a correction too: no insurance accepted
+        Some(plans) => match clean_lines(plans, MAX_PLANS) {
+            Some(plans) => Some(plans),
+            None => return error(StatusCode::BAD_REQUEST, "List up to 50 plans of 100 characters."),
+        },
+        None => None,
+    };
+    let listing = ListingOverride {
+        phone: phone.map(String::from),
+        hours,
+        telehealth: body.telehealth,
+        accepted_insurance,
+    };
+
+    let result = async {
+        let Some(provider) = managed_provider(pool.get_ref(), &id, user_id).await? else {
+            return Ok(None);
+        };
+        if listing.is_empty() {
+            sqlx::query!("DELETE FROM provider_overrides WHERE provider_id = ?", provider.id)
+                .execute(pool.get_ref())
+                .await?;
+            return Ok(Some(provider));
+        }
+
+        let hours = listing.hours.as_ref().map(|hours| json!(hours).to_string());
+        let accepted_insurance = listing.accepted_insurance.as_ref().map(|plans| json!(plans).to_string());
+        sqlx::query!(
+            "INSERT INTO provider_overrides (provider_id, phone, hours, telehealth, accepted_insurance, updated_by)
+             VALUES (?, ?, ?, ?, ?, ?)
+             ON CONFLICT (provider_id) DO UPDATE SET
+                 phone = excluded.phone,
+                 hours = excluded.hours,
+                 telehealth = excluded.telehealth,
+                 accepted_insurance = excluded.accepted_insurance,
+                 updated_by = excluded.updated_by,
+                 updated_at = CURRENT_TIMESTAMP",
+            provider.id,
+            listing.phone,
+            hours,
+            listing.telehealth,
+            accepted_insurance,
+            user_id
+        )
+        .execute(pool.get_ref())
+        .await?;
+        Ok::<_, sqlx::Error>(Some(provider))
+    }
+    .await;
+
+    match result {
+        Ok(Some(provider)) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": format!("Listing for {} updated.", provider.name)
+        })),
+        Ok(None) => error(StatusCode::NOT_FOUND, "You don't manage this listing."),
+        Err(err) => {
+            eprintln!("Failed to update listing {}: {}", id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to update listing. Please try again later.")
+        }
+    }
+}
+
+// Handler for `DELETE /api/providers/{id}/listing` (go back to the upstream data)
+#[delete("/api/providers/{id}/listing")]
+async fn reset_listing(req: HttpRequest, path: web::Path<String>, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let id = path.into_inner();
+
+    let result = async {
+        let Some(provider) = managed_provider(pool.get_ref(), &id, user_id).await? else {
+            return Ok(false);
+        };
+        sqlx::query!("DELETE FROM provider_overrides WHERE provider_id = ?", provider.id)
+            .execute(pool.get_ref())
+            .await?;
+        Ok::<_, sqlx::Error>(true)
+    }
+    .await;
+
+    match result {
+        Ok(true) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Corrections removed. The listing shows the directory data again."
+        })),
+        Ok(false) => error(StatusCode::NOT_FOUND, "You don't manage this listing."),
+        Err(err) => {
+            eprintln!("Failed to reset listing {}: {}", id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to reset listing. Please try again later.")
+        }
+    }
+}
+
+// Handler for `GET /admin/claims?status=pending`
+#[get("/admin/claims")]
+async fn list_claims(req: HttpRequest, query: web::Query<ClaimQuery>, pool: web::Data<SqlitePool>) -> impl Responder {
+    if let Err(response) = require_admin(&req, pool.get_ref()).await {
+        return response;
+    }
+    let status = query.status.as_deref().unwrap_or("pending");
+
+    let rows = sqlx::query!(
+        "SELECT provider_claims.id AS \"id!\", provider_claims.provider_id, provider_claims.role, provider_claims.message,
+                provider_claims.status, provider_claims.created_at AS \"created_at: String\",
+                provider_claims.decided_at AS \"decided_at: String\", users.username,
+                providers.name AS \"provider_name?\", providers.address AS \"provider_address?\"
+         FROM provider_claims
+         JOIN users ON users.id = provider_claims.user_id
+         LEFT JOIN providers ON providers.id = provider_claims.provider_id
+         WHERE provider_claims.status = ? ORDER BY provider_claims.created_at, provider_claims.id LIMIT ?",
+        status,
+        LIST_LIMIT
+    )
+    .fetch_all(pool.get_ref())
+    .await;
+
+    match rows {
+        Ok(rows) => {
+            let claims: Vec<ClaimSummary> = rows
+                .into_iter()
+                .map(|row| ClaimSummary {
+                    id: row.id,
+                    provider_id: row.provider_id,
+                    provider_name: row.provider_name,
+                    provider_address: row.provider_address,
+                    username: row.username,
+                    role: row.role,
+                    message: row.message,
+                    status: row.status,
+                    created_at: row.created_at,
+                    decided_at: row.decided_at,
+                })
+                .collect();
+            HttpResponse::Ok().json(claims)
+        }
+        Err(err) => {
+            eprintln!("Failed to list provider claims: {}", err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to list claims.")
+        }
+    }
+}
+
+// Handler for `POST /admin/claims/{id}/{decision}`: approve or reject a pending claim, or revoke an approved one
+#[post("/admin/claims/{id}/{decision}")]
+async fn decide_claim(req: HttpRequest, path: web::Path<(i64, String)>, pool: web::Data<SqlitePool>) -> impl Responder {
+    let admin_id = match require_admin(&req, pool.get_ref()).await {
+        Ok(admin_id) => admin_id,
+        Err(response) => return response,
+    };
+    let (claim_id, decision) = path.into_inner();
+    let (from, to) = match decision.as_str() {
+        "approve" => ("pending", "approved"),
+        "reject" => ("pending", "rejected"),
+        "revoke" => ("approved", "revoked"),
+        _ => return error(StatusCode::NOT_FOUND, "Claims can be approved, rejected or revoked."),
+    };
+
+    let result = async {
+        let mut tx = pool.begin().await?;
+        let claim = sqlx::query!(
+            "UPDATE provider_claims SET status = ?, decided_at = CURRENT_TIMESTAMP, decided_by = ?
+             WHERE id = ? AND status = ?
+             RETURNING user_id, provider_id",
+            to,
+            admin_id,
+            claim_id,
+            from
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(claim) = claim else {
+            return Ok(false);
+        };
+
+        let name = sqlx::query_scalar!("SELECT name FROM providers WHERE id = ?", claim.provider_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .unwrap_or(claim.provider_id);
+        let message = match to {
+            "approved" => format!("You can now manage the listing for {} from the provider portal.", name),
+            "rejected" => format!("Your claim for the listing of {} wasn't approved.", name),
+            _ => format!("You no longer manage the listing for {}.", name),
+        };
+        notifications::create_for_user(&mut tx, claim.user_id, "claim", &message).await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(true)
+    }
+    .await;
+
+    match result {
+        Ok(true) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": format!("Claim {}.", to)
+        })),
+        Ok(false) => error(StatusCode::NOT_FOUND, &format!("No {} claim with that ID.", from)),
+        Err(err) => {
+            eprintln!("Failed to update provider claim {}: {}", claim_id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to update claim.")
+        }
+    }
+}
@@ -0,0 +1,226 @@
+// Stable provider identifiers. Every search result is stored under an ID that
+// keeps resolving on later visits: "place:<place_id>" for Google places,
+// "npi:<npi>" or "plan-net:<location_id>" for directory locations, and a UUID
+// when the same provider was found in more than one source. Each source ID is
+// kept as an alias, so an ID handed out earlier still finds the record after
+// it has been merged with another source.
+use crate::appointments;
+use crate::find_providers::HealthProvider;
+use crate::insurance::provider_key;
+use crate::messaging;
+use crate::provider_listings;
+use crate::place_details::{cached_place_details, PlaceDetailsCache};
+use crate::waiting_room;
+
+use actix_web::{get, web, HttpResponse, Responder};
+use serde_json::json;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+fn push_unique(values: &mut Vec<String>, value: String) {
+    if !values.contains(&value) {
+        values.push(value);
+    }
+}
+
+// Fold records from different sources that describe the same place (same
+// name and street address) into the first one found
+pub fn merge_duplicates(providers: Vec<HealthProvider>) -> Vec<HealthProvider> {
+    let mut merged: Vec<HealthProvider> = Vec::new();
+
+    for provider in providers {
+        let key = provider_key(&provider.name, &provider.address);
+        let Some(existing) = merged
+            .iter_mut()
+            .find(|existing| provider_key(&existing.name, &existing.address) == key)
+        else {
+            merged.push(provider);
+            continue;
+        };
+
+        existing.source_ids.extend(provider.source_ids);
+        existing.phone = existing.phone.take().or(provider.phone);
+        for service in provider.services {
+            let duplicate = existing
+                .services
+                .iter()
+                .any(|s| s.name == service.name && s.npi == service.npi);
+            if !duplicate {
+                existing.services.push(service);
+            }
+        }
+        for network in provider.networks {
+            push_unique(&mut existing.networks, network);
+        }
+        for plan in provider.accepted_insurance {
+            push_unique(&mut existing.accepted_insurance, plan);
+        }
+    }
+
+    merged
+}
+
+// Assign each provider its stable ID and save the latest copy of its record
+pub async fn register(pool: &SqlitePool, providers: &mut [HealthProvider]) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    for provider in providers.iter_mut() {
+        // Reuse the ID any of the source IDs already resolves to
+        let mut known_id = None;
+        for alias in &provider.source_ids {
+            known_id = sqlx::query_scalar!(
+                "SELECT provider_id FROM provider_aliases WHERE alias = ?",
+                alias
+            )
+            .fetch_optional(&mut *tx)
+            .await?;
+            if known_id.is_some() {
+                break;
+            }
+        }
+
+        provider.id = known_id.unwrap_or_else(|| match provider.source_ids.as_slice() {
+            [source_id] => source_id.clone(),
+            _ => Uuid::new_v4().to_string(),
+        });
+
+        let record = serde_json::to_string(&*provider).expect("HealthProvider serializes to JSON");
+        sqlx::query!(
+            "INSERT INTO providers (id, name, address, record) VALUES (?, ?, ?, ?)
+             ON CONFLICT (id) DO UPDATE SET
+                 name = excluded.name,
+                 address = excluded.address,
+                 record = excluded.record,
+                 updated_at = CURRENT_TIMESTAMP",
+            provider.id,
+            provider.name,
+            provider.address,
+            record
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        for alias in provider.source_ids.iter().chain(std::iter::once(&provider.id)) {
+            sqlx::query!(
+                "INSERT OR IGNORE INTO provider_aliases (alias, provider_id) VALUES (?, ?)",
+                alias,
+                provider.id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    tx.commit().await
+}
+
+// Look up the stored record for a provider ID or any of its aliases
+pub async fn find(pool: &SqlitePool, id: &str) -> Result<Option<HealthProvider>, sqlx::Error> {
+    let record = sqlx::query_scalar!(
+        "SELECT providers.record FROM provider_aliases
+         JOIN providers ON providers.id = provider_aliases.provider_id
+         WHERE provider_aliases.alias = ?",
+        id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.and_then(|record| serde_json::from_str(&record).ok()))
+}
+
+// Handler for the `/providers/{id}` endpoint
+#[get("/providers/{id}")]
+async fn provider_details(
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+    cache: web::Data<PlaceDetailsCache>,
+) -> impl Responder {
+    let id = path.into_inner();
+
+    let mut provider = match find(pool.get_ref(), &id).await {
+        Ok(provider) => provider,
+        Err(err) => {
+            eprintln!("Failed to fetch provider {}: {}", id, err);
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Failed to fetch provider."
+            }));
+        }
+    };
+
+    // Google places can still be looked up if they never appeared in a search here
+    let place_id = provider
+        .as_ref()
+        .and_then(|provider| provider.place_id.clone())
+        .or_else(|| id.strip_prefix("place:").map(String::from));
+
+    let mut details = None;
+    if let Some(place_id) = place_id {
+        let api_key = std::env::var("GOOGLE_MAPS_API_KEY")
+            .expect("GOOGLE_MAPS_API_KEY must be set in environment");
+        match cached_place_details(cache.get_ref(), &place_id, &api_key).await {
+            Ok(place_details) => details = place_details,
+            Err(err) => eprintln!("Failed to fetch place details: {}", err),
+        }
+    }
+
+    // Corrections from the provider's own staff win over the upstream data
+    if let Some(provider) = provider.as_mut() {
+        match provider_listings::find_override(pool.get_ref(), &provider.id).await {
+            Ok(Some(listing)) => {
+                listing.apply(provider);
+                if let Some(details) = details.as_mut() {
+                    listing.apply_to_details(details);
+                }
+            }
+            Ok(None) => {}
+            Err(err) => eprintln!("Failed to fetch listing corrections for {}: {}", id, err),
+        }
+    }
+
+    // Whether appointments can be booked here, whether its drop-in waiting room is open,
+    // and whether its staff take messages
+    let calendar = match appointments::load_calendar(pool.get_ref(), &id).await {
+        Ok(calendar) => calendar,
+        Err(err) => {
+            eprintln!("Failed to fetch calendar for {}: {}", id, err);
+            None
+        }
+    };
+    let bookable = calendar.is_some();
+    let waiting_room_open = match &calendar {
+        Some(calendar) => waiting_room::is_open(pool.get_ref(), &calendar.provider_id)
+            .await
+            .unwrap_or_else(|err| {
+                eprintln!("Failed to fetch waiting room for {}: {}", id, err);
+                false
+            }),
+        None => false,
+    };
+    let messaging = match &calendar {
+        Some(calendar) => messaging::takes_messages(pool.get_ref(), &calendar.provider_id)
+            .await
+            .unwrap_or_else(|err| {
+                eprintln!("Failed to check messaging for {}: {}", id, err);
+                false
+            }),
+        None => false,
+    };
+
+    if provider.is_none() && details.is_none() && !bookable {
+        return HttpResponse::NotFound().json(json!({
+            "status": "error",
+            "message": "Provider not found."
+        }));
+    }
+
+    HttpResponse::Ok().json(json!({
+        "id": provider.as_ref().map(|provider| provider.id.clone()).unwrap_or(id),
+        "provider": provider,
+        "details": details,
+        "bookable": bookable,
+        "waiting_room_open": waiting_room_open,
+        "messaging": messaging,
+        "updated_by_provider": provider.as_ref().is_some_and(|provider| provider.updated_by_provider)
+    }))
+}
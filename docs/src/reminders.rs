@@ -0,0 +1,201 @@
+// Appointment reminders, sent by the background job queue. Booking or rescheduling
+// queues one job per reminder time and channel, so a failed text message is retried
+// without sending the email again. Jobs carry the appointment's sequence number when
+// they were queued, which every reschedule bumps; one whose appointment has since
+// moved (even back to the same time) or been cancelled is skipped, which leaves the
+// jobs queued for the latest time as the only ones that send anything. Whether a
+// channel is used is read from the user's settings when the reminder goes out.
+use crate::appointments::{display_date, display_time, SLOT_FORMAT};
+use crate::jobs;
+use crate::mailer::{Email, Mailer};
+use crate::session::current_user_id;
+use crate::sms::{Sms, SmsSender};
+
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use chrono::{Duration, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::error::Error;
+
+pub const JOB_KIND: &str = "appointment_reminder";
+
+// How long before the appointment each reminder goes out, and how it's described
+const LEADS: [(i64, &str); 2] = [(24 * 60, "tomorrow"), (60, "in one hour")];
+
+const CHANNELS: [&str; 2] = ["email", "sms"];
+
+// Digits a phone number can have, country code included
+const MIN_PHONE_DIGITS: usize = 7;
+const MAX_PHONE_DIGITS: usize = 15;
+
+// Where reminders go out
+pub struct Channels {
+    pub mailer: Box<dyn Mailer>,
+    pub sms: Box<dyn SmsSender>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Reminder {
+    appointment_id: i64,
+    starts_at: String, // As queued
+    #[serde(default)]
+    sequence: i64,     // The appointment's sequence as queued; skipped if it has moved since
+    when: String,      // "tomorrow" or "in one hour"
+    channel: String,   // "email" or "sms"
+}
+
+#[derive(Deserialize)]
+struct ReminderSettings {
+    phone: String,
+    email_reminders: Option<String>, // Checkboxes, only sent when checked
+    sms_reminders: Option<String>,
+}
+
+// Digits with the usual separators, and a plausible number of digits
+pub fn valid_phone(phone: &str) -> bool {
+    let digits = phone.chars().filter(char::is_ascii_digit).count();
+    let allowed = phone.chars().all(|c| c.is_ascii_digit() || "+-(). ".contains(c));
+    allowed && (MIN_PHONE_DIGITS..=MAX_PHONE_DIGITS).contains(&digits)
+}
+
+// Queue reminders for an appointment at `starts_at` ("YYYY-MM-DDTHH:MM" in `timezone`)
+// as part of the caller's transaction. Reminder times already past are left out.
+pub async fn schedule(
+    conn: &mut SqliteConnection,
+    appointment_id: i64,
+    starts_at: &str,
+    timezone: &Tz,
+) -> Result<(), sqlx::Error> {
+    let Some(start) = NaiveDateTime::parse_from_str(starts_at, SLOT_FORMAT)
+        .ok()
+        .and_then(|start| timezone.from_local_datetime(&start).earliest())
+    else {
+        return Ok(());
+    };
+    let now = Utc::now();
+    let sequence = sqlx::query_scalar!("SELECT sequence FROM appointments WHERE id = ?", appointment_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    for (minutes, when) in LEADS {
+        let run_at = start.with_timezone(&Utc) - Duration::minutes(minutes);
+        if run_at <= now {
+            continue;
+        }
+        for channel in CHANNELS {
+            let reminder = Reminder {
+                appointment_id,
+                starts_at: starts_at.to_string(),
+                sequence,
+                when: when.to_string(),
+                channel: channel.to_string(),
+            };
+            let payload = serde_json::to_value(&reminder).expect("reminder serializes");
+            jobs::enqueue(conn, JOB_KIND, &payload, &run_at.naive_utc()).await?;
+        }
+    }
+    Ok(())
+}
+
+// Job handler: send one reminder, unless it's stale or the user turned the channel off
+pub async fn send(pool: &SqlitePool, channels: &Channels, payload: &Value) -> Result<(), Box<dyn Error>> {
+    let reminder: Reminder = serde_json::from_value(payload.clone())?;
+
+    let appointment = sqlx::query!(
+        "SELECT appointments.starts_at, appointments.status, appointments.sequence, provider_calendars.name, provider_calendars.address,
+                provider_calendars.timezone, users.username, users.email, users.phone,
+                users.email_reminders, users.sms_reminders
+         FROM appointments
+         JOIN provider_calendars ON provider_calendars.provider_id = appointments.provider_id
+         JOIN users ON users.id = appointments.user_id
+         WHERE appointments.id = ?",
+        reminder.appointment_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    let Some(appointment) = appointment else {
+        return Ok(());
+    };
+    if appointment.status != "booked"
+        || appointment.sequence != reminder.sequence
+        || appointment.starts_at != reminder.starts_at
+    {
+        return Ok(());
+    }
+
+    let start = NaiveDateTime::parse_from_str(&appointment.starts_at, SLOT_FORMAT)?;
+    let mut message = format!(
+        "Reminder: your appointment with {} is {}, {} at {} ({}).",
+        appointment.name,
+        reminder.when,
+        display_date(&start),
+        display_time(&start),
+        appointment.timezone
+    );
+    if !appointment.address.is_empty() {
+        message.push_str(&format!(" {}", appointment.address));
+    }
+
+    match reminder.channel.as_str() {
+        "email" => {
+            let Some(email) = appointment.email.filter(|email| appointment.email_reminders && !email.is_empty()) else {
+                return Ok(());
+            };
+            channels.mailer.send(&Email {
+                to: email,
+                subject: format!("Your appointment with {} is {}", appointment.name, reminder.when),
+                body: format!(
+                    "Hi {},\n\n{}\n\nYou can reschedule or cancel from your profile page.",
+                    appointment.username, message
+                ),
+            })
+        }
+        "sms" => {
+            let Some(phone) = appointment.phone.filter(|phone| appointment.sms_reminders && !phone.is_empty()) else {
+                return Ok(());
+            };
+            channels.sms.send(&Sms { to: phone, body: message })
+        }
+        other => Err(format!("Unknown reminder channel {:?}", other).into()),
+    }
+}
+
+// Handler for the reminder settings form on the profile page
+#[post("/profile/reminders")]
+async fn update_reminder_settings(
+    req: HttpRequest,
+    form: web::Form<ReminderSettings>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Found().append_header(("Location", "/login")).finish();
+    };
+
+    let phone = form.phone.trim();
+    let phone = (!phone.is_empty()).then_some(phone);
+    if phone.is_some_and(|phone| !valid_phone(phone)) {
+        return HttpResponse::BadRequest().body("Please enter a valid phone number");
+    }
+    let email_reminders = form.email_reminders.is_some();
+    // Text reminders need a number to go to
+    let sms_reminders = form.sms_reminders.is_some() && phone.is_some();
+
+    let result = sqlx::query!(
+        "UPDATE users SET phone = ?, email_reminders = ?, sms_reminders = ? WHERE id = ?",
+        phone,
+        email_reminders,
+        sms_reminders,
+        user_id
+    )
+    .execute(pool.get_ref())
+    .await;
+
+    if let Err(err) = result {
+        eprintln!("Failed to update reminder settings: {}", err);
+        return HttpResponse::InternalServerError().body("Failed to save reminder settings");
+    }
+
+    HttpResponse::Found().append_header(("Location", "/profile")).finish()
+}
@@ -0,0 +1,563 @@
+// First-party reviews. Logged-in users rate a provider from 1 to 5 and can add a
+// written review, one per provider, which they can edit or delete. New and edited
+// reviews wait in a moderation queue until an admin publishes them; anyone can
+// report a published review, which puts it back in the queue. The average of the
+// published reviews is returned with search results next to the Google rating.
+use crate::find_providers::HealthProvider;
+use crate::notifications;
+use crate::provider_store;
+use crate::session::{current_user_id, require_admin};
+
+use actix_web::http::StatusCode;
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use handlebars::Handlebars;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+
+const MAX_BODY_CHARS: usize = 2000;
+const MAX_REASON_CHARS: usize = 500;
+
+// Reviews shown at once on a provider's page, newest first
+const PAGE_SIZE: i64 = 50;
+
+#[derive(Deserialize)]
+struct ReviewRequest {
+    rating: i64,
+    #[serde(default)]
+    body: String,
+}
+
+#[derive(Deserialize)]
+struct ReportRequest {
+    reason: String,
+}
+
+#[derive(Deserialize)]
+struct ModerationRequest {
+    updated_at: String, // When the review the admin saw was last edited
+}
+
+fn error(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+fn not_logged_in() -> HttpResponse {
+    error(StatusCode::UNAUTHORIZED, "User not logged in. Please log in and try again.")
+}
+
+fn round_rating(rating: f64) -> f64 {
+    (rating * 10.0).round() / 10.0
+}
+
+// Fill in the average of published reviews for a list of search results
+pub async fn add_ratings(pool: &SqlitePool, providers: &mut [HealthProvider]) -> Result<(), sqlx::Error> {
+    let ids = json!(providers.iter().map(|provider| &provider.id).collect::<Vec<_>>()).to_string();
+    let rows = sqlx::query!(
+        "SELECT provider_id, AVG(rating) AS \"average!: f64\", COUNT(*) AS \"count!: i64\" FROM reviews
+         WHERE status = 'published' AND provider_id IN (SELECT value FROM json_each(?))
+         GROUP BY provider_id",
+        ids
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let ratings: HashMap<String, (f64, i64)> =
+        rows.into_iter().map(|row| (row.provider_id, (row.average, row.count))).collect();
+
+    for provider in providers.iter_mut() {
+        if let Some((average, count)) = ratings.get(&provider.id) {
+            provider.community_rating = Some(round_rating(*average));
+            provider.community_review_count = u32::try_from(*count).unwrap_or(u32::MAX);
+        }
+    }
+    Ok(())
+}
+
+// Handler for a provider's reviews page; anyone can read it, logged-in users can review
+#[get("/providers/{id}/reviews")]
+async fn reviews_page(
+    req: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+    hb: web::Data<Handlebars<'_>>,
+) -> impl Responder {
+    let user_id = current_user_id(&req);
+    let id = path.into_inner();
+
+    let result = async {
+        let Some(mut provider) = provider_store::find(pool.get_ref(), &id).await? else {
+            return Ok(None);
+        };
+        add_ratings(pool.get_ref(), std::slice::from_mut(&mut provider)).await?;
+
+        let reviews = sqlx::query!(
+            "SELECT reviews.id, reviews.user_id, reviews.rating, reviews.body,
+                    reviews.updated_at AS \"updated_at: String\", users.username
+             FROM reviews JOIN users ON users.id = reviews.user_id
+             WHERE reviews.provider_id = ? AND reviews.status = 'published'
+             ORDER BY reviews.updated_at DESC, reviews.id DESC LIMIT ?",
+            provider.id,
+            PAGE_SIZE
+        )
+        .fetch_all(pool.get_ref())
+        .await?;
+
+        // The user's own review, whatever its status, so they can see and edit it
+        let own = match user_id {
+            Some(user_id) => {
+                sqlx::query!(
+                    "SELECT rating, body, status FROM reviews WHERE provider_id = ? AND user_id = ?",
+                    provider.id,
+                    user_id
+                )
+                .fetch_optional(pool.get_ref())
+                .await?
+            }
+            None => None,
+        };
+        Ok::<_, sqlx::Error>(Some((provider, reviews, own)))
+    }
+    .await;
+
+    let (provider, reviews, own) = match result {
+        Ok(Some(found)) => found,
+        Ok(None) => return HttpResponse::NotFound().body("Provider not found"),
+        Err(err) => {
+            eprintln!("Failed to load reviews for {}: {}", id, err);
+            return HttpResponse::InternalServerError().body("Failed to load reviews");
+        }
+    };
+
+    let reviews: Vec<Value> = reviews
+        .into_iter()
+        .map(|review| {
+            json!({
+                "id": review.id,
+                "username": review.username,
+                "stars": "★".repeat(review.rating as usize),
+                "rating": review.rating,
+                "body": review.body,
+                "updated_at": review.updated_at,
+                "mine": Some(review.user_id) == user_id
+            })
+        })
+        .collect();
+
+    let mut data = Map::new();
+    data.insert("provider_id".to_string(), json!(provider.id));
+    data.insert("name".to_string(), json!(provider.name));
+    data.insert("address".to_string(), json!(provider.address));
+    data.insert("google_rating".to_string(), json!(provider.rating.map(|rating| format!("{:.1}", rating))));
+    data.insert(
+        "community_rating".to_string(),
+        json!(provider.community_rating.map(|rating| format!("{:.1}", rating))),
+    );
+    data.insert("community_review_count".to_string(), json!(provider.community_review_count));
+    data.insert("reviews".to_string(), json!(reviews));
+    data.insert("logged_in".to_string(), json!(user_id.is_some()));
+    if let Some(own) = own {
+        data.insert(
+            "own".to_string(),
+            json!({
+                "rating": own.rating,
+                "body": own.body,
+                "status": own.status,
+                "pending": own.status == "pending",
+                "rejected": own.status == "rejected"
+            }),
+        );
+    }
+
+    let body = hb.render("reviews", &data).unwrap_or_else(|_| "Template error".to_string());
+    HttpResponse::Ok().body(body)
+}
+
+// Handler for `PUT /api/providers/{id}/review` (write or edit the user's review)
+#[put("/api/providers/{id}/review")]
+async fn save_review(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<ReviewRequest>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let id = path.into_inner();
+    if !(1..=5).contains(&body.rating) {
+        return error(StatusCode::BAD_REQUEST, "Please pick a rating from 1 to 5 stars.");
+    }
+    let text = body.body.trim();
+    if text.chars().count() > MAX_BODY_CHARS {
+        return error(StatusCode::BAD_REQUEST, "Reviews can be up to 2000 characters.");
+    }
+
+    let result = async {
+        let Some(provider) = provider_store::find(pool.get_ref(), &id).await? else {
+            return Ok(false);
+        };
+        let mut tx = pool.begin().await?;
+        // Edits are checked again, and reports about the old text no longer apply
+        let review_id = sqlx::query_scalar!(
+            "INSERT INTO reviews (provider_id, user_id, rating, body) VALUES (?, ?, ?, ?)
+             ON CONFLICT (provider_id, user_id) DO UPDATE SET
+                 rating = excluded.rating,
+                 body = excluded.body,
+                 status = 'pending',
+                 updated_at = CURRENT_TIMESTAMP,
+                 moderated_at = NULL,
+                 moderated_by = NULL
+             RETURNING id AS \"id!\"",
+            provider.id,
+            user_id,
+            body.rating,
+            text
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE review_reports SET resolved_at = CURRENT_TIMESTAMP WHERE review_id = ? AND resolved_at IS NULL",
+            review_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(true)
+    }
+    .await;
+
+    match result {
+        Ok(true) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Thanks! Your review will appear once it's been checked."
+        })),
+        Ok(false) => error(StatusCode::NOT_FOUND, "Provider not found."),
+        Err(err) => {
+            eprintln!("Failed to save review for {}: {}", id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to save review. Please try again later.")
+        }
+    }
+}
+
+// Handler for `DELETE /api/providers/{id}/review`
+#[delete("/api/providers/{id}/review")]
+async fn delete_review(req: HttpRequest, path: web::Path<String>, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let id = path.into_inner();
+
+    let result = async {
+        let Some(provider) = provider_store::find(pool.get_ref(), &id).await? else {
+            return Ok(0);
+        };
+        let deleted = sqlx::query!("DELETE FROM reviews WHERE provider_id = ? AND user_id = ?", provider.id, user_id)
+            .execute(pool.get_ref())
+            .await?;
+        Ok::<_, sqlx::Error>(deleted.rows_affected())
+    }
+    .await;
+
+    match result {
+        Ok(0) => error(StatusCode::NOT_FOUND, "You haven't reviewed this provider."),
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Review deleted."
+        })),
+        Err(err) => {
+            eprintln!("Failed to delete review for {}: {}", id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete review. Please try again later.")
+        }
+    }
+}
+
+// Handler for `POST /api/reviews/{id}/reports` (flag a published review for an admin)
+#[post("/api/reviews/{id}/reports")]
+async fn report_review(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: web::Json<ReportRequest>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let review_id = path.into_inner();
+    let reason = body.reason.trim();
+    if reason.is_empty() || reason.chars().count() > MAX_REASON_CHARS {
+        return error(StatusCode::BAD_REQUEST, "Please say what's wrong with this review, in up to 500 characters.");
+    }
+
+    let author = sqlx::query_scalar!(
+        "SELECT user_id FROM reviews WHERE id = ? AND status = 'published'",
+        review_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await;
+    match author {
+        Ok(Some(author)) if author == user_id => {
+            return error(StatusCode::BAD_REQUEST, "You can edit or delete your own review instead.");
+        }
+        Ok(Some(_)) => {}
+        Ok(None) => return error(StatusCode::NOT_FOUND, "Review not found."),
+        Err(err) => {
+            eprintln!("Failed to fetch review {}: {}", review_id, err);
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to report review. Please try again later.");
+        }
+    }
+
+    let result = sqlx::query!(
+        "INSERT INTO review_reports (review_id, user_id, reason) VALUES (?, ?, ?)",
+        review_id,
+        user_id,
+        reason
+    )
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Thanks for letting us know. An admin will take a look."
+        })),
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            error(StatusCode::CONFLICT, "You've already reported this review.")
+        }
+        Err(err) => {
+            eprintln!("Failed to save report for review {}: {}", review_id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to report review. Please try again later.")
+        }
+    }
+}
+
+// Handler for the admin moderation queue: reviews waiting to be published, and
+// published reviews with open reports
+#[get("/admin/reviews")]
+async fn moderation_page(req: HttpRequest, pool: web::Data<SqlitePool>, hb: web::Data<Handlebars<'_>>) -> impl Responder {
+    if let Err(response) = require_admin(&req, pool.get_ref()).await {
+        return response;
+    }
+
+    let result = async {
+        let reviews = sqlx::query!(
+            "SELECT reviews.id AS \"id!\", reviews.provider_id, reviews.rating, reviews.body, reviews.status,
+                    reviews.updated_at AS \"updated_at: String\", users.username, providers.name AS \"provider_name?\"
+             FROM reviews
+             JOIN users ON users.id = reviews.user_id
+             LEFT JOIN providers ON providers.id = reviews.provider_id
+             WHERE reviews.status = 'pending' OR EXISTS (
+                 SELECT 1 FROM review_reports WHERE review_reports.review_id = reviews.id AND review_reports.resolved_at IS NULL
+             )
+             ORDER BY reviews.updated_at, reviews.id"
+        )
+        .fetch_all(pool.get_ref())
+        .await?;
+
+        let reports = sqlx::query!(
+            "SELECT review_reports.review_id, review_reports.reason, users.username
+             FROM review_reports JOIN users ON users.id = review_reports.user_id
+             WHERE review_reports.resolved_at IS NULL ORDER BY review_reports.created_at"
+        )
+        .fetch_all(pool.get_ref())
+        .await?;
+        Ok::<_, sqlx::Error>((reviews, reports))
+    }
+    .await;
+
+    let mut data = Map::new();
+    match result {
+        Ok((reviews, reports)) => {
+            let mut reasons: HashMap<i64, Vec<Value>> = HashMap::new();
+            for report in reports {
+                reasons
+                    .entry(report.review_id)
+                    .or_default()
+                    .push(json!({ "username": report.username, "reason": report.reason }));
+            }
+            let reviews: Vec<Value> = reviews
+                .into_iter()
+                .map(|review| {
+                    json!({
+                        "id": review.id,
+                        "provider_id": review.provider_id,
+                        "provider_name": review.provider_name.unwrap_or_else(|| review.provider_id.clone()),
+                        "username": review.username,
+                        "stars": "★".repeat(review.rating as usize),
+                        "body": review.body,
+                        "updated_at": review.updated_at,
+                        "pending": review.status == "pending",
+                        "reports": reasons.remove(&review.id).unwrap_or_default()
+                    })
+                })
+                .collect();
+            data.insert("reviews".to_string(), json!(reviews));
+        }
+        Err(err) => {
+            eprintln!("Failed to load moderation queue: {}", err);
+            data.insert("error".to_string(), json!("Could not fetch reviews"));
+        }
+    }
+
+    let body = hb.render("review_moderation", &data).unwrap_or_else(|_| "Template error".to_string());
+    HttpResponse::Ok().body(body)
+}
+
+// Handler for `POST /admin/reviews/{id}/{decision}`: publish a review (dismissing any
+// reports) or reject it (hiding it), and let the author know. Refused if the author has
+// edited the review since the admin loaded it.
+#[post("/admin/reviews/{id}/{decision}")]
+async fn moderate_review(
+    req: HttpRequest,
+    path: web::Path<(i64, String)>,
+    body: web::Json<ModerationRequest>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let admin_id = match require_admin(&req, pool.get_ref()).await {
+        Ok(admin_id) => admin_id,
+        Err(response) => return response,
+    };
+    let (review_id, decision) = path.into_inner();
+    let status = match decision.as_str() {
+        "publish" => "published",
+        "reject" => "rejected",
+        _ => return error(StatusCode::NOT_FOUND, "Reviews can be published or rejected."),
+    };
+
+    let result = async {
+        let mut tx = pool.begin().await?;
+        let review = sqlx::query!(
+            "SELECT user_id, provider_id, status, updated_at AS \"updated_at: String\" FROM reviews WHERE id = ?",
+            review_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(review) = review else {
+            return Ok(None);
+        };
+        let updated = sqlx::query!(
+            "UPDATE reviews SET status = ?, moderated_at = CURRENT_TIMESTAMP, moderated_by = ?
+             WHERE id = ? AND updated_at = ?",
+            status,
+            admin_id,
+            review_id,
+            body.updated_at
+        )
+        .execute(&mut *tx)
+        .await?;
+        if review.updated_at != body.updated_at || updated.rows_affected() == 0 {
+            return Ok(Some(false)); // Dropping the transaction leaves the review as it is
+        }
+        sqlx::query!(
+            "UPDATE review_reports SET resolved_at = CURRENT_TIMESTAMP WHERE review_id = ? AND resolved_at IS NULL",
+            review_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        // Dismissing reports about a published review doesn't change anything for its author
+        if review.status == status {
+            tx.commit().await?;
+            return Ok(Some(true));
+        }
+        let name = sqlx::query_scalar!("SELECT name FROM providers WHERE id = ?", review.provider_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .unwrap_or(review.provider_id);
+        let message = if status == "published" {
+            format!("Your review of {} is now published.", name)
+        } else {
+            format!("Your review of {} was removed for breaking the review guidelines.", name)
+        };
+        notifications::create_for_user(&mut tx, review.user_id, "review", &message).await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(Some(true))
+    }
+    .await;
+
+    match result {
+        Ok(Some(true)) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": format!("Review {}.", status)
+        })),
+        Ok(Some(false)) => error(
+            StatusCode::CONFLICT,
+            "The author edited this review after the page loaded. Reload to see the new version.",
+        ),
+        Ok(None) => error(StatusCode::NOT_FOUND, "Review not found."),
+        Err(err) => {
+            eprintln!("Failed to moderate review {}: {}", review_id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to update review.")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(id: &str) -> HealthProvider {
+        serde_json::from_value(json!({
+            "id": id,
+            "place_id": null,
+            "name": id,
+            "address": "",
+            "distance": 0.0,
+            "provider_type": "doctor",
+            "phone": null,
+            "rating": null,
+            "photo_url": null,
+            "open_now": false,
+            "services": [],
+            "networks": [],
+            "accepted_insurance": [],
+            "in_network": false
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn ratings_round_to_one_decimal() {
+        assert_eq!(round_rating(4.0), 4.0);
+        assert_eq!(round_rating(4.333), 4.3);
+        assert_eq!(round_rating(4.666), 4.7);
+        assert_eq!(round_rating(4.25), 4.3);
+    }
+
+    #[actix_web::test]
+    async fn only_published_reviews_count() {
+        // One connection, since each in-memory connection is its own database
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE reviews (provider_id TEXT NOT NULL, rating INTEGER NOT NULL, status TEXT NOT NULL)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO reviews VALUES
+                 ('a', 5, 'published'), ('a', 4, 'published'), ('a', 4, 'published'),
+                 ('a', 1, 'pending'), ('a', 1, 'rejected'),
+                 ('b', 2, 'pending')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let mut providers = vec![provider("a"), provider("b"), provider("c")];
+        add_ratings(&pool, &mut providers).await.unwrap();
+
+        assert_eq!(providers[0].community_rating, Some(4.3));
+        assert_eq!(providers[0].community_review_count, 3);
+        for provider in &providers[1..] {
+            assert_eq!(provider.community_rating, None);
+            assert_eq!(provider.community_review_count, 0);
+        }
+    }
+}
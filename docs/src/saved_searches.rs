@@ -0,0 +1,421 @@
+// Searches a logged-in user saved to be re-run on a schedule. Each run's results
+// are kept in `saved_search_results`; the next run is compared against them and
+// the user is notified about providers that newly appear or have dropped out.
+// The first run only records the baseline, since everything in it is "new".
+use crate::find_providers::{geocode_address, search_nearby, Coordinates};
+use crate::insurance;
+use crate::notifications;
+use crate::provider_listings;
+use crate::session::current_user_id;
+use crate::telehealth::search_catalog;
+
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::error::Error;
+use std::time::Duration;
+
+// How often to look for searches that are due, unless SAVED_SEARCH_INTERVAL_SECS is set
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// Searches re-run per check, to keep Places API usage bounded
+const BATCH_SIZE: i64 = 20;
+
+// A search that fails waits 2^failures hours before it's tried again, up to this many doublings
+const MAX_BACKOFF_DOUBLINGS: i64 = 7;
+
+// Provider names listed in a notification before it says "and N more"
+const NAMES_IN_MESSAGE: usize = 3;
+
+#[derive(Serialize)]
+pub struct SavedSearch {
+    pub id: i64,
+    pub name: String,
+    pub zip: Option<String>,
+    pub lat: Option<f64>,
+    pub lng: Option<f64>,
+    pub service_type: String,
+    pub in_network: bool,
+    pub mode: Option<String>,
+    pub state: Option<String>,
+    pub frequency: String,
+    pub last_run_at: Option<String>,
+}
+
+// The `/services` query to save, as sent by the results page
+#[derive(Deserialize)]
+struct NewSavedSearch {
+    name: String,
+    zip: Option<String>,
+    lat: Option<f64>,
+    lng: Option<f64>,
+    service_type: String,
+    in_network: Option<bool>,
+    mode: Option<String>,
+    state: Option<String>,
+    frequency: Option<String>, // "daily" (default) or "weekly"
+}
+
+fn not_logged_in() -> HttpResponse {
+    HttpResponse::Unauthorized().json(json!({
+        "status": "error",
+        "message": "User not logged in. Please log in and try again."
+    }))
+}
+
+fn bad_request(message: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+// "A, B, C and 2 more"
+fn name_list(names: &[&str]) -> String {
+    if names.len() <= NAMES_IN_MESSAGE {
+        return names.join(", ");
+    }
+    format!(
+        "{} and {} more",
+        names[..NAMES_IN_MESSAGE].join(", "),
+        names.len() - NAMES_IN_MESSAGE
+    )
+}
+
+pub async fn list_saved_searches(pool: &SqlitePool, user_id: i64) -> Result<Vec<SavedSearch>, sqlx::Error> {
+    sqlx::query_as!(
+        SavedSearch,
+        "SELECT id AS \"id!\", name, zip, lat, lng, service_type, in_network AS \"in_network: bool\",
+                mode, state, frequency, last_run_at AS \"last_run_at: String\"
+         FROM saved_searches WHERE user_id = ? ORDER BY created_at, id",
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Run a saved search as `/services` would for its owner. Returns each provider's ID and name.
+async fn run_search(
+    pool: &SqlitePool,
+    user_id: i64,
+    search: &SavedSearch,
+    api_key: &str,
+) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let plan = if search.in_network {
+        // Without a plan nothing is in network, as `/services` shows
+        let Some(plan) = insurance::user_plan(pool, user_id).await? else {
+            return Ok(Vec::new());
+        };
+        Some(plan)
+    } else {
+        None
+    };
+
+    if search.mode.as_deref() == Some("virtual") {
+        let state = search.state.as_deref().ok_or("virtual search has no state")?;
+        let providers = search_catalog(pool, state, &search.service_type).await?;
+        return Ok(providers
+            .into_iter()
+            .filter(|provider| {
+                plan.as_ref().is_none_or(|plan| {
+                    provider
+                        .accepted_insurance
+                        .iter()
+                        .any(|accepted| accepted.eq_ignore_ascii_case(&plan.name))
+                })
+            })
+            .map(|provider| (format!("telehealth:{}", provider.id), provider.name))
+            .collect());
+    }
+
+    let coordinates = match (search.lat, search.lng, search.zip.as_deref()) {
+        (Some(lat), Some(lng), _) => Coordinates { lat, lng },
+        (_, _, Some(zip)) => geocode_address(zip, api_key).await?,
+        _ => return Err("saved search has no location".into()),
+    };
+
+    let mut providers = search_nearby(pool, &coordinates, &search.service_type, api_key).await?;
+    if let Some(plan) = &plan {
+        provider_listings::apply_overrides(pool, &mut providers).await?;
+        insurance::mark_in_network(pool, plan, &mut providers).await?;
+        providers.retain(|provider| provider.in_network);
+    }
+
+    Ok(providers.into_iter().map(|provider| (provider.id, provider.name)).collect())
+}
+
+// Re-run one batch of saved searches that are due. Returns the number of searches run.
+// Searches that failed are left out until their backoff has passed, and the batch is
+// taken in order of the latest attempt so failing searches can't crowd out the rest.
+pub async fn run_due_searches(pool: &SqlitePool, api_key: &str) -> Result<usize, Box<dyn Error>> {
+    let due = sqlx::query!(
+        "SELECT id AS \"id!\", user_id, name, zip, lat, lng, service_type, in_network AS \"in_network: bool\",
+                mode, state, frequency, last_run_at AS \"last_run_at: String\"
+         FROM saved_searches
+         WHERE (last_run_at IS NULL
+                OR last_run_at < datetime('now', CASE frequency WHEN 'weekly' THEN '-7 days' ELSE '-1 day' END))
+           AND (failures = 0
+                OR last_attempt_at < datetime('now', '-' || (1 << MIN(failures, ?)) || ' hours'))
+         ORDER BY last_attempt_at IS NOT NULL, last_attempt_at
+         LIMIT ?",
+        MAX_BACKOFF_DOUBLINGS,
+        BATCH_SIZE
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut run = 0;
+    for row in due {
+        let user_id = row.user_id;
+        let search = SavedSearch {
+            id: row.id,
+            name: row.name,
+            zip: row.zip,
+            lat: row.lat,
+            lng: row.lng,
+            service_type: row.service_type,
+            in_network: row.in_network,
+            mode: row.mode,
+            state: row.state,
+            frequency: row.frequency,
+            last_run_at: row.last_run_at,
+        };
+
+        let results = match run_search(pool, user_id, &search, api_key).await {
+            Ok(results) => results,
+            Err(err) => {
+                // Leave it due, but back off before trying again
+                eprintln!("Failed to run saved search {}: {}", search.id, err);
+                sqlx::query!(
+                    "UPDATE saved_searches SET failures = failures + 1, last_attempt_at = CURRENT_TIMESTAMP WHERE id = ?",
+                    search.id
+                )
+                .execute(pool)
+                .await?;
+                continue;
+            }
+        };
+
+        let mut tx = pool.begin().await?;
+
+        let previous = sqlx::query!(
+            "SELECT provider_id, name FROM saved_search_results WHERE saved_search_id = ? ORDER BY name",
+            search.id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        if search.last_run_at.is_some() {
+            let added: Vec<&str> = results
+                .iter()
+                .filter(|(id, _)| !previous.iter().any(|old| old.provider_id == *id))
+                .map(|(_, name)| name.as_str())
+                .collect();
+            let removed: Vec<&str> = previous
+                .iter()
+                .filter(|old| !results.iter().any(|(id, _)| *id == old.provider_id))
+                .map(|old| old.name.as_str())
+                .collect();
+
+            if !added.is_empty() {
+                let message = format!(
+                    "{} new result{} for \"{}\": {}.",
+                    added.len(),
+                    if added.len() == 1 { "" } else { "s" },
+                    search.name,
+                    name_list(&added)
+                );
+                notifications::create_for_search(&mut tx, user_id, search.id, "new_results", &message).await?;
+            }
+            if !removed.is_empty() {
+                let message = format!(
+                    "{} no longer appear{} in \"{}\".",
+                    name_list(&removed),
+                    if removed.len() == 1 { "s" } else { "" },
+                    search.name
+                );
+                notifications::create_for_search(&mut tx, user_id, search.id, "removed_results", &message).await?;
+            }
+        }
+
+        sqlx::query!("DELETE FROM saved_search_results WHERE saved_search_id = ?", search.id)
+            .execute(&mut *tx)
+            .await?;
+        for (provider_id, name) in &results {
+            sqlx::query!(
+                "INSERT OR IGNORE INTO saved_search_results (saved_search_id, provider_id, name) VALUES (?, ?, ?)",
+                search.id,
+                provider_id,
+                name
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query!(
+            "UPDATE saved_searches
+             SET last_run_at = CURRENT_TIMESTAMP, last_attempt_at = CURRENT_TIMESTAMP, failures = 0
+             WHERE id = ?",
+            search.id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        run += 1;
+    }
+
+    Ok(run)
+}
+
+// Start re-running saved searches on the current runtime; runs for the life of the server
+pub fn spawn(pool: SqlitePool, api_key: String) {
+    let interval = std::env::var("SAVED_SEARCH_INTERVAL_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_INTERVAL);
+
+    actix_web::rt::spawn(async move {
+        let mut ticker = actix_web::rt::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match run_due_searches(&pool, &api_key).await {
+                Ok(0) => {}
+                Ok(run) => println!("Re-ran {} saved searches", run),
+                Err(err) => eprintln!("Saved search run failed: {}", err),
+            }
+        }
+    });
+}
+
+// Handler for `POST /api/saved-searches`
+#[post("/api/saved-searches")]
+async fn save_search(
+    req: HttpRequest,
+    body: web::Json<NewSavedSearch>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let search = body.into_inner();
+
+    let name = search.name.trim();
+    if name.is_empty() {
+        return bad_request("Search name is required.");
+    }
+    let frequency = search.frequency.as_deref().unwrap_or("daily");
+    if frequency != "daily" && frequency != "weekly" {
+        return bad_request("Frequency must be \"daily\" or \"weekly\".");
+    }
+    let virtual_search = search.mode.as_deref() == Some("virtual");
+    let state = search.state.map(|state| state.trim().to_uppercase()).filter(|state| !state.is_empty());
+    if virtual_search && state.is_none() {
+        return bad_request("Virtual searches need a state.");
+    }
+    let zip = search.zip.map(|zip| zip.trim().to_string()).filter(|zip| !zip.is_empty());
+    if !virtual_search && zip.is_none() && (search.lat.is_none() || search.lng.is_none()) {
+        return bad_request("Please provide either a ZIP code or lat/lng");
+    }
+    let mode = virtual_search.then_some("virtual");
+    let in_network = search.in_network.unwrap_or(false);
+
+    // A ZIP that doesn't geocode would fail every run, so don't save it
+    if let (false, Some(zip), None) = (virtual_search, zip.as_deref(), search.lat.zip(search.lng)) {
+        let api_key = std::env::var("GOOGLE_MAPS_API_KEY")
+            .expect("GOOGLE_MAPS_API_KEY must be set in environment");
+        if let Err(err) = geocode_address(zip, &api_key).await {
+            eprintln!("Geocoding failed for saved search: {}", err);
+            return bad_request("We couldn't find that ZIP code. Please check it and try again.");
+        }
+    }
+
+    let result = sqlx::query_scalar!(
+        "INSERT INTO saved_searches (user_id, name, zip, lat, lng, service_type, in_network, mode, state, frequency)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+         RETURNING id AS \"id!\"",
+        user_id,
+        name,
+        zip,
+        search.lat,
+        search.lng,
+        search.service_type,
+        in_network,
+        mode,
+        state,
+        frequency
+    )
+    .fetch_one(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(id) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Search saved. We'll let you know when its results change.",
+            "id": id
+        })),
+        Err(err) => {
+            eprintln!("Failed to save search: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Failed to save search. Please try again later."
+            }))
+        }
+    }
+}
+
+// Handler for `GET /api/saved-searches`
+#[get("/api/saved-searches")]
+async fn get_saved_searches(req: HttpRequest, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+
+    match list_saved_searches(pool.get_ref(), user_id).await {
+        Ok(searches) => HttpResponse::Ok().json(searches),
+        Err(err) => {
+            eprintln!("Failed to fetch saved searches: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Could not fetch saved searches. Please try again later."
+            }))
+        }
+    }
+}
+
+// Handler for `DELETE /api/saved-searches/{id}`
+#[delete("/api/saved-searches/{id}")]
+async fn delete_saved_search(req: HttpRequest, path: web::Path<i64>, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let search_id = path.into_inner();
+
+    let result = sqlx::query!(
+        "DELETE FROM saved_searches WHERE id = ? AND user_id = ?",
+        search_id,
+        user_id
+    )
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(res) if res.rows_affected() == 1 => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Saved search deleted."
+        })),
+        Ok(_) => HttpResponse::NotFound().json(json!({
+            "status": "error",
+            "message": "Saved search not found."
+        })),
+        Err(err) => {
+            eprintln!("Failed to delete saved search {}: {}", search_id, err);
+            HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Failed to delete saved search. Please try again later."
+            }))
+        }
+    }
+}
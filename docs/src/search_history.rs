@@ -0,0 +1,217 @@
+// Each logged-in user's recent searches, listed on the profile page so they can
+// be repeated in one click. Searches are recorded by `/services` unless the user
+// has turned history off, and are deleted once they're older than the retention
+// period. Only ZIP codes are stored: a "use current location" search is saved
+// under the ZIP its coordinates fall in, or not at all if that can't be found.
+use crate::find_providers::{reverse_geocode_zip, Coordinates};
+use crate::session::current_user_id;
+
+use actix_web::{delete, post, web, HttpRequest, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::error::Error;
+use std::time::Duration;
+
+// How long searches are kept, unless SEARCH_HISTORY_DAYS is set
+const DEFAULT_RETENTION_DAYS: u32 = 90;
+
+// How often expired searches are deleted
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// Searches shown on the profile page
+const RECENT_LIMIT: i64 = 10;
+
+#[derive(Serialize)]
+pub struct SearchHistoryEntry {
+    pub id: i64,
+    pub zip: String,
+    pub service_type: String,
+    pub in_network: bool,
+    pub mode: Option<String>,
+    pub created_at: String,
+}
+
+// A search as `/services` received it
+pub struct NewSearch<'a> {
+    pub zip: Option<&'a str>,
+    pub coordinates: Option<Coordinates>, // Only used to look up the ZIP
+    pub service_type: &'a str,
+    pub in_network: bool,
+    pub mode: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct HistorySettings {
+    search_history: Option<String>, // Checkbox, only sent when checked
+}
+
+// SQLite datetime modifier for the oldest search that is kept
+fn retention_cutoff() -> String {
+    let days = std::env::var("SEARCH_HISTORY_DAYS")
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(DEFAULT_RETENTION_DAYS);
+    format!("-{} days", days)
+}
+
+pub async fn is_enabled(pool: &SqlitePool, user_id: i64) -> Result<bool, sqlx::Error> {
+    let enabled = sqlx::query_scalar!(
+        "SELECT search_history AS \"search_history: bool\" FROM users WHERE id = ?",
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(enabled.unwrap_or(false))
+}
+
+// Add a search to the user's history, unless they've turned history off.
+// Repeating a search moves it to the top rather than listing it twice.
+pub async fn record(
+    pool: &SqlitePool,
+    api_key: &str,
+    user_id: i64,
+    search: NewSearch<'_>,
+) -> Result<(), Box<dyn Error>> {
+    if !is_enabled(pool, user_id).await? {
+        return Ok(());
+    }
+
+    let zip = match (search.zip.map(str::trim).filter(|zip| !zip.is_empty()), &search.coordinates) {
+        (Some(zip), _) => Some(zip.to_string()),
+        (None, Some(coordinates)) => reverse_geocode_zip(coordinates, api_key).await?,
+        (None, None) => None,
+    };
+    let Some(zip) = zip else {
+        return Ok(());
+    };
+
+    let mut tx = pool.begin().await?;
+    sqlx::query!(
+        "DELETE FROM search_history
+         WHERE user_id = ? AND zip = ? AND service_type = ? AND in_network = ? AND mode IS ?",
+        user_id,
+        zip,
+        search.service_type,
+        search.in_network,
+        search.mode
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO search_history (user_id, zip, service_type, in_network, mode) VALUES (?, ?, ?, ?, ?)",
+        user_id,
+        zip,
+        search.service_type,
+        search.in_network,
+        search.mode
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// The user's most recent searches, newest first
+pub async fn list_recent(pool: &SqlitePool, user_id: i64) -> Result<Vec<SearchHistoryEntry>, sqlx::Error> {
+    let cutoff = retention_cutoff();
+    sqlx::query_as!(
+        SearchHistoryEntry,
+        "SELECT id AS \"id!\", zip, service_type, in_network AS \"in_network: bool\", mode,
+                created_at AS \"created_at: String\"
+         FROM search_history
+         WHERE user_id = ? AND created_at >= datetime('now', ?)
+         ORDER BY created_at DESC, id DESC
+         LIMIT ?",
+        user_id,
+        cutoff,
+        RECENT_LIMIT
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Delete searches older than the retention period. Returns the number deleted.
+pub async fn delete_expired(pool: &SqlitePool) -> Result<u64, sqlx::Error> {
+    let cutoff = retention_cutoff();
+    let result = sqlx::query!(
+        "DELETE FROM search_history WHERE created_at < datetime('now', ?)",
+        cutoff
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+// Start deleting expired searches on the current runtime; runs for the life of the server
+pub fn spawn_expiry(pool: SqlitePool) {
+    actix_web::rt::spawn(async move {
+        let mut ticker = actix_web::rt::time::interval(EXPIRY_INTERVAL);
+        loop {
+            ticker.tick().await;
+            match delete_expired(&pool).await {
+                Ok(0) => {}
+                Ok(deleted) => println!("Deleted {} expired searches", deleted),
+                Err(err) => eprintln!("Deleting expired searches failed: {}", err),
+            }
+        }
+    });
+}
+
+// Handler for `DELETE /api/search-history` (clear the user's history)
+#[delete("/api/search-history")]
+async fn clear_history(req: HttpRequest, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Unauthorized().json(json!({
+            "status": "error",
+            "message": "User not logged in. Please log in and try again."
+        }));
+    };
+
+    let result = sqlx::query!("DELETE FROM search_history WHERE user_id = ?", user_id)
+        .execute(pool.get_ref())
+        .await;
+
+    match result {
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Search history cleared."
+        })),
+        Err(err) => {
+            eprintln!("Failed to clear search history: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Failed to clear search history. Please try again later."
+            }))
+        }
+    }
+}
+
+// Handler for the search history toggle on the profile page
+#[post("/profile/search-history")]
+async fn update_history_settings(
+    req: HttpRequest,
+    form: web::Form<HistorySettings>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Found().append_header(("Location", "/login")).finish();
+    };
+    let enabled = form.search_history.is_some();
+
+    let result = sqlx::query!(
+        "UPDATE users SET search_history = ? WHERE id = ?",
+        enabled,
+        user_id
+    )
+    .execute(pool.get_ref())
+    .await;
+
+    if let Err(err) = result {
+        eprintln!("Failed to update search history setting: {}", err);
+        return HttpResponse::InternalServerError().body("Failed to save search history setting");
+    }
+
+    HttpResponse::Found().append_header(("Location", "/profile")).finish()
+}
@@ -0,0 +1,34 @@
+use actix_web::{HttpRequest, HttpResponse};
+use serde_json::json;
+use sqlx::SqlitePool;
+
+// Read the logged-in user's ID from the `user_id` cookie set at login
+pub fn current_user_id(req: &HttpRequest) -> Option<i64> {
+    req.cookie("user_id").and_then(|cookie| cookie.value().parse::<i64>().ok())
+}
+
+// Return the user ID if the logged-in user is an admin, or the error response to send otherwise
+pub async fn require_admin(req: &HttpRequest, pool: &SqlitePool) -> Result<i64, HttpResponse> {
+    let Some(user_id) = current_user_id(req) else {
+        return Err(HttpResponse::Unauthorized().json(json!({
+            "status": "error",
+            "message": "User not logged in. Please log in and try again."
+        })));
+    };
+
+    let is_admin = sqlx::query_scalar!("SELECT is_admin FROM users WHERE id = ?", user_id)
+        .fetch_optional(pool)
+        .await;
+
+    match is_admin {
+        Ok(Some(true)) => Ok(user_id),
+        Ok(_) => Err(HttpResponse::Forbidden().json(json!({
+            "status": "error",
+            "message": "Admin access required."
+        }))),
+        Err(_) => Err(HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": "Error retrieving user from database. Please try again later."
+        }))),
+    }
+}
@@ -0,0 +1,286 @@
+// Public links to a favorites collection, for sharing a list of providers with
+// people who don't have an account. Each link carries a random token that is
+// the only thing needed to view the list, so links can be revoked or given an
+// expiry date, and every view is counted. Private notes and tags are never shown.
+use crate::session::current_user_id;
+
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use handlebars::Handlebars;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+#[derive(Serialize)]
+pub struct Share {
+    pub id: i64,
+    pub url: String,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub view_count: i64,
+    pub last_viewed_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct NewShare {
+    expires_in_days: Option<u32>, // None for a link that doesn't expire
+}
+
+#[derive(Serialize)]
+struct SharedFavorite {
+    name: String,
+    address: String,
+    photo: String,
+    rating: Option<f64>,
+    phone: Option<String>,
+}
+
+// Two v4 UUIDs give 244 random bits, written as 64 hex characters
+pub fn new_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+fn share_url(req: &HttpRequest, token: &str) -> String {
+    let connection = req.connection_info();
+    format!("{}://{}/shared/{}", connection.scheme(), connection.host(), token)
+}
+
+fn not_logged_in() -> HttpResponse {
+    HttpResponse::Unauthorized().json(json!({
+        "status": "error",
+        "message": "User not logged in. Please log in and try again."
+    }))
+}
+
+fn collection_not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(json!({
+        "status": "error",
+        "message": "Collection not found."
+    }))
+}
+
+async fn owns_collection(pool: &SqlitePool, user_id: i64, collection_id: i64) -> Result<bool, sqlx::Error> {
+    let owner = sqlx::query_scalar!(
+        "SELECT user_id FROM favorite_collections WHERE id = ?",
+        collection_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(owner == Some(user_id))
+}
+
+// Handler for `POST /api/collections/{id}/shares`
+#[post("/api/collections/{id}/shares")]
+async fn create_share(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    body: web::Json<NewShare>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let collection_id = path.into_inner();
+
+    match owns_collection(pool.get_ref(), user_id, collection_id).await {
+        Ok(true) => {}
+        Ok(false) => return collection_not_found(),
+        Err(err) => {
+            eprintln!("Failed to fetch collection {}: {}", collection_id, err);
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Failed to create link. Please try again later."
+            }));
+        }
+    }
+
+    let token = new_token();
+    let expires_in = body.expires_in_days.map(|days| format!("+{} days", days));
+    let result = sqlx::query_scalar!(
+        "INSERT INTO collection_shares (collection_id, token, expires_at)
+         VALUES (?, ?, CASE WHEN ? IS NULL THEN NULL ELSE datetime('now', ?) END)
+         RETURNING id AS \"id!\"",
+        collection_id,
+        token,
+        expires_in,
+        expires_in
+    )
+    .fetch_one(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(id) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Link created.",
+            "id": id,
+            "url": share_url(&req, &token)
+        })),
+        Err(err) => {
+            eprintln!("Failed to create share link: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Failed to create link. Please try again later."
+            }))
+        }
+    }
+}
+
+// Handler for `GET /api/collections/{id}/shares` (active links with their view counts)
+#[get("/api/collections/{id}/shares")]
+async fn get_shares(req: HttpRequest, path: web::Path<i64>, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let collection_id = path.into_inner();
+
+    let result = sqlx::query!(
+        "SELECT collection_shares.id AS \"id!\", token,
+                created_at AS \"created_at: String\", expires_at AS \"expires_at: String\",
+                view_count, last_viewed_at AS \"last_viewed_at: String\"
+         FROM collection_shares
+         JOIN favorite_collections ON favorite_collections.id = collection_shares.collection_id
+         WHERE collection_shares.collection_id = ? AND favorite_collections.user_id = ?
+           AND revoked_at IS NULL
+           AND (expires_at IS NULL OR expires_at > CURRENT_TIMESTAMP)
+         ORDER BY created_at DESC",
+        collection_id,
+        user_id
+    )
+    .fetch_all(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(rows) => {
+            let shares: Vec<Share> = rows
+                .into_iter()
+                .map(|row| Share {
+                    id: row.id,
+                    url: share_url(&req, &row.token),
+                    created_at: row.created_at,
+                    expires_at: row.expires_at,
+                    view_count: row.view_count,
+                    last_viewed_at: row.last_viewed_at,
+                })
+                .collect();
+            HttpResponse::Ok().json(shares)
+        }
+        Err(err) => {
+            eprintln!("Failed to fetch share links: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Could not fetch links. Please try again later."
+            }))
+        }
+    }
+}
+
+// Handler for `DELETE /api/shares/{id}` (revoke a link)
+#[delete("/api/shares/{id}")]
+async fn revoke_share(req: HttpRequest, path: web::Path<i64>, pool: web::Data<SqlitePool>) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let share_id = path.into_inner();
+
+    let result = sqlx::query!(
+        "UPDATE collection_shares SET revoked_at = CURRENT_TIMESTAMP
+         WHERE id = ? AND revoked_at IS NULL
+           AND collection_id IN (SELECT id FROM favorite_collections WHERE user_id = ?)",
+        share_id,
+        user_id
+    )
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(res) if res.rows_affected() == 1 => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "Link revoked."
+        })),
+        Ok(_) => HttpResponse::NotFound().json(json!({
+            "status": "error",
+            "message": "Link not found."
+        })),
+        Err(err) => {
+            eprintln!("Failed to revoke share link {}: {}", share_id, err);
+            HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Failed to revoke link. Please try again later."
+            }))
+        }
+    }
+}
+
+// Handler for the public `/shared/{token}` page
+#[get("/shared/{token}")]
+async fn view_shared(
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+    hb: web::Data<Handlebars<'_>>,
+) -> impl Responder {
+    let token = path.into_inner();
+
+    // Count the view and find the collection in one step, so dead links aren't counted
+    let share = sqlx::query!(
+        "UPDATE collection_shares SET view_count = view_count + 1, last_viewed_at = CURRENT_TIMESTAMP
+         WHERE token = ? AND revoked_at IS NULL
+           AND (expires_at IS NULL OR expires_at > CURRENT_TIMESTAMP)
+         RETURNING collection_id",
+        token
+    )
+    .fetch_optional(pool.get_ref())
+    .await;
+
+    let collection_id = match share {
+        Ok(Some(share)) => share.collection_id,
+        Ok(None) => {
+            let body = hb
+                .render("shared", &json!({ "not_found": true }))
+                .unwrap_or_else(|_| "Template error".to_string());
+            return HttpResponse::NotFound().body(body);
+        }
+        Err(err) => {
+            eprintln!("Failed to look up share link: {}", err);
+            return HttpResponse::InternalServerError().body("Failed to load shared list");
+        }
+    };
+
+    let result = async {
+        let collection = sqlx::query!(
+            "SELECT favorite_collections.name, users.username
+             FROM favorite_collections JOIN users ON users.id = favorite_collections.user_id
+             WHERE favorite_collections.id = ?",
+            collection_id
+        )
+        .fetch_one(pool.get_ref())
+        .await?;
+
+        let favorites = sqlx::query_as!(
+            SharedFavorite,
+            "SELECT title AS name, address, photo, rating, NULLIF(phone, '') AS \"phone: String\"
+             FROM favorites WHERE collection_id = ? ORDER BY position, id",
+            collection_id
+        )
+        .fetch_all(pool.get_ref())
+        .await?;
+
+        Ok::<_, sqlx::Error>((collection, favorites))
+    }
+    .await;
+
+    match result {
+        Ok((collection, favorites)) => {
+            let data = json!({
+                "name": collection.name,
+                "username": collection.username,
+                "favorites": favorites
+            });
+            let body = hb.render("shared", &data).unwrap_or_else(|_| "Template error".to_string());
+            HttpResponse::Ok().body(body)
+        }
+        Err(err) => {
+            eprintln!("Failed to load shared collection {}: {}", collection_id, err);
+            HttpResponse::InternalServerError().body("Failed to load shared list")
+        }
+    }
+}
@@ -0,0 +1,54 @@
+// Outgoing text messages. The transport is chosen with the SMS_TRANSPORT
+// environment variable: "log" (the default) prints messages to stdout, and "file"
+// writes each one to SMS_DIR (default "./sms") so they can be inspected during
+// development. A gateway only needs to implement `SmsSender`.
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct Sms {
+    pub to: String,
+    pub body: String,
+}
+
+pub trait SmsSender: Send + Sync {
+    fn send(&self, sms: &Sms) -> Result<(), Box<dyn Error>>;
+}
+
+pub struct LogSms;
+
+impl SmsSender for LogSms {
+    fn send(&self, sms: &Sms) -> Result<(), Box<dyn Error>> {
+        println!("SMS to {}: {}\n", sms.to, sms.body);
+        Ok(())
+    }
+}
+
+pub struct FileSms {
+    dir: PathBuf,
+}
+
+impl SmsSender for FileSms {
+    fn send(&self, sms: &Sms) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(&self.dir)?;
+        let sent_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        let path = self.dir.join(format!("{}.txt", sent_at));
+        fs::write(path, format!("To: {}\n\n{}\n", sms.to, sms.body))?;
+        Ok(())
+    }
+}
+
+// Build the SMS transport configured in the environment
+pub fn from_env() -> Box<dyn SmsSender> {
+    match std::env::var("SMS_TRANSPORT").as_deref() {
+        Ok("file") => Box::new(FileSms {
+            dir: std::env::var("SMS_DIR").unwrap_or_else(|_| "./sms".to_string()).into(),
+        }),
+        Ok("log") | Err(_) => Box::new(LogSms),
+        Ok(other) => {
+            eprintln!("Unknown SMS_TRANSPORT {:?}, logging text messages instead", other);
+            Box::new(LogSms)
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use crate::find_providers::{geocode_state, reverse_geocode_state, Coordinates};
+use crate::insurance;
+use crate::session::{current_user_id, require_admin};
+
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+
+#[derive(Debug, Serialize)]
+pub struct TelehealthProvider {
+    pub id: i64,
+    pub name: String,
+    pub specialties: Vec<String>,
+    pub licensed_states: Vec<String>,
+    pub hours: String,
+    pub price: Option<f64>,
+    pub accepted_insurance: Vec<String>,
+    pub booking_url: String,
+    pub in_network: bool, // Whether the logged-in user's plan is accepted
+}
+
+// One row of a catalog CSV. List columns are separated by semicolons, and
+// specialties use the same names as the service type dropdown (e.g. "doctor").
+#[derive(Deserialize)]
+struct CatalogRow {
+    name: String,
+    specialties: String,
+    licensed_states: String,
+    hours: String,
+    price: Option<f64>,
+    accepted_insurance: String,
+    booking_url: String,
+}
+
+// Parameters for a virtual-visit search, taken from the `/services` query
+pub struct VirtualSearch<'a> {
+    pub state: Option<&'a str>,
+    pub zip: Option<&'a str>,
+    pub coordinates: Option<Coordinates>,
+    pub specialty: &'a str,
+    pub in_network: bool, // Only return providers in the user's insurance network
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(';')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn parse_list(value: &str) -> Vec<String> {
+    serde_json::from_str(value).unwrap_or_default()
+}
+
+// Catalog providers licensed in `state` that offer `specialty`, cheapest first
+pub async fn search_catalog(
+    pool: &SqlitePool,
+    state: &str,
+    specialty: &str,
+) -> Result<Vec<TelehealthProvider>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT id, name, specialties, licensed_states, hours, price, accepted_insurance, booking_url
+         FROM telehealth_providers
+         WHERE EXISTS (SELECT 1 FROM json_each(telehealth_providers.licensed_states) WHERE upper(value) = upper(?))
+           AND EXISTS (SELECT 1 FROM json_each(telehealth_providers.specialties) WHERE lower(value) = lower(?))
+         ORDER BY price IS NULL, price, name",
+        state,
+        specialty
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| TelehealthProvider {
+            id: row.id,
+            name: row.name,
+            specialties: parse_list(&row.specialties),
+            licensed_states: parse_list(&row.licensed_states),
+            hours: row.hours,
+            price: row.price,
+            accepted_insurance: parse_list(&row.accepted_insurance),
+            booking_url: row.booking_url,
+            in_network: false,
+        })
+        .collect())
+}
+
+// Handle `/services?mode=virtual`: ignore the search radius and filter the
+// telehealth catalog by the user's state and the requested specialty
+pub async fn virtual_search(
+    req: &HttpRequest,
+    pool: &SqlitePool,
+    api_key: &str,
+    search: VirtualSearch<'_>,
+) -> HttpResponse {
+    let state = if let Some(state) = search.state {
+        Some(state.to_uppercase())
+    } else if let Some(coordinates) = &search.coordinates {
+        match reverse_geocode_state(coordinates, api_key).await {
+            Ok(state) => state,
+            Err(err) => {
+                eprintln!("Reverse geocoding failed: {}", err);
+                return HttpResponse::InternalServerError().body("Failed to look up your state");
+            }
+        }
+    } else if let Some(zip) = search.zip {
+        match geocode_state(zip, api_key).await {
+            Ok(state) => state,
+            Err(err) => {
+                eprintln!("Geocoding failed: {}", err);
+                return HttpResponse::InternalServerError().body("Failed to geocode zip code");
+            }
+        }
+    } else {
+        return HttpResponse::BadRequest().body("Please provide a state, ZIP code or lat/lng");
+    };
+
+    let Some(state) = state else {
+        return HttpResponse::BadRequest().body("Could not determine a US state for that location");
+    };
+
+    let mut providers = match search_catalog(pool, &state, search.specialty).await {
+        Ok(providers) => providers,
+        Err(err) => {
+            eprintln!("Failed to search telehealth catalog: {}", err);
+            return HttpResponse::InternalServerError().body("Failed to fetch telehealth providers");
+        }
+    };
+
+    let user_id = current_user_id(req);
+    let mut insurance_plan = None;
+    if let Some(user_id) = user_id {
+        if let Ok(Some(plan)) = insurance::user_plan(pool, user_id).await {
+            for provider in providers.iter_mut() {
+                provider.in_network = provider
+                    .accepted_insurance
+                    .iter()
+                    .any(|accepted| accepted.eq_ignore_ascii_case(&plan.name));
+            }
+            insurance_plan = Some(plan.name);
+        }
+    }
+
+    // Without a plan nothing is in network, as for in-person searches
+    if search.in_network {
+        providers.retain(|provider| provider.in_network);
+    }
+
+    HttpResponse::Ok().json(json!({
+        "mode": "virtual",
+        "state": state,
+        "providers": providers,
+        "isLoggedIn": user_id.is_some(),
+        "insurancePlan": insurance_plan
+    }))
+}
+
+// Handler for the `/admin/telehealth/import` endpoint (CSV request body)
+#[post("/admin/telehealth/import")]
+async fn import_catalog(req: HttpRequest, body: String, pool: web::Data<SqlitePool>) -> impl Responder {
+    if let Err(response) = require_admin(&req, pool.get_ref()).await {
+        return response;
+    }
+
+    let mut rows = Vec::new();
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(body.as_bytes());
+    for (line, row) in reader.deserialize::<CatalogRow>().enumerate() {
+        match row {
+            Ok(row) => rows.push(row),
+            Err(err) => {
+                return HttpResponse::BadRequest().json(json!({
+                    "status": "error",
+                    "message": format!("Invalid CSV on row {}: {}", line + 1, err)
+                }));
+            }
+        }
+    }
+
+    // Import the whole file or nothing
+    let result: Result<(), sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+        for row in &rows {
+            let specialties = json!(split_list(&row.specialties)).to_string();
+            let licensed_states = json!(split_list(&row.licensed_states.to_uppercase())).to_string();
+            let accepted_insurance = json!(split_list(&row.accepted_insurance)).to_string();
+
+            sqlx::query!(
+                "INSERT INTO telehealth_providers
+                     (name, specialties, licensed_states, hours, price, accepted_insurance, booking_url)
+                 VALUES (?, ?, ?, ?, ?, ?, ?)
+                 ON CONFLICT (name) DO UPDATE SET
+                     specialties = excluded.specialties,
+                     licensed_states = excluded.licensed_states,
+                     hours = excluded.hours,
+                     price = excluded.price,
+                     accepted_insurance = excluded.accepted_insurance,
+                     booking_url = excluded.booking_url",
+                row.name,
+                specialties,
+                licensed_states,
+                row.hours,
+                row.price,
+                accepted_insurance,
+                row.booking_url
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+    .await;
+
+    match result {
+        Ok(()) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "providers": rows.len()
+        })),
+        Err(err) => {
+            eprintln!("Failed to import telehealth catalog: {}", err);
+            HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Failed to import telehealth catalog."
+            }))
+        }
+    }
+}
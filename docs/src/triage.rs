@@ -0,0 +1,406 @@
+// Symptom guidance: an educational wizard for users who aren't sure which service
+// type to search for. Red-flag symptoms are asked about first and go straight to
+// emergency advice; otherwise a short series of questions ends in an outcome with an
+// urgency level, the specialties that usually help, and a search to run. The rules are
+// data, loaded at startup from a versioned JSON file (TRIAGE_RULES, by default
+// ./triage/rules.json) and checked so that every path ends in an outcome. Nothing is
+// stored: each step sends the answers so far and the server replays them.
+use crate::find_providers::SERVICE_TYPES;
+
+use actix_web::http::StatusCode;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use handlebars::Handlebars;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashSet;
+use std::error::Error;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Urgency {
+    SelfCare,
+    Telehealth,
+    PrimaryCare,
+    UrgentCare,
+    Emergency,
+}
+
+impl Urgency {
+    fn label(self) -> &'static str {
+        match self {
+            Urgency::SelfCare => "Self-care",
+            Urgency::Telehealth => "Telehealth",
+            Urgency::PrimaryCare => "Primary care",
+            Urgency::UrgentCare => "Urgent care",
+            Urgency::Emergency => "Emergency",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RedFlag {
+    id: String,
+    text: String,
+}
+
+// Each answer either asks another question or ends in an outcome
+#[derive(Deserialize)]
+struct Answer {
+    label: String,
+    next: Option<String>,
+    outcome: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Question {
+    id: String,
+    text: String,
+    answers: Vec<Answer>,
+}
+
+#[derive(Deserialize)]
+struct Outcome {
+    id: String,
+    urgency: Urgency,
+    title: String,
+    advice: String,
+    specialties: Vec<String>,
+    service_type: Option<String>, // Search to suggest, one of find_providers::SERVICE_TYPES
+}
+
+#[derive(Deserialize)]
+pub struct TriageRules {
+    version: String,
+    disclaimer: String,
+    red_flags: Vec<RedFlag>,
+    red_flag_outcome: String, // Outcome for any red flag; must be an emergency
+    start: String,
+    questions: Vec<Question>,
+    outcomes: Vec<Outcome>,
+}
+
+#[derive(Deserialize)]
+struct StepRequest {
+    version: Option<String>, // Rules version the wizard started with
+    #[serde(default)]
+    red_flags: Vec<String>,
+    #[serde(default)]
+    answers: Vec<usize>, // Index of the answer picked for each question so far
+}
+
+fn error(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+impl TriageRules {
+    // Read and check the rules in TRIAGE_RULES
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let path = std::env::var("TRIAGE_RULES").unwrap_or_else(|_| "./triage/rules.json".to_string());
+        Self::from_file(&path)
+    }
+
+    fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        let rules: TriageRules = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        rules.validate().map_err(|err| format!("{}: {}", path, err))?;
+        Ok(rules)
+    }
+
+    fn question(&self, id: &str) -> Option<&Question> {
+        self.questions.iter().find(|question| question.id == id)
+    }
+
+    fn outcome(&self, id: &str) -> Option<&Outcome> {
+        self.outcomes.iter().find(|outcome| outcome.id == id)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.version.trim().is_empty() {
+            return Err("The rules need a version".to_string());
+        }
+        let mut ids = HashSet::new();
+        for id in self.questions.iter().map(|question| &question.id).chain(self.outcomes.iter().map(|outcome| &outcome.id)) {
+            if !ids.insert(id) {
+                return Err(format!("ID {} is used more than once", id));
+            }
+        }
+        let mut flags = HashSet::new();
+        if let Some(flag) = self.red_flags.iter().find(|flag| !flags.insert(&flag.id)) {
+            return Err(format!("Red flag {} is listed more than once", flag.id));
+        }
+        match self.outcome(&self.red_flag_outcome) {
+            Some(outcome) if outcome.urgency == Urgency::Emergency => {}
+            _ => return Err("red_flag_outcome must name an emergency outcome".to_string()),
+        }
+
+        for question in &self.questions {
+            if question.answers.is_empty() {
+                return Err(format!("Question {} has no answers", question.id));
+            }
+            for answer in &question.answers {
+                match (&answer.next, &answer.outcome) {
+                    (Some(next), None) if self.question(next).is_some() => {}
+                    (None, Some(outcome)) if self.outcome(outcome).is_some() => {}
+                    _ => {
+                        return Err(format!(
+                            "Answer \"{}\" to question {} must lead to one existing question or outcome",
+                            answer.label, question.id
+                        ))
+                    }
+                }
+            }
+        }
+        for outcome in &self.outcomes {
+            if let Some(service_type) = &outcome.service_type {
+                if !SERVICE_TYPES.iter().any(|(value, _)| value == service_type) {
+                    return Err(format!("Outcome {} has unknown service type {}", outcome.id, service_type));
+                }
+            }
+        }
+
+        // Every path from the start must end in an outcome, so no question may lead back to itself
+        let mut done = HashSet::new();
+        self.visit(&self.start, &mut HashSet::new(), &mut done)?;
+        if let Some(question) = self.questions.iter().find(|question| !done.contains(question.id.as_str())) {
+            return Err(format!("Question {} can't be reached from the start", question.id));
+        }
+        Ok(())
+    }
+
+    fn visit<'a>(&'a self, id: &'a str, visiting: &mut HashSet<&'a str>, done: &mut HashSet<&'a str>) -> Result<(), String> {
+        if done.contains(id) {
+            return Ok(());
+        }
+        if !visiting.insert(id) {
+            return Err(format!("Question {} leads back to itself", id));
+        }
+        let question = self.question(id).ok_or_else(|| format!("Question {} doesn't exist", id))?;
+        for next in question.answers.iter().filter_map(|answer| answer.next.as_deref()) {
+            self.visit(next, visiting, done)?;
+        }
+        visiting.remove(id);
+        done.insert(id);
+        Ok(())
+    }
+}
+
+fn outcome_json(outcome: &Outcome) -> serde_json::Value {
+    // Pre-fill the search page; telehealth outcomes search for virtual visits
+    let search_url = outcome.service_type.as_ref().map(|service_type| {
+        let mode = if outcome.urgency == Urgency::Telehealth { "&mode=virtual" } else { "" };
+        format!("/?service_type={}{}", service_type, mode)
+    });
+    json!({
+        "title": outcome.title,
+        "advice": outcome.advice,
+        "urgency": outcome.urgency,
+        "urgency_label": outcome.urgency.label(),
+        "specialties": outcome.specialties,
+        "search_url": search_url
+    })
+}
+
+// Handler for the `/triage` page
+#[get("/triage")]
+async fn triage_page(rules: web::Data<TriageRules>, hb: web::Data<Handlebars<'_>>) -> impl Responder {
+    let data = json!({
+        "version": rules.version,
+        "disclaimer": rules.disclaimer,
+        "red_flags": rules.red_flags
+    });
+    let body = hb.render("triage", &data).unwrap_or_else(|_| "Template error".to_string());
+    HttpResponse::Ok().body(body)
+}
+
+// Handler for `POST /api/triage`: the next question for the answers so far, or the outcome
+#[post("/api/triage")]
+async fn triage_step(body: web::Json<StepRequest>, rules: web::Data<TriageRules>) -> impl Responder {
+    if body.version.as_ref().is_some_and(|version| *version != rules.version) {
+        return error(StatusCode::CONFLICT, "This guide has been updated. Please start again.");
+    }
+
+    if let Some(flag) = body.red_flags.iter().find(|id| !rules.red_flags.iter().any(|flag| flag.id == **id)) {
+        return error(StatusCode::BAD_REQUEST, &format!("Unknown symptom {}", flag));
+    }
+    // Any red flag goes straight to emergency advice
+    if !body.red_flags.is_empty() {
+        let outcome = rules.outcome(&rules.red_flag_outcome).expect("Red flag outcome is checked at load");
+        return HttpResponse::Ok().json(json!({
+            "version": rules.version,
+            "red_flag": true,
+            "outcome": outcome_json(outcome)
+        }));
+    }
+
+    let mut question = rules.question(&rules.start).expect("Start question is checked at load");
+    for (step, &index) in body.answers.iter().enumerate() {
+        let Some(answer) = question.answers.get(index) else {
+            return error(StatusCode::BAD_REQUEST, "That answer isn't one of the choices.");
+        };
+        if let Some(outcome) = answer.outcome.as_deref().and_then(|id| rules.outcome(id)) {
+            if step + 1 < body.answers.len() {
+                return error(StatusCode::BAD_REQUEST, "More answers were sent than there are questions.");
+            }
+            return HttpResponse::Ok().json(json!({
+                "version": rules.version,
+                "red_flag": false,
+                "outcome": outcome_json(outcome)
+            }));
+        }
+        question = answer
+            .next
+            .as_deref()
+            .and_then(|id| rules.question(id))
+            .expect("Answers are checked at load");
+    }
+
+    HttpResponse::Ok().json(json!({
+        "version": rules.version,
+        "question": {
+            "text": question.text,
+            "answers": question.answers.iter().map(|answer| &answer.label).collect::<Vec<_>>()
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+    use actix_web::App;
+    use serde_json::Value;
+
+    // Two questions: "a" leads to "b" or to primary care, and "b" to self-care or the emergency room
+    fn rules_json() -> Value {
+        json!({
+            "version": "1",
+            "disclaimer": "Education only.",
+            "red_flags": [{ "id": "chest_pain", "text": "Chest pain" }],
+            "red_flag_outcome": "emergency",
+            "start": "a",
+            "questions": [
+                { "id": "a", "text": "First?", "answers": [
+                    { "label": "More", "next": "b" },
+                    { "label": "Check-up", "outcome": "primary" }
+                ]},
+                { "id": "b", "text": "Second?", "answers": [
+                    { "label": "Mild", "outcome": "self_care" },
+                    { "label": "Severe", "outcome": "emergency" }
+                ]}
+            ],
+            "outcomes": [
+                { "id": "emergency", "urgency": "emergency", "title": "Go to the ER", "advice": "",
+                  "specialties": [], "service_type": "hospital" },
+                { "id": "primary", "urgency": "primary_care", "title": "See your doctor", "advice": "",
+                  "specialties": [], "service_type": "doctor" },
+                { "id": "self_care", "urgency": "self_care", "title": "Rest", "advice": "",
+                  "specialties": [], "service_type": null }
+            ]
+        })
+    }
+
+    fn rules(value: Value) -> TriageRules {
+        serde_json::from_value(value).unwrap()
+    }
+
+    // The rules with one answer of one question replaced
+    fn with_answer(question: usize, answer: usize, replacement: Value) -> TriageRules {
+        let mut value = rules_json();
+        value["questions"][question]["answers"][answer] = replacement;
+        rules(value)
+    }
+
+    #[test]
+    fn shipped_rules_load() {
+        TriageRules::from_file("./triage/rules.json").unwrap();
+    }
+
+    #[test]
+    fn valid_rules_pass() {
+        rules(rules_json()).validate().unwrap();
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let rules = with_answer(1, 0, json!({ "label": "Back", "next": "a" }));
+        assert_eq!(rules.validate().unwrap_err(), "Question a leads back to itself");
+    }
+
+    #[test]
+    fn unreachable_questions_are_rejected() {
+        let rules = with_answer(0, 0, json!({ "label": "More", "outcome": "self_care" }));
+        assert_eq!(rules.validate().unwrap_err(), "Question b can't be reached from the start");
+    }
+
+    #[test]
+    fn dangling_answers_are_rejected() {
+        for answer in [
+            json!({ "label": "Nowhere", "next": "missing" }),
+            json!({ "label": "Nowhere", "outcome": "missing" }),
+            json!({ "label": "Nowhere" }),
+            json!({ "label": "Both", "next": "b", "outcome": "primary" }),
+        ] {
+            let rules = with_answer(0, 1, answer);
+            assert!(rules.validate().unwrap_err().contains("must lead to one existing question or outcome"));
+        }
+    }
+
+    #[test]
+    fn red_flag_outcome_must_be_an_emergency() {
+        let mut value = rules_json();
+        value["red_flag_outcome"] = json!("primary");
+        assert!(rules(value).validate().is_err());
+        let mut value = rules_json();
+        value["red_flag_outcome"] = json!("missing");
+        assert!(rules(value).validate().is_err());
+    }
+
+    async fn step(body: Value) -> (StatusCode, Value) {
+        let app = init_service(
+            App::new().app_data(web::Data::new(rules(rules_json()))).service(triage_step),
+        )
+        .await;
+        let req = TestRequest::post().uri("/api/triage").set_json(body).to_request();
+        let response = call_service(&app, req).await;
+        let status = response.status();
+        (status, read_body_json(response).await)
+    }
+
+    #[actix_web::test]
+    async fn any_red_flag_goes_to_emergency() {
+        let (status, body) = step(json!({ "red_flags": ["chest_pain"], "answers": [1] })).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["red_flag"], true);
+        assert_eq!(body["outcome"]["urgency"], "emergency");
+
+        let (status, _) = step(json!({ "red_flags": ["unknown"] })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn answers_are_replayed() {
+        let (_, body) = step(json!({ "version": "1", "answers": [] })).await;
+        assert_eq!(body["question"]["text"], "First?");
+        let (_, body) = step(json!({ "answers": [0] })).await;
+        assert_eq!(body["question"]["text"], "Second?");
+        let (_, body) = step(json!({ "answers": [0, 0] })).await;
+        assert_eq!(body["outcome"]["urgency"], "self_care");
+        let (_, body) = step(json!({ "answers": [1] })).await;
+        assert_eq!(body["outcome"]["search_url"], "/?service_type=doctor");
+    }
+
+    #[actix_web::test]
+    async fn bad_replays_are_rejected() {
+        let (status, _) = step(json!({ "answers": [2] })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = step(json!({ "answers": [0, 5] })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        // Extra answers after an outcome
+        let (status, _) = step(json!({ "answers": [1, 0] })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = step(json!({ "version": "0", "answers": [] })).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+}
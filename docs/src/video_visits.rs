@@ -0,0 +1,495 @@
+// Signaling for in-browser video visits. Each booked appointment has a visit room
+// with two seats: the patient who booked it and one of the provider's clinicians.
+// Both connect to the room's WebSocket, and the server relays SDP offers and
+// answers and ICE candidates between them; media goes peer to peer and never
+// passes through here. Rooms live in memory, while joins and leaves are recorded
+// in `visit_events`.
+use crate::appointments::{display_date, display_time, load_calendar, now_in, SLOT_FORMAT};
+use crate::session::{current_user_id, require_admin};
+
+use actix_web::http::StatusCode;
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use actix_ws::{CloseCode, CloseReason, Message, Session};
+use chrono::{Duration, NaiveDateTime};
+use chrono_tz::Tz;
+use handlebars::Handlebars;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration as StdDuration, Instant};
+
+// How long before the appointment the room opens, and how long after it stays open
+const OPENS_BEFORE_MINUTES: i64 = 15;
+const CLOSES_AFTER_MINUTES: i64 = 30;
+
+// Largest signaling message accepted; SDP with many candidates fits well within this
+const MAX_MESSAGE_BYTES: usize = 64 * 1024;
+
+// How often each participant is pinged, and how long without hearing from them before
+// their seat is given up, so a connection that dropped without closing doesn't hold it
+pub const HEARTBEAT_INTERVAL: StdDuration = StdDuration::from_secs(5);
+pub const CLIENT_TIMEOUT: StdDuration = StdDuration::from_secs(15);
+
+// Used when STUN_URL isn't set
+const DEFAULT_STUN_URL: &str = "stun:stun.l.google.com:19302";
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Role {
+    Patient,
+    Clinician,
+}
+
+impl Role {
+    fn as_str(self) -> &'static str {
+        match self {
+            Role::Patient => "patient",
+            Role::Clinician => "clinician",
+        }
+    }
+}
+
+struct Visit {
+    provider_name: String,
+    patient_name: String,
+    starts_at: NaiveDateTime,
+    ends_at: NaiveDateTime,
+    timezone: Tz,
+    role: Role,
+}
+
+struct Peer {
+    connection: u64,
+    session: Session,
+}
+
+// Visit rooms shared across all workers, keyed by appointment ID
+#[derive(Default)]
+pub struct VisitRooms {
+    rooms: Mutex<HashMap<i64, HashMap<Role, Peer>>>,
+    next_connection: AtomicU64,
+}
+
+impl VisitRooms {
+    fn is_taken(&self, appointment_id: i64, role: Role) -> bool {
+        let rooms = self.rooms.lock().unwrap();
+        rooms.get(&appointment_id).is_some_and(|room| room.contains_key(&role))
+    }
+
+    // Take the seat for `role`, returning this connection's ID and the other
+    // participant's session if they're already there
+    fn join(&self, appointment_id: i64, role: Role, session: Session) -> Option<(u64, Option<Session>)> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = rooms.entry(appointment_id).or_default();
+        if room.contains_key(&role) {
+            return None;
+        }
+        let connection = self.next_connection.fetch_add(1, Ordering::Relaxed);
+        let other = room.values().next().map(|peer| peer.session.clone());
+        room.insert(role, Peer { connection, session });
+        Some((connection, other))
+    }
+
+    fn other(&self, appointment_id: i64, role: Role) -> Option<Session> {
+        let rooms = self.rooms.lock().unwrap();
+        let room = rooms.get(&appointment_id)?;
+        room.iter()
+            .find(|(seat, _)| **seat != role)
+            .map(|(_, peer)| peer.session.clone())
+    }
+
+    // Give up the seat taken by `connection`, returning the other participant's session
+    fn leave(&self, appointment_id: i64, role: Role, connection: u64) -> Option<Session> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = rooms.get_mut(&appointment_id)?;
+        if room.get(&role).is_some_and(|peer| peer.connection == connection) {
+            room.remove(&role);
+        }
+        let other = room.values().next().map(|peer| peer.session.clone());
+        if room.is_empty() {
+            rooms.remove(&appointment_id);
+        }
+        other
+    }
+}
+
+#[derive(Serialize)]
+pub struct ClinicianVisit {
+    pub id: i64,
+    pub provider_name: String,
+    pub patient_name: String,
+    pub when: String, // For display, e.g. "Mon, Oct 20 at 9:30 AM"
+    pub timezone: String,
+    pub intake_submitted: bool,
+}
+
+#[derive(Deserialize)]
+struct NewClinician {
+    username: String,
+}
+
+fn error(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+// Whether the user takes video visits for the provider (by stable ID)
+pub async fn is_clinician(pool: &SqlitePool, provider_id: &str, user_id: i64) -> Result<bool, sqlx::Error> {
+    let count = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM provider_clinicians WHERE provider_id = ? AND user_id = ?",
+        provider_id,
+        user_id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(count > 0)
+}
+
+// The visit for a booked appointment, if `user_id` is its patient or one of the
+// provider's clinicians
+async fn load_visit(pool: &SqlitePool, appointment_id: i64, user_id: i64) -> Result<Option<Visit>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT appointments.user_id, starts_at, ends_at, provider_calendars.name, provider_calendars.timezone,
+                users.username AS patient_name,
+                EXISTS (SELECT 1 FROM provider_clinicians
+                        WHERE provider_clinicians.provider_id = appointments.provider_id
+                          AND provider_clinicians.user_id = ?) AS \"is_clinician!: bool\"
+         FROM appointments
+         JOIN provider_calendars ON provider_calendars.provider_id = appointments.provider_id
+         JOIN users ON users.id = appointments.user_id
+         WHERE appointments.id = ? AND status = 'booked'",
+        user_id,
+        appointment_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.and_then(|row| {
+        let role = if row.user_id == user_id {
+            Role::Patient
+        } else if row.is_clinician {
+            Role::Clinician
+        } else {
+            return None;
+        };
+        Some(Visit {
+            provider_name: row.name,
+            patient_name: row.patient_name,
+            starts_at: NaiveDateTime::parse_from_str(&row.starts_at, SLOT_FORMAT).ok()?,
+            ends_at: NaiveDateTime::parse_from_str(&row.ends_at, SLOT_FORMAT).ok()?,
+            timezone: row.timezone.parse().unwrap_or(Tz::UTC),
+            role,
+        })
+    }))
+}
+
+fn is_open(visit: &Visit) -> bool {
+    let now = now_in(&visit.timezone);
+    now >= visit.starts_at - Duration::minutes(OPENS_BEFORE_MINUTES)
+        && now <= visit.ends_at + Duration::minutes(CLOSES_AFTER_MINUTES)
+}
+
+async fn record_event(pool: &SqlitePool, appointment_id: i64, user_id: i64, role: Role, event: &str) {
+    let role = role.as_str();
+    let result = sqlx::query!(
+        "INSERT INTO visit_events (appointment_id, user_id, role, event) VALUES (?, ?, ?, ?)",
+        appointment_id,
+        user_id,
+        role,
+        event
+    )
+    .execute(pool)
+    .await;
+    if let Err(err) = result {
+        eprintln!("Failed to record {} for visit {}: {}", event, appointment_id, err);
+    }
+}
+
+// Send a message, ignoring a session that has already closed
+async fn send(session: &mut Session, message: Value) {
+    let _ = session.text(message.to_string()).await;
+}
+
+// Pass an offer, answer or candidate on to the other participant. Only the fields
+// WebRTC needs are copied, so the relay can't be used to send anything else.
+async fn relay(rooms: &VisitRooms, appointment_id: i64, role: Role, session: &mut Session, text: &str) {
+    let Ok(message) = serde_json::from_str::<Value>(text) else {
+        return send(session, json!({ "type": "error", "message": "Messages must be JSON." })).await;
+    };
+    let forwarded = match message["type"].as_str() {
+        Some(kind @ ("offer" | "answer")) if message["sdp"].is_string() => {
+            json!({ "type": kind, "from": role.as_str(), "sdp": message["sdp"] })
+        }
+        Some("candidate") if message["candidate"].is_object() => {
+            json!({ "type": "candidate", "from": role.as_str(), "candidate": message["candidate"] })
+        }
+        _ => {
+            return send(
+                session,
+                json!({ "type": "error", "message": "Expected an offer, answer or candidate." }),
+            )
+            .await
+        }
+    };
+
+    match rooms.other(appointment_id, role) {
+        Some(mut other) => send(&mut other, forwarded).await,
+        None => send(session, json!({ "type": "error", "message": "The other participant isn't here yet." })).await,
+    }
+}
+
+// Upcoming video visits with the providers the user is a clinician for, soonest first
+pub async fn list_clinician_visits(pool: &SqlitePool, user_id: i64) -> Result<Vec<ClinicianVisit>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT appointments.id AS \"id!\", starts_at, ends_at, provider_calendars.name, provider_calendars.timezone,
+                users.username AS patient_name,
+                EXISTS (SELECT 1 FROM intake_responses WHERE intake_responses.appointment_id = appointments.id)
+                    AS \"intake_submitted!: bool\"
+         FROM appointments
+         JOIN provider_clinicians ON provider_clinicians.provider_id = appointments.provider_id
+         JOIN provider_calendars ON provider_calendars.provider_id = appointments.provider_id
+         JOIN users ON users.id = appointments.user_id
+         WHERE provider_clinicians.user_id = ? AND status = 'booked'
+         ORDER BY starts_at",
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            let timezone: Tz = row.timezone.parse().unwrap_or(Tz::UTC);
+            let start = NaiveDateTime::parse_from_str(&row.starts_at, SLOT_FORMAT).ok()?;
+            let end = NaiveDateTime::parse_from_str(&row.ends_at, SLOT_FORMAT).ok()?;
+            (end + Duration::minutes(CLOSES_AFTER_MINUTES) > now_in(&timezone)).then(|| ClinicianVisit {
+                id: row.id,
+                provider_name: row.name,
+                patient_name: row.patient_name,
+                when: format!("{} at {}", display_date(&start), display_time(&start)),
+                timezone: row.timezone,
+                intake_submitted: row.intake_submitted,
+            })
+        })
+        .collect())
+}
+
+// Handler for the `/visits/{id}` video visit page
+#[get("/visits/{id}")]
+async fn visit_page(
+    req: HttpRequest,
+    path: web::Path<i64>,
+    pool: web::Data<SqlitePool>,
+    hb: web::Data<Handlebars<'_>>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Found().append_header(("Location", "/login")).finish();
+    };
+    let appointment_id = path.into_inner();
+
+    let visit = match load_visit(pool.get_ref(), appointment_id, user_id).await {
+        Ok(Some(visit)) => visit,
+        Ok(None) => return HttpResponse::NotFound().body("Visit not found"),
+        Err(err) => {
+            eprintln!("Failed to load visit {}: {}", appointment_id, err);
+            return HttpResponse::InternalServerError().body("Failed to load visit");
+        }
+    };
+
+    let other_name = match visit.role {
+        Role::Patient => &visit.provider_name,
+        Role::Clinician => &visit.patient_name,
+    };
+    let data = json!({
+        "appointment_id": appointment_id,
+        "other_name": other_name,
+        "when": format!("{} at {}", display_date(&visit.starts_at), display_time(&visit.starts_at)),
+        "timezone": visit.timezone.name(),
+        "open": is_open(&visit),
+        "opens_before_minutes": OPENS_BEFORE_MINUTES,
+        "stun_url": std::env::var("STUN_URL").unwrap_or_else(|_| DEFAULT_STUN_URL.to_string())
+    });
+    let body = hb.render("visit", &data).unwrap_or_else(|_| "Template error".to_string());
+    HttpResponse::Ok().body(body)
+}
+
+// Handler for the `/ws/visits/{id}` signaling WebSocket
+#[get("/ws/visits/{id}")]
+async fn signal(
+    req: HttpRequest,
+    body: web::Payload,
+    path: web::Path<i64>,
+    pool: web::Data<SqlitePool>,
+    rooms: web::Data<VisitRooms>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return error(StatusCode::UNAUTHORIZED, "User not logged in. Please log in and try again.");
+    };
+    let appointment_id = path.into_inner();
+
+    let visit = match load_visit(pool.get_ref(), appointment_id, user_id).await {
+        Ok(Some(visit)) => visit,
+        Ok(None) => return error(StatusCode::FORBIDDEN, "You're not part of this visit."),
+        Err(err) => {
+            eprintln!("Failed to load visit {}: {}", appointment_id, err);
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to join visit.");
+        }
+    };
+    if !is_open(&visit) {
+        return error(
+            StatusCode::FORBIDDEN,
+            "The visit room opens 15 minutes before the appointment and closes 30 minutes after it ends.",
+        );
+    }
+    let role = visit.role;
+    if rooms.is_taken(appointment_id, role) {
+        return error(StatusCode::CONFLICT, "You're already in this visit in another window.");
+    }
+
+    let (response, mut session, stream) = match actix_ws::handle(&req, body) {
+        Ok(upgrade) => upgrade,
+        Err(err) => return err.error_response(),
+    };
+    let mut stream = stream.max_frame_size(MAX_MESSAGE_BYTES);
+
+    // Checked again now that the socket is open, in case another window got in first
+    let Some((connection, other)) = rooms.join(appointment_id, role, session.clone()) else {
+        let reason = CloseReason {
+            code: CloseCode::Policy,
+            description: Some("Already in this visit in another window".to_string()),
+        };
+        let _ = session.close(Some(reason)).await;
+        return response;
+    };
+    record_event(pool.get_ref(), appointment_id, user_id, role, "join").await;
+    send(&mut session, json!({ "type": "joined", "role": role.as_str(), "peer_present": other.is_some() })).await;
+    if let Some(mut other) = other {
+        send(&mut other, json!({ "type": "peer_joined", "role": role.as_str() })).await;
+    }
+
+    let rooms = rooms.into_inner();
+    let pool = pool.into_inner();
+    actix_web::rt::spawn(async move {
+        let mut last_heard = Instant::now();
+        let mut heartbeat = actix_web::rt::time::interval(HEARTBEAT_INTERVAL);
+        loop {
+            tokio::select! {
+                message = stream.recv() => {
+                    let Some(Ok(message)) = message else {
+                        break;
+                    };
+                    last_heard = Instant::now();
+                    match message {
+                        Message::Text(text) => relay(&rooms, appointment_id, role, &mut session, &text).await,
+                        Message::Ping(bytes) => {
+                            let _ = session.pong(&bytes).await;
+                        }
+                        Message::Close(_) => break,
+                        _ => {}
+                    }
+                }
+                _ = heartbeat.tick() => {
+                    if last_heard.elapsed() > CLIENT_TIMEOUT || session.ping(b"").await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        if let Some(mut other) = rooms.leave(appointment_id, role, connection) {
+            send(&mut other, json!({ "type": "peer_left", "role": role.as_str() })).await;
+        }
+        record_event(&pool, appointment_id, user_id, role, "leave").await;
+        let _ = session.close(None).await;
+    });
+
+    response
+}
+
+// Handler for `POST /admin/providers/{id}/clinicians` (let a user take the provider's video visits)
+#[post("/admin/providers/{id}/clinicians")]
+async fn add_clinician(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<NewClinician>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    if let Err(response) = require_admin(&req, pool.get_ref()).await {
+        return response;
+    }
+    let id = path.into_inner();
+
+    let result = async {
+        let Some(calendar) = load_calendar(pool.get_ref(), &id).await? else {
+            return Ok(Err("This provider doesn't take appointments through the dashboard."));
+        };
+        let clinician_id = sqlx::query_scalar!("SELECT id FROM users WHERE username = ?", body.username)
+            .fetch_optional(pool.get_ref())
+            .await?;
+        let Some(clinician_id) = clinician_id else {
+            return Ok(Err("No user with that username."));
+        };
+        sqlx::query!(
+            "INSERT OR IGNORE INTO provider_clinicians (provider_id, user_id) VALUES (?, ?)",
+            calendar.provider_id,
+            clinician_id
+        )
+        .execute(pool.get_ref())
+        .await?;
+        Ok::<_, sqlx::Error>(Ok(calendar.provider_id))
+    }
+    .await;
+
+    match result {
+        Ok(Ok(provider_id)) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": format!("{} can now take video visits.", body.username),
+            "provider_id": provider_id
+        })),
+        Ok(Err(message)) => error(StatusCode::NOT_FOUND, message),
+        Err(err) => {
+            eprintln!("Failed to add clinician to {}: {}", id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to add clinician.")
+        }
+    }
+}
+
+// Handler for `DELETE /admin/providers/{id}/clinicians/{username}`
+#[delete("/admin/providers/{id}/clinicians/{username}")]
+async fn remove_clinician(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    pool: web::Data<SqlitePool>,
+) -> impl Responder {
+    if let Err(response) = require_admin(&req, pool.get_ref()).await {
+        return response;
+    }
+    let (id, username) = path.into_inner();
+
+    let result = sqlx::query!(
+        "DELETE FROM provider_clinicians
+         WHERE provider_id = COALESCE((SELECT provider_id FROM provider_aliases WHERE alias = ?), ?)
+           AND user_id = (SELECT id FROM users WHERE username = ?)",
+        id,
+        id,
+        username
+    )
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(res) if res.rows_affected() == 1 => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": format!("{} no longer takes video visits.", username)
+        })),
+        Ok(_) => error(StatusCode::NOT_FOUND, "That user isn't a clinician for this provider."),
+        Err(err) => {
+            eprintln!("Failed to remove clinician from {}: {}", id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to remove clinician.")
+        }
+    }
+}
@@ -0,0 +1,543 @@
+// Virtual waiting rooms for drop-in telehealth clinics. A provider's clinicians
+// open the room, patients join its queue, and the clinicians admit or skip whoever
+// is next. Every browser watching a room holds a WebSocket; when the queue changes
+// the server broadcasts the clinic's ID, and each connection works out its own view
+// (a patient's position and estimated wait, or the clinician's queue) and pushes it.
+use crate::appointments::load_calendar;
+use crate::session::current_user_id;
+use crate::video_visits::{is_clinician, CLIENT_TIMEOUT, HEARTBEAT_INTERVAL};
+
+use actix_web::http::StatusCode;
+use actix_web::{get, post, put, web, HttpRequest, HttpResponse, Responder};
+use actix_ws::Message;
+use handlebars::Handlebars;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use std::time::Instant;
+use tokio::sync::broadcast;
+
+// Queue changes that can be waiting for slow connections before they start missing some
+const UPDATE_BUFFER: usize = 256;
+
+// A patient's last visit to the queue is shown for this long after it ends
+const RECENT_ENTRY: &str = "-12 hours";
+
+// Typical visit length until a clinician sets one
+const DEFAULT_VISIT_MINUTES: i64 = 15;
+const MIN_VISIT_MINUTES: i64 = 1;
+const MAX_VISIT_MINUTES: i64 = 120;
+
+// Tells open connections which clinic's queue changed
+pub struct WaitingRoomUpdates {
+    sender: broadcast::Sender<String>,
+}
+
+impl Default for WaitingRoomUpdates {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(UPDATE_BUFFER);
+        WaitingRoomUpdates { sender }
+    }
+}
+
+impl WaitingRoomUpdates {
+    fn notify(&self, provider_id: &str) {
+        // Fails only when nobody is watching any room
+        let _ = self.sender.send(provider_id.to_string());
+    }
+}
+
+#[derive(Serialize)]
+struct PatientView {
+    open: bool,
+    status: Option<String>, // The patient's latest entry: "waiting", "admitted", "skipped" or "left"
+    position: Option<i64>,  // 1 when next
+    estimated_wait_minutes: Option<i64>,
+    waiting: i64,
+}
+
+#[derive(Serialize)]
+struct QueueEntry {
+    username: String,
+    joined_at: String,
+}
+
+#[derive(Serialize)]
+struct ClinicianView {
+    open: bool,
+    visit_minutes: i64,
+    queue: Vec<QueueEntry>,
+}
+
+#[derive(Deserialize)]
+struct RoomSettings {
+    open: bool,
+    visit_minutes: Option<i64>,
+}
+
+fn error(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(json!({
+        "status": "error",
+        "message": message
+    }))
+}
+
+fn not_logged_in() -> HttpResponse {
+    error(StatusCode::UNAUTHORIZED, "User not logged in. Please log in and try again.")
+}
+
+fn not_a_clinic() -> HttpResponse {
+    error(StatusCode::NOT_FOUND, "This provider doesn't see patients through the dashboard.")
+}
+
+// The clinic's stable provider ID and name, found by any ID the provider goes by
+async fn find_clinic(pool: &SqlitePool, id: &str) -> Result<Option<(String, String)>, sqlx::Error> {
+    let calendar = load_calendar(pool, id).await?;
+    Ok(calendar.map(|calendar| (calendar.provider_id, calendar.name)))
+}
+
+// Whether the clinic's waiting room is open, and its typical visit length
+async fn room_settings(pool: &SqlitePool, provider_id: &str) -> Result<(bool, i64), sqlx::Error> {
+    let room = sqlx::query!(
+        "SELECT is_open, visit_minutes FROM waiting_rooms WHERE provider_id = ?",
+        provider_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(room.map(|room| (room.is_open, room.visit_minutes)).unwrap_or((false, DEFAULT_VISIT_MINUTES)))
+}
+
+// Whether the clinic's waiting room is taking patients
+pub async fn is_open(pool: &SqlitePool, provider_id: &str) -> Result<bool, sqlx::Error> {
+    Ok(room_settings(pool, provider_id).await?.0)
+}
+
+async fn patient_view(pool: &SqlitePool, provider_id: &str, user_id: i64) -> Result<PatientView, sqlx::Error> {
+    let (open, visit_minutes) = room_settings(pool, provider_id).await?;
+    let waiting = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM waiting_room_entries WHERE provider_id = ? AND status = 'waiting'",
+        provider_id
+    )
+    .fetch_one(pool)
+    .await?;
+    let entry = sqlx::query!(
+        "SELECT id AS \"id!\", status FROM waiting_room_entries
+         WHERE provider_id = ? AND user_id = ? AND COALESCE(finished_at, joined_at) >= DATETIME('now', ?)
+         ORDER BY id DESC LIMIT 1",
+        provider_id,
+        user_id,
+        RECENT_ENTRY
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(entry) = entry else {
+        return Ok(PatientView { open, status: None, position: None, estimated_wait_minutes: None, waiting });
+    };
+    let position = if entry.status == "waiting" {
+        let ahead = sqlx::query_scalar!(
+            "SELECT COUNT(*) FROM waiting_room_entries WHERE provider_id = ? AND status = 'waiting' AND id < ?",
+            provider_id,
+            entry.id
+        )
+        .fetch_one(pool)
+        .await?;
+        Some(ahead + 1)
+    } else {
+        None
+    };
+
+    Ok(PatientView {
+        open,
+        status: Some(entry.status),
+        position,
+        // Everyone ahead, plus whoever the clinician is seeing now
+        estimated_wait_minutes: position.map(|position| position * visit_minutes),
+        waiting,
+    })
+}
+
+async fn clinician_view(pool: &SqlitePool, provider_id: &str) -> Result<ClinicianView, sqlx::Error> {
+    let (open, visit_minutes) = room_settings(pool, provider_id).await?;
+    let queue = sqlx::query_as!(
+        QueueEntry,
+        "SELECT users.username, waiting_room_entries.joined_at AS \"joined_at: String\"
+         FROM waiting_room_entries JOIN users ON users.id = waiting_room_entries.user_id
+         WHERE waiting_room_entries.provider_id = ? AND status = 'waiting'
+         ORDER BY waiting_room_entries.id",
+        provider_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(ClinicianView { open, visit_minutes, queue })
+}
+
+async fn view(pool: &SqlitePool, provider_id: &str, user_id: i64, clinician: bool) -> Result<Value, sqlx::Error> {
+    Ok(if clinician {
+        json!(clinician_view(pool, provider_id).await?)
+    } else {
+        json!(patient_view(pool, provider_id, user_id).await?)
+    })
+}
+
+// Look up the clinic and check the user is one of its clinicians
+async fn clinic_for_clinician(pool: &SqlitePool, id: &str, user_id: i64) -> Result<String, HttpResponse> {
+    let result = async {
+        let Some((provider_id, _)) = find_clinic(pool, id).await? else {
+            return Ok(None);
+        };
+        let clinician = is_clinician(pool, &provider_id, user_id).await?;
+        Ok::<_, sqlx::Error>(Some((provider_id, clinician)))
+    }
+    .await;
+
+    match result {
+        Ok(Some((provider_id, true))) => Ok(provider_id),
+        Ok(Some((_, false))) => Err(error(StatusCode::FORBIDDEN, "Only the clinic's clinicians can do that.")),
+        Ok(None) => Err(not_a_clinic()),
+        Err(err) => {
+            eprintln!("Failed to load clinic {}: {}", id, err);
+            Err(error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to load waiting room."))
+        }
+    }
+}
+
+// Handler for the `/waiting-room/{id}` page, for patients and clinicians alike
+#[get("/waiting-room/{id}")]
+async fn waiting_room_page(
+    req: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+    hb: web::Data<Handlebars<'_>>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return HttpResponse::Found().append_header(("Location", "/login")).finish();
+    };
+    let id = path.into_inner();
+
+    let result = async {
+        let Some((provider_id, name)) = find_clinic(pool.get_ref(), &id).await? else {
+            return Ok(None);
+        };
+        let clinician = is_clinician(pool.get_ref(), &provider_id, user_id).await?;
+        Ok::<_, sqlx::Error>(Some((provider_id, name, clinician)))
+    }
+    .await;
+
+    match result {
+        Ok(Some((provider_id, name, clinician))) => {
+            let data = json!({ "provider_id": provider_id, "name": name, "clinician": clinician });
+            let body = hb.render("waiting_room", &data).unwrap_or_else(|_| "Template error".to_string());
+            HttpResponse::Ok().body(body)
+        }
+        Ok(None) => HttpResponse::NotFound().body("This provider doesn't see patients through the dashboard"),
+        Err(err) => {
+            eprintln!("Failed to load waiting room {}: {}", id, err);
+            HttpResponse::InternalServerError().body("Failed to load waiting room")
+        }
+    }
+}
+
+// Handler for the `/ws/waiting-room/{id}` live updates WebSocket
+#[get("/ws/waiting-room/{id}")]
+async fn watch(
+    req: HttpRequest,
+    body: web::Payload,
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+    updates: web::Data<WaitingRoomUpdates>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let id = path.into_inner();
+
+    let clinic = async {
+        let Some((provider_id, _)) = find_clinic(pool.get_ref(), &id).await? else {
+            return Ok(None);
+        };
+        let clinician = is_clinician(pool.get_ref(), &provider_id, user_id).await?;
+        Ok::<_, sqlx::Error>(Some((provider_id, clinician)))
+    }
+    .await;
+    let (provider_id, clinician) = match clinic {
+        Ok(Some(clinic)) => clinic,
+        Ok(None) => return not_a_clinic(),
+        Err(err) => {
+            eprintln!("Failed to load waiting room {}: {}", id, err);
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to load waiting room.");
+        }
+    };
+
+    let (response, mut session, mut stream) = match actix_ws::handle(&req, body) {
+        Ok(upgrade) => upgrade,
+        Err(err) => return err.error_response(),
+    };
+    let mut changes = updates.sender.subscribe();
+    let pool = pool.into_inner();
+
+    actix_web::rt::spawn(async move {
+        let mut changed = true; // Send the current view straight away
+        let mut last_heard = Instant::now();
+        let mut heartbeat = actix_web::rt::time::interval(HEARTBEAT_INTERVAL);
+        loop {
+            if changed {
+                match view(&pool, &provider_id, user_id, clinician).await {
+                    Ok(view) => {
+                        if session.text(view.to_string()).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => eprintln!("Failed to load waiting room {}: {}", provider_id, err),
+                }
+            }
+
+            changed = tokio::select! {
+                change = changes.recv() => match change {
+                    Ok(changed_id) => changed_id == provider_id,
+                    // Missed some changes; one of them may have been this clinic's
+                    Err(broadcast::error::RecvError::Lagged(_)) => true,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                message = stream.recv() => match message {
+                    Some(Ok(Message::Ping(bytes))) => {
+                        last_heard = Instant::now();
+                        let _ = session.pong(&bytes).await;
+                        false
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {
+                        last_heard = Instant::now();
+                        false
+                    }
+                },
+                // Drop connections that went away without closing
+                _ = heartbeat.tick() => {
+                    if last_heard.elapsed() > CLIENT_TIMEOUT || session.ping(b"").await.is_err() {
+                        break;
+                    }
+                    false
+                }
+            };
+        }
+        let _ = session.close(None).await;
+    });
+
+    response
+}
+
+// Handler for `POST /api/waiting-room/{id}/join`
+#[post("/api/waiting-room/{id}/join")]
+async fn join_queue(
+    req: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+    updates: web::Data<WaitingRoomUpdates>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let id = path.into_inner();
+
+    let result = async {
+        let Some((provider_id, _)) = find_clinic(pool.get_ref(), &id).await? else {
+            return Ok(None);
+        };
+        if !is_open(pool.get_ref(), &provider_id).await? {
+            return Ok(Some((provider_id, false)));
+        }
+        // Joining again while already waiting keeps the original place
+        sqlx::query!(
+            "INSERT INTO waiting_room_entries (provider_id, user_id) VALUES (?, ?)
+             ON CONFLICT (provider_id, user_id) WHERE status = 'waiting' DO NOTHING",
+            provider_id,
+            user_id
+        )
+        .execute(pool.get_ref())
+        .await?;
+        Ok::<_, sqlx::Error>(Some((provider_id, true)))
+    }
+    .await;
+
+    match result {
+        Ok(Some((provider_id, true))) => {
+            updates.notify(&provider_id);
+            HttpResponse::Ok().json(json!({
+                "status": "success",
+                "message": "You're in the queue."
+            }))
+        }
+        Ok(Some((_, false))) => error(StatusCode::CONFLICT, "The waiting room is closed right now."),
+        Ok(None) => not_a_clinic(),
+        Err(err) => {
+            eprintln!("Failed to join waiting room {}: {}", id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to join the waiting room.")
+        }
+    }
+}
+
+// Handler for `POST /api/waiting-room/{id}/leave`
+#[post("/api/waiting-room/{id}/leave")]
+async fn leave_queue(
+    req: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+    updates: web::Data<WaitingRoomUpdates>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let id = path.into_inner();
+
+    let result = async {
+        let Some((provider_id, _)) = find_clinic(pool.get_ref(), &id).await? else {
+            return Ok(None);
+        };
+        let left = sqlx::query!(
+            "UPDATE waiting_room_entries SET status = 'left', finished_at = CURRENT_TIMESTAMP
+             WHERE provider_id = ? AND user_id = ? AND status = 'waiting'",
+            provider_id,
+            user_id
+        )
+        .execute(pool.get_ref())
+        .await?;
+        Ok::<_, sqlx::Error>(Some((provider_id, left.rows_affected() == 1)))
+    }
+    .await;
+
+    match result {
+        Ok(Some((provider_id, true))) => {
+            updates.notify(&provider_id);
+            HttpResponse::Ok().json(json!({
+                "status": "success",
+                "message": "You've left the queue."
+            }))
+        }
+        Ok(Some((_, false))) => error(StatusCode::NOT_FOUND, "You're not in this queue."),
+        Ok(None) => not_a_clinic(),
+        Err(err) => {
+            eprintln!("Failed to leave waiting room {}: {}", id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to leave the waiting room.")
+        }
+    }
+}
+
+// Take the next waiting patient out of the queue as "admitted" or "skipped"
+async fn call_next(
+    req: HttpRequest,
+    id: &str,
+    pool: &SqlitePool,
+    updates: &WaitingRoomUpdates,
+    outcome: &str,
+) -> HttpResponse {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let provider_id = match clinic_for_clinician(pool, id, user_id).await {
+        Ok(provider_id) => provider_id,
+        Err(response) => return response,
+    };
+
+    let next = sqlx::query!(
+        "UPDATE waiting_room_entries SET status = ?, finished_at = CURRENT_TIMESTAMP
+         WHERE id = (SELECT id FROM waiting_room_entries WHERE provider_id = ? AND status = 'waiting'
+                     ORDER BY id LIMIT 1)
+         RETURNING (SELECT username FROM users WHERE users.id = waiting_room_entries.user_id) AS \"username!: String\"",
+        outcome,
+        provider_id
+    )
+    .fetch_optional(pool)
+    .await;
+
+    match next {
+        Ok(Some(patient)) => {
+            updates.notify(&provider_id);
+            HttpResponse::Ok().json(json!({
+                "status": "success",
+                "message": format!("{} {}.", if outcome == "admitted" { "Admitted" } else { "Skipped" }, patient.username),
+                "username": patient.username
+            }))
+        }
+        Ok(None) => error(StatusCode::NOT_FOUND, "Nobody is waiting."),
+        Err(err) => {
+            eprintln!("Failed to update waiting room {}: {}", provider_id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to update the waiting room.")
+        }
+    }
+}
+
+// Handler for `POST /api/waiting-room/{id}/admit` (admit the next patient)
+#[post("/api/waiting-room/{id}/admit")]
+async fn admit_next(
+    req: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+    updates: web::Data<WaitingRoomUpdates>,
+) -> impl Responder {
+    call_next(req, &path.into_inner(), pool.get_ref(), updates.get_ref(), "admitted").await
+}
+
+// Handler for `POST /api/waiting-room/{id}/skip` (skip the next patient)
+#[post("/api/waiting-room/{id}/skip")]
+async fn skip_next(
+    req: HttpRequest,
+    path: web::Path<String>,
+    pool: web::Data<SqlitePool>,
+    updates: web::Data<WaitingRoomUpdates>,
+) -> impl Responder {
+    call_next(req, &path.into_inner(), pool.get_ref(), updates.get_ref(), "skipped").await
+}
+
+// Handler for `PUT /api/waiting-room/{id}` (open or close the room, and set the typical visit length)
+#[put("/api/waiting-room/{id}")]
+async fn update_room(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<RoomSettings>,
+    pool: web::Data<SqlitePool>,
+    updates: web::Data<WaitingRoomUpdates>,
+) -> impl Responder {
+    let Some(user_id) = current_user_id(&req) else {
+        return not_logged_in();
+    };
+    let id = path.into_inner();
+    let provider_id = match clinic_for_clinician(pool.get_ref(), &id, user_id).await {
+        Ok(provider_id) => provider_id,
+        Err(response) => return response,
+    };
+    if body
+        .visit_minutes
+        .is_some_and(|minutes| !(MIN_VISIT_MINUTES..=MAX_VISIT_MINUTES).contains(&minutes))
+    {
+        return error(StatusCode::BAD_REQUEST, "Visits must be between 1 and 120 minutes long.");
+    }
+
+    let visit_minutes = body.visit_minutes.unwrap_or(DEFAULT_VISIT_MINUTES);
+    let result = sqlx::query!(
+        "INSERT INTO waiting_rooms (provider_id, is_open, visit_minutes) VALUES (?, ?, ?)
+         ON CONFLICT (provider_id) DO UPDATE SET
+             is_open = excluded.is_open,
+             visit_minutes = COALESCE(?, waiting_rooms.visit_minutes),
+             updated_at = CURRENT_TIMESTAMP",
+        provider_id,
+        body.open,
+        visit_minutes,
+        body.visit_minutes
+    )
+    .execute(pool.get_ref())
+    .await;
+
+    match result {
+        Ok(_) => {
+            updates.notify(&provider_id);
+            HttpResponse::Ok().json(json!({
+                "status": "success",
+                "message": if body.open { "Waiting room open." } else { "Waiting room closed to new patients." }
+            }))
+        }
+        Err(err) => {
+            eprintln!("Failed to update waiting room {}: {}", provider_id, err);
+            error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to update the waiting room.")
+        }
+    }
+}
@@ -0,0 +1,78 @@
+// Article editor: saves the article as JSON and shows a preview rendered by the
+// server, so it's sanitized exactly as the published page will be.
+const form = document.getElementById('article-form');
+const articleId = form.dataset.articleId;
+const preview = document.getElementById('preview');
+
+let previewTimer = null;
+
+async function updatePreview() {
+    try {
+        const response = await fetch('/admin/articles/preview', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ body: form.elements.body.value }),
+        });
+        if (!response.ok) {
+            throw new Error('Failed to render preview');
+        }
+        const { html } = await response.json();
+        preview.innerHTML = html;
+    } catch (error) {
+        console.error('Error rendering preview:', error);
+    }
+}
+
+form.elements.body.addEventListener('input', () => {
+    clearTimeout(previewTimer);
+    previewTimer = setTimeout(updatePreview, 400);
+});
+
+form.addEventListener('submit', async (event) => {
+    event.preventDefault();
+    const elements = form.elements;
+    try {
+        const response = await fetch(articleId ? `/admin/articles/${articleId}` : '/admin/articles', {
+            method: articleId ? 'PUT' : 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({
+                title: elements.title.value,
+                slug: elements.slug.value,
+                summary: elements.summary.value,
+                body: elements.body.value,
+                service_type: elements.service_type.value || null,
+                published: elements.published.checked,
+            }),
+        });
+        const result = await response.json().catch(() => ({}));
+        if (!response.ok) {
+            throw new Error(result.message || 'Failed to save article');
+        }
+        window.location.href = `/admin/articles/${result.id}`;
+    } catch (error) {
+        console.error('Error saving article:', error);
+        alert(error.message);
+    }
+});
+
+const deleteButton = document.getElementById('delete-article');
+if (deleteButton) {
+    deleteButton.addEventListener('click', async () => {
+        if (!confirm('Delete this article?')) {
+            return;
+        }
+        try {
+            const response = await fetch(`/admin/articles/${articleId}`, { method: 'DELETE' });
+            const result = await response.json().catch(() => ({}));
+            if (!response.ok) {
+                throw new Error(result.message || 'Failed to delete article');
+            }
+            window.location.href = '/admin/articles';
+        } catch (error) {
+            console.error('Error deleting article:', error);
+            alert(error.message);
+        }
+    });
+}
+
+updatePreview();
@@ -0,0 +1,34 @@
+// Book the chosen time, or move the appointment being rescheduled to it
+async function chooseSlot(startsAt, label) {
+    const booking = document.getElementById('booking');
+    const providerId = booking.dataset.providerId;
+    const reschedule = booking.dataset.reschedule;
+
+    if (!confirm(`${reschedule ? 'Move your appointment to' : 'Book'} ${label}?`)) {
+        return;
+    }
+
+    try {
+        const response = reschedule
+            ? await fetch(`/api/appointments/${reschedule}`, {
+                  method: 'PATCH',
+                  headers: { 'Content-Type': 'application/json' },
+                  body: JSON.stringify({ starts_at: startsAt }),
+              })
+            : await fetch('/api/appointments', {
+                  method: 'POST',
+                  headers: { 'Content-Type': 'application/json' },
+                  body: JSON.stringify({ provider_id: providerId, starts_at: startsAt }),
+              });
+        const result = await response.json();
+        if (!response.ok) {
+            throw new Error(result.message || 'Failed to book appointment');
+        }
+        alert(result.message);
+        window.location.href = '/profile';
+    } catch (error) {
+        console.error('Error booking appointment:', error);
+        alert(error.message);
+        window.location.reload(); // Show the times as they are now
+    }
+}
@@ -10,19 +10,44 @@ async function fetchHealthServices(location, serviceType, useCurLocation) {
     } else {
         document.getElementById('serviceType').classList.remove('is-invalid');
     }
+    showLearnMore(serviceType);
+
+    // Only logged-in users have the in-network checkbox
+    const inNetworkCheckbox = document.getElementById('inNetwork');
+    const inNetwork = inNetworkCheckbox && inNetworkCheckbox.checked ? '&in_network=true' : '';
+    const mode = document.getElementById('searchMode').value === 'virtual' ? '&mode=virtual' : '';
 
     try {
         let response = null;
         if (!useCurLocation) {
-            response = await fetch(`/services?zip=${location}&service_type=${serviceType}`);
+            response = await fetch(`/services?zip=${location}&service_type=${serviceType}${inNetwork}${mode}`);
         } else {
-            response = await fetch(`/services?lat=${location.lat}&lng=${location.lng}&service_type=${serviceType}`);
+            response = await fetch(`/services?lat=${location.lat}&lng=${location.lng}&service_type=${serviceType}${inNetwork}${mode}`);
         }
         if (!response.ok) {
             throw new Error('Failed to fetch health services');
         }
 
         const data = await response.json();
+
+        // The query as it can be saved and re-run later
+        const search = {
+            service_type: serviceType,
+            in_network: inNetwork !== '',
+            ...(useCurLocation ? { lat: location.lat, lng: location.lng } : { zip: location }),
+        };
+
+        // Virtual results have no location, so list them without the map
+        if (data.mode === 'virtual') {
+            clearResults();
+            populateVirtualResults(data.providers, data.state);
+            if (data.isLoggedIn) {
+                document.getElementById('resultsHeader')
+                    .appendChild(createSaveSearchButton({ ...search, mode: 'virtual', state: data.state }));
+            }
+            return;
+        }
+
         const { coordinates, providers, isLoggedIn } = data;
         console.log(isLoggedIn);
 
@@ -46,14 +71,23 @@ async function fetchHealthServices(location, serviceType, useCurLocation) {
             return;
         }
 
-        populateCarousel(providers, markers, isLoggedIn);
+        headerDiv.appendChild(createExportLinks(providers, coordinates));
+        headerDiv.appendChild(createCompareButton(coordinates));
+        if (isLoggedIn) {
+            headerDiv.appendChild(createSaveSearchButton(search));
+        }
+
+        // Look up existing favorites so saved providers show a solid star
+        const favoriteIds = isLoggedIn ? await fetchFavoriteIds() : new Map();
+
+        populateCarousel(providers, markers, isLoggedIn, favoriteIds);
     } catch (error) {
         console.error('Error fetching health services:', error);
         headerDiv.innerHTML = '<p class="text-danger">Failed to load health services.</p>';
     }
 }
 
-function populateCarousel(providers, markers, isLoggedIn) {
+function populateCarousel(providers, markers, isLoggedIn, favoriteIds = new Map()) {
     const carouselInner = document.querySelector('#resultsCarousel .carousel-inner');
     const carouselDiv = document.getElementById('resultsCarousel');
     carouselDiv.hidden = false;
@@ -69,10 +103,10 @@ function populateCarousel(providers, markers, isLoggedIn) {
     // Populate carousel and link cards to markers
     providers.forEach((service) => {
         console.log(service);
-        const card = createServiceCard(service, isLoggedIn);
+        const card = createServiceCard(service, isLoggedIn, favoriteIds.get(service.id));
 
         // Find the corresponding marker
-        const markerEntry = markers.find(({ provider }) => provider.name === service.name && provider.address === service.address);
+        const markerEntry = markers.find(({ provider }) => provider.id === service.id);
 
         if (markerEntry) {
             card.addEventListener('click', () => {
@@ -95,6 +129,12 @@ function populateCarousel(providers, markers, isLoggedIn) {
                     if (starIcon.classList.contains('fas')) {
                         starIcon.classList.remove('fas');
                         starIcon.classList.add('far');
+
+                        const favoriteId = starButton.getAttribute('data-favorite-id');
+                        if (favoriteId) {
+                            removeFavorite(favoriteId);
+                            starButton.removeAttribute('data-favorite-id');
+                        }
                     }
                     // If the star icon is regular, change it to solid (favorited)
                     else {
@@ -102,12 +142,18 @@ function populateCarousel(providers, markers, isLoggedIn) {
                         starIcon.classList.add('fas');
 
                         // Get the service details from the star button's data attributes
+                        var providerId = starButton.getAttribute('data-provider-id');
                         var photo = starButton.getAttribute('data-photo');
                         var name = starButton.getAttribute('data-name');
                         var address = starButton.getAttribute('data-address');
                         // const phone = starButton.getAttribute('data-phone');
                         var rating = starButton.getAttribute('data-rating');
-                        saveFavorites(photo, name, address, rating);
+                        rating = rating ? parseFloat(rating) : null; // Places without reviews have no rating
+                        saveFavorites(providerId, photo, name, address, rating).then((favoriteId) => {
+                            if (favoriteId) {
+                                starButton.setAttribute('data-favorite-id', favoriteId);
+                            }
+                        });
                     }
                 });
             }
@@ -116,23 +162,84 @@ function populateCarousel(providers, markers, isLoggedIn) {
     });
 }
 
+// Link articles about the service type searched for, if there are any
+async function showLearnMore(serviceType) {
+    const learnMoreDiv = document.getElementById('learnMore');
+    learnMoreDiv.innerHTML = '';
+    try {
+        const response = await fetch(`/api/articles?service_type=${encodeURIComponent(serviceType)}`);
+        if (!response.ok) {
+            throw new Error('Failed to fetch articles');
+        }
+        const articles = await response.json();
+        if (articles.length === 0) {
+            return;
+        }
+        learnMoreDiv.innerHTML = '<i class="fa-solid fa-book-medical"></i> Learn more: ';
+        articles.forEach((article, index) => {
+            const link = document.createElement('a');
+            link.href = article.url;
+            link.title = article.summary;
+            link.textContent = article.title;
+            learnMoreDiv.append(index > 0 ? ' · ' : '', link);
+        });
+    } catch (error) {
+        console.error('Error fetching articles:', error);
+    }
+}
+
+// Function to list telehealth providers for a virtual-visit search
+function populateVirtualResults(providers, state) {
+    const carouselInner = document.querySelector('#resultsCarousel .carousel-inner');
+    const headerDiv = document.getElementById('resultsHeader');
+    const carouselDiv = document.getElementById('resultsCarousel');
+
+    headerDiv.innerHTML = `${providers.length} virtual providers licensed in ${state}`;
+    if (providers.length === 0) {
+        return;
+    }
+    carouselDiv.hidden = false;
+
+    providers.forEach((provider) => {
+        const card = document.createElement('div');
+        card.classList.add('card');
+        card.innerHTML = `
+        <div class="card-body position-relative">
+            <h5 class="card-title">${provider.name}</h5>
+            <p class="card-text">${provider.specialties.join(', ')}</p>
+            <p class="card-text">${provider.hours ? `Hours: ${provider.hours}` : ''}</p>
+            <p class="card-text">${provider.price != null ? `Self-pay: $${provider.price.toFixed(2)} per visit` : ''}</p>
+            ${
+                provider.accepted_insurance.length > 0
+                    ? `<p class="card-text"><small class="text-muted">Accepts: ${provider.accepted_insurance.join(', ')}</small></p>`
+                    : ''
+            }
+            <a href="${provider.booking_url}" class="btn btn-primary" target="_blank" rel="noopener">Book a visit</a>
+            ${provider.in_network ? `<span class="badge bg-primary position-absolute" style="top: 10px; right: 10px;">In Network</span>` : ''}
+        </div>
+        `;
+        carouselInner.appendChild(card);
+    });
+}
+
 // Function to save favorites
-function saveFavorites(photo, name, address, rating) {
+function saveFavorites(providerId, photo, name, address, rating) {
     console.log('Saving favorite:', photo, name, address, rating);
     // Ensure inputs are valid
     if (!photo || !name || !address || !rating == null) {
         console.error('Invalid data provided to saveFavorites');
         alert('Please provide all required fields.');
-        return;
+        return Promise.resolve(null);
     }
 
-    // Send POST request
-    fetch('/favorites', {
+    // Send POST request; resolves to the favorite's ID
+    return fetch('/api/favorites', {
             method: 'POST',
             headers: {
                 'Content-Type': 'application/json',
             },
             body: JSON.stringify({
+                provider_id: providerId,
                 photo: photo,
                 name: name,
                 address: address,
@@ -152,13 +259,44 @@ function saveFavorites(photo, name, address, rating) {
         })
         .then((data) => {
             console.log('Favorite saved:', data);
+            return data.id;
         })
         .catch((error) => {
             console.error('Error saving favorite:', error);
             alert('Failed to save favorite. Please try again.');
+            return null;
         });
 }
 
+// Function to remove a favorite
+function removeFavorite(favoriteId) {
+    return fetch(`/api/favorites/${favoriteId}`, { method: 'DELETE' })
+        .then((response) => {
+            if (!response.ok) {
+                throw new Error('Failed to remove favorite');
+            }
+        })
+        .catch((error) => {
+            console.error('Error removing favorite:', error);
+            alert('Failed to remove favorite. Please try again.');
+        });
+}
+
+// Function to map the user's favorited provider IDs to favorite IDs
+async function fetchFavoriteIds() {
+    try {
+        const response = await fetch('/api/favorites');
+        if (!response.ok) {
+            throw new Error('Failed to fetch favorites');
+        }
+        const favorites = await response.json();
+        return new Map(favorites.filter((f) => f.provider_id).map((f) => [f.provider_id, f.id]));
+    } catch (error) {
+        console.error('Error fetching favorites:', error);
+        return new Map();
+    }
+}
+
 
 
 function updateMap(coordinates, providers, useCurLocation) {
@@ -206,10 +344,10 @@ function updateMap(coordinates, providers, useCurLocation) {
                                             const infoWindow = new google.maps.InfoWindow({
                                                         content: `
                         <div>
-                            <h3>${provider.name}</h3>
+                            <h3>${escapeHtml(provider.name)}</h3>
                             <img src="${provider.photo_url}" style="max-height: 150px; width: auto;" class="img-fluid rounded-start""><br><br>
-                            <p>${provider.address}</p>
-                            <p>${provider.phone ? `Phone: ${provider.phone}` : ''}</p>
+                            <p>${escapeHtml(provider.address)}</p>
+                            <p>${provider.phone ? `Phone: ${escapeHtml(provider.phone)}` : ''}</p>
                             <p>${provider.rating ? `Rating: ${provider.rating.toFixed(1)}` : 'No ratings'}</p>
                             <div class="mt-3">
                                 <span class="badge ${provider.open_now ? 'bg-success' : 'bg-danger'}">
@@ -259,19 +397,28 @@ function generateUniqueId() {
     return id.toString(); // Return as a string if needed
 }
 
+// Text from providers and outside sources, made safe to put in HTML
+function escapeHtml(text) {
+    const div = document.createElement('div');
+    div.textContent = text;
+    return div.innerHTML;
+}
+
 // Function to create a Bootstrap card for a service
-function createServiceCard(service, isLoggedIn = false) {
+function createServiceCard(service, isLoggedIn = false, favoriteId = null) {
     const card = document.createElement('div');
     card.classList.add('card'); // Bootstrap card classes
     const uniqueId = `services-${generateUniqueId()}`;
 
     const starButtonHTML = isLoggedIn ? `
     <a class="btn btn-primary star-button"
+            data-provider-id="${escapeHtml(service.id)}"
             data-photo="${service.photo_url || '/static/images/default_image.png'}"
-            data-name="${service.name}" 
-            data-address="${service.address}" 
-            data-rating="${service.rating || ''}">
-            <i class="far fa-star star-icon"></i>
+            data-name="${escapeHtml(service.name)}" 
+            data-address="${escapeHtml(service.address)}" 
+            data-rating="${service.rating || ''}"
+            ${favoriteId ? `data-favorite-id="${favoriteId}"` : ''}>
+            <i class="${favoriteId ? 'fas' : 'far'} fa-star star-icon"></i>
         </a>`
         : `
         <a class="btn btn-primary star-button off disabled" style="pointer-events: none;"
@@ -291,15 +438,42 @@ function createServiceCard(service, isLoggedIn = false) {
                 <img src="${service.photo_url || '/static/images/default_image.png'}" 
                     class="img-fluid rounded-start" 
                     style="max-height: 150px; width: 100%; object-fit: cover;" 
-                    alt="${service.name}">
+                    alt="${escapeHtml(service.name)}">
             </div>
             <div class="col-md-8">
                 <div class="card-body">
-                    <h5 class="card-title">${service.name}</h5>
-                    <p class="card-text">${service.address}</p>
-                    <p class="card-text">${service.phone ? `Phone: ${service.phone}` : ''}</p>
+                    <h5 class="card-title">${escapeHtml(service.name)}</h5>
+                    <p class="card-text">${escapeHtml(service.address)}</p>
+                    <p class="card-text">${service.phone ? `Phone: ${escapeHtml(service.phone)}` : ''}</p>
                     <p class="card-text">${service.rating ? `Rating: ${service.rating.toFixed(1)}` : 'No ratings'}</p>
+                    <p class="card-text">
+                        ${service.community_rating ? `Reviews here: ${service.community_rating.toFixed(1)} (${service.community_review_count})` : 'No reviews here yet'}
+                        <a href="/providers/${encodeURIComponent(service.id)}/reviews" class="ms-1">${service.community_rating ? 'Read reviews' : 'Write one'}</a>
+                    </p>
+                    ${
+                        service.accepted_insurance && service.accepted_insurance.length > 0
+                            ? `<p class="card-text"><small class="text-muted">Accepts: ${escapeHtml(service.accepted_insurance.join(', '))}</small></p>`
+                            : ''
+                    }
+                    ${
+                        service.hours && service.hours.length > 0
+                            ? `<ul class="list-unstyled small text-muted">${service.hours.map((line) => `<li>${escapeHtml(line)}</li>`).join('')}</ul>`
+                            : ''
+                    }
+                    ${service.telehealth ? '<span class="badge bg-info text-dark mb-2"><i class="fa-solid fa-video"></i> Offers telehealth</span>' : ''}
+                    ${service.updated_by_provider ? '<span class="badge bg-secondary mb-2" title="Details corrected by the provider\'s staff">Updated by provider</span>' : ''}
+                    <div class="form-check mb-2">
+                        <input class="form-check-input compare-checkbox" type="checkbox" id="compare-${uniqueId}" data-provider-id="${escapeHtml(service.id)}">
+                        <label class="form-check-label" for="compare-${uniqueId}">Compare</label>
+                    </div>
                     ${starButtonHTML}
+                    ${
+                        service.place_id
+                            ? `<button class="btn btn-outline-primary details-button" data-provider-id="${escapeHtml(service.id)}">
+                                    More Info
+                                </button>`
+                            : ''
+                    }
                     ${
                         service.services && service.services.length > 0
                             ? `<button class="btn btn-primary toggle-services" data-bs-toggle="collapse" 
@@ -309,14 +483,15 @@ function createServiceCard(service, isLoggedIn = false) {
                                 </button>`
                             : ''
                     }
+                    <div class="details-content mt-3" hidden></div>
                     <div id="${uniqueId}" class="collapse mt-3" style="padding-bottom: 30px;">
                         <div class="services-content">
                             <ul class="list-group">
                                 ${service.services.map((s) => `
                                     <li class="list-group-item">
-                                        <strong>${s.name}</strong><br>
-                                        <em>${s.taxonomy}</em><br>
-                                        <span>NPI ID: ${s.npi}</span>
+                                        <strong>${escapeHtml(s.name)}</strong><br>
+                                        <em>${escapeHtml(s.taxonomy)}</em><br>
+                                        <span>NPI ID: ${escapeHtml(s.npi)}</span>
                                     </li>
                                 `).join('')}
                             </ul>
@@ -325,6 +500,7 @@ function createServiceCard(service, isLoggedIn = false) {
                 </div>
             </div>
         </div>
+        ${service.in_network ? `<span class="badge bg-primary position-absolute" style="top: 10px; right: 10px;">In Network</span>` : ''}
         <span class="badge position-absolute ${service.open_now ? 'bg-success' : 'bg-danger'}" 
               style="bottom: 10px; right: 10px;">
             ${service.open_now ? 'Open Now' : 'Closed Now'}
@@ -336,6 +512,97 @@ function createServiceCard(service, isLoggedIn = false) {
 }
 
 
+// Download links for the current results, generated server-side from their provider IDs.
+// Distances are measured from the search location.
+function createExportLinks(providers, coordinates) {
+    const ids = encodeURIComponent(providers.map((provider) => provider.id).join(','));
+    const origin = coordinates ? `&lat=${coordinates.lat}&lng=${coordinates.lng}` : '';
+    const formats = [['csv', 'CSV'], ['vcard', 'vCard'], ['geojson', 'GeoJSON'], ['pdf', 'Printable PDF']];
+
+    const dropdown = document.createElement('div');
+    dropdown.className = 'dropdown d-inline-block ms-2';
+    dropdown.innerHTML = `
+        <button class="btn btn-sm btn-outline-secondary dropdown-toggle" type="button" data-bs-toggle="dropdown" aria-expanded="false">
+            <i class="fa-solid fa-download"></i> Export
+        </button>
+        <ul class="dropdown-menu">
+            ${formats.map(([format, label]) => `
+                <li><a class="dropdown-item" href="/services/export?format=${format}&ids=${ids}${origin}">${label}</a></li>`).join('')}
+        </ul>
+    `;
+    return dropdown;
+}
+
+// Providers that can be compared side by side at once
+const MIN_COMPARE = 2;
+const MAX_COMPARE = 5;
+
+// Button that opens the comparison page for the checked results, measuring distance from the search location
+function createCompareButton(coordinates) {
+    const button = document.createElement('button');
+    button.id = 'compareButton';
+    button.className = 'btn btn-sm btn-outline-primary ms-2';
+    button.disabled = true;
+    button.textContent = `Compare (0)`;
+    button.addEventListener('click', () => {
+        const ids = Array.from(document.querySelectorAll('.compare-checkbox:checked'))
+            .map((checkbox) => checkbox.getAttribute('data-provider-id'));
+        window.location.href = `/compare?ids=${encodeURIComponent(ids.join(','))}&lat=${coordinates.lat}&lng=${coordinates.lng}`;
+    });
+    return button;
+}
+
+// Keep the compare button in step with the checked results
+document.addEventListener('change', function (event) {
+    if (!event.target.classList.contains('compare-checkbox')) {
+        return;
+    }
+    const checked = document.querySelectorAll('.compare-checkbox:checked').length;
+    if (checked > MAX_COMPARE) {
+        event.target.checked = false;
+        alert(`You can compare up to ${MAX_COMPARE} providers at a time.`);
+        return;
+    }
+    const button = document.getElementById('compareButton');
+    if (button) {
+        button.textContent = `Compare (${checked})`;
+        button.disabled = checked < MIN_COMPARE;
+    }
+});
+
+// Button that saves the current query to be re-run in the background, with alerts when its results change
+function createSaveSearchButton(search) {
+    const button = document.createElement('button');
+    button.className = 'btn btn-sm btn-outline-secondary ms-2';
+    button.innerHTML = '<i class="fa-regular fa-bookmark"></i> Save search';
+    button.addEventListener('click', async () => {
+        const where = search.zip ? `ZIP ${search.zip}` : search.state ? search.state : 'my location';
+        const name = prompt('Name this search', `${search.service_type} near ${where}`);
+        if (!name) {
+            return;
+        }
+        const frequency = confirm('Check for new results daily? Choose Cancel to check weekly.') ? 'daily' : 'weekly';
+
+        try {
+            const response = await fetch('/api/saved-searches', {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify({ ...search, name, frequency }),
+            });
+            const result = await response.json();
+            if (!response.ok) {
+                throw new Error(result.message || 'Failed to save search');
+            }
+            button.disabled = true;
+            button.innerHTML = '<i class="fa-solid fa-bookmark"></i> Saved';
+        } catch (error) {
+            console.error('Error saving search:', error);
+            alert(error.message);
+        }
+    });
+    return button;
+}
+
 // Function to clear the search results
 function clearResults() {
     const carouselInner = document.querySelector('#resultsCarousel .carousel-inner');
@@ -411,6 +678,68 @@ document.addEventListener('click', function (event) {
     }
 });
 
+// Fetch phone, website and weekly hours when "More Info" is clicked
+document.addEventListener('click', async function (event) {
+    const button = event.target.closest('.details-button');
+    if (!button) {
+        return;
+    }
+    event.preventDefault();
+    event.stopPropagation(); // Don't also trigger the card's marker click
+
+    const detailsDiv = button.closest('.card-body').querySelector('.details-content');
+    if (!detailsDiv.hidden) {
+        detailsDiv.hidden = true;
+        button.textContent = 'More Info';
+        return;
+    }
+
+    try {
+        const response = await fetch(`/providers/${encodeURIComponent(button.getAttribute('data-provider-id'))}`);
+        if (!response.ok) {
+            throw new Error('Failed to fetch provider details');
+        }
+        const { id, details, bookable, waiting_room_open, messaging } = await response.json();
+        if (!details && !bookable) {
+            throw new Error('No Place Details for provider');
+        }
+
+        const bookingLink = (bookable
+            ? `<a class="btn btn-sm btn-success mb-2" href="/providers/${encodeURIComponent(id)}/book">Book an appointment</a>`
+            : '') + (waiting_room_open
+            ? ` <a class="btn btn-sm btn-outline-success mb-2" href="/waiting-room/${encodeURIComponent(id)}">See a clinician now</a>`
+            : '') + (messaging
+            ? ` <a class="btn btn-sm btn-outline-primary mb-2" href="/messages?provider=${encodeURIComponent(id)}"><i class="fa-solid fa-envelope"></i> Message</a>`
+            : '');
+        const claimLink = `<p class="card-text"><small><a href="/provider-portal?claim=${encodeURIComponent(id)}">Work here? Claim this listing</a></small></p>`;
+        if (!details) {
+            detailsDiv.innerHTML = bookingLink + claimLink;
+            detailsDiv.hidden = false;
+            button.textContent = 'Less Info';
+            return;
+        }
+
+        detailsDiv.innerHTML = `
+            ${bookingLink}
+            ${details.phone ? `<p class="card-text">Phone: <a href="tel:${encodeURIComponent(details.phone)}">${escapeHtml(details.phone)}</a></p>` : ''}
+            ${details.website ? `<p class="card-text"><a href="${details.website}" target="_blank" rel="noopener">Website</a></p>` : ''}
+            ${details.wheelchair_accessible ? '<p class="card-text"><i class="fa-solid fa-wheelchair"></i> Wheelchair accessible entrance</p>' : ''}
+            ${
+                details.opening_hours.length > 0
+                    ? `<ul class="list-unstyled small">${details.opening_hours.map((day) => `<li>${escapeHtml(day)}</li>`).join('')}</ul>`
+                    : '<p class="card-text">No hours listed</p>'
+            }
+            ${claimLink}
+        `;
+        detailsDiv.hidden = false;
+        button.textContent = 'Less Info';
+    } catch (error) {
+        console.error('Error fetching provider details:', error);
+        detailsDiv.innerHTML = '<p class="text-danger">Failed to load provider details.</p>';
+        detailsDiv.hidden = false;
+    }
+});
+
 // Update button state based on collapse events
 document.addEventListener('shown.bs.collapse', function (event) {
     const button = document.querySelector(`[data-bs-target="#${event.target.id}"]`);
@@ -458,6 +787,31 @@ function initMap() {
         center: { lat: 37.7749, lng: -122.4194 },
         zoom: 12
     });
+    repeatSearchFromUrl();
+}
+
+// Run the search in the page URL, as linked from the search history on the profile page.
+// Links from the guidance wizard have no location, so they only fill in the form.
+function repeatSearchFromUrl() {
+    const params = new URLSearchParams(window.location.search);
+    const zip = params.get('zip');
+    const serviceType = params.get('service_type');
+    if (!serviceType) {
+        return;
+    }
+
+    document.getElementById('serviceType').value = serviceType;
+    document.getElementById('searchMode').value = params.get('mode') === 'virtual' ? 'virtual' : '';
+    if (!zip) {
+        document.getElementById('zip').focus();
+        return;
+    }
+    document.getElementById('zip').value = zip;
+    const inNetworkCheckbox = document.getElementById('inNetwork');
+    if (inNetworkCheckbox) {
+        inNetworkCheckbox.checked = params.get('in_network') === 'true';
+    }
+    fetchHealthServices(zip, serviceType, false);
 }
 
 function getUserLocation() {
@@ -0,0 +1,86 @@
+// Intake form: shows conditional questions as the answers they depend on change, and
+// sends the answers as JSON. The server checks them again and reports problems per
+// question, which are shown under each one.
+const form = document.getElementById('intake-form');
+const questions = Array.from(form.querySelectorAll('.intake-question'));
+
+// The answer to a question as the server expects it, or null if unanswered
+function answerOf(question) {
+    const id = question.dataset.questionId;
+    switch (question.dataset.type) {
+        case 'boolean': {
+            const checked = form.querySelector(`input[name="${id}"]:checked`);
+            return checked ? checked.value === 'true' : null;
+        }
+        case 'choice': {
+            const checked = Array.from(form.querySelectorAll(`input[name="${id}"]:checked`)).map((input) => input.value);
+            if (question.dataset.multiple === 'true') {
+                return checked;
+            }
+            return checked[0] ?? null;
+        }
+        case 'integer': {
+            const value = form.elements[id].value;
+            return value === '' ? null : Number(value);
+        }
+        default:
+            return form.elements[id].value;
+    }
+}
+
+function matches(answer, equals) {
+    return Array.isArray(answer) ? answer.includes(equals) : answer === equals;
+}
+
+// Conditions only refer to earlier questions, so one pass in order settles them all
+function updateVisibility() {
+    const shown = new Map();
+    for (const question of questions) {
+        const dependsOn = question.dataset.enableQuestion;
+        const visible = !dependsOn || (shown.has(dependsOn)
+            && matches(shown.get(dependsOn), JSON.parse(question.dataset.enableEquals)));
+        question.hidden = !visible;
+        if (visible) {
+            shown.set(question.dataset.questionId, answerOf(question));
+        }
+    }
+}
+
+function showErrors(errors) {
+    for (const question of questions) {
+        question.querySelector('.question-error').textContent = errors[question.dataset.questionId] || '';
+    }
+}
+
+form.addEventListener('input', updateVisibility);
+form.addEventListener('change', updateVisibility);
+
+form.addEventListener('submit', async (event) => {
+    event.preventDefault();
+    const answers = {};
+    for (const question of questions) {
+        if (!question.hidden) {
+            answers[question.dataset.questionId] = answerOf(question);
+        }
+    }
+
+    try {
+        const response = await fetch(`/api/appointments/${form.dataset.appointmentId}/intake`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ answers }),
+        });
+        const result = await response.json().catch(() => ({}));
+        showErrors(result.errors || {});
+        if (!response.ok) {
+            throw new Error(result.message || 'Failed to send answers');
+        }
+        alert(result.message);
+        window.location.href = '/profile';
+    } catch (error) {
+        console.error('Error sending intake answers:', error);
+        alert(error.message);
+    }
+});
+
+updateVisibility();
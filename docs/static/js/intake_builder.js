@@ -0,0 +1,237 @@
+// Intake form builder: edits the list of questions in memory and saves the whole form
+// as JSON. The server checks the form on save and says what to fix.
+const builder = document.getElementById('builder');
+const providerId = builder.dataset.providerId;
+const formUrl = `/admin/intake-forms/${encodeURIComponent(providerId)}`;
+const definition = JSON.parse(builder.dataset.definition);
+
+const TYPES = {
+    string: 'Short answer',
+    text: 'Long answer',
+    boolean: 'Yes / no',
+    integer: 'Number',
+    date: 'Date',
+    choice: 'Choice',
+};
+
+let questions = definition.questions;
+
+function escapeHtml(text) {
+    const div = document.createElement('div');
+    div.textContent = text;
+    return div.innerHTML;
+}
+
+function nextId() {
+    let number = questions.length + 1;
+    while (questions.some((question) => question.id === `q${number}`)) {
+        number += 1;
+    }
+    return `q${number}`;
+}
+
+// The input for the answer a condition waits for, matching the earlier question's type
+function conditionValueInput(index, dependsOn, equals) {
+    if (dependsOn.type === 'boolean') {
+        return `
+            <select class="form-select form-select-sm" onchange="setCondition(${index}, 'equals', this.value === 'true')">
+                <option value="true" ${equals === true ? 'selected' : ''}>Yes</option>
+                <option value="false" ${equals === false ? 'selected' : ''}>No</option>
+            </select>`;
+    }
+    if (dependsOn.type === 'choice') {
+        return `
+            <select class="form-select form-select-sm" onchange="setCondition(${index}, 'equals', this.value)">
+                ${(dependsOn.options || []).map((option) => `
+                    <option value="${escapeHtml(option)}" ${equals === option ? 'selected' : ''}>${escapeHtml(option)}</option>`).join('')}
+            </select>`;
+    }
+    return `<input type="text" class="form-control form-control-sm" value="${escapeHtml(equals ?? '')}"
+                onchange="setCondition(${index}, 'equals', ${dependsOn.type === 'integer' ? 'Number(this.value)' : 'this.value'})">`;
+}
+
+function renderQuestion(question, index) {
+    const earlier = questions.slice(0, index);
+    const dependsOn = question.enable_when && earlier.find((other) => other.id === question.enable_when.question);
+
+    return `
+        <div class="card shadow-sm mb-3">
+            <div class="card-header d-flex align-items-center gap-2">
+                <strong>${index + 1}.</strong>
+                <input type="text" class="form-control form-control-sm w-auto" value="${escapeHtml(question.id)}" title="Question ID"
+                    onchange="setField(${index}, 'id', this.value.trim())">
+                <select class="form-select form-select-sm w-auto" onchange="setType(${index}, this.value)">
+                    ${Object.entries(TYPES).map(([type, label]) => `
+                        <option value="${type}" ${question.type === type ? 'selected' : ''}>${label}</option>`).join('')}
+                </select>
+                <div class="form-check mb-0">
+                    <input class="form-check-input" type="checkbox" id="required-${index}" ${question.required ? 'checked' : ''}
+                        onchange="setField(${index}, 'required', this.checked)">
+                    <label class="form-check-label" for="required-${index}">Required</label>
+                </div>
+                <div class="ms-auto btn-group btn-group-sm">
+                    <button class="btn btn-outline-secondary" onclick="moveQuestion(${index}, -1)" ${index === 0 ? 'disabled' : ''}><i class="fa-solid fa-arrow-up"></i></button>
+                    <button class="btn btn-outline-secondary" onclick="moveQuestion(${index}, 1)" ${index === questions.length - 1 ? 'disabled' : ''}><i class="fa-solid fa-arrow-down"></i></button>
+                    <button class="btn btn-outline-danger" onclick="removeQuestion(${index})"><i class="fa-solid fa-trash"></i></button>
+                </div>
+            </div>
+            <div class="card-body">
+                <input type="text" class="form-control mb-2" placeholder="Question text" maxlength="500" value="${escapeHtml(question.text)}"
+                    onchange="setField(${index}, 'text', this.value)">
+
+                ${question.type === 'choice' ? `
+                    <textarea class="form-control mb-2" rows="3" placeholder="One option per line"
+                        onchange="setField(${index}, 'options', this.value.split('\\n').map((option) => option.trim()).filter(Boolean))">${escapeHtml((question.options || []).join('\n'))}</textarea>
+                    <div class="form-check mb-2">
+                        <input class="form-check-input" type="checkbox" id="multiple-${index}" ${question.multiple ? 'checked' : ''}
+                            onchange="setField(${index}, 'multiple', this.checked)">
+                        <label class="form-check-label" for="multiple-${index}">Allow picking several</label>
+                    </div>` : ''}
+
+                ${question.type === 'integer' ? `
+                    <div class="d-flex gap-2 mb-2">
+                        <input type="number" class="form-control form-control-sm" placeholder="Minimum" value="${question.min ?? ''}"
+                            onchange="setField(${index}, 'min', this.value === '' ? undefined : Number(this.value))">
+                        <input type="number" class="form-control form-control-sm" placeholder="Maximum" value="${question.max ?? ''}"
+                            onchange="setField(${index}, 'max', this.value === '' ? undefined : Number(this.value))">
+                    </div>` : ''}
+
+                ${earlier.length > 0 ? `
+                    <div class="d-flex align-items-center gap-2">
+                        <small class="text-nowrap">Only ask when</small>
+                        <select class="form-select form-select-sm" onchange="setCondition(${index}, 'question', this.value)">
+                            <option value="">(always ask)</option>
+                            ${earlier.map((other) => `
+                                <option value="${escapeHtml(other.id)}" ${dependsOn === other ? 'selected' : ''}>${escapeHtml(other.id)}: ${escapeHtml(other.text)}</option>`).join('')}
+                        </select>
+                        ${dependsOn ? `<small>is</small> ${conditionValueInput(index, dependsOn, question.enable_when.equals)}` : ''}
+                    </div>` : ''}
+            </div>
+        </div>`;
+}
+
+function render() {
+    document.getElementById('questions').innerHTML = questions.map(renderQuestion).join('');
+}
+
+function addQuestion() {
+    questions.push({ id: nextId(), text: '', type: 'string', required: false });
+    render();
+}
+
+function removeQuestion(index) {
+    const [removed] = questions.splice(index, 1);
+    // Questions that depended on it are always asked now
+    for (const question of questions) {
+        if (question.enable_when && question.enable_when.question === removed.id) {
+            delete question.enable_when;
+        }
+    }
+    render();
+}
+
+function moveQuestion(index, offset) {
+    const [question] = questions.splice(index, 1);
+    questions.splice(index + offset, 0, question);
+    render();
+}
+
+function setField(index, field, value) {
+    if (field === 'id') {
+        // Keep conditions pointing at the renamed question
+        for (const question of questions) {
+            if (question.enable_when && question.enable_when.question === questions[index].id) {
+                question.enable_when.question = value;
+            }
+        }
+    }
+    if (value === undefined) {
+        delete questions[index][field];
+    } else {
+        questions[index][field] = value;
+    }
+    render();
+}
+
+function setType(index, type) {
+    const question = questions[index];
+    question.type = type;
+    if (type !== 'choice') {
+        delete question.options;
+        delete question.multiple;
+    }
+    if (type !== 'integer') {
+        delete question.min;
+        delete question.max;
+    }
+    render();
+}
+
+// Default answer to wait for when a condition's question is picked
+function defaultEquals(dependsOn) {
+    switch (dependsOn.type) {
+        case 'boolean':
+            return true;
+        case 'choice':
+            return (dependsOn.options || [])[0] ?? '';
+        case 'integer':
+            return 0;
+        default:
+            return '';
+    }
+}
+
+function setCondition(index, field, value) {
+    const question = questions[index];
+    if (field === 'question') {
+        const dependsOn = questions.find((other) => other.id === value);
+        if (dependsOn) {
+            question.enable_when = { question: value, equals: defaultEquals(dependsOn) };
+        } else {
+            delete question.enable_when;
+        }
+    } else {
+        question.enable_when.equals = value;
+    }
+    render();
+}
+
+async function saveForm() {
+    try {
+        const response = await fetch(formUrl, {
+            method: 'PUT',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ title: document.getElementById('form-title').value, questions }),
+        });
+        const result = await response.json().catch(() => ({}));
+        if (!response.ok) {
+            throw new Error(result.message || 'Failed to save form');
+        }
+        alert(result.message);
+        window.location.reload();
+    } catch (error) {
+        console.error('Error saving intake form:', error);
+        alert(error.message);
+    }
+}
+
+async function deleteForm() {
+    if (!confirm('Delete this form and every response to it?')) {
+        return;
+    }
+
+    try {
+        const response = await fetch(formUrl, { method: 'DELETE' });
+        const result = await response.json().catch(() => ({}));
+        if (!response.ok) {
+            throw new Error(result.message || 'Failed to delete form');
+        }
+        window.location.href = '/admin/intake-forms';
+    } catch (error) {
+        console.error('Error deleting intake form:', error);
+        alert(error.message);
+    }
+}
+
+document.getElementById('form-title').value = definition.title;
+render();
@@ -0,0 +1,201 @@
+// Secure messaging: the server announces new messages over a WebSocket, and this page
+// reloads the conversation list and the open conversation when one arrives. While the
+// socket is down, the unread count is polled instead.
+const page = document.getElementById('messages');
+const maxAttachmentBytes = Number(page.dataset.maxAttachmentMb) * 1024 * 1024;
+
+// Seconds between unread count checks while the socket is down
+const POLL_INTERVAL = 30;
+
+// Seconds to wait before reconnecting after the socket drops
+const RECONNECT_DELAY = 5;
+
+let openThreadId = null;
+let lastUnread = null;
+let pollTimer = null;
+
+function escapeHtml(text) {
+    const div = document.createElement('div');
+    div.textContent = text;
+    return div.innerHTML;
+}
+
+function formatSize(bytes) {
+    return bytes < 1024 * 1024 ? `${Math.ceil(bytes / 1024)} KB` : `${(bytes / (1024 * 1024)).toFixed(1)} MB`;
+}
+
+async function request(url, options = {}) {
+    const response = await fetch(url, options);
+    const result = await response.json().catch(() => ({}));
+    if (!response.ok) {
+        throw new Error(result.message || `Request to ${url} failed`);
+    }
+    return result;
+}
+
+function postJson(url, body) {
+    return request(url, {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify(body),
+    });
+}
+
+async function loadThreads() {
+    try {
+        const threads = await request('/api/threads');
+        document.getElementById('threads').innerHTML = threads.length === 0
+            ? '<li class="list-group-item text-muted">No conversations yet.</li>'
+            : threads.map((thread) => `
+                <li class="list-group-item list-group-item-action ${thread.id === openThreadId ? 'active' : ''}"
+                    role="button" onclick="openThread(${thread.id})">
+                    <div class="d-flex justify-content-between">
+                        <strong>${escapeHtml(thread.subject)}</strong>
+                        ${thread.unread > 0 ? `<span class="badge bg-danger">${thread.unread}</span>` : ''}
+                    </div>
+                    <small>${escapeHtml(thread.role === 'patient' ? thread.provider_name : `${thread.patient_name} (${thread.provider_name})`)}</small>
+                </li>`).join('');
+
+        const open = threads.find((thread) => thread.id === openThreadId);
+        if (open) {
+            document.getElementById('thread-subject').textContent = open.subject;
+        }
+    } catch (error) {
+        console.error('Error loading conversations:', error);
+    }
+}
+
+function renderMessage(message) {
+    const attachments = message.attachments.map((attachment) => `
+        <a href="/api/attachments/${attachment.id}" class="d-block">
+            <i class="fa-solid fa-paperclip"></i> ${escapeHtml(attachment.filename)} (${formatSize(attachment.size)})
+        </a>`).join('');
+    const sender = message.mine ? 'You' : message.sender_role === 'staff' ? `${message.sender_name} (staff)` : message.sender_name;
+
+    return `
+        <div class="message border rounded p-2 mb-2 ${message.mine ? 'mine' : 'bg-white'}">
+            <small class="text-muted d-block">${escapeHtml(sender)} &middot; ${escapeHtml(message.created_at)}</small>
+            ${escapeHtml(message.body)}${attachments}
+        </div>`;
+}
+
+async function openThread(threadId) {
+    openThreadId = threadId;
+    try {
+        const thread = await request(`/api/threads/${threadId}`);
+        const conversation = document.getElementById('conversation');
+        conversation.innerHTML = thread.messages.map(renderMessage).join('');
+        conversation.scrollTop = conversation.scrollHeight;
+        document.getElementById('thread').hidden = false;
+    } catch (error) {
+        console.error('Error loading conversation:', error);
+        alert(error.message);
+    }
+    // Opening a thread marks it read
+    loadThreads();
+}
+
+async function sendAttachment() {
+    const input = document.getElementById('attachment');
+    const file = input.files[0];
+    if (!file || openThreadId === null) {
+        return;
+    }
+    if (file.size > maxAttachmentBytes) {
+        alert(`Attachments can be up to ${page.dataset.maxAttachmentMb} MB.`);
+        return;
+    }
+
+    try {
+        await request(`/api/threads/${openThreadId}/attachments?filename=${encodeURIComponent(file.name)}`, {
+            method: 'POST',
+            body: file,
+        });
+        input.value = '';
+        openThread(openThreadId);
+    } catch (error) {
+        console.error('Error sending attachment:', error);
+        alert(error.message);
+    }
+}
+
+document.getElementById('reply').addEventListener('submit', async (event) => {
+    event.preventDefault();
+    const form = event.target;
+    try {
+        await postJson(`/api/threads/${openThreadId}/messages`, { body: form.body.value });
+        form.reset();
+        openThread(openThreadId);
+    } catch (error) {
+        console.error('Error sending message:', error);
+        alert(error.message);
+    }
+});
+
+const newThreadForm = document.getElementById('new-thread');
+if (newThreadForm) {
+    newThreadForm.addEventListener('submit', async (event) => {
+        event.preventDefault();
+        try {
+            const result = await postJson('/api/threads', {
+                provider_id: newThreadForm.dataset.providerId,
+                subject: newThreadForm.subject.value,
+                body: newThreadForm.body.value,
+            });
+            newThreadForm.closest('.row').remove();
+            history.replaceState(null, '', '/messages');
+            openThread(result.id);
+        } catch (error) {
+            console.error('Error starting conversation:', error);
+            alert(error.message);
+        }
+    });
+}
+
+function refresh(threadId) {
+    if (openThreadId !== null && (threadId === null || threadId === openThreadId)) {
+        openThread(openThreadId);
+    } else {
+        loadThreads();
+    }
+}
+
+async function poll() {
+    try {
+        const { unread } = await request('/api/messages/unread');
+        if (unread !== lastUnread) {
+            lastUnread = unread;
+            refresh(null);
+        }
+    } catch (error) {
+        console.error('Error checking messages:', error);
+    }
+}
+
+function startPolling() {
+    if (pollTimer === null) {
+        pollTimer = setInterval(poll, POLL_INTERVAL * 1000);
+    }
+}
+
+function stopPolling() {
+    clearInterval(pollTimer);
+    pollTimer = null;
+}
+
+function connect() {
+    const scheme = window.location.protocol === 'https:' ? 'wss' : 'ws';
+    const socket = new WebSocket(`${scheme}://${window.location.host}/ws/messages`);
+    socket.onopen = stopPolling;
+    socket.onmessage = (event) => {
+        const { thread_id } = JSON.parse(event.data);
+        refresh(thread_id);
+    };
+    socket.onclose = () => {
+        startPolling();
+        setTimeout(connect, RECONNECT_DELAY * 1000);
+    };
+}
+
+loadThreads();
+connect();
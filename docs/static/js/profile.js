@@ -1,4 +1,444 @@
-function viewServiceDetails(serviceId) {
-    // Implement service details view logic
-    console.log('Viewing details for service:', serviceId);
-}
\ No newline at end of file
+// Show a favorite's full provider record in the details modal
+async function viewServiceDetails(providerId) {
+    const modalBody = document.getElementById('serviceDetailsBody');
+    const modalTitle = document.getElementById('serviceDetailsLabel');
+    const modal = bootstrap.Modal.getOrCreateInstance(document.getElementById('serviceDetailsModal'));
+
+    modalTitle.textContent = 'Provider Details';
+    modalBody.innerHTML = '<p>Loading...</p>';
+    modal.show();
+
+    try {
+        const response = await fetch(`/providers/${encodeURIComponent(providerId)}`);
+        if (!response.ok) {
+            throw new Error('Failed to fetch provider details');
+        }
+        const { provider, details } = await response.json();
+
+        const name = (provider && provider.name) || (details && details.name) || 'Provider Details';
+        const phone = (details && details.phone) || (provider && provider.phone);
+        modalTitle.textContent = name;
+        modalBody.innerHTML = `
+            ${provider ? `<p>${provider.address}</p>` : ''}
+            ${phone ? `<p>Phone: <a href="tel:${phone}">${phone}</a></p>` : ''}
+            ${details && details.website ? `<p><a href="${details.website}" target="_blank" rel="noopener">Website</a></p>` : ''}
+            ${provider && provider.rating ? `<p>Rating: ${provider.rating.toFixed(1)}</p>` : ''}
+            ${details && details.wheelchair_accessible ? '<p><i class="fa-solid fa-wheelchair"></i> Wheelchair accessible entrance</p>' : ''}
+            ${
+                details && details.opening_hours.length > 0
+                    ? `<h6>Hours</h6><ul class="list-unstyled small">${details.opening_hours.map((day) => `<li>${day}</li>`).join('')}</ul>`
+                    : ''
+            }
+            ${
+                provider && provider.accepted_insurance.length > 0
+                    ? `<p><small class="text-muted">Accepts: ${provider.accepted_insurance.join(', ')}</small></p>`
+                    : ''
+            }
+            ${
+                provider && provider.services.length > 0
+                    ? `<h6>Services</h6><ul class="list-group">${provider.services.map((s) => `
+                        <li class="list-group-item">
+                            <strong>${s.name}</strong><br>
+                            <em>${s.taxonomy}</em>${s.npi ? `<br><span>NPI ID: ${s.npi}</span>` : ''}
+                        </li>`).join('')}</ul>`
+                    : ''
+            }
+        `;
+    } catch (error) {
+        console.error('Error fetching provider details:', error);
+        modalBody.innerHTML = '<p class="text-danger">Failed to load provider details.</p>';
+    }
+}
+
+// Remove a favorite and its card from the page
+async function removeFavorite(favoriteId, button) {
+    if (!confirm('Remove this provider from your favorites?')) {
+        return;
+    }
+
+    try {
+        const response = await fetch(`/api/favorites/${favoriteId}`, { method: 'DELETE' });
+        if (!response.ok) {
+            throw new Error('Failed to remove favorite');
+        }
+
+        button.closest('.favorite-col').remove();
+        // Reload to show the empty-state message once the last favorite is gone
+        if (document.querySelectorAll('.favorite-col').length === 0) {
+            window.location.reload();
+        }
+    } catch (error) {
+        console.error('Error removing favorite:', error);
+        alert('Failed to remove favorite. Please try again.');
+    }
+}
+
+// Send a JSON request and throw on a non-2xx response
+async function sendJson(method, url, body) {
+    const response = await fetch(url, {
+        method,
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify(body),
+    });
+    const result = await response.json().catch(() => ({}));
+    if (!response.ok) {
+        throw new Error(result.message || `Request to ${url} failed`);
+    }
+    return result;
+}
+
+// Show only the favorites carrying the selected tag
+function filterByTag(button) {
+    const tag = button.dataset.tag;
+
+    document.querySelectorAll('#tag-filters button').forEach((b) => {
+        b.classList.toggle('btn-primary', b === button);
+        b.classList.toggle('btn-outline-primary', b !== button);
+    });
+    document.querySelectorAll('.favorite-col').forEach((col) => {
+        const tags = col.dataset.tags.split('|');
+        col.classList.toggle('d-none', tag !== '' && !tags.includes(tag));
+    });
+}
+
+async function createCollection(event) {
+    event.preventDefault();
+    const name = document.getElementById('new-collection-name').value;
+
+    try {
+        await sendJson('POST', '/api/collections', { name });
+        window.location.reload();
+    } catch (error) {
+        console.error('Error creating collection:', error);
+        alert(error.message);
+    }
+}
+
+async function renameCollection(collectionId, button) {
+    const current = button.closest('.collection-section').querySelector('.collection-name').textContent;
+    const name = prompt('Rename collection', current);
+    if (!name || name === current) {
+        return;
+    }
+
+    try {
+        await sendJson('PATCH', `/api/collections/${collectionId}`, { name });
+        window.location.reload();
+    } catch (error) {
+        console.error('Error renaming collection:', error);
+        alert(error.message);
+    }
+}
+
+async function deleteCollection(collectionId) {
+    if (!confirm('Delete this collection? Its favorites will be kept as unsorted.')) {
+        return;
+    }
+
+    try {
+        const response = await fetch(`/api/collections/${collectionId}`, { method: 'DELETE' });
+        if (!response.ok) {
+            throw new Error('Failed to delete collection');
+        }
+        window.location.reload();
+    } catch (error) {
+        console.error('Error deleting collection:', error);
+        alert('Failed to delete collection. Please try again.');
+    }
+}
+
+// Swap a collection with its neighbour and save the new order
+async function moveCollection(button, direction) {
+    const section = button.closest('.collection-section');
+    const sibling = direction < 0 ? section.previousElementSibling : section.nextElementSibling;
+    if (!sibling || !sibling.classList.contains('collection-section')) {
+        return;
+    }
+    if (direction < 0) {
+        sibling.before(section);
+    } else {
+        sibling.after(section);
+    }
+
+    const ids = Array.from(document.querySelectorAll('.collection-section')).map((s) => Number(s.dataset.collectionId));
+    try {
+        await sendJson('PUT', '/api/collections/order', { ids });
+    } catch (error) {
+        console.error('Error reordering collections:', error);
+        alert('Failed to save the new order. Please try again.');
+    }
+}
+
+// Swap a favorite with its neighbour in the same list and save the new order
+async function moveFavoriteInList(button, direction) {
+    const col = button.closest('.favorite-col');
+    const sibling = direction < 0 ? col.previousElementSibling : col.nextElementSibling;
+    if (!sibling || !sibling.classList.contains('favorite-col')) {
+        return;
+    }
+    if (direction < 0) {
+        sibling.before(col);
+    } else {
+        sibling.after(col);
+    }
+
+    const ids = Array.from(col.closest('.favorite-list').querySelectorAll('.favorite-col')).map((c) => Number(c.dataset.favoriteId));
+    try {
+        await sendJson('PUT', '/api/favorites/order', { ids });
+    } catch (error) {
+        console.error('Error reordering favorites:', error);
+        alert('Failed to save the new order. Please try again.');
+    }
+}
+
+async function moveFavorite(favoriteId, collectionId) {
+    try {
+        await sendJson('PUT', `/api/favorites/${favoriteId}/collection`, {
+            collection_id: collectionId === '' ? null : Number(collectionId),
+        });
+        window.location.reload();
+    } catch (error) {
+        console.error('Error moving favorite:', error);
+        alert('Failed to move favorite. Please try again.');
+    }
+}
+
+// Open the notes and tags editor for a favorite
+function editFavorite(button) {
+    document.getElementById('edit-favorite-id').value = button.closest('.favorite-col').dataset.favoriteId;
+    document.getElementById('edit-favorite-notes').value = button.dataset.notes;
+    document.getElementById('edit-favorite-tags').value = button.dataset.tags;
+    bootstrap.Modal.getOrCreateInstance(document.getElementById('editFavoriteModal')).show();
+}
+
+async function saveFavoriteNotes(event) {
+    event.preventDefault();
+    const favoriteId = document.getElementById('edit-favorite-id').value;
+    const notes = document.getElementById('edit-favorite-notes').value;
+    const tags = document.getElementById('edit-favorite-tags').value.split(',').map((tag) => tag.trim()).filter(Boolean);
+
+    try {
+        await sendJson('PATCH', `/api/favorites/${favoriteId}`, { notes, tags });
+        window.location.reload();
+    } catch (error) {
+        console.error('Error saving notes:', error);
+        alert('Failed to save notes. Please try again.');
+    }
+}
+
+// Preselect each favorite's current collection in its move dropdown
+document.addEventListener('DOMContentLoaded', () => {
+    document.querySelectorAll('.collection-select').forEach((select) => {
+        select.value = select.dataset.collectionId;
+    });
+});
+
+// Update the unread badge after notifications are marked as read
+function updateUnreadCount() {
+    const badge = document.getElementById('unread-count');
+    const unread = document.querySelectorAll('.notification-unread').length;
+    if (badge) {
+        badge.textContent = unread;
+        badge.hidden = unread === 0;
+    }
+}
+
+async function markNotificationRead(notificationId, button) {
+    try {
+        const response = await fetch(`/api/notifications/${notificationId}/read`, { method: 'POST' });
+        if (!response.ok) {
+            throw new Error('Failed to mark notification as read');
+        }
+
+        const item = button.closest('.list-group-item');
+        item.classList.remove('list-group-item-warning', 'notification-unread');
+        button.remove();
+        updateUnreadCount();
+    } catch (error) {
+        console.error('Error marking notification as read:', error);
+    }
+}
+
+async function markAllNotificationsRead() {
+    try {
+        const response = await fetch('/api/notifications/read', { method: 'POST' });
+        if (!response.ok) {
+            throw new Error('Failed to mark notifications as read');
+        }
+        window.location.reload();
+    } catch (error) {
+        console.error('Error marking notifications as read:', error);
+    }
+}
+
+// Collection whose links are shown in the share modal
+let sharingCollectionId = null;
+
+async function loadShares() {
+    const list = document.getElementById('share-list');
+    try {
+        const response = await fetch(`/api/collections/${sharingCollectionId}/shares`);
+        if (!response.ok) {
+            throw new Error('Failed to fetch links');
+        }
+        const shares = await response.json();
+
+        list.innerHTML = shares.length === 0
+            ? '<li class="list-group-item text-muted">No active links.</li>'
+            : shares.map((share) => `
+                <li class="list-group-item">
+                    <input type="text" class="form-control form-control-sm mb-1" value="${share.url}" readonly onclick="this.select()">
+                    <div class="d-flex justify-content-between align-items-center">
+                        <small class="text-muted">
+                            ${share.view_count} view${share.view_count === 1 ? '' : 's'}
+                            &middot; ${share.expires_at ? `expires ${share.expires_at}` : 'never expires'}
+                        </small>
+                        <button class="btn btn-sm btn-outline-danger" onclick="revokeShare('${share.id}')">Revoke</button>
+                    </div>
+                </li>`).join('');
+    } catch (error) {
+        console.error('Error fetching links:', error);
+        list.innerHTML = '<li class="list-group-item text-danger">Failed to load links.</li>';
+    }
+}
+
+function openShares(collectionId) {
+    sharingCollectionId = collectionId;
+    bootstrap.Modal.getOrCreateInstance(document.getElementById('sharesModal')).show();
+    loadShares();
+}
+
+async function createShare() {
+    const expiry = document.getElementById('share-expiry').value;
+    try {
+        await sendJson('POST', `/api/collections/${sharingCollectionId}/shares`, {
+            expires_in_days: expiry === '' ? null : Number(expiry),
+        });
+        loadShares();
+    } catch (error) {
+        console.error('Error creating link:', error);
+        alert('Failed to create link. Please try again.');
+    }
+}
+
+async function revokeShare(shareId) {
+    if (!confirm('Revoke this link? People who have it will no longer be able to see the list.')) {
+        return;
+    }
+
+    try {
+        const response = await fetch(`/api/shares/${shareId}`, { method: 'DELETE' });
+        if (!response.ok) {
+            throw new Error('Failed to revoke link');
+        }
+        loadShares();
+    } catch (error) {
+        console.error('Error revoking link:', error);
+        alert('Failed to revoke link. Please try again.');
+    }
+}
+
+async function cancelAppointment(appointmentId) {
+    if (!confirm('Cancel this appointment?')) {
+        return;
+    }
+
+    try {
+        const response = await fetch(`/api/appointments/${appointmentId}`, { method: 'DELETE' });
+        if (!response.ok) {
+            throw new Error('Failed to cancel appointment');
+        }
+        window.location.reload();
+    } catch (error) {
+        console.error('Error cancelling appointment:', error);
+        alert('Failed to cancel appointment. Please try again.');
+    }
+}
+
+async function createCalendarFeed() {
+    if (document.getElementById('calendar-feed-url')
+        && !confirm('Replace your calendar link? Calendars subscribed with the old link will stop updating.')) {
+        return;
+    }
+
+    try {
+        await sendJson('POST', '/api/calendar-feed', {});
+        window.location.reload();
+    } catch (error) {
+        console.error('Error creating calendar feed:', error);
+        alert('Failed to create calendar feed. Please try again.');
+    }
+}
+
+async function revokeCalendarFeed() {
+    if (!confirm('Turn off your calendar feed? Subscribed calendars will stop updating.')) {
+        return;
+    }
+
+    try {
+        const response = await fetch('/api/calendar-feed', { method: 'DELETE' });
+        if (!response.ok) {
+            throw new Error('Failed to turn off calendar feed');
+        }
+        window.location.reload();
+    } catch (error) {
+        console.error('Error turning off calendar feed:', error);
+        alert('Failed to turn off calendar feed. Please try again.');
+    }
+}
+
+async function deleteSavedSearch(searchId) {
+    if (!confirm('Delete this saved search? You will no longer be notified about its results.')) {
+        return;
+    }
+
+    try {
+        const response = await fetch(`/api/saved-searches/${searchId}`, { method: 'DELETE' });
+        if (!response.ok) {
+            throw new Error('Failed to delete saved search');
+        }
+        window.location.reload();
+    } catch (error) {
+        console.error('Error deleting saved search:', error);
+        alert('Failed to delete saved search. Please try again.');
+    }
+}
+
+async function clearSearchHistory() {
+    if (!confirm('Clear your search history?')) {
+        return;
+    }
+
+    try {
+        const response = await fetch('/api/search-history', { method: 'DELETE' });
+        if (!response.ok) {
+            throw new Error('Failed to clear search history');
+        }
+        window.location.reload();
+    } catch (error) {
+        console.error('Error clearing search history:', error);
+        alert('Failed to clear search history. Please try again.');
+    }
+}
+
+// Favorites that can be compared side by side at once
+const MIN_COMPARE = 2;
+const MAX_COMPARE = 5;
+
+function updateCompareButton(checkbox) {
+    const checked = document.querySelectorAll('.compare-checkbox:checked').length;
+    if (checked > MAX_COMPARE) {
+        checkbox.checked = false;
+        alert(`You can compare up to ${MAX_COMPARE} providers at a time.`);
+        return;
+    }
+    const button = document.getElementById('compareButton');
+    button.textContent = `Compare (${checked})`;
+    button.disabled = checked < MIN_COMPARE;
+}
+
+function compareFavorites() {
+    const ids = Array.from(document.querySelectorAll('.compare-checkbox:checked'))
+        .map((checkbox) => checkbox.dataset.providerId);
+    window.location.href = `/compare?ids=${encodeURIComponent(ids.join(','))}`;
+}
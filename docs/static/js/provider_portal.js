@@ -0,0 +1,87 @@
+// Provider portal: sends claims for a listing, and saves or removes the staff's
+// corrections to a listing they manage. Used by both portal pages.
+const claimForm = document.getElementById('claim-form');
+const listingForm = document.getElementById('listing-form');
+
+async function sendJson(url, method, body) {
+    const response = await fetch(url, {
+        method,
+        headers: { 'Content-Type': 'application/json' },
+        body: body === undefined ? undefined : JSON.stringify(body),
+    });
+    const result = await response.json().catch(() => ({}));
+    if (!response.ok) {
+        throw new Error(result.message || 'Request failed');
+    }
+    return result;
+}
+
+function lines(text) {
+    return text.split('\n').map((line) => line.trim()).filter(Boolean);
+}
+
+if (claimForm) {
+    claimForm.addEventListener('submit', async (event) => {
+        event.preventDefault();
+        try {
+            const result = await sendJson(`/api/providers/${encodeURIComponent(claimForm.dataset.providerId)}/claims`, 'POST', {
+                role: claimForm.elements.role.value,
+                message: claimForm.elements.message.value,
+            });
+            alert(result.message);
+            window.location.href = '/provider-portal';
+        } catch (error) {
+            console.error('Error sending claim:', error);
+            alert(error.message);
+        }
+    });
+}
+
+if (listingForm) {
+    const listingUrl = `/api/providers/${encodeURIComponent(listingForm.dataset.providerId)}/listing`;
+    const insuranceFields = document.getElementById('insurance-fields');
+
+    listingForm.elements.override_insurance.addEventListener('change', (event) => {
+        insuranceFields.hidden = !event.target.checked;
+    });
+
+    listingForm.addEventListener('submit', async (event) => {
+        event.preventDefault();
+        const elements = listingForm.elements;
+        const telehealth = elements.telehealth.value;
+        const plans = Array.from(listingForm.querySelectorAll('input[name="plan"]:checked')).map((input) => input.value);
+
+        try {
+            const result = await sendJson(listingUrl, 'PUT', {
+                phone: elements.phone.value.trim() || null,
+                hours: lines(elements.hours.value),
+                telehealth: telehealth === '' ? null : telehealth === 'true',
+                accepted_insurance: elements.override_insurance.checked
+                    ? plans.concat(lines(elements.other_plans.value))
+                    : null,
+            });
+            alert(result.message);
+            window.location.reload();
+        } catch (error) {
+            console.error('Error saving listing:', error);
+            alert(error.message);
+        }
+    });
+
+    const resetButton = document.getElementById('reset-listing');
+    if (resetButton) {
+        resetButton.addEventListener('click', async () => {
+            if (!confirm('Remove all corrections and show the directory data again?')) {
+                return;
+            }
+            try {
+                const result = await sendJson(listingUrl, 'DELETE');
+                alert(result.message);
+                window.location.reload();
+            } catch (error) {
+                console.error('Error removing corrections:', error);
+                alert(error.message);
+            }
+        });
+    }
+}
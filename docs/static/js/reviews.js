@@ -0,0 +1,84 @@
+// Reviews: writing, editing and deleting the user's own review, reporting other
+// people's, and the admin moderation queue. Used by both the reviews page and the
+// moderation page.
+const reviewForm = document.getElementById('review-form');
+
+async function sendJson(url, method, body) {
+    const response = await fetch(url, {
+        method,
+        headers: { 'Content-Type': 'application/json' },
+        body: body === undefined ? undefined : JSON.stringify(body),
+    });
+    const result = await response.json().catch(() => ({}));
+    if (!response.ok) {
+        throw new Error(result.message || 'Request failed');
+    }
+    return result;
+}
+
+if (reviewForm) {
+    const reviewUrl = `/api/providers/${encodeURIComponent(reviewForm.dataset.providerId)}/review`;
+
+    reviewForm.addEventListener('submit', async (event) => {
+        event.preventDefault();
+        try {
+            const result = await sendJson(reviewUrl, 'PUT', {
+                rating: Number(reviewForm.elements.rating.value),
+                body: reviewForm.elements.body.value,
+            });
+            alert(result.message);
+            window.location.reload();
+        } catch (error) {
+            console.error('Error saving review:', error);
+            alert(error.message);
+        }
+    });
+
+    const deleteButton = document.getElementById('delete-review');
+    if (deleteButton) {
+        deleteButton.addEventListener('click', async () => {
+            if (!confirm('Delete your review?')) {
+                return;
+            }
+            try {
+                await sendJson(reviewUrl, 'DELETE');
+                window.location.reload();
+            } catch (error) {
+                console.error('Error deleting review:', error);
+                alert(error.message);
+            }
+        });
+    }
+}
+
+document.addEventListener('click', async (event) => {
+    const reportButton = event.target.closest('.report-review');
+    if (reportButton) {
+        const reason = prompt('What is wrong with this review?');
+        if (!reason) {
+            return;
+        }
+        try {
+            const result = await sendJson(`/api/reviews/${reportButton.dataset.reviewId}/reports`, 'POST', { reason });
+            alert(result.message);
+            reportButton.disabled = true;
+        } catch (error) {
+            console.error('Error reporting review:', error);
+            alert(error.message);
+        }
+        return;
+    }
+
+    const moderateButton = event.target.closest('.moderate-review');
+    if (moderateButton) {
+        try {
+            await sendJson(`/admin/reviews/${moderateButton.dataset.reviewId}/${moderateButton.dataset.decision}`, 'POST', {
+                updated_at: moderateButton.dataset.updatedAt,
+            });
+            moderateButton.closest('.list-group-item').remove();
+        } catch (error) {
+            console.error('Error moderating review:', error);
+            alert(error.message);
+        }
+    }
+});
@@ -0,0 +1,106 @@
+// Symptom guidance wizard: keeps the answers picked so far and asks the server for
+// the next question or the outcome each time one is picked.
+const triage = document.getElementById('triage');
+const version = triage.dataset.version;
+
+const URGENCY_BADGES = {
+    emergency: 'bg-danger',
+    urgent_care: 'bg-warning text-dark',
+    primary_care: 'bg-primary',
+    telehealth: 'bg-info text-dark',
+    self_care: 'bg-success',
+};
+
+let redFlags = [];
+let answers = [];
+
+function show(id) {
+    for (const section of ['red-flags', 'question', 'outcome']) {
+        document.getElementById(section).hidden = section !== id;
+    }
+}
+
+function showQuestion(question) {
+    document.getElementById('question-text').textContent = question.text;
+    const list = document.getElementById('answers');
+    list.innerHTML = '';
+    question.answers.forEach((label, index) => {
+        const button = document.createElement('button');
+        button.className = 'list-group-item list-group-item-action';
+        button.textContent = label;
+        button.addEventListener('click', () => {
+            answers.push(index);
+            step();
+        });
+        list.appendChild(button);
+    });
+    show('question');
+}
+
+function showOutcome(outcome) {
+    const badge = document.getElementById('outcome-urgency');
+    badge.className = `badge mb-2 ${URGENCY_BADGES[outcome.urgency] || 'bg-secondary'}`;
+    badge.textContent = outcome.urgency_label;
+    document.getElementById('outcome-title').textContent = outcome.title;
+    document.getElementById('outcome-advice').textContent = outcome.advice;
+    document.getElementById('outcome-specialties').textContent = outcome.specialties.length > 0
+        ? `Who usually helps: ${outcome.specialties.join(', ')}`
+        : '';
+    const search = document.getElementById('outcome-search');
+    search.hidden = !outcome.search_url;
+    if (outcome.search_url) {
+        search.href = outcome.search_url;
+    }
+    show('outcome');
+}
+
+function restart() {
+    redFlags = [];
+    answers = [];
+    document.getElementById('red-flag-form').reset();
+    show('red-flags');
+}
+
+async function step() {
+    try {
+        const response = await fetch('/api/triage', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ version, red_flags: redFlags, answers }),
+        });
+        const result = await response.json().catch(() => ({}));
+        if (response.status === 409) {
+            alert(result.message);
+            window.location.reload();
+            return;
+        }
+        if (!response.ok) {
+            throw new Error(result.message || 'Failed to load the next question');
+        }
+        if (result.outcome) {
+            showOutcome(result.outcome);
+        } else {
+            showQuestion(result.question);
+        }
+    } catch (error) {
+        console.error('Error loading guidance:', error);
+        alert(error.message);
+    }
+}
+
+document.getElementById('red-flag-form').addEventListener('submit', (event) => {
+    event.preventDefault();
+    redFlags = Array.from(event.target.querySelectorAll('input[name="red_flag"]:checked')).map((input) => input.value);
+    step();
+});
+
+document.getElementById('back').addEventListener('click', () => {
+    if (answers.length === 0) {
+        show('red-flags');
+        return;
+    }
+    answers.pop();
+    step();
+});
+
+document.getElementById('restart').addEventListener('click', restart);
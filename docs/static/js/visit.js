@@ -0,0 +1,142 @@
+// Peer-to-peer video for a visit. The server only relays signaling: whoever joins
+// second sends the offer, the other answers, and both trade ICE candidates.
+const visit = document.getElementById('visit');
+const appointmentId = visit.dataset.appointmentId;
+const iceServers = [{ urls: visit.dataset.stunUrl }];
+
+let socket = null;
+let connection = null;
+let localStream = null;
+
+function setStatus(message, kind = 'info') {
+    const status = document.getElementById('visit-status');
+    status.className = `alert alert-${kind} text-center`;
+    status.textContent = message;
+}
+
+function signal(message) {
+    if (socket && socket.readyState === WebSocket.OPEN) {
+        socket.send(JSON.stringify(message));
+    }
+}
+
+// Start a fresh peer connection carrying our camera and microphone
+function newConnection() {
+    closeConnection();
+    connection = new RTCPeerConnection({ iceServers });
+    localStream.getTracks().forEach((track) => connection.addTrack(track, localStream));
+
+    connection.onicecandidate = (event) => {
+        if (event.candidate) {
+            signal({ type: 'candidate', candidate: event.candidate.toJSON() });
+        }
+    };
+    connection.ontrack = (event) => {
+        document.getElementById('remote-video').srcObject = event.streams[0];
+    };
+    connection.onconnectionstatechange = () => {
+        if (connection.connectionState === 'connected') {
+            setStatus('Connected.', 'success');
+        } else if (connection.connectionState === 'failed') {
+            setStatus('The video connection failed. Try leaving and joining again.', 'danger');
+        }
+    };
+}
+
+function closeConnection() {
+    if (connection) {
+        connection.close();
+        connection = null;
+    }
+    document.getElementById('remote-video').srcObject = null;
+}
+
+async function sendOffer() {
+    newConnection();
+    const offer = await connection.createOffer();
+    await connection.setLocalDescription(offer);
+    signal({ type: 'offer', sdp: offer.sdp });
+}
+
+async function handleSignal(message) {
+    switch (message.type) {
+        case 'joined':
+            if (message.peer_present) {
+                setStatus('Connecting to the other participant…');
+                await sendOffer();
+            } else {
+                setStatus('Waiting for the other participant to join…');
+            }
+            break;
+        case 'peer_joined':
+            setStatus(`The ${message.role} joined. Connecting…`);
+            break;
+        case 'offer': {
+            newConnection();
+            await connection.setRemoteDescription({ type: 'offer', sdp: message.sdp });
+            const answer = await connection.createAnswer();
+            await connection.setLocalDescription(answer);
+            signal({ type: 'answer', sdp: answer.sdp });
+            break;
+        }
+        case 'answer':
+            await connection?.setRemoteDescription({ type: 'answer', sdp: message.sdp });
+            break;
+        case 'candidate':
+            await connection?.addIceCandidate(message.candidate);
+            break;
+        case 'peer_left':
+            closeConnection();
+            setStatus(`The ${message.role} left. Waiting for them to rejoin…`, 'warning');
+            break;
+        case 'error':
+            console.error('Signaling error:', message.message);
+            break;
+    }
+}
+
+async function joinVisit() {
+    try {
+        localStream = await navigator.mediaDevices.getUserMedia({ video: true, audio: true });
+    } catch (error) {
+        console.error('Error opening camera:', error);
+        setStatus('We need access to your camera and microphone for the visit.', 'danger');
+        return;
+    }
+    document.getElementById('local-video').srcObject = localStream;
+
+    const scheme = window.location.protocol === 'https:' ? 'wss' : 'ws';
+    socket = new WebSocket(`${scheme}://${window.location.host}/ws/visits/${appointmentId}`);
+    socket.onmessage = (event) => {
+        handleSignal(JSON.parse(event.data)).catch((error) => console.error('Error handling signal:', error));
+    };
+    socket.onclose = () => {
+        closeConnection();
+        setStatus('You left the visit, or it is open in another window. Reload to join again.', 'secondary');
+    };
+}
+
+function toggleMute() {
+    const [track] = localStream.getAudioTracks();
+    track.enabled = !track.enabled;
+    document.getElementById('mute-button').innerHTML = track.enabled
+        ? '<i class="fa-solid fa-microphone"></i> Mute'
+        : '<i class="fa-solid fa-microphone-slash"></i> Unmute';
+}
+
+function toggleCamera() {
+    const [track] = localStream.getVideoTracks();
+    track.enabled = !track.enabled;
+    document.getElementById('camera-button').innerHTML = track.enabled
+        ? '<i class="fa-solid fa-video"></i> Camera off'
+        : '<i class="fa-solid fa-video-slash"></i> Camera on';
+}
+
+function leaveVisit() {
+    closeConnection();
+    socket?.close();
+    localStream?.getTracks().forEach((track) => track.stop());
+    window.location.href = '/profile';
+}
+
+joinVisit();
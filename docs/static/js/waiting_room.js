@@ -0,0 +1,142 @@
+// Live waiting room: the server pushes the current view over a WebSocket whenever
+// the queue changes, and buttons here call the REST endpoints that change it.
+const room = document.getElementById('waiting-room');
+const providerId = room.dataset.providerId;
+const isClinician = room.dataset.clinician === 'true';
+const roomUrl = `/api/waiting-room/${encodeURIComponent(providerId)}`;
+
+// Seconds to wait before reconnecting after the socket drops
+const RECONNECT_DELAY = 3;
+
+let roomOpen = false;
+
+function escapeHtml(text) {
+    const div = document.createElement('div');
+    div.textContent = text;
+    return div.innerHTML;
+}
+
+async function post(url, method = 'POST', body = undefined) {
+    const response = await fetch(url, {
+        method,
+        headers: body ? { 'Content-Type': 'application/json' } : {},
+        body: body ? JSON.stringify(body) : undefined,
+    });
+    const result = await response.json().catch(() => ({}));
+    if (!response.ok) {
+        throw new Error(result.message || `Request to ${url} failed`);
+    }
+    return result;
+}
+
+function renderPatient(view) {
+    const joinButton = view.open
+        ? '<button class="btn btn-success" onclick="joinQueue()"><i class="fa-solid fa-right-to-bracket"></i> Join the queue</button>'
+        : '';
+    const waitingNow = `<p class="text-muted">${view.waiting} ${view.waiting === 1 ? 'person' : 'people'} waiting.</p>`;
+    let html;
+
+    switch (view.status) {
+        case 'waiting':
+            html = `
+                <p class="mb-0">Your place in line</p>
+                <div class="queue-position">${view.position}</div>
+                <p>Estimated wait: about ${view.estimated_wait_minutes} minutes</p>
+                <p class="text-muted small">Keep this page open. It updates as the line moves.</p>
+                <button class="btn btn-outline-danger" onclick="leaveQueue()">Leave the queue</button>`;
+            break;
+        case 'admitted':
+            html = `
+                <h4 class="text-success"><i class="fa-solid fa-circle-check"></i> The clinician is ready for you</h4>
+                <p>They'll contact you now.</p>`;
+            break;
+        case 'skipped':
+            html = `
+                <p>The clinician couldn't see you this time.</p>
+                ${view.open ? `${waitingNow}${joinButton}` : '<p class="text-muted">The waiting room is closed right now.</p>'}`;
+            break;
+        default:
+            html = view.open
+                ? `${waitingNow}${joinButton}`
+                : '<p class="text-muted">The waiting room is closed right now. Check back later.</p>';
+    }
+    document.getElementById('patient-view').innerHTML = html;
+}
+
+function renderClinician(view) {
+    roomOpen = view.open;
+    const state = document.getElementById('room-state');
+    state.textContent = view.open ? 'Open' : 'Closed';
+    state.className = `badge ${view.open ? 'bg-success' : 'bg-secondary'}`;
+    document.getElementById('toggle-room').textContent = view.open ? 'Close' : 'Open';
+
+    const minutes = document.getElementById('visit-minutes');
+    if (document.activeElement !== minutes) {
+        minutes.value = view.visit_minutes;
+    }
+
+    document.getElementById('queue').innerHTML = view.queue.length === 0
+        ? '<li class="list-group-item text-muted">Nobody is waiting.</li>'
+        : view.queue.map((entry, index) => `
+            <li class="list-group-item d-flex justify-content-between">
+                <span><strong>${index + 1}.</strong> ${escapeHtml(entry.username)}</span>
+                <small class="text-muted">since ${escapeHtml(entry.joined_at)}</small>
+            </li>`).join('');
+}
+
+function connect() {
+    const scheme = window.location.protocol === 'https:' ? 'wss' : 'ws';
+    const socket = new WebSocket(`${scheme}://${window.location.host}/ws/waiting-room/${encodeURIComponent(providerId)}`);
+    socket.onmessage = (event) => {
+        const view = JSON.parse(event.data);
+        if (isClinician) {
+            renderClinician(view);
+        } else {
+            renderPatient(view);
+        }
+    };
+    socket.onclose = () => setTimeout(connect, RECONNECT_DELAY * 1000);
+}
+
+async function joinQueue() {
+    try {
+        await post(`${roomUrl}/join`);
+    } catch (error) {
+        console.error('Error joining queue:', error);
+        alert(error.message);
+    }
+}
+
+async function leaveQueue() {
+    if (!confirm('Leave the queue? You would lose your place.')) {
+        return;
+    }
+
+    try {
+        await post(`${roomUrl}/leave`);
+    } catch (error) {
+        console.error('Error leaving queue:', error);
+        alert(error.message);
+    }
+}
+
+async function callNext(action) {
+    try {
+        await post(`${roomUrl}/${action}`);
+    } catch (error) {
+        console.error(`Error calling ${action}:`, error);
+        alert(error.message);
+    }
+}
+
+async function toggleRoom() {
+    const minutes = Number(document.getElementById('visit-minutes').value);
+    try {
+        await post(roomUrl, 'PUT', { open: !roomOpen, visit_minutes: minutes || null });
+    } catch (error) {
+        console.error('Error updating waiting room:', error);
+        alert(error.message);
+    }
+}
+
+connect();